@@ -0,0 +1,125 @@
+//! Animations: scenes whose camera and objects move from one frame to
+//! the next, given by their poses at a few key frames and interpolated
+//! in between, or by splines the camera flies along.
+
+use crate::math::Vector;
+
+/// How long an animation is and how fast it plays.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Animation {
+    pub frames: u32, // Frames are numbered from 1 to this, a still image has one
+    pub fps: f32,
+    pub easing: Easing
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self { frames: 1, fps: 24.0, easing: Easing::default() }
+    }
+}
+
+impl Animation {
+    pub fn is_animated(&self) -> bool {
+        self.frames > 1
+    }
+}
+
+/// How the motion between two key frames speeds up and slows down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// At a steady pace.
+    #[default]
+    Linear,
+    /// Starting and stopping gently at the key frames, along the
+    /// smoothstep curve.
+    Smooth
+}
+
+impl Easing {
+    /// How far along the way between two key frames the motion is at
+    /// the fraction `t` of the time between them.
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::Smooth => t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+/// Value at `frame` of the key frames given as (frame, value) pairs in
+/// the order of the frames, `lerp` interpolating between two of them.
+/// Before the first key frame and after the last one, the value stays
+/// put. `None` without key frames.
+pub fn interpolate<T: Clone>(keys: &[(f32, T)], frame: f32, easing: Easing, lerp: impl Fn(&T, &T, f32) -> T) -> Option<T> {
+    let (first, last) = (keys.first()?, keys.last()?);
+    if frame <= first.0 {
+        return Some(first.1.clone());
+    }
+    if frame >= last.0 {
+        return Some(last.1.clone());
+    }
+
+    let k = keys.windows(2).position(|pair| frame < pair[1].0)?;
+    let ((f0, v0), (f1, v1)) = (&keys[k], &keys[k + 1]);
+    Some(lerp(v0, v1, easing.apply((frame - f0) / (f1 - f0))))
+}
+
+/// Kinds of curves through or along a few control points.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Spline {
+    /// Catmull–Rom spline, going through all the points.
+    #[default]
+    CatmullRom,
+    /// Cubic Bézier curves joined end to end: the points are the start,
+    /// two control points and the end of the first curve, then two
+    /// control points and the end of every next one.
+    Bezier
+}
+
+impl Spline {
+    /// Why the spline can't be drawn along `points` points, if it can't.
+    pub fn check(&self, points: usize) -> Result<(), String> {
+        match self {
+            _ if points == 0 => Err("a spline needs at least one point".to_string()),
+            Spline::Bezier if points % 3 != 1 => {
+                Err(format!("a Bézier spline needs 3n + 1 points, not {}", points))
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Point at `t`, from 0 at the start of the spline to 1 at its end,
+    /// the same stretch of `t` for every segment. The points have to
+    /// pass `check`.
+    pub fn at(&self, points: &[Vector], t: f32) -> Vector {
+        let segments = match self {
+            Spline::CatmullRom => points.len() - 1,
+            Spline::Bezier => (points.len() - 1) / 3
+        };
+        if segments == 0 {
+            return points[0];
+        }
+
+        let s = t.clamp(0.0, 1.0) * segments as f32;
+        let k = (s as usize).min(segments - 1);
+        let u = s - k as f32;
+        match self {
+            Spline::CatmullRom => {
+                // The ends are repeated for the tangents at the first and
+                // the last points.
+                let p0 = points[k.saturating_sub(1)];
+                let (p1, p2) = (points[k], points[k + 1]);
+                let p3 = points[(k + 2).min(points.len() - 1)];
+                0.5 * (2.0 * p1
+                    + u * (p2 - p0)
+                    + u * u * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3)
+                    + u * u * u * (3.0 * p1 - p0 - 3.0 * p2 + p3))
+            },
+            Spline::Bezier => {
+                let p = &points[3 * k .. 3 * k + 4];
+                let v = 1.0 - u;
+                v * v * v * p[0] + 3.0 * v * v * u * p[1] + 3.0 * v * u * u * p[2] + u * u * u * p[3]
+            }
+        }
+    }
+}
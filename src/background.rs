@@ -0,0 +1,77 @@
+//! What a ray sees when it escapes the scene without hitting anything.
+
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use image::ImageResult;
+
+use crate::geometry::Ray;
+use crate::math::Vector;
+
+/// HDR image wrapped around the scene in the equirectangular (latitude-
+/// longitude) projection, with +Y pointing up and -Z at the center.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentMap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vector> // Linear radiance, top row first
+}
+
+impl EnvironmentMap {
+    /// Load a map from a Radiance `.hdr` file (or any other image
+    /// format the image crate understands).
+    pub fn load<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let image = image::open(path)?.into_rgb32f();
+        let pixels = image.pixels()
+            .map(|p| Vector{ x: p[0], y: p[1], z: p[2] })
+            .collect();
+
+        Ok(Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels
+        })
+    }
+
+    /// Radiance coming from the direction `d`.
+    pub fn sample(&self, d: Vector) -> Vector {
+        let d = d.unit();
+        let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+
+        let i = ((v * self.height as f32) as usize).min(self.height - 1);
+        let j = ((u * self.width as f32) as usize).min(self.width - 1);
+
+        self.pixels[i * self.width + j]
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Background {
+    /// Blue-white gradient of a daylight sky.
+    #[default]
+    Sky,
+    /// The same color in every direction. Black for scenes lit only by
+    /// emissive objects.
+    Solid(Vector),
+    /// Image-based lighting from an environment map.
+    Environment(Arc<EnvironmentMap>)
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Vector {
+        match self {
+            Background::Sky => {
+                let y = ray.direction.y;
+                let t = 0.5 * (y + 1.0);
+                let blue  = Vector {x: 0.5, y: 0.7, z: 1.0};
+                let white = Vector {x: 1.0, y: 1.0, z: 1.0};
+
+                (1.0 - t) * white + t * blue
+            },
+            Background::Solid(color) => *color,
+            Background::Environment(map) => map.sample(ray.direction)
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Bidirectional path tracing.
+//!
+//! Every sample grows one path from the camera and one from a light and
+//! joins every vertex of the one with every vertex of the other by a
+//! shadow ray. A path of a given length can then be built in several
+//! ways, and each of them counts for it as much as the power heuristic
+//! says. Light that the camera paths hardly ever find, such as the one
+//! coming into a room through a doorway, is found by the light paths
+//! instead.
+//!
+//! The light paths are not joined with the camera itself, which would
+//! add their light to the other pixels.
+
+use crate::geometry::{Hit, Hittable, Ray, World};
+use crate::math::Vector;
+use crate::sampler::Sampler;
+
+/// Point of a path from the camera or from a light.
+#[derive(Debug, Copy, Clone)]
+struct Vertex<'a> {
+    p: Vector,
+    n: Vector,                       // Outer normal, zero at the camera and on point lights
+    surface: Option<(Hit<'a>, Ray)>, // Hit and the ray that got there, but at the camera and the lights
+    beta: Vector,                    // Throughput of the path up to the vertex
+    pdf_fwd: f32,                    // Density of the vertex per unit area, as its path sampled it
+    pdf_rev: f32,                    // The same, had the other path sampled it
+    delta: bool                      // Whether the paths can't be joined at the vertex
+}
+
+impl<'a> Vertex<'a> {
+    /// First vertex of a path.
+    fn start(p: Vector, n: Vector, beta: Vector, pdf: f32) -> Self {
+        Self { p, n, surface: None, beta, pdf_fwd: pdf, pdf_rev: 0.0, delta: false }
+    }
+
+    /// Fraction of the light coming from the point `q` that leaves the
+    /// vertex the way the path came.
+    fn eval(&self, q: Vector) -> Vector {
+        match &self.surface {
+            Some((hit, ray)) => hit.material.eval(ray, hit, (q - self.p).unit()),
+            None => Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        }
+    }
+
+    /// Density per unit area with which a path coming to the vertex from
+    /// the point `prev` goes on to the vertex `next`.
+    fn pdf(&self, prev: Vector, next: &Vertex) -> f32 {
+        match &self.surface {
+            Some((hit, _)) => {
+                let ray = Ray::new(prev, self.p - prev);
+                to_area(hit.material.pdf(&ray, hit, (next.p - self.p).unit()), self.p, next)
+            },
+            None => 0.0
+        }
+    }
+}
+
+/// Cosine of the angle between the normal and the unit direction, 1
+/// where there is no normal.
+fn cos(n: Vector, direction: Vector) -> f32 {
+    if n.is_near_zero() { 1.0 } else { n.dot(direction).abs() }
+}
+
+/// Turn a density per unit solid angle of the direction from `p` to
+/// the vertex into a density per unit area around the vertex.
+fn to_area(pdf: f32, p: Vector, vertex: &Vertex) -> f32 {
+    let d = vertex.p - p;
+    let distance2 = d.sqnorm();
+    pdf * cos(vertex.n, d / distance2.sqrt()) / distance2
+}
+
+/// Bounce the ray around the world, adding a vertex to the path at
+/// every hit, until the path has `max` vertices or the ray gets
+/// absorbed. The ray leaves the last vertex of the path with the
+/// throughput `beta`, in a direction picked with the density `pdf` per
+/// unit solid angle. Returns the ray that flew off into the background
+/// and its throughput, if any did.
+fn walk<'a>(world: &'a World, mut ray: Ray, mut beta: Vector, mut pdf: f32, max: usize, path: &mut Vec<Vertex<'a>>, sampler: &mut dyn Sampler) -> Option<(Ray, Vector)> {
+    while path.len() < max {
+        let h = match world.hit(&ray) {
+            Some(h) => h,
+            None => return Some((ray, beta))
+        };
+
+        let prev = path.len() - 1;
+        let mut vertex = Vertex::start(h.p, h.n, beta, 0.0);
+        vertex.surface = Some((h, ray));
+        vertex.pdf_fwd = to_area(pdf, path[prev].p, &vertex);
+        path.push(vertex);
+        if path.len() == max {
+            break;
+        }
+
+        let s = match h.material.scatter(&ray, &h, sampler) {
+            Some(s) => s,
+            None => break
+        };
+
+        // Specular bounces have no density to speak of, and the paths
+        // are never joined there.
+        let delta = h.material.is_specular();
+        let (pdf_fwd, pdf_rev) = if delta {
+            (0.0, 0.0)
+        } else {
+            let back = Ray::new(h.p, -s.ray.direction);
+            (h.material.pdf(&ray, &h, s.ray.direction), h.material.pdf(&back, &h, -ray.direction))
+        };
+        path[prev + 1].delta = delta;
+        path[prev].pdf_rev = to_area(pdf_rev, h.p, &path[prev]);
+
+        pdf = pdf_fwd;
+        beta = beta * s.attenuation;
+        ray = s.ray;
+    }
+
+    None
+}
+
+/// Light arriving along the ray, found by joining paths from the camera
+/// and from the lights that bounce at most `depth` times in between.
+/// With `transparent`, the ray given comes out black if it misses
+/// everything, as in `ray_color`.
+pub fn bdpt_color(ray: &Ray, world: &World, depth: u8, transparent: bool, sampler: &mut dyn Sampler) -> Vector {
+    let depth = depth as usize;
+    let zero = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+    let one = Vector{ x: 1.0, y: 1.0, z: 1.0 };
+    let mut color = zero;
+
+    // The camera path only ever ends on a light or in the background
+    // past the last bounce.
+    let mut eye = vec![Vertex::start(ray.origin, zero, one, 1.0)];
+    if let Some((escaped, beta)) = walk(world, *ray, one, 1.0, depth + 2, &mut eye, sampler) {
+        if !(transparent && eye.len() == 1) {
+            color += beta * world.background.color(&escaped);
+        }
+    }
+
+    if world.lights.is_empty() {
+        return color + emitted(world, &eye);
+    }
+    let selection = 1.0 / world.lights.len() as f32;
+
+    let mut light = vec![];
+    let index = pick(world, sampler);
+    let e = world.lights[index].emit(sampler);
+    let pdf = selection * e.pdf_position;
+    light.push(Vertex::start(e.point, e.normal, e.radiance / pdf, pdf));
+    if e.pdf_direction > 0.0 {
+        let ray = Ray::new(e.point, e.direction).with_time(ray.time);
+        let beta = cos(e.normal, e.direction) / (pdf * e.pdf_direction) * e.radiance;
+        walk(world, ray, beta, e.pdf_direction, depth, &mut light, sampler);
+    }
+
+    color += emitted(world, &eye);
+    for t in 2 .. eye.len() + 1 {
+        let z = &eye[t - 1];
+        if z.delta {
+            continue;
+        }
+
+        // A shadow ray to a light of its own.
+        if t - 1 <= depth {
+            let index = pick(world, sampler);
+            let sample = world.lights[index].sample(z.p, sampler);
+            let f = z.eval(z.p + sample.direction);
+            let contribution = z.beta * f * sample.intensity;
+            if !contribution.is_near_zero() && !world.is_occluded(z.p, sample.direction, sample.distance, ray.time) {
+                let q = z.p + sample.distance * sample.direction;
+                let (position, _) = world.lights[index].emission_pdf(q, -sample.direction);
+                let y = Vertex::start(q, sample.normal, zero, selection * position);
+                let weight = mis_weight(world, &eye[.. t], &[y], index);
+                color += (weight / selection) * contribution;
+            }
+        }
+
+        // Joins with the vertices of the light path past the light.
+        for s in 2 .. light.len() + 1 {
+            if t - 1 + s - 1 > depth {
+                break;
+            }
+
+            let y = &light[s - 1];
+            if y.delta {
+                continue;
+            }
+
+            let d = y.p - z.p;
+            let distance = d.norm();
+            let f = z.eval(y.p) * y.eval(z.p);
+            if f.is_near_zero() || world.is_occluded(z.p, d / distance, distance, ray.time) {
+                continue;
+            }
+
+            let weight = mis_weight(world, &eye[.. t], &light[.. s], index);
+            color += (weight / (distance * distance)) * (z.beta * y.beta * f);
+        }
+    }
+
+    color
+}
+
+/// Index of a light picked uniformly.
+fn pick(world: &World, sampler: &mut dyn Sampler) -> usize {
+    let n = world.lights.len();
+    ((sampler.next_1d() * n as f32) as usize).min(n - 1)
+}
+
+/// Light of the glowing surfaces the camera path ran into, weighted
+/// against finding the same paths from the lights.
+fn emitted(world: &World, eye: &[Vertex]) -> Vector {
+    let mut color = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+    for t in 2 .. eye.len() + 1 {
+        let (hit, ray) = match &eye[t - 1].surface {
+            Some(surface) => surface,
+            None => continue
+        };
+
+        let radiance = hit.material.emitted(ray, hit);
+        if radiance.is_near_zero() {
+            continue;
+        }
+
+        let weight = match world.emitters.get(&hit.object) {
+            Some(&index) => mis_weight(world, &eye[.. t], &[], index),
+            None => 1.0
+        };
+        color += weight * (eye[t - 1].beta * radiance);
+    }
+
+    color
+}
+
+/// Weight of the path made of the camera path `eye` and the light path
+/// `light` starting on the light `index`, against all the other ways
+/// the two could have split it. With no light path, the camera path
+/// ends on the light.
+fn mis_weight(world: &World, eye: &[Vertex], light: &[Vertex], index: usize) -> f32 {
+    let (s, t) = (light.len(), eye.len());
+    let source = &world.lights[index];
+    let selection = 1.0 / world.lights.len() as f32;
+    let z = &eye[t - 1];
+
+    // Densities of the vertices at the join, had the other path sampled
+    // them.
+    let mut eye_rev: Vec<f32> = eye.iter().map(|v| v.pdf_rev).collect();
+    let mut light_rev: Vec<f32> = light.iter().map(|v| v.pdf_rev).collect();
+    if s == 0 {
+        let (position, direction) = source.emission_pdf(z.p, (eye[t - 2].p - z.p).unit());
+        eye_rev[t - 1] = selection * position;
+        eye_rev[t - 2] = to_area(direction, z.p, &eye[t - 2]);
+    } else {
+        let y = &light[s - 1];
+        eye_rev[t - 1] = if s == 1 {
+            let (_, direction) = source.emission_pdf(y.p, (z.p - y.p).unit());
+            to_area(direction, y.p, z)
+        } else {
+            y.pdf(light[s - 2].p, z)
+        };
+        eye_rev[t - 2] = z.pdf(y.p, &eye[t - 2]);
+        light_rev[s - 1] = z.pdf(eye[t - 2].p, y);
+        if s > 1 {
+            light_rev[s - 2] = y.pdf(z.p, &light[s - 2]);
+        }
+    }
+
+    // Whatever is not sampled at all, such as the direction of a
+    // specular bounce, is sampled the same either way.
+    let remap = |pdf: f32| if pdf == 0.0 { 1.0 } else { pdf };
+    let mut sum = 0.0;
+
+    // Moving the join towards the camera, up to the first bounce.
+    let mut ratio = 1.0;
+    for i in (2 .. t).rev() {
+        ratio *= remap(eye_rev[i]) / remap(eye[i].pdf_fwd);
+        if !eye[i].delta && !eye[i - 1].delta {
+            sum += ratio * ratio;
+        }
+    }
+
+    // Moving the join towards the light, up to the camera path running
+    // into the light, which it can't do for the lights that are no
+    // objects of the world.
+    let hittable = world.is_hittable(index);
+    let mut ratio = 1.0;
+    for i in (0 .. s).rev() {
+        ratio *= remap(light_rev[i]) / remap(light[i].pdf_fwd);
+        let joinable = if i > 0 { !light[i - 1].delta } else { hittable };
+        if !light[i].delta && joinable {
+            sum += ratio * ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
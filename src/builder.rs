@@ -0,0 +1,285 @@
+//! Scenes put together in code, for programs using the ray tracer as a
+//! library:
+//!
+//! ```text
+//! let scene = Scene::builder()
+//!     .size(800, 400)
+//!     .camera(Vector{ x: 0.0, y: 1.0, z: 3.0 }, Vector{ x: 0.0, y: 0.0, z: -1.0 }, 40.0)
+//!     .material("glass", Dielectric{ refractive_index: 1.5 })
+//!     .emissive("lamp", Vector{ x: 4.0, y: 4.0, z: 4.0 })
+//!     .sphere(Vector{ x: 0.0, y: 0.0, z: -1.0 }, 0.5, "glass")
+//!     .sphere(Vector{ x: 0.0, y: 3.0, z: -1.0 }, 0.5, "lamp")
+//!     .build()?;
+//! ```
+//!
+//! Objects refer to the materials by name, as in the scene files, and
+//! nothing is checked till `build`, which tells the first mistake: an
+//! unknown material, a sphere of no radius, a camera looking at itself.
+//! The camera is made for the aspect ratio of the image, and glowing
+//! spheres, quads and triangles are sampled as lights, as the scene
+//! files do.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::animation::Animation;
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::geometry::{Hittable, Plane, Quad, Sphere, Triangle, World};
+use crate::integrator::IntegratorKind;
+use crate::light::{AreaLight, Light, PointLight, SphereLight, TriangleLight};
+use crate::loaders::LoadError;
+use crate::material::{Emissive, Material};
+use crate::math::{Vector, EY};
+use crate::photon::PhotonMap;
+use crate::render::Settings;
+use crate::scene::Scene;
+
+/// Scene in the making, see the module documentation.
+pub struct SceneBuilder {
+    settings: Settings,
+    origin: Vector,
+    look_at: Vector,
+    up: Vector,
+    vfov: f32,
+    aperture: f32,
+    focus_distance: Option<f32>, // The distance to `look_at` if not given
+    background: Background,
+    materials: HashMap<String, Arc<dyn Material>>,
+    radiances: HashMap<String, Vector>, // Of the glowing materials
+    shapes: Vec<Shape>,
+    lights: Vec<Box<dyn Light>>,
+    error: Option<String> // The first mistake, told by `build`
+}
+
+/// Object waiting for its material.
+enum Shape {
+    Sphere { center: Vector, radius: f32, material: String },
+    Plane { point: Vector, normal: Vector, material: String },
+    Quad { corner: Vector, u: Vector, v: Vector, material: String },
+    Triangle { a: Vector, b: Vector, c: Vector, material: String },
+    Object(Box<dyn Hittable>)
+}
+
+impl Scene {
+    /// Start putting a scene together in code.
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder::new()
+    }
+}
+
+impl Default for SceneBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SceneBuilder {
+    /// Empty scene under the sky, seen with the default settings by a
+    /// camera at the origin looking down the -z axis.
+    pub fn new() -> Self {
+        Self {
+            settings: Settings::default(),
+            origin: Vector{ x: 0.0, y: 0.0, z: 0.0 },
+            look_at: Vector{ x: 0.0, y: 0.0, z: -1.0 },
+            up: EY,
+            vfov: 90.0,
+            aperture: 0.0,
+            focus_distance: None,
+            background: Background::default(),
+            materials: HashMap::new(),
+            radiances: HashMap::new(),
+            shapes: vec![],
+            lights: vec![],
+            error: None
+        }
+    }
+
+    /// Remember the first mistake only.
+    fn fail(&mut self, message: String) {
+        self.error.get_or_insert(message);
+    }
+
+    /// All the render settings at once.
+    pub fn settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        self.settings.width = width;
+        self.settings.height = height;
+        self
+    }
+
+    pub fn samples(mut self, samples_per_pixel: u32) -> Self {
+        self.settings.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u8) -> Self {
+        self.settings.max_depth = max_depth;
+        self
+    }
+
+    pub fn integrator(mut self, integrator: IntegratorKind) -> Self {
+        self.settings.integrator = integrator;
+        self
+    }
+
+    /// Camera at `origin` looking at `look_at`, seeing `vfov` degrees
+    /// across the height of the image.
+    pub fn camera(mut self, origin: Vector, look_at: Vector, vfov: f32) -> Self {
+        self.origin = origin;
+        self.look_at = look_at;
+        self.vfov = vfov;
+        self
+    }
+
+    /// Which way is up for the camera, +y unless told otherwise.
+    pub fn up(mut self, up: Vector) -> Self {
+        self.up = up;
+        self
+    }
+
+    /// Depth of field: the diameter of the lens and the distance in
+    /// focus.
+    pub fn lens(mut self, aperture: f32, focus_distance: f32) -> Self {
+        self.aperture = aperture;
+        self.focus_distance = Some(focus_distance);
+        self
+    }
+
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Name a material for the objects to refer to.
+    pub fn material<M: Material + 'static>(mut self, name: &str, material: M) -> Self {
+        if self.materials.insert(name.to_string(), Arc::new(material)).is_some() {
+            self.fail(format!("material '{}' is given twice", name));
+        }
+        self
+    }
+
+    /// Name a material glowing with the `radiance`. The spheres, quads
+    /// and triangles of it light the scene as lights do.
+    pub fn emissive(mut self, name: &str, radiance: Vector) -> Self {
+        self.radiances.insert(name.to_string(), radiance);
+        self.material(name, Emissive { radiance })
+    }
+
+    pub fn sphere(mut self, center: Vector, radius: f32, material: &str) -> Self {
+        if radius <= 0.0 {
+            self.fail(format!("the sphere at {:?} has a radius of {}", center, radius));
+        }
+        self.shapes.push(Shape::Sphere { center, radius, material: material.to_string() });
+        self
+    }
+
+    /// Infinite plane through the `point`.
+    pub fn plane(mut self, point: Vector, normal: Vector, material: &str) -> Self {
+        if normal.is_near_zero() {
+            self.fail(format!("the plane through {:?} has no normal", point));
+        }
+        self.shapes.push(Shape::Plane { point, normal, material: material.to_string() });
+        self
+    }
+
+    /// Parallelogram spanned by `u` and `v` from the `corner`.
+    pub fn quad(mut self, corner: Vector, u: Vector, v: Vector, material: &str) -> Self {
+        if u.cross(v).is_near_zero() {
+            self.fail(format!("the quad at {:?} is flat", corner));
+        }
+        self.shapes.push(Shape::Quad { corner, u, v, material: material.to_string() });
+        self
+    }
+
+    pub fn triangle(mut self, a: Vector, b: Vector, c: Vector, material: &str) -> Self {
+        if (b - a).cross(c - a).is_near_zero() {
+            self.fail(format!("the triangle at {:?} is flat", a));
+        }
+        self.shapes.push(Shape::Triangle { a, b, c, material: material.to_string() });
+        self
+    }
+
+    /// Any other object, with a material of its own.
+    pub fn object<H: Hittable + 'static>(mut self, object: H) -> Self {
+        self.shapes.push(Shape::Object(Box::new(object)));
+        self
+    }
+
+    pub fn point_light(self, position: Vector, intensity: Vector) -> Self {
+        self.light(PointLight { position, intensity })
+    }
+
+    pub fn light<L: Light + 'static>(mut self, light: L) -> Self {
+        self.lights.push(Box::new(light));
+        self
+    }
+
+    /// The scene, or the first mistake made putting it together.
+    pub fn build(self) -> Result<Scene, LoadError> {
+        if let Some(error) = self.error {
+            return Err(LoadError::invalid(error));
+        }
+        let settings = self.settings;
+        if settings.width < 2 || settings.height < 2 {
+            return Err(LoadError::invalid("the image must be at least 2×2 pixels"));
+        }
+        if settings.samples_per_pixel == 0 {
+            return Err(LoadError::invalid("there must be at least one sample per pixel"));
+        }
+        if (self.look_at - self.origin).is_near_zero() {
+            return Err(LoadError::invalid("the camera looks at itself"));
+        }
+        if (self.look_at - self.origin).cross(self.up).is_near_zero() {
+            return Err(LoadError::invalid("the camera looks along its up direction"));
+        }
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            return Err(LoadError::invalid(format!("the camera sees {} degrees", self.vfov)));
+        }
+
+        let focus_distance = self.focus_distance.unwrap_or_else(|| (self.look_at - self.origin).norm());
+        let camera = Camera::new(self.origin, self.look_at, self.up, self.vfov, settings.aspect_ratio())
+            .with_lens(self.aperture, focus_distance);
+
+        let mut world = World::new();
+        world.background = self.background;
+        let (materials, radiances) = (self.materials, self.radiances);
+        let material = |name: &str| materials.get(name)
+            .cloned()
+            .ok_or_else(|| LoadError::invalid(format!("unknown material '{}'", name)));
+        for shape in self.shapes {
+            let (object, emitter): (Box<dyn Hittable>, Option<Box<dyn Light>>) = match shape {
+                Shape::Sphere { center, radius, material: name } => (
+                    Box::new(Sphere { center, radius, material: material(&name)? }),
+                    radiances.get(&name).map(|&radiance| Box::new(SphereLight { center, radius, radiance }) as Box<dyn Light>)
+                ),
+                Shape::Plane { point, normal, material: name } => {
+                    (Box::new(Plane { point, normal, material: material(&name)? }), None)
+                },
+                Shape::Quad { corner, u, v, material: name } => (
+                    Box::new(Quad { corner, u, v, material: material(&name)? }),
+                    radiances.get(&name).map(|&radiance| Box::new(AreaLight { corner, u, v, radiance }) as Box<dyn Light>)
+                ),
+                Shape::Triangle { a, b, c, material: name } => (
+                    Box::new(Triangle { a, b, c, material: material(&name)? }),
+                    radiances.get(&name).map(|&radiance| Box::new(TriangleLight { a, b, c, radiance }) as Box<dyn Light>)
+                ),
+                Shape::Object(object) => (object, None)
+            };
+            if let Some(light) = emitter {
+                world.emitters.insert(world.objects.len(), world.lights.len());
+                world.lights.push(light);
+            }
+            world.objects.push(object);
+        }
+        world.lights.extend(self.lights);
+        world.prepare(settings.accelerator, settings.bvh);
+        world.caustics = PhotonMap::build(&world, &settings);
+
+        Ok(Scene { settings, camera, world, colors: vec![], animation: Animation::default() })
+    }
+}
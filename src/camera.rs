@@ -0,0 +1,436 @@
+//! Camera that turns viewport coordinates into primary rays.
+
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use crate::geometry::Ray;
+use crate::lens::Lens;
+use crate::math::{Transform, Vector, EX, EY, EZ};
+use crate::sampler::Sampler;
+
+/// How the points of the image are mapped to the directions of the
+/// camera rays.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Projection {
+    /// Through the viewport, as with a pinhole or a thin lens.
+    #[default]
+    Perspective,
+    /// Six 90° views from the origin along the axes of the world, for
+    /// skyboxes and reflection probes. They go side by side, left to
+    /// right, in the order +X, -X, +Y, -Y, +Z, -Z, so the image is to be
+    /// six times as wide as it is high. The side views are upright, the
+    /// top one has +Z at its top and the bottom one -Z.
+    Cubemap,
+    /// All the directions around the origin, the longitude going across
+    /// the image and the latitude up it, for 360° panoramas. The middle
+    /// of the image is straight ahead and its top straight up, and it is
+    /// to be twice as wide as it is high.
+    Equirectangular,
+    /// Fisheye lens seeing `fov` degrees, which can be more than 180 and
+    /// up to 360, across the circle inscribed in the height of the image.
+    /// Outside of the circle, it is black.
+    Fisheye { mapping: FisheyeMapping, fov: f32 },
+    /// Parallel rays straight ahead from the points of a rectangle as
+    /// large as the viewport, for technical drawings and isometric views.
+    /// Things look as large however far they are.
+    Orthographic,
+    /// Through a lens of real elements onto a film, in place of the
+    /// viewport and the thin lens. It has a field of view of its own and
+    /// darkens the corners of the image as the real ones do. The lens is
+    /// that of the camera, focused at its focus distance.
+    Realistic
+}
+
+/// How far from the middle of a fisheye image a direction goes, for the
+/// angle θ between it and the direction straight ahead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum FisheyeMapping {
+    /// In proportion to θ, so that the angles are spaced evenly.
+    #[default]
+    Equidistant,
+    /// In proportion to sin(θ / 2), so that the pixels cover equal solid
+    /// angles, like most of the fisheye lenses made.
+    Equisolid
+}
+
+/// Field of view of the fisheye lenses unless told otherwise, in degrees.
+pub const FISHEYE_FOV: f32 = 180.0;
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "cubemap" => Ok(Projection::Cubemap),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            "equidistant" => Ok(Projection::Fisheye { mapping: FisheyeMapping::Equidistant, fov: FISHEYE_FOV }),
+            "equisolid" => Ok(Projection::Fisheye { mapping: FisheyeMapping::Equisolid, fov: FISHEYE_FOV }),
+            "orthographic" => Ok(Projection::Orthographic),
+            "realistic" => Ok(Projection::Realistic),
+            _ => Err(format!(
+                "unknown projection {}, expected perspective, cubemap, equirectangular, equidistant, equisolid, \
+                 orthographic or realistic", s
+            ))
+        }
+    }
+}
+
+/// Direction of the cube map point (u, v) of the image, both ranging
+/// from 0 to 1.
+fn cubemap_direction(u: f32, v: f32) -> Vector {
+    // Direction every face looks in, and the directions of its right and
+    // top edges.
+    const FACES: [(Vector, Vector, Vector); 6] = [
+        (EX, EZ, EY),
+        (Vector{ x: -1.0, y: 0.0, z: 0.0 }, Vector{ x: 0.0, y: 0.0, z: -1.0 }, EY),
+        (EY, EX, EZ),
+        (Vector{ x: 0.0, y: -1.0, z: 0.0 }, EX, Vector{ x: 0.0, y: 0.0, z: -1.0 }),
+        (EZ, Vector{ x: -1.0, y: 0.0, z: 0.0 }, EY),
+        (Vector{ x: 0.0, y: 0.0, z: -1.0 }, EX, EY)
+    ];
+
+    let face = ((6.0 * u) as usize).min(5);
+    let s = 2.0 * (6.0 * u - face as f32) - 1.0;
+    let t = 2.0 * v - 1.0;
+    let (forward, right, up) = FACES[face];
+    forward + s * right + t * up
+}
+
+/// Settings of a physical camera telling how bright the image comes out
+/// for the light in physical units, the radiance of the surfaces in
+/// candelas per square meter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Exposure {
+    pub iso: f32,     // Sensitivity of the film
+    pub shutter: f32, // Time the shutter is open for, in seconds
+    pub f_stop: f32   // Focal length over the diameter of the aperture
+}
+
+impl Default for Exposure {
+    /// The "sunny 16" rule: f/16 and 1/100 s at ISO 100 for a sunny day.
+    fn default() -> Self {
+        Self { iso: 100.0, shutter: 0.01, f_stop: 16.0 }
+    }
+}
+
+impl Exposure {
+    /// Exposure value of the settings at ISO 100: one more for every
+    /// halving of the light that gets to the film.
+    pub fn ev100(&self) -> f32 {
+        (self.f_stop * self.f_stop / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    /// Stops the radiance is to be made brighter by for the film to be
+    /// saturated, at 1, by the luminance of 1.2 · 2^EV100, as the
+    /// saturation based speed of the ISO standard has it.
+    pub fn stops(&self) -> f32 {
+        -self.ev100() - 1.2_f32.log2()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera {
+    pub origin: Vector,
+    pub lower_left_corner: Vector,
+    pub horizontal: Vector,
+    pub vertical: Vector,
+    pub u: Vector, // Unit vector pointing to the right of the image
+    pub v: Vector, // Unit vector pointing to the top of the image
+    pub lens_radius: f32,
+    pub focus_distance: f32,
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+    pub projection: Projection,
+    pub eye_separation: f32, // Distance between the eyes for stereo, or 0 for a single view
+    pub realistic_lens: Lens, // Lens of the realistic projection
+    pub exposure: Option<Exposure> // Physical exposure, or none for the radiance as it is
+}
+
+impl Camera {
+    /// Camera placed at `origin` looking at `look_at`. The `up` vector
+    /// fixes the roll and `vfov` is the vertical field of view in
+    /// degrees.
+    pub fn new(origin: Vector, look_at: Vector, up: Vector, vfov: f32, aspect_ratio: f32) -> Self {
+        let h = (vfov.to_radians() / 2.0).tan();
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        // Orthonormal basis of the camera: w points backwards, u to the
+        // right and v upwards.
+        let w = (origin - look_at).unit();
+        let u = up.cross(w).unit();
+        let v = w.cross(u);
+
+        let horizontal = viewport_width * u;
+        let vertical = viewport_height * v;
+        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w;
+
+        Self {
+            origin,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            lens_radius: 0.0,
+            focus_distance: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            projection: Projection::Perspective,
+            eye_separation: 0.0,
+            realistic_lens: Lens::double_gauss().focused(1.0),
+            exposure: None
+        }
+    }
+
+    /// Turn the pinhole camera into a thin lens one with the given
+    /// aperture (lens diameter) that is in focus at `focus_distance`.
+    /// Everything nearer or further gets blurred.
+    pub fn with_lens(self, aperture: f32, focus_distance: f32) -> Self {
+        // The viewport is moved to the focal plane and scaled along, so
+        // the field of view stays the same.
+        Self {
+            lower_left_corner: self.origin + focus_distance * (self.lower_left_corner - self.origin),
+            horizontal: focus_distance * self.horizontal,
+            vertical: focus_distance * self.vertical,
+            lens_radius: aperture / 2.0,
+            focus_distance,
+            realistic_lens: self.realistic_lens.focused(focus_distance),
+            ..self
+        }
+    }
+
+    /// Keep the shutter open from `open` till `close`, so that every ray
+    /// exists at a random moment of that interval and moving objects get
+    /// blurred.
+    pub fn with_shutter(self, open: f32, close: f32) -> Self {
+        Self {
+            shutter_open: open,
+            shutter_close: close,
+            ..self
+        }
+    }
+
+    /// Map the image to the ray directions with `projection` instead of
+    /// the viewport. The thin lens is only there for the perspective
+    /// one.
+    pub fn with_projection(self, projection: Projection) -> Self {
+        Self { projection, ..self }
+    }
+
+    /// Expose the image as a physical camera with the given settings
+    /// would, for the light in physical units.
+    pub fn with_exposure(self, exposure: Exposure) -> Self {
+        Self { exposure: Some(exposure), ..self }
+    }
+
+    /// Stops the image is made brighter by for the physical exposure, if
+    /// any, before the tone mapping.
+    pub fn exposure_stops(&self) -> f32 {
+        self.exposure.map_or(0.0, |e| e.stops())
+    }
+
+    /// Look through `lens` with the realistic projection, focused at the
+    /// focus distance.
+    pub fn with_realistic_lens(self, lens: Lens) -> Self {
+        Self { realistic_lens: lens.focused(self.focus_distance), ..self }
+    }
+
+    /// Render for both eyes, `separation` apart, side by side: the left
+    /// half of the image for the left eye and the right half for the
+    /// right one. Each of the halves gets half of the viewport, so the
+    /// image is to be twice as wide. A separation of 0 is a single view
+    /// again.
+    pub fn with_stereo(self, separation: f32) -> Self {
+        let scale = match (self.eye_separation > 0.0, separation > 0.0) {
+            (false, true) => 0.5,
+            (true, false) => 2.0,
+            _ => 1.0
+        };
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let horizontal = scale * self.horizontal;
+
+        Self {
+            lower_left_corner: center - horizontal / 2.0 - self.vertical / 2.0,
+            horizontal,
+            eye_separation: separation.max(0.0),
+            ..self
+        }
+    }
+
+    /// Viewport scaled around its center to be `height` high, keeping the
+    /// aspect ratio. That is how much an orthographic camera sees.
+    pub fn with_view_height(self, height: f32) -> Self {
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let scale = height / self.vertical.norm();
+        let horizontal = scale * self.horizontal;
+        let vertical = scale * self.vertical;
+
+        Self {
+            lower_left_corner: center - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            ..self
+        }
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn vfov(&self) -> f32 {
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let distance = (center - self.origin).norm();
+        2.0 * (self.vertical.norm() / 2.0 / distance).atan().to_degrees()
+    }
+
+    /// Zoom the viewport in or out around its center to the vertical
+    /// field of view `vfov` in degrees.
+    pub fn with_vfov(self, vfov: f32) -> Self {
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let scale = (vfov.to_radians() / 2.0).tan() / (self.vfov().to_radians() / 2.0).tan();
+        let horizontal = scale * self.horizontal;
+        let vertical = scale * self.vertical;
+
+        Self {
+            lower_left_corner: center - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            ..self
+        }
+    }
+
+    /// Direction the camera looks in.
+    pub fn forward(&self) -> Vector {
+        self.v.cross(self.u)
+    }
+
+    /// Camera moved by `offset` without turning.
+    pub fn translated(self, offset: Vector) -> Self {
+        Self {
+            origin: self.origin + offset,
+            lower_left_corner: self.lower_left_corner + offset,
+            ..self
+        }
+    }
+
+    /// Camera turned around its origin with the rotation `rotation`.
+    pub fn rotated(self, rotation: Transform) -> Self {
+        let corner = rotation.apply_vector(self.lower_left_corner - self.origin);
+        Self {
+            lower_left_corner: self.origin + corner,
+            horizontal: rotation.apply_vector(self.horizontal),
+            vertical: rotation.apply_vector(self.vertical),
+            u: rotation.apply_vector(self.u),
+            v: rotation.apply_vector(self.v),
+            ..self
+        }
+    }
+
+    /// Camera carried around `center` by the rotation `rotation`, turning
+    /// with it, so that what it looks at goes round with it.
+    pub fn orbited(self, center: Vector, rotation: Transform) -> Self {
+        let origin = center + rotation.apply_vector(self.origin - center);
+        let offset = origin - self.origin;
+        self.rotated(rotation).translated(offset)
+    }
+
+    /// Ray from the center of the lens through the point (u, v) of the
+    /// viewport, when the shutter opens. Unlike `get_ray`, there is
+    /// nothing random about it. `None` where the camera sees nothing.
+    pub fn center_ray(&self, u: f32, v: f32) -> Option<Ray> {
+        Some(self.ray(u, v, Vector{ x: 0.0, y: 0.0, z: 0.0 })?.with_time(self.shutter_open))
+    }
+
+    /// Ray through the point (u, v) of the image from the point `disk` of
+    /// the unit disk scaled to the lens, for the eye that sees the point.
+    /// `None` where the camera sees nothing, outside of the circle of a
+    /// fisheye or where a realistic lens blocks the ray.
+    fn ray(&self, u: f32, v: f32, disk: Vector) -> Option<Ray> {
+        let (u, eye) = self.eye(u);
+        let origin = self.origin + eye * self.u;
+
+        let direction = match self.projection {
+            Projection::Perspective => {
+                // Both eyes look through the same viewport, so that they
+                // agree on what is at the focus distance.
+                let origin = origin + self.lens_radius * (disk.x * self.u + disk.y * self.v);
+                let target = self.lower_left_corner + u * self.horizontal + v * self.vertical;
+                return Some(Ray::new(origin, target - origin));
+            },
+            Projection::Cubemap => cubemap_direction(u, v),
+            Projection::Equirectangular => {
+                let longitude = 2.0 * PI * (u - 0.5);
+                let latitude = PI * (v - 0.5);
+                let ahead = longitude.sin() * self.u + longitude.cos() * self.forward();
+                latitude.cos() * ahead + latitude.sin() * self.v
+            },
+            Projection::Fisheye { mapping, fov } => self.fisheye_direction(mapping, fov, u, v)?,
+            Projection::Orthographic => {
+                let origin = origin + (u - 0.5) * self.horizontal + (v - 0.5) * self.vertical;
+                return Some(Ray::new(origin, self.forward()));
+            },
+            Projection::Realistic => {
+                // The lens turns the image upside down, so the top of the
+                // image is at the bottom of the film.
+                let lens = &self.realistic_lens;
+                let height = lens.sensor_height();
+                let width = height * self.horizontal.norm() / self.vertical.norm();
+                let (o, d) = lens.ray(-(u - 0.5) * width, -(v - 0.5) * height, disk)?;
+                let forward = self.forward();
+                let origin = origin + o.x * self.u + o.y * self.v + o.z * forward;
+                return Some(Ray::new(origin, d.x * self.u + d.y * self.v + d.z * forward));
+            }
+        };
+        Some(Ray::new(origin, direction))
+    }
+
+    /// Point of the image of one eye that the point u across the whole
+    /// image is, and how far to the right of the origin that eye is. With
+    /// stereo, the left half of the image is for the left eye and the
+    /// right half for the right one.
+    fn eye(&self, u: f32) -> (f32, f32) {
+        if self.eye_separation <= 0.0 {
+            (u, 0.0)
+        } else if u < 0.5 {
+            (2.0 * u, -self.eye_separation / 2.0)
+        } else {
+            (2.0 * u - 1.0, self.eye_separation / 2.0)
+        }
+    }
+
+    /// Direction of the fisheye at the point (u, v) of the image, if it
+    /// sees anything there.
+    fn fisheye_direction(&self, mapping: FisheyeMapping, fov: f32, u: f32, v: f32) -> Option<Vector> {
+        // Coordinates of the point with the circle of the field of view
+        // of radius 1.
+        let aspect_ratio = self.horizontal.norm() / self.vertical.norm();
+        let x = aspect_ratio * (2.0 * u - 1.0);
+        let y = 2.0 * v - 1.0;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let half = (fov.to_radians() / 2.0).min(PI);
+        let theta = match mapping {
+            FisheyeMapping::Equidistant => r * half,
+            FisheyeMapping::Equisolid => 2.0 * (r * (half / 2.0).sin()).asin()
+        };
+
+        let (x, y) = if r > 0.0 { (x / r, y / r) } else { (0.0, 0.0) };
+        Some(theta.sin() * (x * self.u + y * self.v) + theta.cos() * self.forward())
+    }
+
+    /// Ray going through the point of the viewport with the relative
+    /// coordinates (u, v), both ranging from 0 to 1. `None` where the
+    /// camera sees nothing.
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut dyn Sampler) -> Option<Ray> {
+        // Rays start from a random point of the lens and all converge at
+        // the focal plane.
+        let disk = sampler.in_unit_disk();
+
+        let time = self.shutter_open
+            + sampler.next_1d() * (self.shutter_close - self.shutter_open);
+
+        Some(self.ray(u, v, disk)?.with_time(time))
+    }
+}
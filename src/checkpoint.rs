@@ -0,0 +1,218 @@
+//! Checkpoints of renders in progress, so that a long render lost to a
+//! crash or a reboot can be picked up where it stopped.
+//!
+//! The random numbers of every sample are drawn from the seed, the pixel
+//! and the index of the sample alone, so the sums of the samples taken
+//! so far and their counts are all there is to the state of a render.
+//! A render resumed from a checkpoint takes the very samples the one
+//! that saved it would have taken next.
+//!
+//! The file is little-endian binary: the magic `RTCK`, the width and the
+//! height of the image, the seed, the sampler and whether it adds blue
+//! noise, the passes made over the image and the ones made over every
+//! tile, the accumulated image, the running sums of every pixel, and the
+//! accumulated arbitrary output variables if there are any.
+
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::math::Vector;
+use crate::render::{Aovs, Image, Moments};
+use crate::sampler::SamplerKind;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+
+/// State of a render in progress.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub seed: u64,              // Seed the samples were drawn from
+    pub sampler: SamplerKind,   // Sampler they were drawn with
+    pub blue_noise: bool,       // Whether the sampler shifted them by a blue noise mask
+    pub samples: u32,           // Passes made over the whole image
+    pub tile_samples: Vec<u32>, // Passes made over every tile
+    pub image: Image,           // Sums of the samples of every pixel
+    pub moments: Vec<Moments>,  // For every pixel, in the order of the image
+    pub aovs: Option<Aovs>      // Sums of the arbitrary output variables, if sampled
+}
+
+impl Checkpoint {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = MAGIC.to_vec();
+        out.extend((self.image.width as u32).to_le_bytes());
+        out.extend((self.image.height as u32).to_le_bytes());
+        out.extend(self.seed.to_le_bytes());
+        out.push(match self.sampler {
+            SamplerKind::Random => 0,
+            SamplerKind::Halton => 1
+        });
+        out.push(self.blue_noise as u8);
+        for n in [self.samples, self.tile_samples.len() as u32].iter().chain(&self.tile_samples) {
+            out.extend(n.to_le_bytes());
+        }
+
+        write_image(&mut out, &self.image);
+        for m in &self.moments {
+            out.extend(m.count.to_le_bytes());
+            out.extend(m.sum.to_le_bytes());
+            out.extend(m.sum_squares.to_le_bytes());
+        }
+        match &self.aovs {
+            Some(aovs) => {
+                out.push(1);
+                for image in [&aovs.albedo, &aovs.normal, &aovs.depth, &aovs.alpha] {
+                    write_image(&mut out, image);
+                }
+            },
+            None => out.push(0)
+        }
+
+        // Written aside first, so that a crash while writing leaves the
+        // last checkpoint as it was.
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, out)?;
+        fs::rename(temporary, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut reader = Reader { data: &data, position: 0 };
+        if reader.bytes(4)? != MAGIC {
+            return Err(invalid("not a checkpoint"));
+        }
+
+        let width = reader.u32()? as usize;
+        let height = reader.u32()? as usize;
+        let seed = u64::from_le_bytes(reader.bytes(8)?.try_into().unwrap());
+        let sampler = match reader.bytes(1)?[0] {
+            0 => SamplerKind::Random,
+            1 => SamplerKind::Halton,
+            _ => return Err(invalid("unknown sampler"))
+        };
+        let blue_noise = reader.bytes(1)?[0] != 0;
+        let samples = reader.u32()?;
+        let tiles = reader.u32()? as usize;
+        let tile_samples = (0 .. tiles).map(|_| reader.u32()).collect::<io::Result<_>>()?;
+
+        let image = reader.image(width, height)?;
+        let moments = (0 .. width * height)
+            .map(|_| Ok(Moments { count: reader.u32()?, sum: reader.f32()?, sum_squares: reader.f32()? }))
+            .collect::<io::Result<_>>()?;
+        let aovs = match reader.bytes(1)?[0] {
+            0 => None,
+            _ => Some(Aovs {
+                albedo: reader.image(width, height)?,
+                normal: reader.image(width, height)?,
+                depth: reader.image(width, height)?,
+                alpha: reader.image(width, height)?
+            })
+        };
+
+        Ok(Self { seed, sampler, blue_noise, samples, tile_samples, image, moments, aovs })
+    }
+}
+
+fn write_image(out: &mut Vec<u8>, image: &Image) {
+    for pixel in &image.pixels {
+        for c in [pixel.x, pixel.y, pixel.z] {
+            out.extend(c.to_le_bytes());
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.data.get(self.position .. self.position + n).ok_or_else(|| invalid("truncated checkpoint"))?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn image(&mut self, width: usize, height: usize) -> io::Result<Image> {
+        let pixels = (0 .. width * height)
+            .map(|_| Ok(Vector{ x: self.f32()?, y: self.f32()?, z: self.f32()? }))
+            .collect::<io::Result<_>>()?;
+        Ok(Image { width, height, pixels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(aovs: bool) -> Checkpoint {
+        let (width, height) = (3, 2);
+        let mut image = Image::new(width, height);
+        for (k, pixel) in image.pixels.iter_mut().enumerate() {
+            *pixel = Vector{ x: k as f32, y: 0.5, z: -1.0 };
+        }
+        Checkpoint {
+            seed: 0x0123_4567_89AB_CDEF,
+            sampler: SamplerKind::Halton,
+            blue_noise: true,
+            samples: 7,
+            tile_samples: vec![7, 8],
+            image: image.clone(),
+            moments: (0 .. width * height).map(|k| Moments { count: k as u32, sum: 0.25, sum_squares: 2.0 }).collect(),
+            aovs: aovs.then(|| Aovs { albedo: image.clone(), normal: image.clone(), depth: image.clone(), alpha: image })
+        }
+    }
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rtrace-{}-{}.checkpoint", std::process::id(), name))
+    }
+
+    #[test]
+    fn round_trip() {
+        for aovs in [false, true] {
+            let path = path(&format!("round-trip-{}", aovs));
+            let saved = checkpoint(aovs);
+            saved.save(&path).unwrap();
+            let loaded = Checkpoint::load(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded, saved);
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let path = path("truncated");
+        checkpoint(true).save(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        for length in [0, 3, 10, data.len() / 2, data.len() - 1] {
+            fs::write(&path, &data[.. length]).unwrap();
+            assert_eq!(Checkpoint::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bad_magic() {
+        let path = path("bad-magic");
+        checkpoint(false).save(&path).unwrap();
+        let mut data = fs::read(&path).unwrap();
+        data[..4].copy_from_slice(b"PNG!");
+        fs::write(&path, data).unwrap();
+        let error = Checkpoint::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
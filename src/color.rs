@@ -0,0 +1,161 @@
+//! Colors on their way out of the renderer, to the screen and the image
+//! files.
+//!
+//! The renderer works with the linear light in `Vector`s, which can be
+//! as bright as it gets. A `Color` is that light as it is about to be
+//! shown: clamped to the range of the screen and encoded with the sRGB
+//! transfer function the screens and the files expect.
+
+use crate::math::Vector;
+use crate::sampler::hash;
+
+/// Linear color with the channels in 0 .. 1 once clamped.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32
+}
+
+impl Color {
+    pub fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    /// The channels cut off to 0 .. 1; NaNs, the remains of a sample
+    /// gone wrong, are black.
+    pub fn clamped(&self) -> Self {
+        let clamp = |c: f32| if c.is_nan() { 0.0 } else { c.clamp(0.0, 1.0) };
+        Self { r: clamp(self.r), g: clamp(self.g), b: clamp(self.b) }
+    }
+
+    /// Clamped and sRGB encoded channels, still in 0 .. 1.
+    pub fn to_srgb(&self) -> [f32; 3] {
+        let c = self.clamped();
+        [srgb_encode(c.r), srgb_encode(c.g), srgb_encode(c.b)]
+    }
+
+    /// Clamped and sRGB encoded 8-bit channels.
+    pub fn to_srgb8(&self) -> [u8; 3] {
+        self.to_srgb().map(|c| (255.0 * c).round() as u8)
+    }
+
+    /// Clamped and sRGB encoded 8-bit channels of the pixel (i, j),
+    /// dithered: noise of up to a level either way, more likely the
+    /// smaller it is, is added before rounding. Smooth gradients, like
+    /// the sky, then turn into fine grain rather than visible bands. The
+    /// noise is the same for the same pixel every time.
+    pub fn to_srgb8_dithered(&self, i: usize, j: usize) -> [u8; 3] {
+        // Ten bits for each of the two uniform numbers of every channel,
+        // their sum has a triangular distribution.
+        let mut bits = hash(i as u32, j as u32);
+        let mut uniform = || {
+            let u = (bits & 0x3FF) as f32 / 1024.0;
+            bits >>= 10;
+            u
+        };
+
+        let mut rgb = [0; 3];
+        for (channel, c) in rgb.iter_mut().zip(self.to_srgb()) {
+            let noise = uniform() + uniform() - 1.0;
+            *channel = (255.0 * c + noise).round().clamp(0.0, 255.0) as u8;
+        }
+        rgb
+    }
+}
+
+impl From<Vector> for Color {
+    fn from(v: Vector) -> Self {
+        Self { r: v.x, g: v.y, b: v.z }
+    }
+}
+
+/// The sRGB transfer function (the OETF) taking a linear channel in
+/// 0 .. 1 to the encoded one: a straight line near black and a power of
+/// 1 / 2.4 above it.
+pub fn srgb_encode(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of `srgb_encode`, for the colors of textures and models,
+/// which are stored encoded.
+pub fn srgb_decode(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_transfer_function() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        // Mid gray is about the middle of the encoded range.
+        assert!((srgb_encode(0.18) - 0.4614).abs() < 1e-3);
+
+        // The two pieces meet where the straight line ends.
+        let knee: f32 = 0.003_130_8;
+        assert!((12.92 * knee - (1.055 * knee.powf(1.0 / 2.4) - 0.055)).abs() < 1e-5);
+
+        for k in 0 ..= 1000 {
+            let c = k as f32 / 1000.0;
+            assert!((srgb_decode(srgb_encode(c)) - c).abs() < 1e-5, "{}", c);
+            if k > 0 {
+                assert!(srgb_encode(c) > srgb_encode(c - 0.001));
+            }
+        }
+    }
+
+    #[test]
+    fn clamping() {
+        let color = Color::new(-1.0, 0.5, 7.0);
+        assert_eq!(color.clamped(), Color::new(0.0, 0.5, 1.0));
+        assert_eq!(Color::new(f32::NAN, f32::INFINITY, f32::NEG_INFINITY).clamped(), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(Color::from(Vector{ x: 0.1, y: 0.2, z: 0.3 }), Color::new(0.1, 0.2, 0.3));
+    }
+
+    #[test]
+    fn eight_bits() {
+        assert_eq!(Color::new(0.0, 1.0, 2.0).to_srgb8(), [0, 255, 255]);
+        assert_eq!(Color::new(f32::NAN, -1.0, 0.18).to_srgb8(), [0, 0, 118]);
+        // Colors too bright for the range do not wrap around to black.
+        assert_eq!(Color::new(1000.0, 1000.0, 1000.0).to_srgb8(), [255, 255, 255]);
+    }
+
+    #[test]
+    fn dithering() {
+        // A level and a third above the middle gray of 8 bits.
+        let level = 118.0 + 1.0 / 3.0;
+        let color = Color::new(srgb_decode(level / 255.0), 0.0, 1.0);
+
+        let mut sum = 0;
+        let mut counts = [0; 256];
+        for i in 0 .. 256 {
+            for j in 0 .. 256 {
+                let [r, g, b] = color.to_srgb8_dithered(i, j);
+                assert_eq!(color.to_srgb8_dithered(i, j), [r, g, b]);
+                // The noise moves a channel by a level at most, and never
+                // out of the range.
+                assert!((117 ..= 120).contains(&r), "{}", r);
+                assert!(g <= 1 && b >= 254, "{} {}", g, b);
+                sum += r as u32;
+                counts[r as usize] += 1;
+            }
+        }
+
+        // On average the pixels have the level in between the two they
+        // are rounded to, which is what keeps gradients smooth.
+        let mean = sum as f32 / (256.0 * 256.0);
+        assert!((mean - level).abs() < 0.02, "{}", mean);
+        assert!(counts[118] > counts[119] && counts[119] > 0);
+    }
+}
@@ -0,0 +1,123 @@
+//! Scenes built in, rendered without a scene file.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::material::{Dielectric, Lambertian, Metal};
+use crate::math::{Vector, EY};
+use crate::render::Settings;
+use crate::sampler::Pcg32;
+use crate::scene::Scene;
+use crate::texture::{Checker, CheckerSpace, SolidColor};
+
+/// Which built-in scene to render.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Demo {
+    /// Three balls of different materials on a checkered floor.
+    #[default]
+    Balls,
+    /// The cover of "Ray Tracing in One Weekend": three big balls among
+    /// hundreds of small ones of random colors and materials.
+    FinalScene
+}
+
+impl FromStr for Demo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "balls" => Ok(Demo::Balls),
+            "final-scene" => Ok(Demo::FinalScene),
+            _ => Err(format!("unknown demo {}, expected balls or final-scene", s))
+        }
+    }
+}
+
+impl Demo {
+    /// The scene, its random parts drawn from the `seed`.
+    pub fn scene(&self, seed: u64) -> Scene {
+        match self {
+            Demo::Balls => balls(),
+            Demo::FinalScene => final_scene(seed)
+        }
+    }
+}
+
+fn solid(color: Vector) -> Lambertian {
+    Lambertian{ albedo: Arc::new(SolidColor{ color }) }
+}
+
+fn balls() -> Scene {
+    let ground = Lambertian{
+        albedo: Arc::new(Checker{
+            even: Arc::new(SolidColor{ color: Vector{ x: 0.8, y: 0.8, z: 0.0 } }),
+            odd: Arc::new(SolidColor{ color: Vector{ x: 0.2, y: 0.3, z: 0.1 } }),
+            size: 0.3,
+            space: CheckerSpace::World
+        })
+    };
+
+    Scene::builder()
+        .settings(Settings { photons: 100_000, ..Settings::default() })
+        .camera(Vector{ x: 0.0, y: 0.0, z: 0.0 }, Vector{ x: 0.0, y: 0.0, z: -1.0 }, 90.0)
+        .material("ground", ground)
+        .material("matte", solid(Vector{ x: 0.7, y: 0.3, z: 0.3 }))
+        .material("glass", Dielectric{ refractive_index: 1.5 })
+        .material("gold", Metal::new(Vector{ x: 0.8, y: 0.6, z: 0.2 }, 0.3))
+        .sphere(Vector{ x: 0.0, y: 0.0, z: -1.0 }, 0.5, "matte")
+        .sphere(Vector{ x: -1.0, y: 0.0, z: -1.0 }, 0.5, "glass")
+        .sphere(Vector{ x: 1.0, y: 0.0, z: -1.0 }, 0.5, "gold")
+        .plane(Vector{ x: 0.0, y: -0.5, z: 0.0 }, EY, "ground")
+        .point_light(Vector{ x: 2.0, y: 3.0, z: 1.0 }, Vector{ x: 5.0, y: 5.0, z: 5.0 })
+        .build()
+        .expect("the balls demo is valid")
+}
+
+fn final_scene(seed: u64) -> Scene {
+    let mut rng = Pcg32::new(seed, 0);
+    let mut scene = Scene::builder()
+        .size(800, 450)
+        .max_depth(50)
+        .camera(Vector{ x: 13.0, y: 2.0, z: 3.0 }, Vector{ x: 0.0, y: 0.0, z: 0.0 }, 20.0)
+        .lens(0.1, 10.0)
+        .material("ground", solid(Vector{ x: 0.5, y: 0.5, z: 0.5 }))
+        .material("glass", Dielectric{ refractive_index: 1.5 })
+        .material("matte", solid(Vector{ x: 0.4, y: 0.2, z: 0.1 }))
+        .material("mirror", Metal::new(Vector{ x: 0.7, y: 0.6, z: 0.5 }, 0.0))
+        .sphere(Vector{ x: 0.0, y: -1000.0, z: 0.0 }, 1000.0, "ground")
+        .sphere(Vector{ x: 0.0, y: 1.0, z: 0.0 }, 1.0, "glass")
+        .sphere(Vector{ x: -4.0, y: 1.0, z: 0.0 }, 1.0, "matte")
+        .sphere(Vector{ x: 4.0, y: 1.0, z: 0.0 }, 1.0, "mirror");
+
+    // A small ball on every square of a grid, nudged off its corner,
+    // unless it would touch the big metal one.
+    for a in -11 .. 11 {
+        for b in -11 .. 11 {
+            let (x, z) = (rng.next_f32(), rng.next_f32());
+            let center = Vector{ x: a as f32 + 0.9 * x, y: 0.2, z: b as f32 + 0.9 * z };
+            if (center - Vector{ x: 4.0, y: 0.2, z: 0.0 }).norm() <= 0.9 {
+                continue;
+            }
+
+            let name = format!("ball {} {}", a, b);
+            let choice = rng.next_f32();
+            scene = if choice < 0.8 {
+                let albedo = random_color(&mut rng) * random_color(&mut rng);
+                scene.material(&name, solid(albedo))
+            } else if choice < 0.95 {
+                let albedo = 0.5 * random_color(&mut rng) + Vector{ x: 0.5, y: 0.5, z: 0.5 };
+                scene.material(&name, Metal::new(albedo, 0.5 * rng.next_f32()))
+            } else {
+                scene.material(&name, Dielectric{ refractive_index: 1.5 })
+            };
+            scene = scene.sphere(center, 0.2, &name);
+        }
+    }
+
+    scene.build().expect("the final scene demo is valid")
+}
+
+/// Color of random components from 0 to 1.
+fn random_color(rng: &mut Pcg32) -> Vector {
+    Vector{ x: rng.next_f32(), y: rng.next_f32(), z: rng.next_f32() }
+}
@@ -0,0 +1,63 @@
+//! Denoising of the finished image with a joint bilateral filter.
+//!
+//! Every pixel is averaged with its neighbours, each weighted by how far
+//! it is and by how much what the camera sees there looks alike: the
+//! albedo, the normal and the distance, taken from the arbitrary output
+//! variables. Those have next to no noise, so the noise of the image is
+//! smoothed out while the edges of the objects and of their textures
+//! stay sharp.
+
+use rayon::prelude::*;
+
+use crate::math::Vector;
+use crate::render::{Aovs, Image};
+
+/// Farthest a neighbour can be to count, in pixels.
+pub const RADIUS: isize = 6;
+
+/// Deviations of the distance to a neighbour in pixels, of the
+/// difference of the albedos, of the difference of the normals, and of
+/// the difference of the distances relative to the distance.
+const SIGMA_SPATIAL: f32 = 3.0;
+const SIGMA_ALBEDO: f32 = 0.1;
+const SIGMA_NORMAL: f32 = 0.2;
+const SIGMA_DEPTH: f32 = 0.05;
+
+/// Denoised copy of the averaged image, guided by the averaged AOVs of
+/// it.
+pub fn denoise(image: &Image, aovs: &Aovs) -> Image {
+    let (width, height) = (image.width, image.height);
+
+    let mut pixels = vec![Vector{ x: 0.0, y: 0.0, z: 0.0 }; width * height];
+    pixels.par_chunks_mut(width).enumerate().for_each(|(i, row)| {
+        for (j, pixel) in row.iter_mut().enumerate() {
+            let k = i * width + j;
+            let (a, n, z) = (aovs.albedo.pixels[k], aovs.normal.pixels[k], aovs.depth.pixels[k].x);
+
+            let mut sum = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+            let mut total = 0.0;
+            for di in -RADIUS ..= RADIUS {
+                for dj in -RADIUS ..= RADIUS {
+                    let (ni, nj) = (i as isize + di, j as isize + dj);
+                    if ni < 0 || nj < 0 || ni >= height as isize || nj >= width as isize {
+                        continue;
+                    }
+                    let q = ni as usize * width + nj as usize;
+
+                    let spatial = (di * di + dj * dj) as f32 / (SIGMA_SPATIAL * SIGMA_SPATIAL);
+                    let albedo = (aovs.albedo.pixels[q] - a).sqnorm() / (SIGMA_ALBEDO * SIGMA_ALBEDO);
+                    let normal = (aovs.normal.pixels[q] - n).sqnorm() / (SIGMA_NORMAL * SIGMA_NORMAL);
+                    let depth = ((aovs.depth.pixels[q].x - z) / (SIGMA_DEPTH * z.max(1E-3))).powi(2);
+                    let weight = (-0.5 * (spatial + albedo + normal + depth)).exp();
+
+                    sum += weight * image.pixels[q];
+                    total += weight;
+                }
+            }
+
+            *pixel = sum / total;
+        }
+    });
+
+    Image { width, height, pixels }
+}
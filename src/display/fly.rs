@@ -0,0 +1,86 @@
+use std::time::Instant;
+
+use crate::camera::Camera;
+use crate::math::{Transform, EY};
+
+use super::{Event, Key};
+
+/// Flying around the scene: W, A, S, D or the arrows move the camera
+/// and dragging the mouse turns it. The camera turns around the y axis
+/// and can't look further up or down than straight.
+pub struct FlyControls {
+    pub speed: f32, // Units per second
+    pub sensitivity: f32, // Radians per pixel of the mouse movement
+    held: Vec<Key>,
+    drag: Option<(i32, i32)>,
+    turn: (f32, f32), // Pixels dragged since the last update
+    last: Instant
+}
+
+impl FlyControls {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            sensitivity: 0.005,
+            held: vec![],
+            drag: None,
+            turn: (0.0, 0.0),
+            last: Instant::now()
+        }
+    }
+
+    pub fn handle(&mut self, event: Event) {
+        match event {
+            Event::KeyDown(key) if !self.held.contains(&key) => self.held.push(key),
+            Event::KeyUp(key) => self.held.retain(|&k| k != key),
+            Event::MouseDown { x, y } => self.drag = Some((x, y)),
+            Event::MouseUp {..} => self.drag = None,
+            Event::MouseMove { x, y } => {
+                if let Some((x0, y0)) = self.drag {
+                    self.turn.0 += (x - x0) as f32;
+                    self.turn.1 += (y - y0) as f32;
+                    self.drag = Some((x, y));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Move and turn the camera for the input since the last update.
+    /// Returns whether it moved.
+    pub fn update(&mut self, camera: &mut Camera) -> bool {
+        let now = Instant::now();
+        let dt = (now - self.last).as_secs_f32();
+        self.last = now;
+
+        let held = |keys: &[Key]| keys.iter().any(|key| self.held.contains(key));
+        let axis = |positive: &[Key], negative: &[Key]| {
+            (held(positive) as i32 - held(negative) as i32) as f32
+        };
+        let forward = axis(&[Key::W, Key::Up], &[Key::S, Key::Down]);
+        let right = axis(&[Key::D, Key::Right], &[Key::A, Key::Left]);
+
+        let mut moved = false;
+
+        if forward != 0.0 || right != 0.0 {
+            let direction = forward * camera.forward() + right * camera.u;
+            *camera = camera.translated(self.speed * dt * direction.unit());
+            moved = true;
+        }
+
+        let (dx, dy) = std::mem::replace(&mut self.turn, (0.0, 0.0));
+        if dx != 0.0 || dy != 0.0 {
+            // Dragging to the right looks to the right, dragging down
+            // looks down, but never past straight down or up.
+            let limit = 89f32.to_radians();
+            let pitch = camera.forward().dot(EY).clamp(-1.0, 1.0).asin();
+            let turn = (pitch - self.sensitivity * dy).clamp(-limit, limit) - pitch;
+
+            let yaw = Transform::rotate(EY, -self.sensitivity * dx);
+            *camera = camera.rotated(yaw * Transform::rotate(camera.u, turn));
+            moved = true;
+        }
+
+        moved
+    }
+}
@@ -0,0 +1,96 @@
+use ::minifb::{Key as MinifbKey, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+
+use super::{Display, Event, Frame, Key};
+
+pub struct MinifbDisplay {
+    window: Window,
+    buffer: Vec<u32>, // 0RGB, top row first
+    updated: bool, // Whether the events were collected by `show`
+    mouse: (i32, i32),
+    mouse_down: bool
+}
+
+impl MinifbDisplay {
+    pub fn new(title: &str, width: usize, height: usize) -> Result<MinifbDisplay, String> {
+        let window = Window::new(title, width, height, WindowOptions::default())
+            .map_err(|err| err.to_string())?;
+
+        Ok(MinifbDisplay {
+            window,
+            buffer: vec![0; width * height],
+            updated: false,
+            mouse: (0, 0),
+            mouse_down: false
+        })
+    }
+}
+
+impl Display for MinifbDisplay {
+    fn show(&mut self, frame: &Frame) {
+        self.buffer.resize(frame.width * frame.height, 0);
+        for (pixel, &[r, g, b]) in self.buffer.iter_mut().zip(&frame.pixels) {
+            *pixel = u32::from_be_bytes([0, r, g, b]);
+        }
+        // Fails only if the window went away, which is reported as a quit.
+        let _ = self.window.update_with_buffer(&self.buffer, frame.width, frame.height);
+        self.updated = true;
+    }
+
+    fn poll_events(&mut self) -> Vec<Event> {
+        // minifb only collects events when the window is updated, and
+        // another update would drop the keys pressed before it.
+        if !self.updated {
+            self.window.update();
+        }
+        self.updated = false;
+
+        if !self.window.is_open() {
+            return vec![Event::Quit];
+        }
+
+        let pressed = self.window.get_keys_pressed(KeyRepeat::No).into_iter().filter_map(key).map(Event::KeyDown);
+        let released = self.window.get_keys_released().into_iter().filter_map(key).map(Event::KeyUp);
+        let mut events: Vec<Event> = pressed.chain(released).collect();
+
+        // The mouse is only polled, so its events are made up from the
+        // changes of its state.
+        if let Some((x, y)) = self.window.get_mouse_pos(MouseMode::Discard) {
+            let (x, y) = (x as i32, y as i32);
+            if (x, y) != self.mouse {
+                self.mouse = (x, y);
+                events.push(Event::MouseMove{ x, y });
+            }
+        }
+        let (x, y) = self.mouse;
+        let down = self.window.get_mouse_down(MouseButton::Left);
+        if down != self.mouse_down {
+            self.mouse_down = down;
+            events.push(if down { Event::MouseDown{ x, y } } else { Event::MouseUp{ x, y } });
+        }
+
+        events
+    }
+}
+
+fn key(key: MinifbKey) -> Option<Key> {
+    let key = match key {
+        MinifbKey::W => Key::W,
+        MinifbKey::A => Key::A,
+        MinifbKey::S => Key::S,
+        MinifbKey::D => Key::D,
+        MinifbKey::P => Key::P,
+        MinifbKey::R => Key::R,
+        MinifbKey::T => Key::T,
+        MinifbKey::Space => Key::Space,
+        MinifbKey::Tab => Key::Tab,
+        // + shares the key with = on most layouts.
+        MinifbKey::Equal | MinifbKey::NumPadPlus => Key::Plus,
+        MinifbKey::Minus | MinifbKey::NumPadMinus => Key::Minus,
+        MinifbKey::Up => Key::Up,
+        MinifbKey::Down => Key::Down,
+        MinifbKey::Left => Key::Left,
+        MinifbKey::Right => Key::Right,
+        _ => return None
+    };
+    Some(key)
+}
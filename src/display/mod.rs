@@ -0,0 +1,95 @@
+//! Presenting the accumulated image in a window.
+//!
+//! The renderer only talks to a `Display`, so the windowing library is a
+//! matter of the enabled features: `sdl2` (the default) or `minifb`.
+//! With `egui`, a `Panel` of parameters can be drawn over the image.
+
+mod fly;
+#[cfg(feature = "minifb")]
+mod minifb;
+#[cfg(feature = "egui")]
+mod panel;
+#[cfg(feature = "sdl2")]
+mod sdl;
+
+pub use self::fly::FlyControls;
+#[cfg(feature = "minifb")]
+pub use self::minifb::MinifbDisplay;
+#[cfg(feature = "egui")]
+pub use self::panel::Panel;
+#[cfg(feature = "sdl2")]
+pub use self::sdl::SdlDisplay;
+
+use crate::color::Color;
+use crate::render::Image;
+
+/// Keys the renderer reacts to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    P,
+    R,
+    T,
+    Space,
+    Tab,
+    Plus,
+    Minus,
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+/// Input of the window. Mouse positions are in pixels from the top left
+/// corner and only the left button is reported.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    Quit,
+    KeyDown(Key),
+    KeyUp(Key),
+    MouseMove { x: i32, y: i32 },
+    MouseDown { x: i32, y: i32 },
+    MouseUp { x: i32, y: i32 }
+}
+
+/// Picture on the screen, with sRGB encoded colors and the top row
+/// first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>
+}
+
+impl Frame {
+    /// The accumulation buffer divided by `samples`, dithered like the
+    /// PNG files.
+    pub fn from_image(image: &Image, samples: u32) -> Self {
+        let pixels = image.rows()
+            .enumerate()
+            .rev()
+            .flat_map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(move |(j, pixel)| Color::from(*pixel / (samples as f32)).to_srgb8_dithered(i, j))
+            })
+            .collect();
+        Self{ width: image.width, height: image.height, pixels }
+    }
+}
+
+/// Window the image is shown in while it is being rendered.
+pub trait Display {
+    fn show(&mut self, frame: &Frame);
+
+    /// Show the accumulation buffer divided by `samples`.
+    fn present(&mut self, image: &Image, samples: u32) {
+        self.show(&Frame::from_image(image, samples));
+    }
+
+    /// Events that happened since the last call.
+    fn poll_events(&mut self) -> Vec<Event>;
+}
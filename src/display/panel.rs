@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use egui::epaint::{ClippedPrimitive, ColorImage, ImageData, Primitive, TextureId};
+use egui::{Context, PointerButton, Pos2, RawInput, Rect};
+
+use crate::camera::Camera;
+use crate::math::Vector;
+use crate::render::Settings;
+use crate::texture::LiveColor;
+use crate::tonemap::ToneMap;
+
+use super::{Event, Frame, Key};
+
+/// Window of egui widgets for the render settings, the field of view
+/// and the colors of the materials, drawn over the image. Tab hides and
+/// shows it.
+pub struct Panel {
+    context: Context,
+    start: Instant,
+    visible: bool,
+    events: Vec<egui::Event>,
+    textures: HashMap<TextureId, ColorImage>,
+    primitives: Vec<ClippedPrimitive>
+}
+
+impl Default for Panel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Panel {
+    pub fn new() -> Self {
+        Self {
+            context: Context::default(),
+            start: Instant::now(),
+            visible: true,
+            events: vec![],
+            textures: HashMap::new(),
+            primitives: vec![]
+        }
+    }
+
+    /// Pass an input event on to the widgets.
+    pub fn handle(&mut self, event: Event) {
+        let button = |x: i32, y: i32, pressed| egui::Event::PointerButton {
+            pos: Pos2::new(x as f32, y as f32),
+            button: PointerButton::Primary,
+            pressed,
+            modifiers: Default::default()
+        };
+
+        match event {
+            Event::KeyDown(Key::Tab) => self.visible = !self.visible,
+            Event::MouseMove { x, y } => {
+                self.events.push(egui::Event::PointerMoved(Pos2::new(x as f32, y as f32)));
+            },
+            Event::MouseDown { x, y } => self.events.push(button(x, y, true)),
+            Event::MouseUp { x, y } => self.events.push(button(x, y, false)),
+            _ => {}
+        }
+    }
+
+    /// Whether the panel is under the mouse, so that the clicks and drags
+    /// are meant for it rather than for the image.
+    pub fn wants_mouse(&self) -> bool {
+        self.visible && self.context.is_pointer_over_area()
+    }
+
+    /// Lay out the widgets for the current input and apply the changes
+    /// made with them. Returns whether anything changed.
+    pub fn update(&mut self, settings: &mut Settings, camera: &mut Camera, colors: &[(String, Arc<LiveColor>)]) -> bool {
+        let width = settings.width as f32;
+        let height = settings.height as f32;
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, egui::vec2(width, height))),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+
+        let visible = self.visible;
+        let mut changed = false;
+        let output = self.context.run(input, |context| {
+            if visible {
+                changed |= parameters(context, settings, camera, colors);
+            }
+        });
+
+        for (id, delta) in output.textures_delta.set {
+            let ImageData::Color(image) = delta.image;
+            match (delta.pos, self.textures.get_mut(&id)) {
+                (Some([x0, y0]), Some(texture)) => {
+                    // Patch of an existing texture.
+                    let [w, h] = image.size;
+                    for y in 0 .. h {
+                        let row = (y0 + y) * texture.size[0] + x0;
+                        texture.pixels[row .. row + w].copy_from_slice(&image.pixels[y * w .. (y + 1) * w]);
+                    }
+                },
+                _ => {
+                    self.textures.insert(id, (*image).clone());
+                }
+            }
+        }
+        for id in output.textures_delta.free {
+            self.textures.remove(&id);
+        }
+
+        self.primitives = self.context.tessellate(output.shapes, output.pixels_per_point);
+        changed
+    }
+
+    /// Draw the widgets laid out by the last `update` over the frame.
+    pub fn paint(&self, frame: &mut Frame) {
+        for primitive in &self.primitives {
+            let mesh = match &primitive.primitive {
+                Primitive::Mesh(mesh) => mesh,
+                Primitive::Callback(_) => continue
+            };
+            let texture = match self.textures.get(&mesh.texture_id) {
+                Some(texture) => texture,
+                None => continue
+            };
+
+            for triangle in mesh.indices.chunks_exact(3) {
+                let vertices = [
+                    &mesh.vertices[triangle[0] as usize],
+                    &mesh.vertices[triangle[1] as usize],
+                    &mesh.vertices[triangle[2] as usize]
+                ];
+                fill_triangle(frame, primitive.clip_rect, vertices, texture);
+            }
+        }
+    }
+}
+
+/// The widgets themselves.
+fn parameters(context: &Context, settings: &mut Settings, camera: &mut Camera, colors: &[(String, Arc<LiveColor>)]) -> bool {
+    let mut changed = false;
+
+    egui::Window::new("Parameters").show(context, |ui| {
+        changed |= ui.add(egui::Slider::new(&mut settings.samples_per_pixel, 1 ..= 10000)
+            .logarithmic(true)
+            .text("samples per pixel")).changed();
+        changed |= ui.add(egui::Slider::new(&mut settings.max_depth, 1 ..= 50).text("max depth")).changed();
+
+        let mut vfov = camera.vfov();
+        if ui.add(egui::Slider::new(&mut vfov, 1.0 ..= 179.0).text("field of view")).changed() {
+            *camera = camera.with_vfov(vfov);
+            changed = true;
+        }
+
+        // The tone mapping only changes how the samples look, so the
+        // sampling goes on.
+        ui.add(egui::Slider::new(&mut settings.exposure, -5.0 ..= 5.0).text("exposure"));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut settings.tone_map, ToneMap::Clamp, "clamp");
+            ui.radio_value(&mut settings.tone_map, ToneMap::Reinhard, "reinhard");
+            ui.radio_value(&mut settings.tone_map, ToneMap::Aces, "aces");
+        });
+
+        for (name, color) in colors {
+            ui.horizontal(|ui| {
+                let c = color.get();
+                let mut rgb = [c.x, c.y, c.z];
+                if ui.color_edit_button_rgb(&mut rgb).changed() {
+                    color.set(Vector{ x: rgb[0], y: rgb[1], z: rgb[2] });
+                    changed = true;
+                }
+                ui.label(name);
+            });
+        }
+    });
+
+    changed
+}
+
+/// Rasterize a textured triangle of a mesh with premultiplied alpha
+/// blending.
+fn fill_triangle(frame: &mut Frame, clip: Rect, vertices: [&egui::epaint::Vertex; 3], texture: &ColorImage) {
+    let [a, b, c] = vertices.map(|v| v.pos);
+    let area = (b - a).x * (c - a).y - (b - a).y * (c - a).x;
+    if area.abs() < 1E-6 {
+        return;
+    }
+
+    let x0 = a.x.min(b.x).min(c.x).max(clip.min.x).max(0.0).floor() as usize;
+    let y0 = a.y.min(b.y).min(c.y).max(clip.min.y).max(0.0).floor() as usize;
+    let x1 = (a.x.max(b.x).max(c.x).min(clip.max.x).ceil() as usize).min(frame.width);
+    let y1 = (a.y.max(b.y).max(c.y).min(clip.max.y).ceil() as usize).min(frame.height);
+
+    let [tw, th] = texture.size;
+    for y in y0 .. y1 {
+        for x in x0 .. x1 {
+            // Barycentric coordinates of the pixel center.
+            let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let edge = |p0: Pos2, p1: Pos2| ((p1 - p0).x * (p - p0).y - (p1 - p0).y * (p - p0).x) / area;
+            let weights = [edge(b, c), edge(c, a), edge(a, b)];
+            if weights.iter().any(|&w| w < 0.0) {
+                continue;
+            }
+
+            let uv = vertices.iter().zip(&weights).fold(egui::Vec2::ZERO, |uv, (v, &w)| uv + w * v.uv.to_vec2());
+            let tx = ((uv.x * tw as f32) as usize).min(tw - 1);
+            let ty = ((uv.y * th as f32) as usize).min(th - 1);
+            let texel = texture.pixels[ty * tw + tx];
+
+            let mut color = [0.0; 4];
+            for (v, &w) in vertices.iter().zip(&weights) {
+                for (channel, &value) in color.iter_mut().zip(&v.color.to_array()) {
+                    *channel += w * value as f32;
+                }
+            }
+            let texel = texel.to_array();
+            let source: [f32; 4] = std::array::from_fn(|k| color[k] * texel[k] as f32 / 255.0);
+
+            let pixel = &mut frame.pixels[y * frame.width + x];
+            let alpha = source[3] / 255.0;
+            for k in 0 .. 3 {
+                pixel[k] = (source[k] + pixel[k] as f32 * (1.0 - alpha)).round().min(255.0) as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use sdl2::event::Event as SdlEvent;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::EventPump;
+
+use super::{Display, Event, Frame, Key};
+
+pub struct SdlDisplay {
+    canvas: Canvas<Window>,
+    event_pump: EventPump
+}
+
+impl SdlDisplay {
+    pub fn new(title: &str, width: usize, height: usize) -> Result<SdlDisplay, String> {
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+
+        let window = video_subsystem.window(title, width as u32, height as u32)
+            .position_centered()
+            .build()
+            .map_err(|err| err.to_string())?;
+
+        let canvas = window.into_canvas()
+            .present_vsync()
+            .build()
+            .map_err(|err| err.to_string())?;
+
+        let event_pump = sdl_context.event_pump()?;
+
+        Ok(SdlDisplay{ canvas, event_pump })
+    }
+}
+
+impl Display for SdlDisplay {
+    fn show(&mut self, frame: &Frame) {
+        for (i, row) in frame.pixels.chunks(frame.width).enumerate() {
+            for (j, &[r, g, b]) in row.iter().enumerate() {
+                self.canvas.set_draw_color(Color::RGB(r, g, b));
+                self.canvas.draw_point(Point::new(j as i32, i as i32)).unwrap();
+            }
+        }
+        self.canvas.present();
+    }
+
+    fn poll_events(&mut self) -> Vec<Event> {
+        self.event_pump.poll_iter()
+            .filter_map(|event| match event {
+                SdlEvent::Quit {..} => Some(Event::Quit),
+                SdlEvent::KeyDown { keycode: Some(keycode), repeat: false, .. } => key(keycode).map(Event::KeyDown),
+                SdlEvent::KeyUp { keycode: Some(keycode), .. } => key(keycode).map(Event::KeyUp),
+                SdlEvent::MouseMotion { x, y, .. } => Some(Event::MouseMove{ x, y }),
+                SdlEvent::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                    Some(Event::MouseDown{ x, y })
+                },
+                SdlEvent::MouseButtonUp { mouse_btn: MouseButton::Left, x, y, .. } => {
+                    Some(Event::MouseUp{ x, y })
+                },
+                _ => None
+            })
+            .collect()
+    }
+}
+
+fn key(keycode: Keycode) -> Option<Key> {
+    let key = match keycode {
+        Keycode::W => Key::W,
+        Keycode::A => Key::A,
+        Keycode::S => Key::S,
+        Keycode::D => Key::D,
+        Keycode::P => Key::P,
+        Keycode::R => Key::R,
+        Keycode::T => Key::T,
+        Keycode::Space => Key::Space,
+        Keycode::Tab => Key::Tab,
+        // + shares the key with = on most layouts.
+        Keycode::Plus | Keycode::Equals | Keycode::KpPlus => Key::Plus,
+        Keycode::Minus | Keycode::KpMinus => Key::Minus,
+        Keycode::Up => Key::Up,
+        Keycode::Down => Key::Down,
+        Keycode::Left => Key::Left,
+        Keycode::Right => Key::Right,
+        _ => return None
+    };
+    Some(key)
+}
@@ -0,0 +1,427 @@
+//! Rendering spread over several machines.
+//!
+//! A coordinator splits the image into jobs, each a tile and a range of
+//! the samples of its pixels, and hands them out over TCP to the workers
+//! that connect to it, one job at a time per connection. A worker takes
+//! the samples and sends back their sums, the full range of the light in
+//! floats, and the coordinator adds them up. A job of a worker that
+//! drops out is handed to another one.
+//!
+//! The workers load the scene themselves, so they are to be run with the
+//! same scene file and options as the coordinator. As the samples are
+//! drawn from the seed, the pixel and the index of the sample alone, it
+//! makes no difference which worker takes which job. There is no
+//! adaptive sampling: every pixel gets all of its samples.
+//!
+//! All numbers on the wire are little-endian. A worker opens with the
+//! magic `RTWK`, the width and the height of the image and whether it
+//! samples the AOVs. A job is a byte 1 followed by the rows `i0 .. i1`
+//! and the columns `j0 .. j1` of the tile and the samples `n0 .. n1`, a
+//! byte 0 tells that there are no more. The result is the sums of the
+//! colors of the pixels of the tile, in the order of `Tile::pixels`,
+//! followed by those of the albedo, the normal, the depth and the alpha
+//! if the AOVs are sampled.
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::camera::Camera;
+use crate::geometry::World;
+use crate::math::Vector;
+use crate::render::{render_aovs, render_tile, tiles, AovSample, Aovs, Image, Settings, Tile};
+
+const MAGIC: &[u8; 4] = b"RTWK";
+
+/// Samples of every pixel of a tile a job takes.
+pub const SAMPLES_PER_JOB: u32 = 4;
+
+/// Tile and the samples `n0 .. n1` of its pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Job {
+    tile: Tile,
+    n0: u32,
+    n1: u32
+}
+
+struct Progress {
+    jobs: VecDeque<Job>, // Not handed out yet, or handed back by the workers that dropped out
+    done: usize,
+    total: usize,
+    image: Image,
+    aovs: Option<Aovs> // If the settings need them
+}
+
+/// Render the image on the workers connecting to `address`, and return
+/// it and the AOVs, if the settings need them, averaged.
+pub fn coordinate<A: ToSocketAddrs>(address: A, settings: &Settings) -> io::Result<(Image, Option<Aovs>)> {
+    let listener = TcpListener::bind(address)?;
+    println!("Waiting for workers on {}", listener.local_addr()?);
+
+    let mut jobs = VecDeque::new();
+    for n0 in (0 .. settings.samples_per_pixel).step_by(SAMPLES_PER_JOB as usize) {
+        let n1 = (n0 + SAMPLES_PER_JOB).min(settings.samples_per_pixel);
+        jobs.extend(tiles(settings.width, settings.height).into_iter().map(|tile| Job { tile, n0, n1 }));
+    }
+    let progress = Progress {
+        total: jobs.len(),
+        jobs,
+        done: 0,
+        image: Image::new(settings.width, settings.height),
+        aovs: if settings.needs_aovs() { Some(Aovs::new(settings.width, settings.height)) } else { None }
+    };
+    let shared = Arc::new((Mutex::new(progress), Condvar::new()));
+
+    // Every worker is served on a thread of its own. The one accepting
+    // them is left behind once everything is done.
+    {
+        let shared = shared.clone();
+        let settings = *settings;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let shared = shared.clone();
+                match stream {
+                    Ok(stream) => {
+                        thread::spawn(move || {
+                            if let Err(err) = serve(stream, &settings, &shared) {
+                                eprintln!("Lost a worker: {}", err);
+                            }
+                        });
+                    },
+                    Err(err) => eprintln!("Failed to accept a worker: {}", err)
+                }
+            }
+        });
+    }
+
+    let (lock, condvar) = &*shared;
+    let mut progress = lock.lock().unwrap();
+    let mut percent = 0;
+    while progress.done < progress.total {
+        progress = condvar.wait(progress).unwrap();
+        if 100 * progress.done / progress.total > percent {
+            percent = 100 * progress.done / progress.total;
+            println!("{}%", percent);
+        }
+    }
+
+    let samples = settings.samples_per_pixel as f32;
+    let average = |image: &Image| Image {
+        width: image.width,
+        height: image.height,
+        pixels: image.pixels.iter().map(|&p| p / samples).collect()
+    };
+    let aovs = progress.aovs.as_ref().map(|aovs| Aovs {
+        albedo: average(&aovs.albedo),
+        normal: average(&aovs.normal),
+        depth: average(&aovs.depth),
+        alpha: average(&aovs.alpha)
+    });
+    Ok((average(&progress.image), aovs))
+}
+
+/// Hand out jobs to the worker at the other end of `stream` till there
+/// are none left, and add up what it sends back.
+fn serve(stream: TcpStream, settings: &Settings, shared: &(Mutex<Progress>, Condvar)) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    let (width, height) = (read_u32(&mut reader)? as usize, read_u32(&mut reader)? as usize);
+    let mut aovs = [0];
+    reader.read_exact(&mut aovs)?;
+    if &magic != MAGIC || (width, height) != (settings.width, settings.height) || (aovs[0] != 0) != settings.needs_aovs() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} renders another scene", peer)));
+    }
+    println!("Worker {} connected", peer);
+
+    let (lock, condvar) = shared;
+    loop {
+        // With no jobs left to hand out, the ones in flight may still
+        // come back from the workers that drop out.
+        let job = {
+            let mut progress = lock.lock().unwrap();
+            loop {
+                if let Some(job) = progress.jobs.pop_front() {
+                    break Some(job);
+                }
+                if progress.done == progress.total {
+                    break None;
+                }
+                progress = condvar.wait(progress).unwrap();
+            }
+        };
+        let job = match job {
+            Some(job) => job,
+            None => {
+                writer.write_all(&[0])?;
+                return writer.flush();
+            }
+        };
+
+        match exchange(&mut reader, &mut writer, job, settings.needs_aovs()) {
+            Ok((colors, aovs)) => {
+                let mut progress = lock.lock().unwrap();
+                for ((i, j), color) in job.tile.pixels().zip(colors) {
+                    progress.image.pixels[i * width + j] += color;
+                }
+                if let Some(buffers) = progress.aovs.as_mut() {
+                    for ((i, j), sample) in job.tile.pixels().zip(&aovs) {
+                        buffers.add(i * width + j, sample);
+                    }
+                }
+                progress.done += 1;
+                condvar.notify_all();
+            },
+            Err(err) => {
+                lock.lock().unwrap().jobs.push_back(job);
+                condvar.notify_all();
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Send the job and wait for the sums of its samples.
+fn exchange(reader: &mut impl Read, writer: &mut impl Write, job: Job, aovs: bool) -> io::Result<(Vec<Vector>, Vec<AovSample>)> {
+    let Job { tile, n0, n1 } = job;
+    writer.write_all(&[1])?;
+    for n in [tile.i0 as u32, tile.i1 as u32, tile.j0 as u32, tile.j1 as u32, n0, n1] {
+        writer.write_all(&n.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    let pixels = tile.pixels().count();
+    let colors = (0 .. pixels).map(|_| read_vector(reader)).collect::<io::Result<_>>()?;
+    let aovs = if aovs {
+        (0 .. pixels)
+            .map(|_| Ok(AovSample {
+                albedo: read_vector(reader)?,
+                normal: read_vector(reader)?,
+                depth: read_f32(reader)?,
+                alpha: read_f32(reader)?
+            }))
+            .collect::<io::Result<_>>()?
+    } else {
+        vec![]
+    };
+    Ok((colors, aovs))
+}
+
+/// Connect to the coordinator at `address` and take the jobs it hands
+/// out till there are none left, on as many connections as there are
+/// threads.
+pub fn work<A: ToSocketAddrs>(address: A, settings: &Settings, camera: &Camera, world: &World) -> io::Result<()> {
+    let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0 .. threads)
+            .map(|_| scope.spawn(|| take_jobs(&addresses, settings, camera, world)))
+            .collect();
+        handles.into_iter().try_for_each(|handle| handle.join().unwrap())
+    })
+}
+
+/// Take jobs over a connection of its own, one at a time.
+fn take_jobs(address: &[SocketAddr], settings: &Settings, camera: &Camera, world: &World) -> io::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(settings.width as u32).to_le_bytes())?;
+    writer.write_all(&(settings.height as u32).to_le_bytes())?;
+    writer.write_all(&[settings.needs_aovs() as u8])?;
+    writer.flush()?;
+
+    loop {
+        // The coordinator may be gone without a word once it has all the
+        // samples.
+        let mut tag = [0];
+        match reader.read_exact(&mut tag) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?
+        }
+        if tag[0] == 0 {
+            return Ok(());
+        }
+
+        let tile = Tile {
+            i0: read_u32(&mut reader)? as usize,
+            i1: read_u32(&mut reader)? as usize,
+            j0: read_u32(&mut reader)? as usize,
+            j1: read_u32(&mut reader)? as usize
+        };
+        let (n0, n1) = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+
+        let zero = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+        let mut colors = vec![zero; tile.pixels().count()];
+        for n in n0 .. n1 {
+            for (sum, color) in colors.iter_mut().zip(render_tile(tile, n, settings, camera, world)) {
+                *sum += color;
+            }
+        }
+        for color in colors {
+            write_vector(&mut writer, color)?;
+        }
+
+        if settings.needs_aovs() {
+            let mut sampler = settings.sampler();
+            let mut sums = vec![AovSample { albedo: zero, normal: zero, depth: 0.0, alpha: 0.0 }; tile.pixels().count()];
+            for n in n0 .. n1 {
+                for (sum, pixel) in sums.iter_mut().zip(tile.pixels()) {
+                    let sample = render_aovs(pixel, n, settings, camera, world, sampler.as_mut());
+                    sum.albedo += sample.albedo;
+                    sum.normal += sample.normal;
+                    sum.depth += sample.depth;
+                    sum.alpha += sample.alpha;
+                }
+            }
+            for sum in sums {
+                write_vector(&mut writer, sum.albedo)?;
+                write_vector(&mut writer, sum.normal)?;
+                writer.write_all(&sum.depth.to_le_bytes())?;
+                writer.write_all(&sum.alpha.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+fn read_vector(reader: &mut impl Read) -> io::Result<Vector> {
+    Ok(Vector{ x: read_f32(reader)?, y: read_f32(reader)?, z: read_f32(reader)? })
+}
+
+fn write_vector(writer: &mut impl Write, v: Vector) -> io::Result<()> {
+    for c in [v.x, v.y, v.z] {
+        writer.write_all(&c.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::scene::Scene;
+    use crate::texture::SolidColor;
+
+    const JOB: Job = Job { tile: Tile { i0: 2, i1: 4, j0: 1, j1: 2 }, n0: 3, n1: 5 };
+
+    fn vector(k: usize) -> Vector {
+        Vector{ x: k as f32, y: 0.5, z: -2.0 }
+    }
+
+    #[test]
+    fn exchange_round_trip() {
+        for aovs in [false, true] {
+            let mut result = vec![];
+            for k in 0 .. 2 {
+                write_vector(&mut result, vector(k)).unwrap();
+            }
+            if aovs {
+                for k in 0 .. 2 {
+                    write_vector(&mut result, vector(k + 10)).unwrap();
+                    write_vector(&mut result, vector(k + 20)).unwrap();
+                    result.extend(1.5f32.to_le_bytes());
+                    result.extend((k as f32).to_le_bytes());
+                }
+            }
+
+            let mut sent = vec![];
+            let (colors, samples) = exchange(&mut Cursor::new(&result), &mut sent, JOB, aovs).unwrap();
+
+            let mut expected = vec![1];
+            for n in [2u32, 4, 1, 2, 3, 5] {
+                expected.extend(n.to_le_bytes());
+            }
+            assert_eq!(sent, expected);
+            assert_eq!(colors, vec![vector(0), vector(1)]);
+            if aovs {
+                let sample = |k: usize| AovSample { albedo: vector(k + 10), normal: vector(k + 20), depth: 1.5, alpha: k as f32 };
+                assert_eq!(samples, vec![sample(0), sample(1)]);
+            } else {
+                assert!(samples.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_result() {
+        let mut result = vec![];
+        write_vector(&mut result, vector(0)).unwrap();
+        let error = exchange(&mut Cursor::new(&result), &mut vec![], JOB, false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn worker_sends_the_sums() {
+        let grey = Lambertian{ albedo: Arc::new(SolidColor{ color: Vector{ x: 0.5, y: 0.5, z: 0.5 } }) };
+        let mut scene = Scene::builder()
+            .size(8, 8)
+            .samples(8)
+            .camera(Vector{ x: 0.0, y: 0.0, z: 2.0 }, Vector{ x: 0.0, y: 0.0, z: 0.0 }, 60.0)
+            .material("grey", grey)
+            .sphere(Vector{ x: 0.0, y: 0.0, z: 0.0 }, 0.8, "grey")
+            .point_light(Vector{ x: 2.0, y: 2.0, z: 2.0 }, Vector{ x: 5.0, y: 5.0, z: 5.0 })
+            .build()
+            .unwrap();
+        scene.settings.aovs = true;
+        let Scene { settings, camera, world, .. } = scene;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = [listener.local_addr().unwrap()];
+        thread::scope(|scope| {
+            let worker = scope.spawn(|| take_jobs(&address, &settings, &camera, &world));
+
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = BufWriter::new(stream);
+            let mut hello = [0; 13];
+            reader.read_exact(&mut hello).unwrap();
+            assert_eq!(&hello[.. 4], MAGIC);
+            assert_eq!(hello[4 ..], [8, 0, 0, 0, 8, 0, 0, 0, 1]);
+
+            let job = Job { tile: Tile { i0: 0, i1: 8, j0: 2, j1: 6 }, n0: 1, n1: 4 };
+            let (colors, aovs) = exchange(&mut reader, &mut writer, job, true).unwrap();
+            writer.write_all(&[0]).unwrap();
+            writer.flush().unwrap();
+            worker.join().unwrap().unwrap();
+
+            let zero = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+            let mut expected = vec![zero; 32];
+            for n in 1 .. 4 {
+                for (sum, color) in expected.iter_mut().zip(render_tile(job.tile, n, &settings, &camera, &world)) {
+                    *sum += color;
+                }
+            }
+            assert_eq!(colors, expected);
+            assert!(colors.iter().any(|c| !c.is_near_zero()));
+
+            let mut sampler = settings.sampler();
+            for (sample, pixel) in aovs.iter().zip(job.tile.pixels()) {
+                let alpha: f32 = (1 .. 4).map(|n| render_aovs(pixel, n, &settings, &camera, &world, sampler.as_mut()).alpha).sum();
+                assert_eq!(sample.alpha, alpha);
+            }
+        });
+    }
+}
@@ -0,0 +1,140 @@
+//! Reconstruction filters, weighing how much a sample counts for the
+//! pixel it belongs to.
+//!
+//! Rather than weighing the samples, the positions of the samples are
+//! importance sampled from the filter: a sample lands near the center of
+//! the pixel as often as the filter is large there. The samples of a
+//! filter with negative lobes count negatively there, so that the
+//! average of the samples comes out the same as with explicit weights.
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Every sample of the pixel counts the same, nothing outside of
+    /// it counts.
+    #[default]
+    Box,
+    /// Linear falloff to a pixel away from the center.
+    Tent,
+    /// Gaussian of half a pixel deviation, cut off at one and a half.
+    Gaussian,
+    /// Mitchell-Netravali cubic with B = C = 1/3, two pixels wide. Its
+    /// negative lobes sharpen the image.
+    Mitchell
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            _ => Err(format!("unknown filter {}, expected box, tent, gaussian or mitchell", s))
+        }
+    }
+}
+
+impl Filter {
+    /// Distance from the center of the pixel at which the filter drops
+    /// to zero.
+    pub fn radius(&self) -> f32 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0
+        }
+    }
+
+    /// Value of the filter at the offset `x` from the center of the
+    /// pixel, along one axis. The filters are separable.
+    pub fn eval(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x >= self.radius() {
+            return 0.0;
+        }
+
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => 1.0 - x,
+            Filter::Gaussian => {
+                // Shifted down to meet zero at the radius.
+                let gaussian = |x: f32| (-2.0 * x * x).exp();
+                gaussian(x) - gaussian(self.radius())
+            },
+            Filter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let x2 = x * x;
+                let x3 = x2 * x;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)) / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x3 + (6.0 * b + 30.0 * c) * x2 + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                }
+            }
+        }
+    }
+
+    /// Turn a number from 0 to 1 into an offset from the center of the
+    /// pixel along one axis, distributed as the filter is, along with
+    /// the weight of the sample there.
+    pub fn sample(&self, u: f32) -> (f32, f32) {
+        static TENT: OnceLock<Table> = OnceLock::new();
+        static GAUSSIAN: OnceLock<Table> = OnceLock::new();
+        static MITCHELL: OnceLock<Table> = OnceLock::new();
+
+        let table = match self {
+            Filter::Box => return (u - 0.5, 1.0),
+            Filter::Tent => &TENT,
+            Filter::Gaussian => &GAUSSIAN,
+            Filter::Mitchell => &MITCHELL
+        };
+        table.get_or_init(|| Table::new(*self)).sample(u)
+    }
+}
+
+/// Cumulative distribution of the absolute value of a filter, tabulated
+/// over its width.
+struct Table {
+    radius: f32,
+    values: Vec<f32>, // Filter at the middle of every bin
+    cdf: Vec<f32>,    // Sum of the absolute values of the bins before
+    weight: f32       // Ratio of the integral of the absolute value to the integral
+}
+
+impl Table {
+    const BINS: usize = 512;
+
+    fn new(filter: Filter) -> Self {
+        let radius = filter.radius();
+        let width = 2.0 * radius / Self::BINS as f32;
+        let values: Vec<f32> = (0 .. Self::BINS)
+            .map(|k| filter.eval(-radius + (k as f32 + 0.5) * width))
+            .collect();
+
+        let mut cdf = vec![0.0];
+        for v in &values {
+            cdf.push(cdf.last().unwrap() + v.abs());
+        }
+        let integral: f32 = values.iter().sum();
+
+        Self { radius, weight: cdf[Self::BINS] / integral, values, cdf }
+    }
+
+    fn sample(&self, u: f32) -> (f32, f32) {
+        let target = u * self.cdf[Self::BINS];
+        let k = self.cdf.partition_point(|&c| c <= target).clamp(1, Self::BINS) - 1;
+
+        // Uniformly within the bin.
+        let within = (target - self.cdf[k]) / self.values[k].abs().max(f32::MIN_POSITIVE);
+        let width = 2.0 * self.radius / Self::BINS as f32;
+        let x = -self.radius + (k as f32 + within.clamp(0.0, 1.0)) * width;
+
+        (x, self.values[k].signum() * self.weight)
+    }
+}
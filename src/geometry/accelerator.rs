@@ -0,0 +1,304 @@
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use super::{Aabb, Bvh, Hit, Hittable, KdTree, Ray, Split};
+
+/// Spatial structure over the primitives of a shape, or the objects of
+/// the world, picking the ones a ray may hit so that it doesn't have to
+/// be tested against all of them. It knows the primitives by their
+/// index only.
+pub trait Accelerator: Debug + Send + Sync {
+    /// Box of everything in the structure.
+    fn bounds(&self) -> Aabb;
+
+    /// Walk the primitives the ray may hit nearer than `t_max`, roughly
+    /// from near to far, `visit` testing the primitive of an index and
+    /// telling the distance of the nearest hit found so far, `t_max` till
+    /// there is one. A primitive may be visited more than once.
+    fn traverse(&self, ray: &Ray, t_max: f32, visit: &mut dyn FnMut(usize) -> f32);
+}
+
+impl dyn Accelerator + '_ {
+    /// Nearest hit of the ray with the primitives nearer than `t_max`,
+    /// `hit` telling where the ray hits the primitive of an index, if
+    /// no farther than the distance given along with it, and anything
+    /// else about the hit. Of primitives hit at the same distance, the
+    /// first one wins, as it would trying them all in order.
+    pub fn nearest<T>(&self, ray: &Ray, t_max: f32, mut hit: impl FnMut(usize, f32) -> Option<(f32, T)>) -> Option<(f32, T)> {
+        let mut nearest: Option<(f32, usize, T)> = None;
+        self.traverse(ray, t_max, &mut |i| {
+            let bound = nearest.as_ref().map_or(t_max, |(t, ..)| *t);
+            if let Some((t, found)) = hit(i, bound) {
+                let better = nearest.as_ref()
+                    .map_or(t < t_max, |&(tn, j, _)| t < tn || (t == tn && i < j));
+                if better {
+                    nearest = Some((t, i, found));
+                }
+            }
+            nearest.as_ref().map_or(t_max, |(t, ..)| *t)
+        });
+        nearest.map(|(t, _, found)| (t, found))
+    }
+}
+
+/// Which spatial structure to find what the rays hit with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AcceleratorKind {
+    /// Bounding volume hierarchy, see `Bvh`.
+    #[default]
+    Bvh,
+    /// Tree of the cells of space, see `KdTree`.
+    KdTree
+}
+
+impl AcceleratorKind {
+    /// Structure of the kind over primitives with the given boxes, the
+    /// nodes of a BVH split the given way.
+    pub fn build(self, bounds: &[Aabb], split: Split) -> Box<dyn Accelerator> {
+        match self {
+            AcceleratorKind::Bvh => Box::new(Bvh::build(bounds, split)),
+            AcceleratorKind::KdTree => Box::new(KdTree::build(bounds))
+        }
+    }
+}
+
+impl FromStr for AcceleratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bvh" => Ok(AcceleratorKind::Bvh),
+            "kdtree" => Ok(AcceleratorKind::KdTree),
+            _ => Err(format!("unknown accelerator {}, expected bvh or kdtree", s))
+        }
+    }
+}
+
+/// Structures that can be built, by their place in `Lazy::built`.
+const CHOICES: [(AcceleratorKind, Split); 3] = [
+    (AcceleratorKind::Bvh, Split::Sah),
+    (AcceleratorKind::Bvh, Split::Median),
+    (AcceleratorKind::KdTree, Split::Sah) // The kd-tree has a split of its own
+];
+
+/// Acceleration structure built the first time it is needed, the way
+/// asked for last. Asked for another way, it is built anew, and the one
+/// built before is kept for if it is asked for again.
+#[derive(Debug)]
+pub(crate) struct Lazy<T> {
+    choice: AtomicUsize, // Place of the structure to use in `CHOICES`
+    built: [OnceLock<T>; CHOICES.len()]
+}
+
+impl<T> Lazy<T> {
+    /// Build the structure the way asked for, if it isn't yet.
+    pub(crate) fn get(&self, build: impl FnOnce(AcceleratorKind, Split) -> T) -> &T {
+        let k = self.choice.load(Ordering::Relaxed);
+        let (kind, split) = CHOICES[k];
+        self.built[k].get_or_init(|| build(kind, split))
+    }
+
+    /// Build the structures of the kind from now on, the nodes of a BVH
+    /// split the given way.
+    pub(crate) fn select(&self, kind: AcceleratorKind, split: Split) {
+        let k = CHOICES.iter()
+            .position(|&(k, s)| k == kind && (s == split || kind == AcceleratorKind::KdTree))
+            .unwrap_or(0);
+        self.choice.store(k, Ordering::Relaxed);
+    }
+
+    /// Forget the structures built, for the ones to be built anew.
+    pub(crate) fn clear(&mut self) {
+        self.built = Default::default();
+    }
+}
+
+impl<T> Default for Lazy<T> {
+    fn default() -> Self {
+        Self { choice: AtomicUsize::new(0), built: Default::default() }
+    }
+}
+
+impl<T: Clone> Clone for Lazy<T> {
+    fn clone(&self) -> Self {
+        Self { choice: AtomicUsize::new(self.choice.load(Ordering::Relaxed)), built: self.built.clone() }
+    }
+}
+
+/// Top level of the two-level acceleration structure: one over the
+/// objects of the world or a group, each of which, a mesh or an
+/// instance of one in particular, may have one of its own. A mesh
+/// placed many times is shared by its instances, and so is its
+/// structure. The objects with no bounds, such as planes, are tried one
+/// by one.
+#[derive(Debug)]
+pub(crate) struct TopLevel {
+    accelerator: Box<dyn Accelerator>,
+    bounded: Vec<usize>, // Indices of the objects by those of their boxes in the structure
+    unbounded: Vec<usize>
+}
+
+impl TopLevel {
+    pub(crate) fn new(objects: &[Box<dyn Hittable>], kind: AcceleratorKind, split: Split) -> Self {
+        let mut bounds = vec![];
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            match object.bounds() {
+                Some(b) => {
+                    bounds.push(b);
+                    bounded.push(i);
+                },
+                None => unbounded.push(i)
+            }
+        }
+        Self { accelerator: kind.build(&bounds, split), bounded, unbounded }
+    }
+
+    /// Nearest hit of the ray with the objects nearer than `t_max`, the
+    /// index of the object it hits filled in. Of objects hit at the same
+    /// distance, the first one wins.
+    pub(crate) fn hit<'a>(&self, objects: &'a [Box<dyn Hittable>], ray: &Ray, t_max: f32) -> Option<Hit<'a>> {
+        let hit = |i: usize| objects[i].hit(ray).map(|hit| Hit { object: i, ..hit });
+
+        let nearest = self.accelerator.nearest(ray, t_max, |k, bound| {
+            hit(self.bounded[k]).filter(|hit| hit.t <= bound).map(|hit| (hit.t, hit))
+        });
+        self.unbounded.iter()
+            .filter_map(|&i| hit(i))
+            .fold(nearest.map(|(_, hit)| hit), |nearest, found| {
+                let better = nearest.as_ref()
+                    .map_or(found.t < t_max, |n| found.t < n.t || (found.t == n.t && found.object < n.object));
+                if better { Some(found) } else { nearest }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vector;
+
+    /// Numbers from 0 to 1, the same on every run.
+    struct Numbers(u64);
+
+    impl Numbers {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+
+        fn vector(&mut self, scale: f32) -> Vector {
+            Vector{ x: scale * (self.next() - 0.5), y: scale * (self.next() - 0.5), z: scale * (self.next() - 0.5) }
+        }
+    }
+
+    /// Distance along the ray to the sphere, if it hits it nearer than
+    /// `t_max`.
+    fn hit_sphere(ray: &Ray, (center, radius): (Vector, f32), t_max: f32) -> Option<f32> {
+        let oc = ray.origin - center;
+        let b = oc.dot(ray.direction);
+        let d = b * b - (oc.sqnorm() - radius * radius);
+        if d < 0.0 {
+            return None;
+        }
+        let (near, far) = (-b - d.sqrt(), -b + d.sqrt());
+        let t = if near > 1E-4 { near } else { far };
+        (t > 1E-4 && t <= t_max).then_some(t)
+    }
+
+    fn spheres(numbers: &mut Numbers, count: usize) -> Vec<(Vector, f32)> {
+        (0 .. count).map(|_| (numbers.vector(20.0), 0.05 + 1.5 * numbers.next())).collect()
+    }
+
+    fn rays(numbers: &mut Numbers, count: usize) -> Vec<Ray> {
+        let axes = [Vector{ x: 1.0, y: 0.0, z: 0.0 }, Vector{ x: 0.0, y: -1.0, z: 0.0 }, Vector{ x: 0.0, y: 0.0, z: 1.0 }];
+        (0 .. count)
+            .map(|k| {
+                // Most aimed into the spheres, some along the axes, with
+                // no inverse to speak of.
+                let origin = numbers.vector(30.0);
+                let direction = if k % 5 == 0 { axes[k % 3] } else { numbers.vector(20.0) - origin };
+                Ray::new(origin, direction)
+            })
+            .collect()
+    }
+
+    /// Every structure finds the hits trying every sphere in turn finds,
+    /// and as many as there are.
+    fn check(spheres: &[(Vector, f32)], rays: &[Ray]) -> usize {
+        let bounds: Vec<Aabb> = spheres.iter()
+            .map(|&(c, r)| Aabb { min: c - Vector{ x: r, y: r, z: r }, max: c + Vector{ x: r, y: r, z: r } })
+            .collect();
+        let structures = [
+            AcceleratorKind::Bvh.build(&bounds, Split::Sah),
+            AcceleratorKind::Bvh.build(&bounds, Split::Median),
+            AcceleratorKind::KdTree.build(&bounds, Split::Sah)
+        ];
+
+        let mut hits = 0;
+        for ray in rays {
+            for t_max in [f32::INFINITY, 15.0] {
+                let expected = spheres.iter()
+                    .enumerate()
+                    .filter_map(|(i, &s)| hit_sphere(ray, s, t_max).map(|t| (t, i)))
+                    .fold(None, |nearest: Option<(f32, usize)>, (t, i)| match nearest {
+                        Some((tn, _)) if tn <= t => nearest,
+                        _ => Some((t, i))
+                    });
+                for structure in &structures {
+                    let found = structure.nearest(ray, t_max, |i, bound| hit_sphere(ray, spheres[i], bound).map(|t| (t, i)));
+                    assert_eq!(found, expected, "{:?} with {:?}", ray, structure);
+                }
+                hits += expected.is_some() as usize;
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn same_hits_as_brute_force() {
+        let mut numbers = Numbers(0x9E37_79B9_7F4A_7C15);
+        for count in [1, 3, 4, 5, 17, 300] {
+            let spheres = spheres(&mut numbers, count);
+            let hits = check(&spheres, &rays(&mut numbers, 200));
+            assert!(count < 17 || hits > 20, "only {} hits", hits);
+        }
+    }
+
+    #[test]
+    fn rays_from_inside() {
+        let mut numbers = Numbers(42);
+        let spheres = spheres(&mut numbers, 100);
+        let rays: Vec<Ray> = rays(&mut numbers, 100).into_iter()
+            .zip(&spheres)
+            .map(|(ray, &(center, _))| Ray::new(center, ray.direction))
+            .collect();
+        assert_eq!(check(&spheres, &rays), 2 * rays.len());
+    }
+
+    #[test]
+    fn overlapping_and_flat() {
+        // The same sphere many times over, ties going to the first, and
+        // spheres so small that their boxes are all but flat.
+        let mut spheres = vec![(Vector{ x: 0.0, y: 0.0, z: 0.0 }, 1.0); 20];
+        spheres.extend((0 .. 20).map(|k| (Vector{ x: k as f32, y: 3.0, z: 0.0 }, 1E-3)));
+        let mut numbers = Numbers(7);
+        let mut rays = rays(&mut numbers, 100);
+        rays.extend((0 .. 20).map(|k| Ray::new(Vector{ x: k as f32, y: 10.0, z: 0.0 }, Vector{ x: 0.0, y: -1.0, z: 0.0 })));
+        assert!(check(&spheres, &rays) >= 2 * 20);
+    }
+
+    #[test]
+    fn empty() {
+        for kind in [AcceleratorKind::Bvh, AcceleratorKind::KdTree] {
+            let structure = kind.build(&[], Split::Sah);
+            let ray = Ray::new(Vector{ x: 0.0, y: 0.0, z: 0.0 }, Vector{ x: 1.0, y: 0.0, z: 0.0 });
+            assert_eq!(structure.nearest(&ray, f32::INFINITY, |i, _| Some((1.0, i))), None);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::str::FromStr;
+
+use crate::math::Vector;
+
+use super::voxel::axis;
+use super::{Accelerator, Ray};
+
+/// Most primitives a leaf of the hierarchy holds.
+const LEAF_SIZE: usize = 4;
+
+/// Bins the centers are sorted into along every axis for the surface
+/// area heuristic, the bounds between them being the splits tried.
+const BINS: usize = 12;
+
+/// How the nodes of a BVH are split in two.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Split {
+    /// Where the surface area heuristic expects the children to be the
+    /// cheapest to trace: the chance of a ray passing through a box goes
+    /// as its surface area, so the cost of a split is the sum of the
+    /// areas of the children times the numbers of their primitives.
+    /// Only the bounds of a few bins along each axis are tried.
+    #[default]
+    Sah,
+    /// In halves at the median of the centers along the longest axis,
+    /// quicker to build.
+    Median
+}
+
+impl FromStr for Split {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sah" => Ok(Split::Sah),
+            "median" => Ok(Split::Median),
+            _ => Err(format!("unknown BVH split {}, expected sah or median", s))
+        }
+    }
+}
+
+/// Box along the axes, bounding a primitive or a group of them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vector,
+    pub max: Vector
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vector{ x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: Vector{ x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY }
+    };
+
+    /// Smallest box holding all the points, padded a little so that a
+    /// flat one still has some thickness.
+    pub fn around(points: &[Vector]) -> Self {
+        points.iter().fold(Aabb::EMPTY, |bounds, &p| bounds.grow(p)).expand(1E-4)
+    }
+
+    /// The box grown by the `margin` on every side.
+    pub fn expand(self, margin: f32) -> Self {
+        let margin = Vector{ x: margin, y: margin, z: margin };
+        Aabb { min: self.min - margin, max: self.max + margin }
+    }
+
+    /// Smallest box holding this one and the point.
+    pub fn grow(self, p: Vector) -> Self {
+        Aabb {
+            min: Vector{ x: self.min.x.min(p.x), y: self.min.y.min(p.y), z: self.min.z.min(p.z) },
+            max: Vector{ x: self.max.x.max(p.x), y: self.max.y.max(p.y), z: self.max.z.max(p.z) }
+        }
+    }
+
+    /// Smallest box holding both.
+    pub fn union(self, other: Aabb) -> Self {
+        self.grow(other.min).grow(other.max)
+    }
+
+    pub fn centroid(&self) -> Vector {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// The eight corners.
+    pub fn corners(&self) -> [Vector; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vector{ x: a.x, y: a.y, z: a.z },
+            Vector{ x: b.x, y: a.y, z: a.z },
+            Vector{ x: a.x, y: b.y, z: a.z },
+            Vector{ x: b.x, y: b.y, z: a.z },
+            Vector{ x: a.x, y: a.y, z: b.z },
+            Vector{ x: b.x, y: a.y, z: b.z },
+            Vector{ x: a.x, y: b.y, z: b.z },
+            Vector{ x: b.x, y: b.y, z: b.z }
+        ]
+    }
+
+    /// Distances along the ray, its direction inverted componentwise
+    /// beforehand, at which it enters and leaves the box, if it passes
+    /// through it nearer than `t_max`.
+    pub(crate) fn span(&self, ray: &Ray, inverse: Vector, t_max: f32) -> Option<(f32, f32)> {
+        let mut t0 = 0.0f32;
+        let mut t1 = t_max;
+        for a in 0 .. 3 {
+            let (o, inv) = (axis(ray.origin, a), axis(inverse, a));
+            let near = (axis(self.min, a) - o) * inv;
+            let far = (axis(self.max, a) - o) * inv;
+            let (near, far) = if inv < 0.0 { (far, near) } else { (near, far) };
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+        }
+        (t0 <= t1).then_some((t0, t1))
+    }
+}
+
+/// Node of the hierarchy, all of them kept in one array. The first
+/// child of an inner node comes right after it.
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf { bounds: Aabb, start: usize, count: usize }, // Range of `Bvh::order`
+    Inner { bounds: Aabb, second: usize }
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Inner { bounds, .. } => bounds
+        }
+    }
+}
+
+/// Bounding volume hierarchy: a tree of boxes, each holding the boxes
+/// of its children, over the primitives of a shape, so that a ray only
+/// gets tested against the primitives whose boxes it passes through.
+/// It knows the primitives by their index only.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    order: Vec<usize> // Indices of the primitives, those of every leaf together
+}
+
+impl Bvh {
+    /// Hierarchy over primitives with the given boxes, its nodes split
+    /// the given way.
+    pub fn build(bounds: &[Aabb], split: Split) -> Self {
+        let mut bvh = Bvh { nodes: Vec::with_capacity(2 * bounds.len() / LEAF_SIZE + 1), order: (0 .. bounds.len()).collect() };
+        if !bounds.is_empty() {
+            bvh.split(bounds, split, 0, bounds.len());
+        }
+        bvh
+    }
+
+    fn split(&mut self, bounds: &[Aabb], split: Split, start: usize, end: usize) {
+        let items = &mut self.order[start .. end];
+        let node_bounds = items.iter().fold(Aabb::EMPTY, |b, &i| b.union(bounds[i]));
+        if items.len() <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf { bounds: node_bounds, start, count: items.len() });
+            return;
+        }
+
+        let centers = items.iter().fold(Aabb::EMPTY, |b, &i| b.grow(bounds[i].centroid()));
+        let middle = match split {
+            Split::Sah => sah_split(items, bounds, &centers),
+            Split::Median => None
+        };
+        let middle = middle.unwrap_or_else(|| median_split(items, bounds, &centers));
+
+        let index = self.nodes.len();
+        self.nodes.push(Node::Inner { bounds: node_bounds, second: 0 });
+        self.split(bounds, split, start, start + middle);
+        let second = self.nodes.len();
+        self.nodes[index] = Node::Inner { bounds: node_bounds, second };
+        self.split(bounds, split, start + middle, end);
+    }
+}
+
+impl Accelerator for Bvh {
+    fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| *node.bounds())
+    }
+
+    fn traverse(&self, ray: &Ray, t_max: f32, visit: &mut dyn FnMut(usize) -> f32) {
+        let d = ray.direction;
+        let inverse = Vector{ x: 1.0 / d.x, y: 1.0 / d.y, z: 1.0 / d.z };
+
+        let mut bound = t_max;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds().span(ray, inverse, bound).is_none() {
+                continue;
+            }
+
+            match *node {
+                Node::Leaf { start, count, .. } => {
+                    for &i in &self.order[start .. start + count] {
+                        bound = visit(i);
+                    }
+                },
+                // The child on the side the ray comes from is visited
+                // first, as its hits can rule the other one out.
+                Node::Inner { second, .. } => {
+                    let first = index + 1;
+                    let toward = |child: usize| (self.nodes[child].bounds().centroid() - ray.origin).dot(d);
+                    if toward(first) <= toward(second) {
+                        stack.push(second);
+                        stack.push(first);
+                    } else {
+                        stack.push(first);
+                        stack.push(second);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Put the items with the centers before the median along the longest
+/// side of the box of the `centers` first, telling how many they are.
+fn median_split(items: &mut [usize], bounds: &[Aabb], centers: &Aabb) -> usize {
+    let size = centers.max - centers.min;
+    let a = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+    let middle = items.len() / 2;
+    items.select_nth_unstable_by(middle, |&i, &j| {
+        axis(bounds[i].centroid(), a).total_cmp(&axis(bounds[j].centroid(), a))
+    });
+    middle
+}
+
+/// Put the items on the near side of the cheapest split of the surface
+/// area heuristic first, telling how many they are, or nothing if the
+/// centers all fall into one bin.
+fn sah_split(items: &mut [usize], bounds: &[Aabb], centers: &Aabb) -> Option<usize> {
+    let bin = |i: usize, a: usize| {
+        let (low, high) = (axis(centers.min, a), axis(centers.max, a));
+        let k = (axis(bounds[i].centroid(), a) - low) / (high - low) * BINS as f32;
+        (k as usize).min(BINS - 1)
+    };
+
+    // Cheapest split: its axis, the first bin past it and its cost.
+    let mut best: Option<(usize, usize, f32)> = None;
+    for a in 0 .. 3 {
+        if axis(centers.max, a) <= axis(centers.min, a) {
+            continue;
+        }
+
+        let mut bins = [(Aabb::EMPTY, 0usize); BINS];
+        for &i in items.iter() {
+            let (b, count) = &mut bins[bin(i, a)];
+            *b = b.union(bounds[i]);
+            *count += 1;
+        }
+
+        // Areas times counts of everything past every bound, swept from
+        // the far end, then of everything before it from the near one.
+        let mut far = [0.0; BINS];
+        let (mut b, mut count) = (Aabb::EMPTY, 0);
+        for k in (1 .. BINS).rev() {
+            b = b.union(bins[k].0);
+            count += bins[k].1;
+            far[k] = if count > 0 { b.surface_area() * count as f32 } else { 0.0 };
+        }
+        let (mut b, mut count) = (Aabb::EMPTY, 0);
+        for k in 1 .. BINS {
+            b = b.union(bins[k - 1].0);
+            count += bins[k - 1].1;
+            if count == 0 || count == items.len() {
+                continue;
+            }
+            let cost = b.surface_area() * count as f32 + far[k];
+            if best.is_none_or(|(.., c)| cost < c) {
+                best = Some((a, k, cost));
+            }
+        }
+    }
+
+    let (a, k, _) = best?;
+    let mut middle = 0;
+    for j in 0 .. items.len() {
+        if bin(items[j], a) < k {
+            items.swap(middle, j);
+            middle += 1;
+        }
+    }
+    Some(middle)
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::voxel::{axis, unit};
+use super::{Aabb, Hit, Hittable, Ray};
+
+/// Box along the axes between the corners `min` and `max`. Every face
+/// has texture coordinates of its own, from 0 to 1 across it along the
+/// next two axes in turn: y and z on the faces square to x, z and x on
+/// those square to y, x and y on those square to z.
+#[derive(Debug, Clone)]
+pub struct Cuboid {
+    pub min: Vector,
+    pub max: Vector,
+    pub material: Arc<dyn Material>
+}
+
+impl Hittable for Cuboid {
+    /// Slab test: the ray is inside the box while it is between the
+    /// planes of every pair of faces.
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let eps = 1E-3;
+
+        let mut near = (f32::NEG_INFINITY, 0);
+        let mut far = (f32::INFINITY, 0);
+        for a in 0 .. 3 {
+            let (o, d) = (axis(ray.origin, a), axis(ray.direction, a));
+            let (min, max) = (axis(self.min, a), axis(self.max, a));
+            if d == 0.0 {
+                if o < min || o > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let (t0, t1) = ((min - o) / d, (max - o) / d);
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t0 > near.0 {
+                near = (t0, a);
+            }
+            if t1 < far.0 {
+                far = (t1, a);
+            }
+        }
+        if near.0 > far.0 {
+            return None;
+        }
+
+        // A ray from inside the box leaves it through the far face.
+        let (t, a, outward) = if near.0 >= eps {
+            (near.0, near.1, -1.0)
+        } else if far.0 >= eps {
+            (far.0, far.1, 1.0)
+        } else {
+            return None;
+        };
+
+        let p = ray.at(t);
+        let n = unit(a, outward * axis(ray.direction, a).signum());
+        let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+        let fraction = |k: usize| (axis(p, k) - axis(self.min, k)) / (axis(self.max, k) - axis(self.min, k));
+        Some(Hit::new(t, p, n, (fraction(b), fraction(c)), self.material.as_ref()).with_tangent(unit(b, 1.0)))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[self.min, self.max]))
+    }
+}
@@ -0,0 +1,161 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::{Aabb, Hit, Hittable, Ray};
+
+/// Nearest hit so far: the distance along the ray, the normal, the
+/// texture coordinates and the direction in which u grows.
+type Candidate = Option<(f32, Vector, (f32, f32), Vector)>;
+
+/// Keep the hit if it is in front of the ray and nearer than the one
+/// kept.
+fn consider(nearest: &mut Candidate, t: f32, n: Vector, uv: (f32, f32), dpdu: Vector) {
+    let eps = 1E-3;
+    if t >= eps && nearest.is_none_or(|(tn, ..)| t < tn) {
+        *nearest = Some((t, n, uv, dpdu));
+    }
+}
+
+/// Texture u coordinate going around the axis, for the offset `q` of a
+/// point from it, square to it.
+fn around(q: Vector, e1: Vector, e2: Vector) -> f32 {
+    (q.dot(e2).atan2(q.dot(e1)) + PI) / (2.0 * PI)
+}
+
+/// Hit on a disk cap `s` up the axis with the given outward normal.
+fn cap(
+    nearest: &mut Candidate,
+    (os, op): (f32, Vector),
+    (ds, dp): (f32, Vector),
+    s: f32,
+    radius: f32,
+    n: Vector,
+    (e1, e2): (Vector, Vector)
+) {
+    if ds.abs() < 1E-8 {
+        return;
+    }
+    let t = (s - os) / ds;
+    let q = op + t * dp;
+    if q.sqnorm() <= radius * radius {
+        let uv = (0.5 + 0.5 * q.dot(e1) / radius, 0.5 + 0.5 * q.dot(e2) / radius);
+        consider(nearest, t, n, uv, e1);
+    }
+}
+
+/// Solutions of a t² + 2 b t + c = 0, the one twice if there is one.
+fn roots(a: f32, b: f32, c: f32) -> Option<[f32; 2]> {
+    if a.abs() < 1E-12 {
+        return if b.abs() < 1E-12 { None } else { Some([-c / (2.0 * b); 2]) };
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let d = discriminant.sqrt();
+    Some([(-b - d) / a, (-b + d) / a])
+}
+
+/// Finite cylinder of the `radius` around the axis from the center of
+/// the `base` to the center of the `top`, closed by disks at both ends
+/// if it has `caps`. On the side, u goes around the axis and v up it.
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    pub base: Vector,
+    pub top: Vector,
+    pub radius: f32,
+    pub caps: bool,
+    pub material: Arc<dyn Material>
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let axis = self.top - self.base;
+        let height = axis.norm();
+        let a = axis / height;
+        let basis = a.basis();
+
+        // Offsets along the axis and square to it.
+        let o = ray.origin - self.base;
+        let d = ray.direction;
+        let (os, ds) = (o.dot(a), d.dot(a));
+        let (op, dp) = (o - os * a, d - ds * a);
+
+        let mut nearest = None;
+        let side = roots(dp.sqnorm(), op.dot(dp), op.sqnorm() - self.radius * self.radius);
+        for t in side.into_iter().flatten() {
+            let s = os + t * ds;
+            if (0.0 ..= height).contains(&s) {
+                let q = op + t * dp;
+                let uv = (around(q, basis.0, basis.1), s / height);
+                consider(&mut nearest, t, q / self.radius, uv, a.cross(q));
+            }
+        }
+        if self.caps {
+            cap(&mut nearest, (os, op), (ds, dp), 0.0, self.radius, -a, basis);
+            cap(&mut nearest, (os, op), (ds, dp), height, self.radius, a, basis);
+        }
+
+        let (t, n, uv, dpdu) = nearest?;
+        Some(Hit::new(t, ray.at(t), n, uv, self.material.as_ref()).with_tangent(dpdu))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[self.base, self.top]).expand(self.radius))
+    }
+}
+
+/// Finite cone narrowing from the disk of the `radius` around the
+/// center of the `base` to the `apex`, closed by the disk if it has a
+/// `cap`. On the side, u goes around the axis and v up it.
+#[derive(Debug, Clone)]
+pub struct Cone {
+    pub base: Vector,
+    pub apex: Vector,
+    pub radius: f32,
+    pub cap: bool,
+    pub material: Arc<dyn Material>
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let axis = self.apex - self.base;
+        let height = axis.norm();
+        let a = axis / height;
+        let basis = a.basis();
+        let k = self.radius / height; // How much the radius shrinks per unit up the axis
+
+        let o = ray.origin - self.base;
+        let d = ray.direction;
+        let (os, ds) = (o.dot(a), d.dot(a));
+        let (op, dp) = (o - os * a, d - ds * a);
+
+        // Square of the distance to the axis equal to that of the radius
+        // at the height: |op + t dp|² = k² (height - os - t ds)².
+        let m = height - os;
+        let mut nearest = None;
+        let side = roots(dp.sqnorm() - k * k * ds * ds, op.dot(dp) + k * k * m * ds, op.sqnorm() - k * k * m * m);
+        for t in side.into_iter().flatten() {
+            let s = os + t * ds;
+            if (0.0 ..= height).contains(&s) {
+                let q = op + t * dp;
+                let n = q.unit() + k * a;
+                let uv = (around(q, basis.0, basis.1), s / height);
+                consider(&mut nearest, t, n, uv, a.cross(q));
+            }
+        }
+        if self.cap {
+            cap(&mut nearest, (os, op), (ds, dp), 0.0, self.radius, -a, basis);
+        }
+
+        let (t, n, uv, dpdu) = nearest?;
+        Some(Hit::new(t, ray.at(t), n, uv, self.material.as_ref()).with_tangent(dpdu))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[self.base, self.apex]).expand(self.radius))
+    }
+}
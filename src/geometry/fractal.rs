@@ -0,0 +1,94 @@
+use crate::math::{Quaternion, Vector};
+
+use super::DistanceField;
+
+/// The Mandelbulb, a 3D Mandelbrot set: the points c for which the
+/// orbit of z ↦ z^n + c stays bounded, the power of a point taken in
+/// spherical coordinates (its distance to the n-th power, its angles
+/// times n). The y axis is the pole. Sized by `scale` around the
+/// `center`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mandelbulb {
+    pub center: Vector,
+    pub scale: f32,
+    pub power: f32,
+    pub iterations: usize, // More bring out finer detail, slower
+    pub bailout: f32 // Distance past which a point escapes
+}
+
+impl DistanceField for Mandelbulb {
+    /// Distance estimated from how fast the orbit of the point escapes,
+    /// along with the derivative of the iteration.
+    fn distance(&self, p: Vector) -> f32 {
+        let c = (p - self.center) / self.scale;
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.norm();
+        for _ in 0 .. self.iterations {
+            if r > self.bailout {
+                break;
+            }
+
+            let theta = (z.y / r.max(1E-12)).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.z.atan2(z.x) * self.power;
+            dr = self.power * r.powf(self.power - 1.0) * dr + 1.0;
+            let zr = r.powf(self.power);
+            z = zr * Vector{ x: theta.sin() * phi.cos(), y: theta.cos(), z: theta.sin() * phi.sin() } + c;
+            r = z.norm();
+        }
+
+        self.scale * 0.5 * r.max(1E-12).ln() * r / dr
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        (self.center, self.scale * self.bailout.min(2.0))
+    }
+}
+
+/// Quaternion Julia set: the points z for which z ↦ z² + c stays
+/// bounded, z being the quaternion x + yi + zj + `slice` k for the 3D
+/// slice through the 4D set. The set is round about the real axis, here
+/// the x one. Sized by `scale` around the `center`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Julia {
+    pub center: Vector,
+    pub scale: f32,
+    pub c: Quaternion,
+    pub slice: f32,
+    pub iterations: usize,
+    pub bailout: f32
+}
+
+impl Julia {
+    /// Distance past which every orbit escapes for the constant `c`.
+    fn escape_radius(&self) -> f32 {
+        0.5 * (1.0 + (1.0 + 4.0 * self.c.norm()).sqrt())
+    }
+}
+
+impl DistanceField for Julia {
+    /// Distance estimated from the size of the orbit of the point and
+    /// of its derivative when it escapes.
+    fn distance(&self, p: Vector) -> f32 {
+        let p = (p - self.center) / self.scale;
+        let mut z = Quaternion { w: p.x, x: p.y, y: p.z, z: self.slice };
+        let mut dz = Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+        for _ in 0 .. self.iterations {
+            if z.dot(z) > self.bailout * self.bailout {
+                break;
+            }
+
+            let d = z * dz;
+            dz = Quaternion { w: 2.0 * d.w, x: 2.0 * d.x, y: 2.0 * d.y, z: 2.0 * d.z };
+            let q = z * z;
+            z = Quaternion { w: q.w + self.c.w, x: q.x + self.c.x, y: q.y + self.c.y, z: q.z + self.c.z };
+        }
+
+        let r = z.norm().max(1E-12);
+        self.scale * 0.5 * r * r.ln() / dz.norm().max(1E-12)
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        (self.center, self.scale * self.escape_radius().min(self.bailout))
+    }
+}
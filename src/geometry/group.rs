@@ -0,0 +1,36 @@
+use super::accelerator::{Lazy, TopLevel};
+use super::{Aabb, AcceleratorKind, Hit, Hittable, Ray, Split};
+
+/// Collection of objects acting as one, such as all the meshes of an
+/// imported model.
+#[derive(Default)]
+pub struct Group {
+    pub objects: Vec<Box<dyn Hittable>>,
+    top: Lazy<TopLevel> // Built on the first hit, once all the objects are in
+}
+
+impl Group {
+    fn top(&self) -> &TopLevel {
+        self.top.get(|kind, split| TopLevel::new(&self.objects, kind, split))
+    }
+}
+
+impl Hittable for Group {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.top().hit(&self.objects, ray, f32::INFINITY)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.objects.iter()
+            .map(|obj| obj.bounds())
+            .try_fold(Aabb::EMPTY, |bounds, b| Some(bounds.union(b?)))
+    }
+
+    fn prepare(&self, kind: AcceleratorKind, split: Split) {
+        for obj in &self.objects {
+            obj.prepare(kind, split);
+        }
+        self.top.select(kind, split);
+        self.top();
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use image::ImageResult;
+
+use crate::material::Material;
+use crate::math::{Vector, EX};
+use crate::perlin::Perlin;
+
+use super::{intersect_triangle, Aabb, Hit, Hittable, Ray};
+
+/// Terrain over the unit square of the xz plane, from the origin to
+/// (1, 0, 1): heights sampled on a grid of `nx` by `nz` points, at least
+/// two each way, the square of every four neighbouring samples split
+/// into two triangles. The normals are interpolated from the slopes at
+/// the samples, so the terrain looks smooth rather than faceted. Place
+/// and size it with a transform.
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    nx: usize,
+    nz: usize,
+    heights: Vec<f32>, // x varies fastest
+    low: f32, // The lowest and the highest of the heights
+    high: f32,
+    material: Arc<dyn Material>
+}
+
+impl Heightfield {
+    pub fn new(nx: usize, nz: usize, heights: Vec<f32>, material: Arc<dyn Material>) -> Self {
+        let low = heights.iter().copied().fold(f32::INFINITY, f32::min);
+        let high = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        Self { nx, nz, heights, low, high, material }
+    }
+
+    /// Heights from 0 to 1 by the brightness of a grayscale image, seen
+    /// from above with the -z axis up: the top row of the image is the
+    /// edge at z = 0 and the left column the one at x = 0.
+    pub fn load<P: AsRef<Path>>(path: P, material: Arc<dyn Material>) -> ImageResult<Self> {
+        let image = image::open(path)?.into_luma16();
+        let (nx, nz) = (image.width() as usize, image.height() as usize);
+        let heights = image.pixels().map(|p| p[0] as f32 / u16::MAX as f32).collect();
+        Ok(Self::new(nx, nz, heights, material))
+    }
+
+    /// Hills of turbulent noise, `frequency` features across the square
+    /// and `octaves` layers of detail, sampled on an `nx` by `nz` grid.
+    /// The heights stay between 0 and 1.
+    pub fn noise(noise: &Perlin, nx: usize, nz: usize, frequency: f32, octaves: usize, material: Arc<dyn Material>) -> Self {
+        let mut heights = Vec::with_capacity(nx * nz);
+        for j in 0 .. nz {
+            for i in 0 .. nx {
+                let x = i as f32 / (nx - 1).max(1) as f32;
+                let z = j as f32 / (nz - 1).max(1) as f32;
+                let p = frequency * Vector{ x, y: 0.0, z };
+                heights.push(0.5 * noise.turbulence(p, octaves));
+            }
+        }
+        Self::new(nx, nz, heights, material)
+    }
+
+    fn height(&self, i: usize, j: usize) -> f32 {
+        self.heights[j * self.nx + i]
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vector {
+        Vector{
+            x: i as f32 / (self.nx - 1) as f32,
+            y: self.height(i, j),
+            z: j as f32 / (self.nz - 1) as f32
+        }
+    }
+
+    /// Normal at a sample, from the slopes to its neighbours on either
+    /// side, or on the one side there is at the edges.
+    fn normal(&self, i: usize, j: usize) -> Vector {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.nx - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.nz - 1));
+        let dx = (i1 - i0) as f32 / (self.nx - 1) as f32;
+        let dz = (j1 - j0) as f32 / (self.nz - 1) as f32;
+        Vector{
+            x: -(self.height(i1, j) - self.height(i0, j)) / dx,
+            y: 1.0,
+            z: -(self.height(i, j1) - self.height(i, j0)) / dz
+        }
+    }
+
+    /// Nearest hit of the ray with the two triangles of the cell from
+    /// the sample (i, j) to (i + 1, j + 1).
+    fn hit_cell(&self, ray: &Ray, i: usize, j: usize) -> Option<Hit<'_>> {
+        let corners = [(i, j), (i, j + 1), (i + 1, j), (i + 1, j + 1)];
+        let halves = [[corners[0], corners[1], corners[2]], [corners[3], corners[2], corners[1]]];
+        let (t, u, v, samples) = halves.iter()
+            .filter_map(|&samples| {
+                let [a, b, c] = samples.map(|(i, j)| self.vertex(i, j));
+                intersect_triangle(ray, a, b, c).map(|(t, u, v)| (t, u, v, samples))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+
+        let [a, b, c] = samples.map(|(i, j)| self.vertex(i, j));
+        let [na, nb, nc] = samples.map(|(i, j)| self.normal(i, j));
+        let geometric = (b - a).cross(c - a);
+        let shading = (1.0 - u - v) * na + u * nb + v * nc;
+        let n = if shading.dot(geometric) < 0.0 { -shading } else { shading };
+
+        let p = ray.at(t);
+        Some(Hit::new(t, p, n, (p.x, 1.0 - p.z), self.material.as_ref()).with_tangent(EX))
+    }
+}
+
+impl Hittable for Heightfield {
+    /// Walk the cells under the ray one by one, as a voxel grid does in
+    /// 3D, skipping those the ray passes wholly above or below, till the
+    /// first one whose surface it hits.
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        if self.nx < 2 || self.nz < 2 {
+            return None;
+        }
+        let cells = [(self.nx - 1) as i64, (self.nz - 1) as i64];
+
+        // Part of the ray inside the bounding box.
+        let (o, d) = (ray.origin, ray.direction);
+        let slabs = [(o.x, d.x, 0.0, 1.0), (o.y, d.y, self.low, self.high), (o.z, d.z, 0.0, 1.0)];
+        let mut t0 = 0.0f32;
+        let mut t1 = f32::INFINITY;
+        for (o, d, low, high) in slabs {
+            if d == 0.0 {
+                if o < low || o > high {
+                    return None;
+                }
+                continue;
+            }
+
+            let (near, far) = if d > 0.0 { ((low - o) / d, (high - o) / d) } else { ((high - o) / d, (low - o) / d) };
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+        }
+        if t1 < t0 {
+            return None;
+        }
+
+        // The cells are walked in the coordinates of the grid, where they
+        // are a unit apart.
+        let origin = [o.x * cells[0] as f32, o.z * cells[1] as f32];
+        let direction = [d.x * cells[0] as f32, d.z * cells[1] as f32];
+        let start = [origin[0] + t0 * direction[0], origin[1] + t0 * direction[1]];
+        let mut cell = [0i64; 2];
+        let mut step = [0i64; 2];
+        let mut t_next = [f32::INFINITY; 2];
+        let mut t_delta = [f32::INFINITY; 2];
+        for a in 0 .. 2 {
+            cell[a] = (start[a].floor() as i64).clamp(0, cells[a] - 1);
+            if direction[a] != 0.0 {
+                step[a] = if direction[a] > 0.0 { 1 } else { -1 };
+                let boundary = (cell[a] + if direction[a] > 0.0 { 1 } else { 0 }) as f32;
+                t_next[a] = (boundary - origin[a]) / direction[a];
+                t_delta[a] = 1.0 / direction[a].abs();
+            }
+        }
+
+        let mut t = t0;
+        loop {
+            let (i, j) = (cell[0] as usize, cell[1] as usize);
+            let t_exit = t_next[0].min(t_next[1]).min(t1);
+            let (y0, y1) = (ray.at(t).y, ray.at(t_exit).y);
+            let corners = [self.height(i, j), self.height(i + 1, j), self.height(i, j + 1), self.height(i + 1, j + 1)];
+            let low = corners.iter().copied().fold(f32::INFINITY, f32::min);
+            let high = corners.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            if y0.min(y1) <= high && y0.max(y1) >= low {
+                if let Some(hit) = self.hit_cell(ray, i, j) {
+                    return Some(hit);
+                }
+            }
+
+            let a = if t_next[0] < t_next[1] { 0 } else { 1 };
+            if t_next[a] > t1 {
+                return None;
+            }
+
+            t = t_next[a];
+            cell[a] += step[a];
+            if cell[a] < 0 || cell[a] >= cells[a] {
+                return None;
+            }
+            t_next[a] += t_delta[a];
+        }
+    }
+
+    /// The field covers the unit square of the xz plane.
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[
+            Vector{ x: 0.0, y: self.low, z: 0.0 },
+            Vector{ x: 1.0, y: self.high, z: 1.0 }
+        ]))
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use crate::math::Transform;
+
+use super::{Aabb, AcceleratorKind, Hit, Hittable, Ray, Split};
+
+/// Shared object placed in the world with a transformation, so the same
+/// shape or mesh can be put at many positions and orientations without
+/// copying it.
+#[derive(Clone)]
+pub struct Instance {
+    pub object: Arc<dyn Hittable>,
+    pub transform: Transform // From the object space to the world space
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        Self { object, transform }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let inverse = self.transform.inverse();
+        let local = Ray::new(
+            inverse.apply_point(ray.origin),
+            inverse.apply_vector(ray.direction)
+        ).with_time(ray.time);
+
+        let hit = self.object.hit(&local)?;
+
+        // Distances are not preserved by all the transformations, so the
+        // one along the world ray is measured anew.
+        let p = self.transform.apply_point(hit.p);
+        let t = (p - ray.origin).dot(ray.direction);
+        let n = self.transform.apply_normal(hit.n);
+        let tangent = self.transform.apply_vector(hit.tangent);
+
+        Some(Hit::new(t, p, n, (hit.u, hit.v), hit.material).with_tangent(tangent))
+    }
+
+    /// Box around the corners of the box of the object, moved into place.
+    fn bounds(&self) -> Option<Aabb> {
+        let corners = self.object.bounds()?.corners();
+        Some(Aabb::around(&corners.map(|c| self.transform.apply_point(c))))
+    }
+
+    fn prepare(&self, kind: AcceleratorKind, split: Split) {
+        self.object.prepare(kind, split);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::math::Vector;
+
+use super::voxel::axis;
+use super::{Aabb, Accelerator, Ray};
+
+/// Fewest primitives a cell is split with.
+const LEAF_SIZE: usize = 4;
+
+/// Planes tried along every axis of a cell, evenly spaced across it.
+const BINS: usize = 32;
+
+/// Cost of stepping through a node of the tree, relative to testing a
+/// primitive.
+const TRAVERSAL_COST: f32 = 0.25;
+
+/// Node of the tree, all of them kept in one array. The child below the
+/// plane of an inner node comes right after it.
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf { start: usize, count: usize }, // Range of `KdTree::items`
+    Inner { axis: usize, plane: f32, above: usize }
+}
+
+/// Tree of the cells of space: every node splits its cell in two by a
+/// plane across an axis, and the primitives overlapping a leaf cell are
+/// listed in it, those across a plane in both cells. A ray walks the
+/// cells it passes through from near to far and stops at the first one
+/// with a hit in it, so, unlike with a BVH, no cell is tried past the
+/// nearest hit. Primitives across many cells may be tested many times
+/// though, and the tree takes more memory.
+#[derive(Debug, Clone)]
+pub struct KdTree {
+    bounds: Aabb,
+    nodes: Vec<Node>,
+    items: Vec<usize> // Indices of the primitives, those of every leaf together
+}
+
+impl KdTree {
+    /// Tree over primitives with the given boxes, the planes chosen by
+    /// the surface area heuristic: a ray passes through a cell with a
+    /// chance going as its surface area, so the cost of a split is the
+    /// sum of the areas of the halves times the numbers of primitives in
+    /// them. Cells are left whole when splitting them costs more than
+    /// testing all their primitives.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let cell = bounds.iter().fold(Aabb::EMPTY, |cell, &b| cell.union(b));
+        let mut tree = KdTree { bounds: cell, nodes: vec![], items: vec![] };
+        if !bounds.is_empty() {
+            let depth = 8 + (1.3 * (bounds.len() as f32).log2()) as usize;
+            tree.split(bounds, (0 .. bounds.len()).collect(), cell, depth);
+        }
+        tree
+    }
+
+    fn split(&mut self, bounds: &[Aabb], items: Vec<usize>, cell: Aabb, depth: usize) {
+        let plane = if items.len() > LEAF_SIZE && depth > 0 { best_plane(bounds, &items, &cell) } else { None };
+        let (a, plane) = match plane {
+            Some(plane) => plane,
+            None => {
+                self.nodes.push(Node::Leaf { start: self.items.len(), count: items.len() });
+                self.items.extend(items);
+                return;
+            }
+        };
+
+        let below: Vec<usize> = items.iter().copied().filter(|&i| axis(bounds[i].min, a) < plane).collect();
+        let above: Vec<usize> = items.into_iter().filter(|&i| axis(bounds[i].max, a) >= plane).collect();
+        let mut below_cell = cell;
+        let mut above_cell = cell;
+        set_axis(&mut below_cell.max, a, plane);
+        set_axis(&mut above_cell.min, a, plane);
+
+        let index = self.nodes.len();
+        self.nodes.push(Node::Inner { axis: a, plane, above: 0 });
+        self.split(bounds, below, below_cell, depth - 1);
+        let above_index = self.nodes.len();
+        self.nodes[index] = Node::Inner { axis: a, plane, above: above_index };
+        self.split(bounds, above, above_cell, depth - 1);
+    }
+}
+
+fn set_axis(v: &mut Vector, a: usize, value: f32) {
+    match a {
+        0 => v.x = value,
+        1 => v.y = value,
+        _ => v.z = value
+    }
+}
+
+/// Axis and position of the plane splitting the cell the cheapest, if
+/// that is cheaper than leaving it whole.
+fn best_plane(bounds: &[Aabb], items: &[usize], cell: &Aabb) -> Option<(usize, f32)> {
+    let area = cell.surface_area();
+    let mut best: Option<(usize, f32, f32)> = None;
+    for a in 0 .. 3 {
+        let (low, high) = (axis(cell.min, a), axis(cell.max, a));
+        if high <= low {
+            continue;
+        }
+
+        // How many primitives start and end in every slab of the cell.
+        let width = (high - low) / BINS as f32;
+        let slab = |x: f32| (((x - low) / width).max(0.0) as usize).min(BINS - 1);
+        let mut starts = [0usize; BINS];
+        let mut ends = [0usize; BINS];
+        for &i in items {
+            starts[slab(axis(bounds[i].min, a))] += 1;
+            ends[slab(axis(bounds[i].max, a))] += 1;
+        }
+
+        let (mut below, mut ended) = (0, 0);
+        for k in 1 .. BINS {
+            below += starts[k - 1];
+            ended += ends[k - 1];
+            let above = items.len() - ended;
+            let plane = low + k as f32 * width;
+
+            let (mut below_cell, mut above_cell) = (*cell, *cell);
+            set_axis(&mut below_cell.max, a, plane);
+            set_axis(&mut above_cell.min, a, plane);
+            let cost = TRAVERSAL_COST
+                + (below_cell.surface_area() * below as f32 + above_cell.surface_area() * above as f32) / area;
+            if best.is_none_or(|(.., c)| cost < c) {
+                best = Some((a, plane, cost));
+            }
+        }
+    }
+
+    let (a, plane, cost) = best?;
+    (cost < items.len() as f32).then_some((a, plane))
+}
+
+impl Accelerator for KdTree {
+    fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    fn traverse(&self, ray: &Ray, t_max: f32, visit: &mut dyn FnMut(usize) -> f32) {
+        let d = ray.direction;
+        let inverse = Vector{ x: 1.0 / d.x, y: 1.0 / d.y, z: 1.0 / d.z };
+        let (t0, t1) = match (self.nodes.is_empty(), self.bounds.span(ray, inverse, t_max)) {
+            (false, Some(span)) => span,
+            _ => return
+        };
+
+        // Cells still to walk, with the stretch of the ray inside them,
+        // the nearest on top.
+        let mut bound = t_max;
+        let mut stack = vec![(0, t0, t1)];
+        while let Some((mut index, t_min, mut t_exit)) = stack.pop() {
+            if t_min > bound {
+                break;
+            }
+
+            loop {
+                match self.nodes[index] {
+                    Node::Inner { axis: a, plane, above } => {
+                        let (o, da) = (axis(ray.origin, a), axis(d, a));
+                        let t_plane = if da == 0.0 { f32::INFINITY } else { (plane - o) * axis(inverse, a) };
+                        let below_first = o < plane || (o == plane && da <= 0.0);
+                        let (first, second) = if below_first { (index + 1, above) } else { (above, index + 1) };
+
+                        if t_plane > t_exit || t_plane <= 0.0 {
+                            index = first;
+                        } else if t_plane < t_min {
+                            index = second;
+                        } else {
+                            stack.push((second, t_plane, t_exit));
+                            index = first;
+                            t_exit = t_plane;
+                        }
+                    },
+                    Node::Leaf { start, count } => {
+                        for &i in &self.items[start .. start + count] {
+                            bound = visit(i);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::material::{Isotropic, Material};
+use crate::math::Vector;
+use crate::perlin::Perlin;
+use crate::texture::Texture;
+
+use super::{Aabb, AcceleratorKind, Hit, Hittable, Ray, Split};
+
+/// Participating medium of constant density, such as fog or smoke,
+/// filling a convex boundary shape. A ray passing through it gets
+/// scattered at a random distance with probability growing with the
+/// density and the length of its path inside.
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Hittable>,
+    pub density: f32,
+    pub phase: Arc<dyn Material>
+}
+
+impl ConstantMedium {
+    /// Medium scattering light isotropically with the given color.
+    pub fn new(boundary: Box<dyn Hittable>, density: f32, albedo: Arc<dyn Texture>) -> Self {
+        Self {
+            boundary,
+            density,
+            phase: Arc::new(Isotropic{ albedo })
+        }
+    }
+}
+
+/// The part of the ray inside a convex boundary, as distances along the
+/// ray where it enters and leaves the shape.
+pub fn inside_segment(boundary: &dyn Hittable, ray: &Ray) -> Option<(f32, f32)> {
+    let first = boundary.hit(ray)?;
+
+    // Going out through the first surface means the ray starts inside.
+    if ray.direction.dot(first.n) > 0.0 {
+        return Some((0.0, first.t));
+    }
+
+    let inner = Ray::new(ray.at(first.t), ray.direction).with_time(ray.time);
+    let second = boundary.hit(&inner)?;
+    Some((first.t, first.t + second.t))
+}
+
+/// The `k`-th number from 0 to 1 that looks random for the ray. Media
+/// are hit while looking for the nearest object, which has no sampler
+/// at hand, so the random numbers come from the ray itself.
+fn ray_random(ray: &Ray, k: u32) -> f32 {
+    let bits = [
+        ray.origin.x, ray.origin.y, ray.origin.z,
+        ray.direction.x, ray.direction.y, ray.direction.z,
+        ray.time
+    ];
+    let mut x = k as u64;
+    for b in bits.iter().map(|b| b.to_bits() as u64) {
+        x = (x ^ b).wrapping_add(0x9E3779B97F4A7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+        x ^= x >> 31;
+    }
+    (x >> 40) as f32 / (1u64 << 24) as f32
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let (enter, exit) = inside_segment(self.boundary.as_ref(), ray)?;
+
+        let distance = -(1.0 - ray_random(ray, 0)).ln() / self.density;
+        if distance > exit - enter {
+            return None;
+        }
+
+        // The normal of a point inside a medium is meaningless, so any
+        // will do.
+        let t = enter + distance;
+        let n = Vector{ x: 1.0, y: 0.0, z: 0.0 };
+        Some(Hit::new(t, ray.at(t), n, (0.0, 0.0), self.phase.as_ref()))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.boundary.bounds()
+    }
+
+    fn prepare(&self, kind: AcceleratorKind, split: Split) {
+        self.boundary.prepare(kind, split);
+    }
+}
+
+/// Density of a medium varying from point to point.
+pub trait DensityField: Debug + Send + Sync {
+    fn density(&self, p: Vector) -> f32;
+}
+
+/// Wispy cloud-like density from turbulent noise: `density` times the
+/// turbulence of the given `frequency` and number of `octaves`.
+#[derive(Debug, Clone)]
+pub struct NoiseDensity {
+    pub noise: Arc<Perlin>,
+    pub density: f32,
+    pub frequency: f32,
+    pub octaves: usize
+}
+
+impl DensityField for NoiseDensity {
+    fn density(&self, p: Vector) -> f32 {
+        self.density * self.noise.turbulence(self.frequency * p, self.octaves)
+    }
+}
+
+/// Density sampled on a regular grid of `nx × ny × nz` points filling
+/// the box between `min` and `max` and interpolated trilinearly in
+/// between. Zero outside of the box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridDensity {
+    pub min: Vector,
+    pub max: Vector,
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub values: Vec<f32> // x changes fastest, then y, then z
+}
+
+impl GridDensity {
+    fn value(&self, i: usize, j: usize, k: usize) -> f32 {
+        self.values[(k * self.ny + j) * self.nx + i]
+    }
+}
+
+impl DensityField for GridDensity {
+    fn density(&self, p: Vector) -> f32 {
+        let size = self.max - self.min;
+        let q = p - self.min;
+
+        // Continuous grid coordinates of the point.
+        let gx = q.x / size.x * (self.nx - 1) as f32;
+        let gy = q.y / size.y * (self.ny - 1) as f32;
+        let gz = q.z / size.z * (self.nz - 1) as f32;
+
+        let inside = |g: f32, n: usize| g >= 0.0 && g <= (n - 1) as f32;
+        if !inside(gx, self.nx) || !inside(gy, self.ny) || !inside(gz, self.nz) {
+            return 0.0;
+        }
+
+        let (i, j, k) = (gx as usize, gy as usize, gz as usize);
+        let (i1, j1, k1) = ((i + 1).min(self.nx - 1), (j + 1).min(self.ny - 1), (k + 1).min(self.nz - 1));
+        let (fx, fy, fz) = (gx - i as f32, gy - j as f32, gz - k as f32);
+
+        let lerp = |a: f32, b: f32, t: f32| a + t * (b - a);
+        let x00 = lerp(self.value(i, j, k), self.value(i1, j, k), fx);
+        let x10 = lerp(self.value(i, j1, k), self.value(i1, j1, k), fx);
+        let x01 = lerp(self.value(i, j, k1), self.value(i1, j, k1), fx);
+        let x11 = lerp(self.value(i, j1, k1), self.value(i1, j1, k1), fx);
+
+        lerp(lerp(x00, x10, fy), lerp(x01, x11, fy), fz)
+    }
+}
+
+/// Participating medium with a density varying inside the boundary.
+/// Scattering distances are sampled with delta (Woodcock) tracking,
+/// which needs `max_density` to bound the density field from above.
+pub struct HeterogeneousMedium {
+    pub boundary: Box<dyn Hittable>,
+    pub field: Arc<dyn DensityField>,
+    pub max_density: f32,
+    pub phase: Arc<dyn Material>
+}
+
+impl HeterogeneousMedium {
+    /// Medium scattering light isotropically with the given color.
+    pub fn new(
+        boundary: Box<dyn Hittable>,
+        field: Arc<dyn DensityField>,
+        max_density: f32,
+        albedo: Arc<dyn Texture>
+    ) -> Self {
+        Self {
+            boundary,
+            field,
+            max_density,
+            phase: Arc::new(Isotropic{ albedo })
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let (enter, exit) = inside_segment(self.boundary.as_ref(), ray)?;
+
+        // Pretend the medium is uniformly as dense as it gets and reject
+        // the tentative collisions in proportion to the actual density.
+        let mut t = enter;
+        let mut k = 0;
+        loop {
+            t -= (1.0 - ray_random(ray, k)).ln() / self.max_density;
+            if t >= exit {
+                return None;
+            }
+
+            let p = ray.at(t);
+            if ray_random(ray, k + 1) * self.max_density < self.field.density(p) {
+                let n = Vector{ x: 1.0, y: 0.0, z: 0.0 };
+                return Some(Hit::new(t, p, n, (0.0, 0.0), self.phase.as_ref()));
+            }
+            k += 2;
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.boundary.bounds()
+    }
+
+    fn prepare(&self, kind: AcceleratorKind, split: Split) {
+        self.boundary.prepare(kind, split);
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::{Transform, Vector};
+
+use super::accelerator::Lazy;
+use super::{intersect_triangle, Aabb, Accelerator, AcceleratorKind, Hit, Hittable, Ray, Split};
+
+/// Triangle mesh sharing its vertices between faces. Each face lists
+/// the indices of its three vertices in counter-clockwise order.
+/// Texture coordinates, normals and colors, if any, are given per
+/// vertex and interpolated over the faces. The faces a ray gets tested
+/// against are picked by an acceleration structure over them, built on
+/// the first hit, so the vertices and faces should be done changing by
+/// then.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub vertices: Vec<Vector>,
+    pub faces: Vec<[usize; 3]>,
+    pub texcoords: Vec<[f32; 2]>, // Either empty or one per vertex
+    pub normals: Vec<Vector>,     // Ditto
+    pub colors: Vec<Vector>,      // Ditto, linear
+    pub material: Arc<dyn Material>,
+    pub(crate) accelerator: Lazy<Arc<dyn Accelerator>>
+}
+
+impl Mesh {
+    /// Mesh with no vertex attributes but the positions.
+    pub fn new(vertices: Vec<Vector>, faces: Vec<[usize; 3]>, material: Arc<dyn Material>) -> Self {
+        Self {
+            vertices,
+            faces,
+            texcoords: vec![],
+            normals: vec![],
+            colors: vec![],
+            material,
+            accelerator: Lazy::default()
+        }
+    }
+
+    /// Give every vertex the average normal of the faces around it,
+    /// weighted by their areas, so that a curved surface of a model
+    /// without normals looks smooth rather than faceted. Sharp edges get
+    /// rounded off as well.
+    pub fn smooth_normals(&mut self) {
+        let mut normals = vec![Vector{ x: 0.0, y: 0.0, z: 0.0 }; self.vertices.len()];
+        for face in &self.faces {
+            let [a, b, c] = face.map(|k| self.vertices[k]);
+            let n = (b - a).cross(c - a);
+            for &k in face {
+                normals[k] += n;
+            }
+        }
+        self.normals = normals.into_iter()
+            .map(|n| if n.is_near_zero() { n } else { n.unit() })
+            .collect();
+    }
+
+    /// Move the mesh by transforming its vertices in place, cheaper to
+    /// render than an instance when the mesh is not shared.
+    pub fn transform(&mut self, transform: Transform) {
+        for vertex in self.vertices.iter_mut() {
+            *vertex = transform.apply_point(*vertex);
+        }
+        for normal in self.normals.iter_mut() {
+            *normal = transform.apply_normal(*normal);
+        }
+        self.accelerator.clear();
+    }
+
+    /// Acceleration structure over the faces, built the first time it is
+    /// needed.
+    fn accelerator(&self) -> &dyn Accelerator {
+        self.accelerator.get(|kind, split| {
+            let bounds: Vec<Aabb> = self.faces.iter()
+                .map(|face| Aabb::around(&face.map(|k| self.vertices[k])))
+                .collect();
+            Arc::from(kind.build(&bounds, split))
+        }).as_ref()
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let (t, (u, v, i)) = self.accelerator().nearest(ray, f32::INFINITY, |i, t_max| {
+            let [a, b, c] = self.faces[i].map(|k| self.vertices[k]);
+            let (t, u, v) = intersect_triangle(ray, a, b, c)?;
+            (t <= t_max).then_some((t, (u, v, i)))
+        })?;
+        let face = self.faces[i];
+        let [a, b, c] = face.map(|k| self.vertices[k]);
+        let w = 1.0 - u - v;
+
+        // Interpolated normals are kept on the side of the geometric one.
+        let mut n = (b - a).cross(c - a);
+        if !self.normals.is_empty() {
+            let [na, nb, nc] = face.map(|k| self.normals[k]);
+            let shading = w * na + u * nb + v * nc;
+            if !shading.is_near_zero() {
+                n = if shading.dot(n) < 0.0 { -shading } else { shading };
+            }
+        }
+
+        // Without explicit texture coordinates the barycentric ones are
+        // used.
+        let mut hit = if self.texcoords.is_empty() {
+            Hit::new(t, ray.at(t), n, (u, v), self.material.as_ref()).with_tangent(b - a)
+        } else {
+            let [ta, tb, tc] = face.map(|k| self.texcoords[k]);
+            let uv = (
+                w * ta[0] + u * tb[0] + v * tc[0],
+                w * ta[1] + u * tb[1] + v * tc[1]
+            );
+
+            // Solve for the direction in which the u coordinate grows
+            // from the edges and their texture coordinate differences.
+            let (du1, dv1) = (tb[0] - ta[0], tb[1] - ta[1]);
+            let (du2, dv2) = (tc[0] - ta[0], tc[1] - ta[1]);
+            let det = du1 * dv2 - du2 * dv1;
+            let dpdu = if det.abs() > 1E-12 {
+                (dv2 * (b - a) - dv1 * (c - a)) / det
+            } else {
+                b - a
+            };
+
+            Hit::new(t, ray.at(t), n, uv, self.material.as_ref()).with_tangent(dpdu)
+        };
+
+        if !self.colors.is_empty() {
+            let [ca, cb, cc] = face.map(|k| self.colors[k]);
+            hit.color = w * ca + u * cb + v * cc;
+        }
+
+        Some(hit)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        (!self.faces.is_empty()).then(|| self.accelerator().bounds())
+    }
+
+    fn prepare(&self, kind: AcceleratorKind, split: Split) {
+        self.accelerator.select(kind, split);
+        self.accelerator();
+    }
+}
@@ -0,0 +1,64 @@
+use std::f32::consts::SQRT_2;
+
+use crate::math::Vector;
+
+use super::sdf::enclose;
+use super::DistanceField;
+
+/// One of the metaballs: a Gaussian bump of the field, `weight` high at
+/// the `center` and falling off over the `radius`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Metaball {
+    pub center: Vector,
+    pub radius: f32,
+    pub weight: f32 // Positive
+}
+
+/// Blobby surface where the sum of the Gaussians of the balls reaches
+/// the `threshold`: far apart, every ball is a sphere, and closing in,
+/// they swell towards each other and merge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metaballs {
+    pub balls: Vec<Metaball>,
+    pub threshold: f32 // Positive
+}
+
+impl Metaballs {
+    /// Sum of the Gaussians at the point.
+    pub fn field(&self, p: Vector) -> f32 {
+        self.balls.iter()
+            .map(|ball| ball.weight * (-(p - ball.center).sqnorm() / (ball.radius * ball.radius)).exp())
+            .sum()
+    }
+
+    /// Steepest the field can get: a Gaussian is steepest at √½ of its
+    /// radius from its center.
+    fn max_slope(&self) -> f32 {
+        self.balls.iter()
+            .map(|ball| ball.weight * SQRT_2 / ball.radius * (-0.5f32).exp())
+            .sum()
+    }
+}
+
+impl DistanceField for Metaballs {
+    /// The field is no distance, but how far it is from the threshold
+    /// over its steepest slope is never more than the distance to the
+    /// surface.
+    fn distance(&self, p: Vector) -> f32 {
+        (self.threshold - self.field(p)) / self.max_slope()
+    }
+
+    /// Wherever the sum reaches the threshold, one of the n balls makes
+    /// at least 1/n of it, which none does farther than r √ln(n w / t)
+    /// from its center.
+    fn bounds(&self) -> (Vector, f32) {
+        let n = self.balls.len() as f32;
+        self.balls.iter()
+            .map(|ball| {
+                let reach = ball.radius * (n * ball.weight / self.threshold).ln().max(0.0).sqrt();
+                (ball.center, reach)
+            })
+            .reduce(enclose)
+            .unwrap_or((Vector{ x: 0.0, y: 0.0, z: 0.0 }, 0.0))
+    }
+}
@@ -0,0 +1,231 @@
+//! Rays, intersections and the shapes that can be hit.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::material::Material;
+use crate::math::Vector;
+use crate::photon::PhotonMap;
+
+mod accelerator;
+mod bvh;
+mod cuboid;
+mod cylinder;
+mod fractal;
+mod group;
+mod heightfield;
+mod instance;
+mod kdtree;
+mod medium;
+mod mesh;
+mod metaballs;
+mod plane;
+mod sdf;
+mod sphere;
+mod triangle;
+mod voxel;
+
+use accelerator::{Lazy, TopLevel};
+
+pub use accelerator::{Accelerator, AcceleratorKind};
+pub use bvh::{Aabb, Bvh, Split};
+pub use cuboid::Cuboid;
+pub use cylinder::{Cone, Cylinder};
+pub use fractal::{Julia, Mandelbulb};
+pub use group::Group;
+pub use heightfield::Heightfield;
+pub use instance::Instance;
+pub use kdtree::KdTree;
+pub use medium::{inside_segment, ConstantMedium, DensityField, GridDensity, HeterogeneousMedium, NoiseDensity};
+pub use mesh::Mesh;
+pub use metaballs::{Metaball, Metaballs};
+pub use plane::{Plane, Quad};
+pub use sdf::{DistanceField, Sdf, SdfBox, SdfSphere, SdfTorus, SmoothDifference, SmoothIntersection, SmoothUnion};
+pub use sphere::{MovingSphere, Sphere};
+pub use triangle::{intersect_triangle, Triangle};
+pub use voxel::VoxelGrid;
+
+/// Minimal ray abstraction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Vector,
+    pub direction: Vector,
+    pub time: f32 // Moment within the shutter interval the ray exists at
+}
+
+impl Ray {
+    pub fn new(origin: Vector, direction: Vector) -> Self {
+        Self {
+            origin,
+            direction: direction.unit(),
+            time: 0.0
+        }
+    }
+
+    pub fn with_time(self, time: f32) -> Self {
+        Self { time, ..self }
+    }
+
+    pub fn at(self, t: f32) -> Vector {
+        self.origin + t * self.direction
+    }
+}
+
+/// Geometry
+#[derive(Debug, Copy, Clone)]
+pub struct Hit<'a> {
+    pub t: f32,    // Distance along the ray to the intersection with the shape
+    pub p: Vector, // Cartesian coordinates of the intersection
+    pub n: Vector, // Outer surface normal at the intersection
+    pub u: f32,    // Texture coordinates of the intersection
+    pub v: f32,
+    pub tangent: Vector, // Unit tangent in the direction of growing u
+    pub color: Vector,   // Vertex color tinting the material, white if there is none
+    pub material: &'a dyn Material, // Material of the surface that was hit
+    pub object: usize, // Index of the object of the world that was hit
+}
+
+impl<'a> Hit<'a> {
+    pub fn new(t: f32, p: Vector, n: Vector, (u, v): (f32, f32), material: &'a dyn Material) -> Self {
+        let n = n.unit();
+        Self {
+            t,
+            p,
+            n,
+            u,
+            v,
+            tangent: n.basis().0,
+            color: Vector{ x: 1.0, y: 1.0, z: 1.0 },
+            material,
+            object: 0
+        }
+    }
+
+    /// Replace the arbitrary default tangent with the actual direction
+    /// in which the u texture coordinate grows, projected onto the
+    /// tangent plane.
+    pub fn with_tangent(self, dpdu: Vector) -> Self {
+        let tangent = dpdu - self.n.dot(dpdu) * self.n;
+        if tangent.is_near_zero() {
+            return self;
+        }
+
+        Self { tangent: tangent.unit(), ..self }
+    }
+
+    /// Tangent frame of the hit: tangent, bitangent and normal.
+    pub fn frame(&self) -> (Vector, Vector, Vector) {
+        (self.tangent, self.n.cross(self.tangent), self.n)
+    }
+
+    /// Surface normal on the side the ray came from.
+    pub fn facing_normal(&self, ray: &Ray) -> Vector {
+        if ray.direction.dot(self.n) < 0.0 { self.n } else { -self.n }
+    }
+}
+
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>>;
+
+    /// Box the object fits in, for the acceleration structure of the
+    /// world. Objects with none, such as planes, are tried against every
+    /// ray.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// Build the acceleration structures of the object of the kind, the
+    /// nodes of a BVH split the given way, before the rays come. Till
+    /// then, they are built on the first hit, BVHs split the default way.
+    fn prepare(&self, _kind: AcceleratorKind, _split: Split) {}
+}
+
+/// Shared objects, such as the ones placed several times with
+/// instancing, can be put into the world directly.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.as_ref().hit(ray)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.as_ref().bounds()
+    }
+
+    fn prepare(&self, kind: AcceleratorKind, split: Split) {
+        self.as_ref().prepare(kind, split)
+    }
+}
+
+#[derive(Default)]
+pub struct World {
+    pub objects: Vec<Box<dyn Hittable>>,
+    pub lights: Vec<Box<dyn Light>>,
+    pub emitters: HashMap<usize, usize>, // Lights sampling the glowing objects, by the index of the object
+    pub caustics: Option<PhotonMap>,
+    pub background: Background,
+    top: Lazy<TopLevel>, // Built on the first hit, once all the objects are in
+    hittable: OnceLock<Vec<bool>> // Whether every light is in `emitters`, found out on the first question
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            objects: vec![],
+            lights: vec![],
+            emitters: HashMap::new(),
+            caustics: None,
+            background: Background::default(),
+            top: Lazy::default(),
+            hittable: OnceLock::new()
+        }
+    }
+
+    /// Whether the light samples one of the glowing objects, which the
+    /// bounces can run into as well.
+    pub fn is_hittable(&self, light: usize) -> bool {
+        let hittable = self.hittable.get_or_init(|| {
+            let mut hittable = vec![false; self.lights.len()];
+            for &l in self.emitters.values() {
+                hittable[l] = true;
+            }
+            hittable
+        });
+        hittable[light]
+    }
+
+    fn top(&self) -> &TopLevel {
+        self.top.get(|kind, split| TopLevel::new(&self.objects, kind, split))
+    }
+
+    /// Shadow ray query: is there anything between `p` and the point
+    /// `distance` away from it along `direction` at the given moment?
+    pub fn is_occluded(&self, p: Vector, direction: Vector, distance: f32, time: f32) -> bool {
+        let eps = 1E-3;
+        let ray = Ray::new(p, direction).with_time(time);
+
+        self.top().hit(&self.objects, &ray, distance - eps).is_some()
+    }
+
+    /// What is seen through the point (u, v) of the viewport of the
+    /// camera, both coordinates ranging from 0 to 1.
+    pub fn pick(&self, camera: &Camera, u: f32, v: f32) -> Option<Hit<'_>> {
+        self.hit(&camera.center_ray(u, v)?)
+    }
+}
+
+impl Hittable for World {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.top().hit(&self.objects, ray, f32::INFINITY)
+    }
+
+    fn prepare(&self, kind: AcceleratorKind, split: Split) {
+        for obj in &self.objects {
+            obj.prepare(kind, split);
+        }
+        self.top.select(kind, split);
+        self.top();
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::{Aabb, Hit, Hittable, Ray};
+
+/// Distance along the ray to the plane through `point` with normal `n`.
+fn intersect_plane(ray: &Ray, point: Vector, n: Vector) -> Option<f32> {
+    let eps = 1E-3;
+
+    let denominator = n.dot(ray.direction);
+    if denominator.abs() < 1E-8 {
+        return None;
+    }
+
+    let t = n.dot(point - ray.origin) / denominator;
+    if t < eps {
+        return None;
+    }
+
+    Some(t)
+}
+
+/// Infinite plane going through `point`. The outer side is the one the
+/// `normal` points to.
+#[derive(Debug, Clone)]
+pub struct Plane {
+    pub point: Vector,
+    pub normal: Vector,
+    pub material: Arc<dyn Material>
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let t = intersect_plane(ray, self.point, self.normal)?;
+        let p = ray.at(t);
+
+        // Texture coordinates are the unbounded coordinates in the plane,
+        // so the textures that repeat themselves tile it.
+        let (e1, e2) = self.normal.unit().basis();
+        let d = p - self.point;
+
+        Some(Hit::new(t, p, self.normal, (d.dot(e1), d.dot(e2)), self.material.as_ref()).with_tangent(e1))
+    }
+}
+
+/// Parallelogram spanned by the edges `u` and `v` from the `corner`.
+/// The outer side is the one `u × v` points to, so a rectangle seen
+/// with `u` to the right and `v` upwards faces the viewer.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    pub corner: Vector,
+    pub u: Vector,
+    pub v: Vector,
+    pub material: Arc<dyn Material>
+}
+
+impl Quad {
+    /// Coordinates of a point on the plane of the quad in the basis of
+    /// its edges.
+    pub fn coordinates(&self, p: Vector) -> (f32, f32) {
+        let n = self.u.cross(self.v);
+        let w = n / n.sqnorm();
+        let d = p - self.corner;
+        (w.dot(d.cross(self.v)), w.dot(self.u.cross(d)))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let n = self.u.cross(self.v);
+        let t = intersect_plane(ray, self.corner, n)?;
+        let p = ray.at(t);
+
+        let (a, b) = self.coordinates(p);
+        if !(0.0 ..= 1.0).contains(&a) || !(0.0 ..= 1.0).contains(&b) {
+            return None;
+        }
+
+        Some(Hit::new(t, p, n, (a, b), self.material.as_ref()).with_tangent(self.u))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let c = self.corner;
+        Some(Aabb::around(&[c, c + self.u, c + self.v, c + self.u + self.v]))
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::{Aabb, Hit, Hittable, Ray, Sphere};
+
+/// Most steps a ray takes towards the surface before giving up.
+const MAX_STEPS: usize = 512;
+
+/// How close to the surface a ray has to get to hit it.
+const HIT_DISTANCE: f32 = 1E-4;
+
+/// Shape given by the distance from any point to its surface, negative
+/// inside. The distance may be underestimated, which only slows the
+/// sphere tracing down, but never overestimated.
+pub trait DistanceField: Debug + Send + Sync {
+    fn distance(&self, p: Vector) -> f32;
+
+    /// Center and radius of a sphere the shape fits in.
+    fn bounds(&self) -> (Vector, f32);
+}
+
+/// Object whose surface is where the distance field is zero, found by
+/// sphere tracing: stepping along the ray by the distance to the
+/// nearest surface, which can't be overshot, till it gets close enough.
+#[derive(Debug, Clone)]
+pub struct Sdf {
+    pub field: Arc<dyn DistanceField>,
+    pub material: Arc<dyn Material>
+}
+
+impl Sdf {
+    /// Outward normal at a point of the surface, the gradient of the
+    /// field taken by central differences.
+    fn normal(&self, p: Vector) -> Vector {
+        let h = 1E-4;
+        let d = |dp: Vector| self.field.distance(p + dp) - self.field.distance(p - dp);
+        Vector{
+            x: d(Vector{ x: h, y: 0.0, z: 0.0 }),
+            y: d(Vector{ x: 0.0, y: h, z: 0.0 }),
+            z: d(Vector{ x: 0.0, y: 0.0, z: h })
+        }
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let eps = 1E-3;
+
+        // Part of the ray inside the bounding sphere.
+        let (center, radius) = self.field.bounds();
+        let o = ray.origin - center;
+        let b = ray.direction.dot(o);
+        let discriminant = b * b - o.sqnorm() + radius * radius;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t0 = (-b - discriminant.sqrt()).max(eps);
+        let t1 = -b + discriminant.sqrt();
+
+        // A ray starting inside, refracted into the shape, marches out
+        // by the distance of the opposite sign.
+        let mut t = t0;
+        let side = self.field.distance(ray.at(t)).signum();
+        for _ in 0 .. MAX_STEPS {
+            if t > t1 {
+                return None;
+            }
+
+            let d = side * self.field.distance(ray.at(t));
+            if d < HIT_DISTANCE {
+                let p = ray.at(t);
+                let n = self.normal(p);
+                return Some(Hit::new(t, p, n, Sphere::uv(n.unit()), self.material.as_ref()));
+            }
+            t += d;
+        }
+
+        None
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let (center, radius) = self.field.bounds();
+        Some(Aabb::around(&[center]).expand(radius))
+    }
+}
+
+/// Ball of the `radius` around the `center`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdfSphere {
+    pub center: Vector,
+    pub radius: f32
+}
+
+impl DistanceField for SdfSphere {
+    fn distance(&self, p: Vector) -> f32 {
+        (p - self.center).norm() - self.radius
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        (self.center, self.radius)
+    }
+}
+
+/// Box along the axes around the `center`, reaching `half_size` from it
+/// along every axis, its edges rounded off by the `rounding` radius.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdfBox {
+    pub center: Vector,
+    pub half_size: Vector,
+    pub rounding: f32
+}
+
+impl DistanceField for SdfBox {
+    fn distance(&self, p: Vector) -> f32 {
+        let p = p - self.center;
+        let r = self.rounding;
+        let q = Vector{
+            x: p.x.abs() - self.half_size.x + r,
+            y: p.y.abs() - self.half_size.y + r,
+            z: p.z.abs() - self.half_size.z + r
+        };
+        let outside = Vector{ x: q.x.max(0.0), y: q.y.max(0.0), z: q.z.max(0.0) };
+        outside.norm() + q.x.max(q.y).max(q.z).min(0.0) - r
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        (self.center, self.half_size.norm())
+    }
+}
+
+/// Ring around the `center` in the xz plane: a tube of the `minor`
+/// radius around a circle of the `major` one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdfTorus {
+    pub center: Vector,
+    pub major: f32,
+    pub minor: f32
+}
+
+impl DistanceField for SdfTorus {
+    fn distance(&self, p: Vector) -> f32 {
+        let p = p - self.center;
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major;
+        (ring * ring + p.y * p.y).sqrt() - self.minor
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        (self.center, self.major + self.minor)
+    }
+}
+
+/// Minimum blended over the `smoothness` distance (the polynomial
+/// smooth minimum), the plain one for no smoothness.
+fn smooth_min(a: f32, b: f32, smoothness: f32) -> f32 {
+    if smoothness <= 0.0 {
+        return a.min(b);
+    }
+    let h = (smoothness - (a - b).abs()).max(0.0) / smoothness;
+    a.min(b) - 0.25 * smoothness * h * h
+}
+
+/// Sphere holding both bounding spheres.
+pub(crate) fn enclose((c1, r1): (Vector, f32), (c2, r2): (Vector, f32)) -> (Vector, f32) {
+    let d = (c2 - c1).norm();
+    if d + r2 <= r1 {
+        return (c1, r1);
+    }
+    if d + r1 <= r2 {
+        return (c2, r2);
+    }
+    let radius = 0.5 * (d + r1 + r2);
+    (c1 + (radius - r1) / d * (c2 - c1), radius)
+}
+
+/// Both shapes, melting into each other where they are within the
+/// `smoothness` distance.
+#[derive(Debug, Clone)]
+pub struct SmoothUnion {
+    pub a: Arc<dyn DistanceField>,
+    pub b: Arc<dyn DistanceField>,
+    pub smoothness: f32
+}
+
+impl DistanceField for SmoothUnion {
+    fn distance(&self, p: Vector) -> f32 {
+        smooth_min(self.a.distance(p), self.b.distance(p), self.smoothness)
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        let (center, radius) = enclose(self.a.bounds(), self.b.bounds());
+        (center, radius + self.smoothness)
+    }
+}
+
+/// What the shapes share, its edges rounded over the `smoothness`
+/// distance.
+#[derive(Debug, Clone)]
+pub struct SmoothIntersection {
+    pub a: Arc<dyn DistanceField>,
+    pub b: Arc<dyn DistanceField>,
+    pub smoothness: f32
+}
+
+impl DistanceField for SmoothIntersection {
+    fn distance(&self, p: Vector) -> f32 {
+        -smooth_min(-self.a.distance(p), -self.b.distance(p), self.smoothness)
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        let (a, b) = (self.a.bounds(), self.b.bounds());
+        if a.1 < b.1 { a } else { b }
+    }
+}
+
+/// The first shape with the second one carved out of it, the edges of
+/// the cut rounded over the `smoothness` distance.
+#[derive(Debug, Clone)]
+pub struct SmoothDifference {
+    pub a: Arc<dyn DistanceField>,
+    pub b: Arc<dyn DistanceField>,
+    pub smoothness: f32
+}
+
+impl DistanceField for SmoothDifference {
+    fn distance(&self, p: Vector) -> f32 {
+        -smooth_min(-self.a.distance(p), self.b.distance(p), self.smoothness)
+    }
+
+    fn bounds(&self) -> (Vector, f32) {
+        self.a.bounds()
+    }
+}
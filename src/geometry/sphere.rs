@@ -0,0 +1,103 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::{Aabb, Hit, Hittable, Ray};
+
+#[derive(Debug, Clone)]
+pub struct Sphere {
+    pub center: Vector,
+    pub radius: f32,
+    pub material: Arc<dyn Material>
+}
+
+impl Sphere {
+    /// Texture coordinates of a point on the unit sphere: u goes around
+    /// the y axis starting from -x, v goes from the south pole up.
+    pub fn uv(n: Vector) -> (f32, f32) {
+        let theta = (-n.y).clamp(-1.0, 1.0).acos();
+        let phi = (-n.z).atan2(n.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+}
+
+/// Nearest intersection of the ray with a sphere.
+fn hit_sphere<'a>(ray: &Ray, center: Vector, radius: f32, material: &'a dyn Material) -> Option<Hit<'a>> {
+    let eps = 1E-3;
+
+    let o = ray.origin - center;
+    let b = ray.direction.dot(o);
+    let c = o.sqnorm() - radius * radius;
+    let discriminant = b * b - c;
+
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let d = discriminant.sqrt();
+
+    let t1 = - b + d;
+    let t2 = - b - d;
+
+    if t1 < eps && t2 < eps {
+        return None;
+    }
+
+    let t: f32 = match (t1 >= eps, t2 >= eps) {
+        (false, true) => t2,
+        (true, false) => t1,
+        (true, true)  => t1.min(t2),
+        _ => unreachable!()
+    };
+
+    // Dividing by the radius instead of normalizing flips the normals
+    // of a sphere with a negative radius inwards.
+    let p = ray.at(t);
+    let n = (p - center) / radius;
+
+    // The u coordinate grows going around the y axis.
+    let dpdu = Vector{ x: n.z, y: 0.0, z: -n.x };
+
+    Some(Hit::new(t, p, n, Sphere::uv(n), material).with_tangent(dpdu))
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        hit_sphere(ray, self.center, self.radius, self.material.as_ref())
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[self.center]).expand(self.radius))
+    }
+}
+
+/// Sphere moving with a constant velocity from `center0` at `time0` to
+/// `center1` at `time1`.
+#[derive(Debug, Clone)]
+pub struct MovingSphere {
+    pub center0: Vector,
+    pub center1: Vector,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+    pub material: Arc<dyn Material>
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f32) -> Vector {
+        let s = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        hit_sphere(ray, self.center(ray.time), self.radius, self.material.as_ref())
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[self.center0, self.center1]).expand(self.radius))
+    }
+}
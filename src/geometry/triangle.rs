@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::{Aabb, Hit, Hittable, Ray};
+
+/// Möller–Trumbore ray-triangle intersection. Returns the distance
+/// along the ray together with the barycentric coordinates (u, v) of
+/// the intersection relative to the vertices `b` and `c`.
+pub fn intersect_triangle(ray: &Ray, a: Vector, b: Vector, c: Vector) -> Option<(f32, f32, f32)> {
+    let eps = 1E-3;
+
+    let e1 = b - a;
+    let e2 = c - a;
+
+    let p = ray.direction.cross(e2);
+    let det = e1.dot(p);
+
+    // The ray is parallel to the plane of the triangle.
+    if det.abs() < 1E-8 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = inv_det * s.dot(p);
+    if !(0.0 ..= 1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(e1);
+    let v = inv_det * ray.direction.dot(q);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = inv_det * e2.dot(q);
+    if t < eps {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+/// A single triangle. The outer side is the one from which the vertices
+/// go counter-clockwise.
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    pub a: Vector,
+    pub b: Vector,
+    pub c: Vector,
+    pub material: Arc<dyn Material>
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let (t, u, v) = intersect_triangle(ray, self.a, self.b, self.c)?;
+        let n = (self.b - self.a).cross(self.c - self.a);
+        Some(Hit::new(t, ray.at(t), n, (u, v), self.material.as_ref()).with_tangent(self.b - self.a))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[self.a, self.b, self.c]))
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::{Aabb, Hit, Hittable, Ray};
+
+/// Dense grid of unit cubes filling the box from the origin to
+/// `(nx, ny, nz)`. Every voxel stores the index of its material, zero
+/// standing for an empty one.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub voxels: Vec<u8>, // x varies fastest, then y, then z
+    pub materials: Vec<Arc<dyn Material>> // Indexed by the voxel values
+}
+
+impl VoxelGrid {
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.voxels[(z * self.ny + y) * self.nx + x]
+    }
+}
+
+/// Coordinate of the vector along the axis numbered from 0 for x.
+pub(crate) fn axis(v: Vector, a: usize) -> f32 {
+    match a {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z
+    }
+}
+
+/// Vector of the `length` along the axis numbered from 0 for x.
+pub(crate) fn unit(a: usize, length: f32) -> Vector {
+    match a {
+        0 => Vector{ x: length, y: 0.0, z: 0.0 },
+        1 => Vector{ x: 0.0, y: length, z: 0.0 },
+        _ => Vector{ x: 0.0, y: 0.0, z: length }
+    }
+}
+
+impl Hittable for VoxelGrid {
+    /// Walk the voxels pierced by the ray one by one (Amanatides and Woo)
+    /// till the first filled one.
+    fn hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let eps = 1E-3;
+        let size = [self.nx as i64, self.ny as i64, self.nz as i64];
+
+        // Part of the ray inside the bounding box.
+        let mut t0 = 0.0f32;
+        let mut t1 = f32::INFINITY;
+        let mut entry_axis = None;
+        for (a, &n) in size.iter().enumerate() {
+            let (o, d) = (axis(ray.origin, a), axis(ray.direction, a));
+            if d == 0.0 {
+                if o < 0.0 || o > n as f32 {
+                    return None;
+                }
+                continue;
+            }
+
+            let (near, far) = if d > 0.0 { (-o / d, (n as f32 - o) / d) } else { ((n as f32 - o) / d, -o / d) };
+            if near > t0 {
+                t0 = near;
+                entry_axis = Some(a);
+            }
+            t1 = t1.min(far);
+        }
+        if t1 <= t0 {
+            return None;
+        }
+
+        // Nudging the starting point along the ray settles which of the
+        // voxels sharing a face the ray goes through.
+        let start = ray.at(t0 + 1E-4);
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut t_next = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for a in 0 .. 3 {
+            let (o, d) = (axis(ray.origin, a), axis(ray.direction, a));
+            cell[a] = (axis(start, a).floor() as i64).clamp(0, size[a] - 1);
+            if d != 0.0 {
+                step[a] = if d > 0.0 { 1 } else { -1 };
+                let boundary = (cell[a] + if d > 0.0 { 1 } else { 0 }) as f32;
+                t_next[a] = (boundary - o) / d;
+                t_delta[a] = 1.0 / d.abs();
+            }
+        }
+
+        let mut t = t0;
+        let mut face_axis = entry_axis;
+        loop {
+            let value = self.get(cell[0] as usize, cell[1] as usize, cell[2] as usize);
+            if value != 0 && t >= eps {
+                if let Some(a) = face_axis {
+                    let p = ray.at(t);
+                    let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+                    let n = unit(a, -step[a] as f32);
+                    let uv = (axis(p, b).rem_euclid(1.0), axis(p, c).rem_euclid(1.0));
+                    let material = self.materials[value as usize].as_ref();
+                    return Some(Hit::new(t, p, n, uv, material).with_tangent(unit(b, 1.0)));
+                }
+            }
+
+            let a = (0 .. 3)
+                .min_by(|&i, &j| t_next[i].total_cmp(&t_next[j]))
+                .unwrap();
+            if t_next[a] > t1 {
+                return None;
+            }
+
+            t = t_next[a];
+            cell[a] += step[a];
+            if cell[a] < 0 || cell[a] >= size[a] {
+                return None;
+            }
+            t_next[a] += t_delta[a];
+            face_axis = Some(a);
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let size = Vector{ x: self.nx as f32, y: self.ny as f32, z: self.nz as f32 };
+        Some(Aabb::around(&[Vector{ x: 0.0, y: 0.0, z: 0.0 }, size]))
+    }
+}
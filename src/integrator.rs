@@ -0,0 +1,211 @@
+//! Integrators: the algorithms finding the light that arrives at the
+//! camera along a ray.
+//!
+//! Besides the ones meant for the final image, there are cheaper ones
+//! showing only part of the light, and ones showing the geometry
+//! itself, for finding out what is wrong with a scene.
+
+use std::str::FromStr;
+
+use crate::bdpt::bdpt_color;
+use crate::geometry::{Hittable, Ray, World};
+use crate::math::Vector;
+use crate::microfacet::sample_cosine;
+use crate::render::{direct_light, ray_color};
+use crate::sampler::Sampler;
+
+/// Algorithm finding the light arriving along the camera rays.
+pub trait Integrator {
+    /// Light arriving along the ray.
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut dyn Sampler) -> Vector;
+}
+
+/// Paths from the camera bouncing at most `max_depth` times, see
+/// `ray_color`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PathTracer {
+    pub max_depth: u8,
+    pub transparent: bool // Whether the background is black where the camera sees it, still lighting the scene
+}
+
+impl Integrator for PathTracer {
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut dyn Sampler) -> Vector {
+        ray_color(ray, world, self.max_depth, self.transparent, sampler)
+    }
+}
+
+/// Paths from the camera and from the lights joined together, see the
+/// `bdpt` module.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BidirectionalPathTracer {
+    pub max_depth: u8,
+    pub transparent: bool // As for `PathTracer`
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut dyn Sampler) -> Vector {
+        bdpt_color(ray, world, self.max_depth, self.transparent, sampler)
+    }
+}
+
+/// Light coming straight from the lights, and from the glowing objects
+/// seen directly, with no bounces off diffuse surfaces in between.
+/// Mirrors and glass are still followed, at most `max_depth` times, so
+/// that they don't come out black.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DirectLighting {
+    pub max_depth: u8,
+    pub transparent: bool // As for `PathTracer`
+}
+
+impl Integrator for DirectLighting {
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut dyn Sampler) -> Vector {
+        let mut color = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+        let mut throughput = Vector{ x: 1.0, y: 1.0, z: 1.0 };
+        let mut ray = *ray;
+
+        for bounce in 0 .. self.max_depth {
+            let h = match world.hit(&ray) {
+                Some(h) => h,
+                None => {
+                    if !(self.transparent && bounce == 0) {
+                        color += throughput * world.background.color(&ray);
+                    }
+                    break;
+                }
+            };
+
+            color += throughput * h.material.emitted(&ray, &h);
+            if !h.material.is_specular() {
+                color += throughput * direct_light(&ray, &h, world, false, sampler);
+                break;
+            }
+
+            let s = match h.material.scatter(&ray, &h, sampler) {
+                Some(s) => s,
+                None => break
+            };
+            throughput = throughput * s.attenuation;
+            ray = s.ray;
+        }
+
+        color
+    }
+}
+
+/// Ambient occlusion: how much of the hemisphere above the first
+/// surface hit is not blocked by the objects within `distance` of it,
+/// weighted by the cosine, as a shade of gray. Shows the shapes of the objects without
+/// any lights, and the shading to bake into them. Where nothing is hit,
+/// it is white, or black if `transparent`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AmbientOcclusion {
+    pub distance: f32,
+    pub transparent: bool
+}
+
+impl Integrator for AmbientOcclusion {
+    fn li(&self, ray: &Ray, world: &World, sampler: &mut dyn Sampler) -> Vector {
+        let h = match world.hit(ray) {
+            Some(h) => h,
+            None if self.transparent => return Vector{ x: 0.0, y: 0.0, z: 0.0 },
+            None => return Vector{ x: 1.0, y: 1.0, z: 1.0 }
+        };
+
+        // Picking the directions as often as the cosine weighs them
+        // leaves every one that is not blocked to count the same.
+        let direction = sample_cosine(h.facing_normal(ray), sampler);
+        if world.is_occluded(h.p, direction, self.distance, ray.time) {
+            Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        } else {
+            Vector{ x: 1.0, y: 1.0, z: 1.0 }
+        }
+    }
+}
+
+/// Shading normal of the first surface hit, mapped from -1 .. 1 to the
+/// colors 0 .. 1. Black where nothing is hit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn li(&self, ray: &Ray, world: &World, _sampler: &mut dyn Sampler) -> Vector {
+        match world.hit(ray) {
+            Some(h) => 0.5 * (h.material.shading_normal(&h) + Vector{ x: 1.0, y: 1.0, z: 1.0 }),
+            None => Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        }
+    }
+}
+
+/// Distance to the first surface hit as a shade of gray, white up close
+/// and fading out as 1 / (1 + distance). Black where nothing is hit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Depth;
+
+impl Integrator for Depth {
+    fn li(&self, ray: &Ray, world: &World, _sampler: &mut dyn Sampler) -> Vector {
+        match world.hit(ray) {
+            Some(h) => {
+                let shade = 1.0 / (1.0 + h.t);
+                Vector{ x: shade, y: shade, z: shade }
+            },
+            None => Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        }
+    }
+}
+
+/// Texture coordinates of the first surface hit as the red and green
+/// colors, wrapped to 0 .. 1 so that tiled textures show their tiles.
+/// Black where nothing is hit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Uvs;
+
+impl Integrator for Uvs {
+    fn li(&self, ray: &Ray, world: &World, _sampler: &mut dyn Sampler) -> Vector {
+        match world.hit(ray) {
+            Some(h) => Vector{ x: h.u.rem_euclid(1.0), y: h.v.rem_euclid(1.0), z: 0.0 },
+            None => Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        }
+    }
+}
+
+/// Which integrator to render with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum IntegratorKind {
+    /// See `PathTracer`.
+    #[default]
+    Path,
+    /// See `BidirectionalPathTracer`.
+    Bidirectional,
+    /// Paths from the camera explored by Markov chains, see the `mlt`
+    /// module.
+    Metropolis,
+    /// See `DirectLighting`.
+    Direct,
+    /// See `AmbientOcclusion`.
+    AmbientOcclusion,
+    /// See `Normals`.
+    Normals,
+    /// See `Depth`.
+    Depth,
+    /// See `Uvs`.
+    Uvs
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(IntegratorKind::Path),
+            "bdpt" => Ok(IntegratorKind::Bidirectional),
+            "mlt" => Ok(IntegratorKind::Metropolis),
+            "direct" => Ok(IntegratorKind::Direct),
+            "ao" => Ok(IntegratorKind::AmbientOcclusion),
+            "normals" => Ok(IntegratorKind::Normals),
+            "depth" => Ok(IntegratorKind::Depth),
+            "uvs" => Ok(IntegratorKind::Uvs),
+            _ => Err(format!("unknown integrator {}, expected path, bdpt, mlt, direct, ao, normals, depth or uvs", s))
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Camera lenses made of real elements: spherical glass surfaces and an
+//! aperture stop, which the camera rays are refracted through on their
+//! way from the film to the scene.
+//!
+//! Unlike the ideal thin lens, such a lens blocks some of the rays near
+//! the edges of the image, which darkens the corners (the vignetting),
+//! and doesn't bring all of the rays from a point to a point, which blurs
+//! and distorts the image a bit (the aberrations).
+//!
+//! The elements are listed the way lens prescriptions are, from the front
+//! of the lens to the back, with the lengths in millimeters. Inside, the
+//! film is at z = 0 and the lens in front of it at negative z.
+
+use crate::math::Vector;
+
+/// Most elements a lens can have, so that cameras can be copied around.
+pub const MAX_LENS_ELEMENTS: usize = 16;
+
+/// Height of the film in millimeters unless told otherwise, that of the
+/// 35 mm film.
+pub const SENSOR_HEIGHT: f32 = 24.0;
+
+/// Millimeters in a unit of the scene, which is taken to be a meter.
+const MM_PER_UNIT: f32 = 1000.0;
+
+/// One surface of the lens.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct LensElement {
+    pub radius: f32,    // Radius of the curvature, positive with the center behind; 0 for the aperture stop
+    pub thickness: f32, // Distance along the axis to the next surface
+    pub ior: f32,       // Refractive index of what is behind the surface, 0 or 1 for the air
+    pub aperture: f32   // Diameter
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.radius == 0.0
+    }
+
+    /// Refractive index behind the surface.
+    fn ior(&self) -> f32 {
+        if self.ior == 0.0 { 1.0 } else { self.ior }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lens {
+    elements: [LensElement; MAX_LENS_ELEMENTS],
+    count: usize,
+    sensor_height: f32,
+    film_distance: f32 // From the back of the lens to the film
+}
+
+impl Lens {
+    /// Lens of `elements` from the front to the back in front of a film
+    /// `sensor_height` millimeters high, focused at infinity. The
+    /// thickness of the last element is ignored, as it is the distance to
+    /// the film.
+    pub fn new(elements: &[LensElement], sensor_height: f32) -> Result<Self, String> {
+        if elements.is_empty() || elements.len() > MAX_LENS_ELEMENTS {
+            return Err(format!("a lens has 1 to {} elements, not {}", MAX_LENS_ELEMENTS, elements.len()));
+        }
+
+        let mut lens = Self {
+            elements: [LensElement::default(); MAX_LENS_ELEMENTS],
+            count: elements.len(),
+            sensor_height,
+            film_distance: 0.0
+        };
+        lens.elements[.. elements.len()].copy_from_slice(elements);
+        let (_, _, focal_point, _) = lens.cardinal_points().ok_or("the lens doesn't focus")?;
+        lens.film_distance = focal_point;
+        Ok(lens)
+    }
+
+    /// The 50 mm f/2 double Gauss lens of Tronnier's patent.
+    pub fn double_gauss() -> Self {
+        const ELEMENTS: [(f32, f32, f32, f32); 11] = [
+            (29.475, 3.76, 1.67, 25.2),
+            (84.83, 0.12, 1.0, 25.2),
+            (19.275, 4.025, 1.67, 23.0),
+            (40.77, 3.275, 1.699, 23.0),
+            (12.75, 5.705, 1.0, 18.0),
+            (0.0, 4.5, 0.0, 17.1),
+            (-14.495, 1.18, 1.603, 17.0),
+            (40.77, 6.065, 1.658, 20.0),
+            (-20.385, 0.19, 1.0, 20.0),
+            (437.065, 3.22, 1.717, 20.0),
+            (-39.73, 0.0, 1.0, 20.0)
+        ];
+
+        let elements: Vec<LensElement> = ELEMENTS.iter()
+            .map(|&(radius, thickness, ior, aperture)| LensElement { radius, thickness, ior, aperture })
+            .collect();
+        Self::new(&elements, SENSOR_HEIGHT).unwrap()
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements[.. self.count]
+    }
+
+    pub fn sensor_height(&self) -> f32 {
+        self.sensor_height
+    }
+
+    /// The lens moved away from the film so that what is `distance` in
+    /// front of the film is sharp.
+    pub fn focused(self, distance: f32) -> Self {
+        let at_infinity = Self { film_distance: 0.0, ..self };
+        let (principal_plane, object_plane, focal_point, focal_length) = match at_infinity.cardinal_points() {
+            Some(points) => points,
+            None => return self
+        };
+
+        // With the back of the lens at the origin for now, the film at z
+        // and the object at z - distance, the object is `s` in front of
+        // its principal plane and its image `focal_length * s / (s -
+        // focal_length)` behind the other one. Requiring that image to be
+        // on the film gives a quadratic equation for s, whose larger root
+        // is the lens close to the film. Nearer than four focal lengths,
+        // nothing can be focused and the lens goes as far as it gets.
+        let a = object_plane + MM_PER_UNIT * distance - principal_plane;
+        let discriminant = (a * a - 4.0 * a * focal_length).max(0.0);
+        let s = (a + discriminant.sqrt()) / 2.0;
+        let z = object_plane + MM_PER_UNIT * distance - s;
+
+        Self { film_distance: z.max(focal_point), ..self }
+    }
+
+    /// The principal plane and the focal point behind the lens, the
+    /// principal plane in front of it and the focal length, with the back
+    /// of the lens at z = 0, found by tracing rays close to the axis.
+    fn cardinal_points(&self) -> Option<(f32, f32, f32, f32)> {
+        let height = 1E-3 * self.sensor_height;
+        let front: f32 = self.elements().iter().map(|e| e.thickness).sum::<f32>()
+            - self.elements()[self.count - 1].thickness;
+
+        // A ray parallel to the axis from the scene crosses the axis at
+        // the focal point and the ray it came in as at the principal
+        // plane. And the same the other way round.
+        let (o, d) = self.trace_from_scene(
+            Vector{ x: height, y: 0.0, z: -front - 1.0 },
+            Vector{ x: 0.0, y: 0.0, z: 1.0 }
+        )?;
+        let focal_point = o.z - o.x / d.x * d.z;
+        let principal_plane = o.z + (height - o.x) / d.x * d.z;
+
+        let (o, d) = self.trace_from_film(
+            Vector{ x: height, y: 0.0, z: 1.0 },
+            Vector{ x: 0.0, y: 0.0, z: -1.0 }
+        )?;
+        let object_plane = o.z + (height - o.x) / d.x * d.z;
+
+        let focal_length = focal_point - principal_plane;
+        if focal_length.is_finite() && focal_length > 0.0 {
+            Some((principal_plane, object_plane, focal_point, focal_length))
+        } else {
+            None
+        }
+    }
+
+    /// Distance along the axis from the element `i` to the next one, or
+    /// to the film for the last one.
+    fn thickness(&self, i: usize) -> f32 {
+        if i + 1 == self.count { self.film_distance } else { self.elements[i].thickness }
+    }
+
+    /// Where the ray from `o` in the direction `d` crosses the element
+    /// with the vertex at `z`, and the normal there facing against it.
+    fn intersect(element: &LensElement, z: f32, o: Vector, d: Vector) -> Option<(Vector, Vector)> {
+        let (t, n) = if element.is_stop() {
+            ((z - o.z) / d.z, -d)
+        } else {
+            // Of the two intersections with the sphere, the one on the
+            // same side of the center as the vertex.
+            let center = Vector{ x: 0.0, y: 0.0, z: z + element.radius };
+            let oc = o - center;
+            let b = oc.dot(d);
+            let discriminant = b * b - oc.dot(oc) + element.radius * element.radius;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let root = discriminant.sqrt();
+            let t = if (d.z > 0.0) != (element.radius < 0.0) { -b - root } else { -b + root };
+            let n = (o + t * d - center).unit();
+            (t, if n.dot(d) > 0.0 { -n } else { n })
+        };
+
+        let p = o + t * d;
+        let r = element.aperture / 2.0;
+        if t <= 0.0 || p.x * p.x + p.y * p.y > r * r {
+            return None;
+        }
+        Some((p, n))
+    }
+
+    /// The ray from `o` in the direction `d` behind the lens as it comes
+    /// out in front, unless the lens blocks it.
+    fn trace_from_film(&self, o: Vector, d: Vector) -> Option<(Vector, Vector)> {
+        let (mut o, mut d) = (o, d.unit());
+        let mut z = 0.0;
+        for i in (0 .. self.count).rev() {
+            let element = &self.elements[i];
+            z -= self.thickness(i);
+            let (p, n) = Self::intersect(element, z, o, d)?;
+            o = p;
+            if !element.is_stop() {
+                let ior = if i > 0 { self.elements[i - 1].ior() } else { 1.0 };
+                d = d.refract(n, element.ior() / ior)?;
+            }
+        }
+        Some((o, d))
+    }
+
+    /// The ray from `o` in the direction `d` in front of the lens as it
+    /// comes out behind, unless the lens blocks it.
+    fn trace_from_scene(&self, o: Vector, d: Vector) -> Option<(Vector, Vector)> {
+        let (mut o, mut d) = (o, d.unit());
+        let mut z = -(0 .. self.count).map(|i| self.thickness(i)).sum::<f32>();
+        for i in 0 .. self.count {
+            let element = &self.elements[i];
+            let (p, n) = Self::intersect(element, z, o, d)?;
+            o = p;
+            if !element.is_stop() {
+                let ior = if i > 0 { self.elements[i - 1].ior() } else { 1.0 };
+                d = d.refract(n, ior / element.ior())?;
+            }
+            z += self.thickness(i);
+        }
+        Some((o, d))
+    }
+
+    /// Ray from the point (x, y) of the film, in millimeters from its
+    /// center, through the point `disk` of the unit disk scaled to the
+    /// back of the lens, as it comes out in front. The origin and the
+    /// direction are in the units of the scene, with x to the right, y
+    /// up and z ahead. `None` if the lens blocks the ray.
+    pub fn ray(&self, x: f32, y: f32, disk: Vector) -> Option<(Vector, Vector)> {
+        let back = &self.elements[self.count - 1];
+        let r = back.aperture / 2.0;
+        let film = Vector{ x, y, z: 0.0 };
+        let target = Vector{ x: r * disk.x, y: r * disk.y, z: -self.film_distance };
+
+        let (o, d) = self.trace_from_film(film, target - film)?;
+        Some((
+            Vector{ x: o.x / MM_PER_UNIT, y: o.y / MM_PER_UNIT, z: -o.z / MM_PER_UNIT },
+            Vector{ x: d.x, y: d.y, z: -d.z }
+        ))
+    }
+}
@@ -0,0 +1,36 @@
+//! A toy ray tracer following Peter Shirley's "Ray Tracing in One
+//! Weekend".
+
+pub mod animation;
+pub mod background;
+pub mod bdpt;
+pub mod builder;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod demo;
+pub mod denoise;
+pub mod display;
+pub mod distributed;
+pub mod filter;
+pub mod geometry;
+pub mod integrator;
+pub mod lens;
+pub mod light;
+pub mod loaders;
+pub mod material;
+pub mod math;
+pub mod microfacet;
+pub mod mlt;
+pub mod output;
+pub mod perlin;
+pub mod photon;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod server;
+pub mod texture;
+pub mod tonemap;
+pub mod video;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
@@ -0,0 +1,293 @@
+//! Light sources. Most of them are not part of the geometry: they
+//! illuminate the scene, but rays never hit them. The triangle and
+//! sphere lights stand in for glowing objects of the world instead, so
+//! that their light can be sampled directly.
+
+use std::f32::consts::PI;
+use std::fmt::Debug;
+
+use crate::math::Vector;
+use crate::microfacet::sample_cosine;
+use crate::sampler::Sampler;
+
+/// Light arriving at a point from a single (sampled) point of a light
+/// source.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightSample {
+    pub direction: Vector, // Unit vector from the lit point towards the light
+    pub distance: f32,     // Distance to the sampled point of the light
+    pub intensity: Vector, // Incident light, not yet weighted by the surface cosine
+    pub pdf: f32,          // Density of the direction per unit solid angle, infinite for a point
+    pub normal: Vector,    // Normal of the light at the sampled point, zero for a point
+}
+
+/// Light leaving a (sampled) point of a light source in a (sampled)
+/// direction, for tracing paths starting from the lights.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Emission {
+    pub point: Vector,
+    pub normal: Vector,     // Zero for a point light
+    pub direction: Vector,  // Unit vector the light leaves in
+    pub radiance: Vector,   // Light carried in the direction
+    pub pdf_position: f32,  // Density of the point per unit area, 1 for a point light
+    pub pdf_direction: f32  // Density of the direction per unit solid angle
+}
+
+pub trait Light: Debug + Send + Sync {
+    /// Pick a point on the light and tell how much light it sends
+    /// towards `p`.
+    fn sample(&self, p: Vector, sampler: &mut dyn Sampler) -> LightSample;
+
+    /// Density per unit solid angle with which `sample` picks the point
+    /// `q` of the light as seen from `p`. Zero for a point light, which
+    /// has no area to pick from.
+    fn pdf(&self, _p: Vector, _q: Vector) -> f32 {
+        0.0
+    }
+
+    /// Pick a point on the light and a direction for the light to leave
+    /// it in.
+    fn emit(&self, sampler: &mut dyn Sampler) -> Emission;
+
+    /// Densities with which `emit` picks the point `q` of the light and
+    /// the `direction` from it, as in `Emission`.
+    fn emission_pdf(&self, q: Vector, direction: Vector) -> (f32, f32);
+}
+
+/// Infinitely small light source with inverse-square falloff.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PointLight {
+    pub position: Vector,
+    pub intensity: Vector
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Vector, _sampler: &mut dyn Sampler) -> LightSample {
+        let d = self.position - p;
+        let distance = d.norm();
+
+        LightSample {
+            direction: d / distance,
+            distance,
+            intensity: self.intensity / (distance * distance),
+            pdf: f32::INFINITY,
+            normal: Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        }
+    }
+
+    fn emit(&self, sampler: &mut dyn Sampler) -> Emission {
+        Emission {
+            point: self.position,
+            normal: Vector{ x: 0.0, y: 0.0, z: 0.0 },
+            direction: sampler.unit_vector(),
+            radiance: self.intensity,
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (4.0 * PI)
+        }
+    }
+
+    fn emission_pdf(&self, _q: Vector, _direction: Vector) -> (f32, f32) {
+        (1.0, 1.0 / (4.0 * PI))
+    }
+}
+
+/// One-sided rectangular light spanned by the edges `u` and `v` from
+/// the `corner`, emitting `radiance` to the side `u × v` points to.
+/// Sampling a random point on the rectangle gives soft shadows.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AreaLight {
+    pub corner: Vector,
+    pub u: Vector,
+    pub v: Vector,
+    pub radiance: Vector
+}
+
+impl Light for AreaLight {
+    fn sample(&self, p: Vector, sampler: &mut dyn Sampler) -> LightSample {
+        let (s, t) = sampler.next_2d();
+        let point = self.corner + s * self.u + t * self.v;
+
+        let d = point - p;
+        let distance = d.norm();
+        let direction = d / distance;
+
+        let pdf = self.pdf(p, point);
+        let intensity = if pdf > 0.0 {
+            self.radiance / pdf
+        } else {
+            Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        };
+
+        LightSample { direction, distance, intensity, pdf, normal: self.u.cross(self.v).unit() }
+    }
+
+    fn pdf(&self, p: Vector, q: Vector) -> f32 {
+        let normal = self.u.cross(self.v);
+        area_pdf(p, q, normal, normal.norm())
+    }
+
+    fn emit(&self, sampler: &mut dyn Sampler) -> Emission {
+        let (s, t) = sampler.next_2d();
+        let normal = self.u.cross(self.v);
+        emit_cosine(self.corner + s * self.u + t * self.v, normal.unit(), normal.norm(), self.radiance, sampler)
+    }
+
+    fn emission_pdf(&self, _q: Vector, direction: Vector) -> (f32, f32) {
+        let normal = self.u.cross(self.v);
+        let area = normal.norm();
+        (1.0 / area, (direction.dot(normal) / area).max(0.0) / PI)
+    }
+}
+
+/// One-sided triangular light with the corners `a`, `b` and `c`,
+/// emitting `radiance` to the side `(b - a) × (c - a)` points to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TriangleLight {
+    pub a: Vector,
+    pub b: Vector,
+    pub c: Vector,
+    pub radiance: Vector
+}
+
+impl Light for TriangleLight {
+    fn sample(&self, p: Vector, sampler: &mut dyn Sampler) -> LightSample {
+        // Points past the diagonal of the parallelogram of the edges are
+        // folded back into the triangle.
+        let (mut s, mut t) = sampler.next_2d();
+        if s + t > 1.0 {
+            s = 1.0 - s;
+            t = 1.0 - t;
+        }
+        let (u, v) = (self.b - self.a, self.c - self.a);
+        let point = self.a + s * u + t * v;
+
+        let d = point - p;
+        let distance = d.norm();
+        let direction = d / distance;
+
+        let pdf = self.pdf(p, point);
+        let intensity = if pdf > 0.0 {
+            self.radiance / pdf
+        } else {
+            Vector{ x: 0.0, y: 0.0, z: 0.0 }
+        };
+
+        LightSample { direction, distance, intensity, pdf, normal: u.cross(v).unit() }
+    }
+
+    fn pdf(&self, p: Vector, q: Vector) -> f32 {
+        let normal = (self.b - self.a).cross(self.c - self.a);
+        area_pdf(p, q, normal, normal.norm() / 2.0)
+    }
+
+    fn emit(&self, sampler: &mut dyn Sampler) -> Emission {
+        let (mut s, mut t) = sampler.next_2d();
+        if s + t > 1.0 {
+            s = 1.0 - s;
+            t = 1.0 - t;
+        }
+        let (u, v) = (self.b - self.a, self.c - self.a);
+        let normal = u.cross(v);
+        emit_cosine(self.a + s * u + t * v, normal.unit(), normal.norm() / 2.0, self.radiance, sampler)
+    }
+
+    fn emission_pdf(&self, _q: Vector, direction: Vector) -> (f32, f32) {
+        let normal = (self.b - self.a).cross(self.c - self.a);
+        let area = normal.norm() / 2.0;
+        (1.0 / area, (direction.dot(normal) / (2.0 * area)).max(0.0) / PI)
+    }
+}
+
+/// Glowing sphere emitting `radiance` outwards. Only the directions
+/// within the cone the sphere is seen in are sampled, so there is no
+/// waste on its far side.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SphereLight {
+    pub center: Vector,
+    pub radius: f32,
+    pub radiance: Vector
+}
+
+impl Light for SphereLight {
+    fn sample(&self, p: Vector, sampler: &mut dyn Sampler) -> LightSample {
+        let d = self.center - p;
+        let distance = d.norm();
+        let w = d / distance;
+
+        // Nothing to see from inside.
+        if distance <= self.radius {
+            let zero = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+            return LightSample{ direction: w, distance, intensity: zero, pdf: 0.0, normal: zero };
+        }
+
+        let cos_max = (1.0 - self.radius * self.radius / (distance * distance)).max(0.0).sqrt();
+        let (xi1, xi2) = sampler.next_2d();
+        let cos = 1.0 - xi1 * (1.0 - cos_max);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * xi2;
+
+        let (e1, e2) = w.basis();
+        let direction = (sin * phi.cos()) * e1 + (sin * phi.sin()) * e2 + cos * w;
+
+        // Nearest intersection with the sphere along the direction.
+        let b = direction.dot(d);
+        let c = d.sqnorm() - self.radius * self.radius;
+        let hit = b - (b * b - c).max(0.0).sqrt();
+
+        // Uniform over the solid angle of the cone.
+        let q = p + hit * direction;
+        let pdf = self.pdf(p, q);
+        let normal = (q - self.center) / self.radius;
+        LightSample { direction, distance: hit, intensity: self.radiance / pdf, pdf, normal }
+    }
+
+    fn pdf(&self, p: Vector, _q: Vector) -> f32 {
+        let distance = (self.center - p).norm();
+        if distance <= self.radius {
+            return 0.0;
+        }
+
+        let cos_max = (1.0 - self.radius * self.radius / (distance * distance)).max(0.0).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+
+    fn emit(&self, sampler: &mut dyn Sampler) -> Emission {
+        // Unlike for the shadow rays, the whole sphere glows.
+        let normal = sampler.unit_vector();
+        let area = 4.0 * PI * self.radius * self.radius;
+        emit_cosine(self.center + self.radius * normal, normal, area, self.radiance, sampler)
+    }
+
+    fn emission_pdf(&self, q: Vector, direction: Vector) -> (f32, f32) {
+        let normal = (q - self.center) / self.radius;
+        (1.0 / (4.0 * PI * self.radius * self.radius), direction.dot(normal).max(0.0) / PI)
+    }
+}
+
+/// Light leaving the `point` of a light of the given total `area` in a
+/// cosine-distributed direction around the `normal` there.
+fn emit_cosine(point: Vector, normal: Vector, area: f32, radiance: Vector, sampler: &mut dyn Sampler) -> Emission {
+    let direction = sample_cosine(normal, sampler);
+    Emission {
+        point,
+        normal,
+        direction,
+        radiance,
+        pdf_position: 1.0 / area,
+        pdf_direction: direction.dot(normal).max(0.0) / PI
+    }
+}
+
+/// Density per unit solid angle of picking the point `q` uniformly on a
+/// flat light of the given `area`, seen from `p`. The `normal` gives the
+/// side the light shines to.
+fn area_pdf(p: Vector, q: Vector, normal: Vector, area: f32) -> f32 {
+    let d = q - p;
+    let distance = d.norm();
+    let cos = -d.dot(normal) / (distance * normal.norm());
+    if cos <= 0.0 {
+        return 0.0;
+    }
+
+    distance * distance / (cos * area)
+}
@@ -0,0 +1,266 @@
+//! glTF 2.0 models, both `.gltf` with external or embedded buffers and
+//! binary `.glb`.
+//!
+//! Every mesh of the default scene is imported with the transformations
+//! of the nodes it hangs from, along with its vertex normals and texture
+//! coordinates. Materials become `Pbr` ones with the base
+//! color factor and texture, the metallic and roughness factors, and the
+//! normal map if there is one. Metallic-roughness textures, emission,
+//! cameras and lights are ignored.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use gltf::image::Format;
+use gltf::mesh::Mode;
+
+use crate::color::srgb_decode;
+use crate::geometry::{Group, Hittable, Instance, Mesh};
+use crate::material::{Lambertian, Material, NormalMapped, Pbr};
+use crate::math::{Mat4, Transform, Vector};
+use crate::texture::{ImageTexture, SolidColor, Texture};
+
+use super::LoadError;
+
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<Group, LoadError> {
+    let (document, buffers, images) = gltf::import(path)
+        .map_err(|err| LoadError::invalid(err.to_string()))?;
+
+    let scene = document.default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| LoadError::invalid("glTF file has no scenes"))?;
+
+    let mut importer = Importer {
+        buffers: &buffers,
+        images: &images,
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        group: Group::default()
+    };
+
+    for node in scene.nodes() {
+        importer.node(&node, Transform::IDENTITY)?;
+    }
+
+    Ok(importer.group)
+}
+
+struct Importer<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    materials: HashMap<Option<usize>, Arc<dyn Material>>,
+    meshes: HashMap<(usize, usize), Arc<Mesh>>, // By mesh and primitive index
+    group: Group
+}
+
+impl Importer<'_> {
+    fn node(&mut self, node: &gltf::Node, parent: Transform) -> Result<(), LoadError> {
+        // glTF matrices are stored column by column.
+        let local = Transform::from_matrix(Mat4{ m: node.transform().matrix() }.transpose())
+            .ok_or_else(|| LoadError::invalid(format!("node {} has a singular transform", node.index())))?;
+        let transform = parent * local;
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if let Some(mesh) = self.primitive(&mesh, &primitive)? {
+                    self.group.objects.push(Box::new(Instance::new(mesh, transform)));
+                }
+            }
+        }
+
+        for child in node.children() {
+            self.node(&child, transform)?;
+        }
+
+        Ok(())
+    }
+
+    /// Triangle mesh of a primitive, shared between all the nodes that
+    /// use it. Points and lines have nothing to render.
+    fn primitive(&mut self, mesh: &gltf::Mesh, primitive: &gltf::Primitive) -> Result<Option<Arc<dyn Hittable>>, LoadError> {
+        if primitive.mode() != Mode::Triangles {
+            return Ok(None);
+        }
+
+        let key = (mesh.index(), primitive.index());
+        if let Some(mesh) = self.meshes.get(&key) {
+            return Ok(Some(mesh.clone()));
+        }
+
+        let buffers = self.buffers;
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+        let vertices: Vec<Vector> = reader.read_positions()
+            .ok_or_else(|| LoadError::invalid(format!("mesh {} has no positions", mesh.index())))?
+            .map(|[x, y, z]| Vector{ x, y, z })
+            .collect();
+
+        // glTF puts the origin of the texture space in the top left
+        // corner, textures here have it in the bottom left one.
+        let texcoords: Vec<[f32; 2]> = reader.read_tex_coords(0)
+            .map(|coords| coords.into_f32().map(|[u, v]| [u, 1.0 - v]).collect())
+            .unwrap_or_default();
+
+        let normals: Vec<Vector> = reader.read_normals()
+            .map(|normals| normals.map(|[x, y, z]| Vector{ x, y, z }).collect())
+            .unwrap_or_default();
+
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0 .. vertices.len()).collect()
+        };
+        if indices.iter().any(|&i| i >= vertices.len()) {
+            return Err(LoadError::invalid(format!("mesh {} has an index out of range", mesh.index())));
+        }
+        let faces = indices.chunks_exact(3).map(|f| [f[0], f[1], f[2]]).collect();
+
+        let material = self.material(&primitive.material())?;
+        let result = Arc::new(Mesh { texcoords, normals, ..Mesh::new(vertices, faces, material) });
+        self.meshes.insert(key, result.clone());
+
+        Ok(Some(result))
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Result<Arc<dyn Material>, LoadError> {
+        if let Some(material) = self.materials.get(&material.index()) {
+            return Ok(material.clone());
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let factor = Vector{ x: r, y: g, z: b };
+
+        let base_color: Arc<dyn Texture> = match pbr.base_color_texture() {
+            Some(info) => Arc::new(self.texture(info.texture().source().index(), factor, false)?),
+            None => Arc::new(SolidColor{ color: factor })
+        };
+
+        // The default material of glTF is plain white and matte.
+        let mut result: Arc<dyn Material> = if material.index().is_none() {
+            Arc::new(Lambertian{ albedo: base_color })
+        } else {
+            Arc::new(Pbr {
+                base_color,
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor()
+            })
+        };
+
+        if let Some(normal) = material.normal_texture() {
+            let one = Vector{ x: 1.0, y: 1.0, z: 1.0 };
+            result = Arc::new(NormalMapped {
+                material: result,
+                normal_map: Arc::new(self.texture(normal.texture().source().index(), one, true)?)
+            });
+        }
+
+        self.materials.insert(material.index(), result.clone());
+        Ok(result)
+    }
+
+    /// Texture from one of the decoded images, its colors multiplied by
+    /// `factor`.
+    fn texture(&self, index: usize, factor: Vector, linear: bool) -> Result<ImageTexture, LoadError> {
+        let image = &self.images[index];
+        let channels = match image.format {
+            Format::R8 => 1,
+            Format::R8G8 => 2,
+            Format::R8G8B8 => 3,
+            Format::R8G8B8A8 => 4,
+            format => return Err(LoadError::invalid(format!("unsupported image format {:?}", format)))
+        };
+
+        let decode = |c: u8| {
+            let c = c as f32 / 255.0;
+            if linear { c } else { srgb_decode(c) }
+        };
+        let pixels = image.pixels
+            .chunks_exact(channels)
+            .map(|p| {
+                let color = match channels {
+                    1 | 2 => Vector{ x: decode(p[0]), y: decode(p[0]), z: decode(p[0]) },
+                    _ => Vector{ x: decode(p[0]), y: decode(p[1]), z: decode(p[2]) }
+                };
+                factor * color
+            })
+            .collect();
+
+        Ok(ImageTexture {
+            width: image.width as usize,
+            height: image.height as usize,
+            pixels
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Binary glTF file with one triangle mesh with the given indices of
+    /// four vertices.
+    fn glb(indices: &[u16]) -> Vec<u8> {
+        let mut bin = vec![];
+        for v in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]] {
+            for c in v {
+                bin.extend(c.to_le_bytes());
+            }
+        }
+        for i in indices {
+            bin.extend(i.to_le_bytes());
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let mut json = format!(
+            r#"{{"asset":{{"version":"2.0"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],
+               "meshes":[{{"primitives":[{{"attributes":{{"POSITION":0}},"indices":1}}]}}],
+               "buffers":[{{"byteLength":{}}}],
+               "bufferViews":[{{"buffer":0,"byteLength":48}},{{"buffer":0,"byteOffset":48,"byteLength":{}}}],
+               "accessors":[{{"bufferView":0,"componentType":5126,"count":4,"type":"VEC3","min":[0,0,0],"max":[1,1,0]}},
+                            {{"bufferView":1,"componentType":5123,"count":{},"type":"SCALAR"}}]}}"#,
+            bin.len(), 2 * indices.len(), indices.len()
+        ).into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+
+        let mut data = b"glTF".to_vec();
+        data.extend(2u32.to_le_bytes());
+        data.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        data.extend((json.len() as u32).to_le_bytes());
+        data.extend(b"JSON");
+        data.extend(json);
+        data.extend((bin.len() as u32).to_le_bytes());
+        data.extend(b"BIN\0");
+        data.extend(bin);
+        data
+    }
+
+    fn load(name: &str, indices: &[u16]) -> Result<Group, LoadError> {
+        let path = std::env::temp_dir().join(format!("rtrace-{}-{}.glb", std::process::id(), name));
+        std::fs::write(&path, glb(indices)).unwrap();
+        let group = load_gltf(&path);
+        std::fs::remove_file(&path).unwrap();
+        group
+    }
+
+    #[test]
+    fn triangle_mesh() {
+        let group = load("mesh", &[0, 1, 2, 2, 1, 3]).unwrap();
+        assert_eq!(group.objects.len(), 1);
+        let bounds = group.objects[0].bounds().unwrap();
+        assert!(bounds.min.x <= 0.0 && bounds.max.x >= 1.0 && bounds.max.y >= 1.0);
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        match load("out-of-range", &[0, 1, 4]) {
+            Err(LoadError::Invalid(message)) => assert_eq!(message, "mesh 0 has an index out of range"),
+            Err(err) => panic!("{}", err),
+            Ok(_) => panic!("loaded")
+        }
+    }
+}
@@ -0,0 +1,878 @@
+//! Mitsuba XML scene files, both the camelCase names of Mitsuba 0.6 and
+//! the snake_case ones of Mitsuba 2 and 3.
+//!
+//! Understood are:
+//!
+//! - `perspective` and `thinlens` sensors with their film size and
+//!   sample count, and the maximum depth of the integrator;
+//! - `sphere`, `rectangle`, `cube`, `obj` and `ply` shapes, shape
+//!   groups and their instances, with `area` emitters making shapes
+//!   emissive and sampling rectangles and untransformed spheres as
+//!   lights;
+//! - `diffuse`, `plastic`, `conductor`, `dielectric` and `principled`
+//!   BSDFs with their rough variants, `twosided`, `normalmap` and
+//!   `bumpmap`;
+//! - `bitmap` and `checkerboard` textures;
+//! - `point`, `constant` and `envmap` emitters;
+//! - `<default>` parameters and `$name` references to them.
+//!
+//! Spectra given as wavelength-value pairs are averaged into grey. The
+//! rest is skipped and unknown BSDFs fall back to `diffuse`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use roxmltree::{Document, Node};
+
+use crate::animation::Animation;
+use crate::background::{Background, EnvironmentMap};
+use crate::camera::Camera;
+use crate::geometry::{Group, Hittable, Instance, Mesh, Quad, Sphere, World};
+use crate::light::{AreaLight, Light, PointLight, SphereLight};
+use crate::material::{
+    BumpMapped, Dielectric, Emissive, Lambertian, Material, Metal, NormalMapped, Pbr, Principled
+};
+use crate::math::{Mat4, Transform, Vector, EX, EY, EZ, OG};
+use crate::microfacet::conductor_f0;
+use crate::render::Settings;
+use crate::scene::Scene;
+use crate::texture::{Checker, CheckerSpace, ImageTexture, SolidColor, Texture};
+
+use super::obj::load_obj;
+use super::ply::load_ply;
+use super::{framed_camera, vertical_fov, FovAxis, LoadError};
+
+pub fn load_mitsuba<P: AsRef<Path>>(path: P) -> Result<Scene, LoadError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    parse_mitsuba(&source, base)
+}
+
+/// Build a scene from the Mitsuba XML source, resolving the relative
+/// paths against `base`.
+pub fn parse_mitsuba(source: &str, base: &Path) -> Result<Scene, LoadError> {
+    let document = Document::parse(source).map_err(|err| LoadError::invalid(err.to_string()))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "scene" {
+        return Err(LoadError::invalid("expected a <scene> element"));
+    }
+
+    let mut world = World::new();
+    world.background = Background::Solid(grey(0.0));
+
+    let mut importer = Importer {
+        document: &document,
+        base: base.to_path_buf(),
+        defaults: HashMap::new(),
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        groups: HashMap::new(),
+        settings: Settings {
+            width: 768,
+            height: 576,
+            samples_per_pixel: 4,
+            ..Settings::default()
+        },
+        camera: None,
+        world
+    };
+
+    for node in elements(root) {
+        importer.element(node)?;
+    }
+
+    let camera = match importer.camera {
+        Some(camera) => camera,
+        None => importer.camera(root)?
+    };
+
+    Ok(Scene { settings: importer.settings, camera, world: importer.world, colors: vec![], animation: Animation::default() })
+}
+
+fn elements<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(|child| child.is_element())
+}
+
+/// Property names of Mitsuba 0.6 are in camelCase, the later versions
+/// use snake_case.
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut previous_lower = false;
+
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && previous_lower {
+            result.push('_');
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        result.push(c.to_ascii_lowercase());
+    }
+
+    result
+}
+
+/// The `area` emitter nested in a shape.
+fn area_emitter<'a, 'input>(node: Node<'a, 'input>) -> Option<Node<'a, 'input>> {
+    elements(node).find(|child| child.has_tag_name("emitter") && child.attribute("type") == Some("area"))
+}
+
+fn grey(x: f32) -> Vector {
+    Vector{ x, y: x, z: x }
+}
+
+/// Numbers separated by commas or whitespace.
+fn numbers(text: &str) -> Option<Vec<f32>> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect()
+}
+
+/// Refractive indices of the materials Mitsuba knows by name.
+fn named_ior(name: &str) -> Option<f32> {
+    let ior = match name {
+        "vacuum" => 1.0,
+        "air" => 1.000277,
+        "water" => 1.333,
+        "acetone" => 1.36,
+        "ethanol" => 1.361,
+        "fused quartz" => 1.458,
+        "pyrex" => 1.47,
+        "acrylic glass" | "polypropylene" => 1.49,
+        "bk7" => 1.5046,
+        "sodium chloride" => 1.544,
+        "diamond" => 2.419,
+        _ => return None
+    };
+    Some(ior)
+}
+
+/// Approximate reflectance at normal incidence of some of the named
+/// conductors.
+fn named_conductor(name: &str) -> Option<Vector> {
+    let f0 = match name {
+        "none" => grey(1.0),
+        "Ag" => Vector{ x: 0.95, y: 0.93, z: 0.88 },
+        "Al" => Vector{ x: 0.91, y: 0.92, z: 0.92 },
+        "Au" => Vector{ x: 1.0, y: 0.71, z: 0.29 },
+        "Cr" => Vector{ x: 0.55, y: 0.56, z: 0.55 },
+        "Cu" => Vector{ x: 0.95, y: 0.64, z: 0.54 },
+        "Fe" => Vector{ x: 0.56, y: 0.57, z: 0.58 },
+        "Ni" => Vector{ x: 0.66, y: 0.61, z: 0.53 },
+        "Ti" => Vector{ x: 0.54, y: 0.50, z: 0.45 },
+        _ => return None
+    };
+    Some(f0)
+}
+
+struct Importer<'a, 'input> {
+    document: &'a Document<'input>,
+    base: PathBuf,
+    defaults: HashMap<String, String>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    groups: HashMap<String, Arc<Group>>,
+    settings: Settings,
+    camera: Option<Camera>,
+    world: World
+}
+
+impl<'a, 'input> Importer<'a, 'input> {
+    fn element(&mut self, node: Node) -> Result<(), LoadError> {
+        match node.tag_name().name() {
+            "default" => {
+                let name = self.required(node, "name")?;
+                let value = self.required(node, "value")?;
+                self.defaults.entry(name).or_insert(value);
+            },
+            "integrator" => {
+                if let Some(depth) = self.max_depth(node)? {
+                    self.settings.max_depth = depth;
+                }
+            },
+            "sensor" => {
+                self.sensor(node)?;
+                self.camera = Some(self.camera(node)?);
+            },
+            "bsdf" => {
+                let material = self.bsdf(node)?;
+                if let Some(id) = self.attribute(node, "id")? {
+                    self.materials.insert(id, material);
+                }
+            },
+            "texture" => {
+                let texture = self.texture(node)?;
+                if let Some(id) = self.attribute(node, "id")? {
+                    self.textures.insert(id, texture);
+                }
+            },
+            "shape" => {
+                if self.attribute(node, "type")?.as_deref() == Some("shapegroup") {
+                    let mut group = Group::default();
+                    for child in elements(node).filter(|child| child.has_tag_name("shape")) {
+                        group.objects.extend(self.shape(child)?);
+                    }
+                    let id = self.required(node, "id")?;
+                    self.groups.insert(id, Arc::new(group));
+                } else if let Some(shape) = self.shape(node)? {
+                    if let Some(light) = self.area_light(node)? {
+                        self.world.emitters.insert(self.world.objects.len(), self.world.lights.len());
+                        self.world.lights.push(light);
+                    }
+                    self.world.objects.push(shape);
+                }
+            },
+            "emitter" => self.emitter(node)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn line(&self, node: Node) -> usize {
+        self.document.text_pos_at(node.range().start).row as usize
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.base.join(path)
+    }
+
+    /// Attribute with the `$name` references to the defaults resolved.
+    fn attribute(&self, node: Node, name: &str) -> Result<Option<String>, LoadError> {
+        let value = match node.attribute(name) {
+            Some(value) => value,
+            None => return Ok(None)
+        };
+
+        match value.strip_prefix('$') {
+            Some(key) => self.defaults.get(key)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| LoadError::parse(self.line(node), format!("no default for '{}'", key))),
+            None => Ok(Some(value.to_string()))
+        }
+    }
+
+    fn required(&self, node: Node, name: &str) -> Result<String, LoadError> {
+        self.attribute(node, name)?.ok_or_else(|| {
+            let tag = node.tag_name().name();
+            LoadError::parse(self.line(node), format!("<{}> needs the '{}' attribute", tag, name))
+        })
+    }
+
+    fn numbers(&self, node: Node, name: &str) -> Result<Vec<f32>, LoadError> {
+        let value = self.required(node, name)?;
+        numbers(&value).ok_or_else(|| LoadError::parse(self.line(node), format!("bad numbers '{}'", value)))
+    }
+
+    /// Child element setting the named property.
+    fn property<'b>(&self, node: Node<'b, 'input>, name: &str) -> Option<Node<'b, 'input>> {
+        elements(node).find(|child| child.attribute("name").is_some_and(|n| snake_case(n) == name))
+    }
+
+    fn float(&self, node: Node, name: &str, default: f32) -> Result<f32, LoadError> {
+        let property = match self.property(node, name) {
+            Some(property) if property.has_tag_name("float") || property.has_tag_name("integer") => property,
+            _ => return Ok(default)
+        };
+
+        match self.numbers(property, "value")?[..] {
+            [x] => Ok(x),
+            _ => Err(LoadError::parse(self.line(property), format!("'{}' expects a number", name)))
+        }
+    }
+
+    fn string(&self, node: Node, name: &str) -> Result<Option<String>, LoadError> {
+        match self.property(node, name) {
+            Some(property) if property.has_tag_name("string") => self.attribute(property, "value"),
+            _ => Ok(None)
+        }
+    }
+
+    fn boolean(&self, node: Node, name: &str) -> Result<bool, LoadError> {
+        match self.property(node, name) {
+            Some(property) if property.has_tag_name("boolean") => Ok(self.required(property, "value")? == "true"),
+            _ => Ok(false)
+        }
+    }
+
+    /// Point given either by its coordinates or with a `value`.
+    fn point(&self, node: Node) -> Result<Vector, LoadError> {
+        let coordinates = match node.attribute("value") {
+            Some(_) => self.numbers(node, "value")?,
+            None => vec![
+                self.attribute(node, "x")?.and_then(|x| x.parse().ok()).unwrap_or(0.0),
+                self.attribute(node, "y")?.and_then(|y| y.parse().ok()).unwrap_or(0.0),
+                self.attribute(node, "z")?.and_then(|z| z.parse().ok()).unwrap_or(0.0)
+            ]
+        };
+
+        match coordinates[..] {
+            [x, y, z] => Ok(Vector{ x, y, z }),
+            [x] => Ok(grey(x)),
+            _ => Err(LoadError::parse(self.line(node), "expected three coordinates"))
+        }
+    }
+
+    /// Color of an `rgb`, `spectrum` or `float` element.
+    fn color_value(&self, node: Node) -> Result<Option<Vector>, LoadError> {
+        let color = match node.tag_name().name() {
+            "rgb" | "srgb" | "color" | "float" => self.point(node)?,
+            "spectrum" => {
+                let value = self.required(node, "value")?;
+                let values: Option<Vec<f32>> = value.split(',')
+                    .map(|entry| entry.rsplit(':').next().unwrap_or("").trim().parse().ok())
+                    .collect();
+                match values {
+                    Some(values) if !values.is_empty() => grey(values.iter().sum::<f32>() / values.len() as f32),
+                    _ => return Err(LoadError::parse(self.line(node), format!("bad spectrum '{}'", value)))
+                }
+            },
+            _ => return Ok(None)
+        };
+
+        Ok(Some(color))
+    }
+
+    fn color(&self, node: Node, name: &str, default: Vector) -> Result<Vector, LoadError> {
+        match self.property(node, name) {
+            Some(property) => Ok(self.color_value(property)?.unwrap_or(default)),
+            None => Ok(default)
+        }
+    }
+
+    /// Color property that can also be given by a nested or referenced
+    /// texture.
+    fn color_texture(&self, node: Node, name: &str, default: Vector) -> Result<Arc<dyn Texture>, LoadError> {
+        if let Some(property) = self.property(node, name) {
+            match property.tag_name().name() {
+                "texture" => return self.texture(property),
+                "ref" => {
+                    let id = self.required(property, "id")?;
+                    if let Some(texture) = self.textures.get(&id) {
+                        return Ok(texture.clone());
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        Ok(Arc::new(SolidColor{ color: self.color(node, name, default)? }))
+    }
+
+    /// Transformation of the `to_world` property, applying the listed
+    /// operations one after another.
+    fn transform(&self, node: Node) -> Result<Transform, LoadError> {
+        let property = match self.property(node, "to_world") {
+            Some(property) if property.has_tag_name("transform") => property,
+            _ => return Ok(Transform::IDENTITY)
+        };
+
+        let mut transform = Transform::IDENTITY;
+        for op in elements(property) {
+            let line = self.line(op);
+            let step = match op.tag_name().name() {
+                "translate" => Transform::translate(self.point(op)?),
+                "scale" => {
+                    let factor = match op.attribute("value") {
+                        Some(_) => self.point(op)?,
+                        None => Vector {
+                            x: self.attribute(op, "x")?.and_then(|x| x.parse().ok()).unwrap_or(1.0),
+                            y: self.attribute(op, "y")?.and_then(|y| y.parse().ok()).unwrap_or(1.0),
+                            z: self.attribute(op, "z")?.and_then(|z| z.parse().ok()).unwrap_or(1.0)
+                        }
+                    };
+                    Transform::scale(factor)
+                },
+                "rotate" => {
+                    let angle: f32 = self.required(op, "angle")?
+                        .parse()
+                        .map_err(|_| LoadError::parse(line, "bad rotation angle"))?;
+                    Transform::rotate(self.point(op)?, angle.to_radians())
+                },
+                "matrix" => {
+                    let elements = self.numbers(op, "value")?;
+                    if elements.len() != 16 {
+                        return Err(LoadError::parse(line, "matrix expects 16 numbers"));
+                    }
+
+                    // Listed row by row.
+                    let mut m = Mat4::IDENTITY;
+                    for (k, &x) in elements.iter().enumerate() {
+                        m.m[k / 4][k % 4] = x;
+                    }
+                    Transform::from_matrix(m).ok_or_else(|| LoadError::parse(line, "singular matrix"))?
+                },
+                "lookat" => {
+                    let point = |name: &str| -> Result<Vector, LoadError> {
+                        match numbers(&self.required(op, name)?).as_deref() {
+                            Some([x, y, z]) => Ok(Vector{ x: *x, y: *y, z: *z }),
+                            _ => Err(LoadError::parse(line, format!("bad lookat {}", name)))
+                        }
+                    };
+                    let up = if op.attribute("up").is_some() { point("up")? } else { EY };
+                    look_at(point("origin")?, point("target")?, up)
+                        .ok_or_else(|| LoadError::parse(line, "degenerate lookat"))?
+                },
+                _ => continue
+            };
+
+            transform = step * transform;
+        }
+
+        Ok(transform)
+    }
+
+    /// Maximum depth of the integrator, or of the one it wraps.
+    fn max_depth(&self, node: Node) -> Result<Option<u8>, LoadError> {
+        let depth = self.float(node, "max_depth", -1.0)?;
+        if depth > 0.0 {
+            return Ok(Some(depth.min(255.0) as u8));
+        }
+
+        for child in elements(node).filter(|child| child.has_tag_name("integrator")) {
+            if let Some(depth) = self.max_depth(child)? {
+                return Ok(Some(depth));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn sensor(&mut self, node: Node) -> Result<(), LoadError> {
+        if let Some(film) = elements(node).find(|child| child.has_tag_name("film")) {
+            let width = self.float(film, "width", 768.0)?;
+            let height = self.float(film, "height", 576.0)?;
+            if width < 2.0 || height < 2.0 {
+                return Err(LoadError::parse(self.line(film), "the image must be at least 2×2 pixels"));
+            }
+            self.settings.width = width as usize;
+            self.settings.height = height as usize;
+        }
+
+        if let Some(sampler) = elements(node).find(|child| child.has_tag_name("sampler")) {
+            self.settings.samples_per_pixel = self.float(sampler, "sample_count", 4.0)?.max(1.0) as u32;
+        }
+
+        Ok(())
+    }
+
+    /// Camera of a sensor element. Mitsuba cameras look along z, with y
+    /// up and x to the left of the image.
+    fn camera(&self, node: Node) -> Result<Camera, LoadError> {
+        let aspect_ratio = self.settings.aspect_ratio();
+        let to_world = self.transform(node)?;
+
+        let axis = match self.string(node, "fov_axis")?.as_deref() {
+            None | Some("x") => FovAxis::X,
+            Some("y") => FovAxis::Y,
+            Some("diagonal") => FovAxis::Diagonal,
+            Some("smaller") => FovAxis::Smaller,
+            Some("larger") => FovAxis::Larger,
+            Some(axis) => return Err(LoadError::parse(self.line(node), format!("unknown fov axis '{}'", axis)))
+        };
+        let vfov = vertical_fov(self.float(node, "fov", 45.0)?, axis, aspect_ratio);
+
+        let camera = framed_camera(
+            to_world.apply_point(OG),
+            to_world.apply_vector(EZ),
+            to_world.apply_vector(EY),
+            -to_world.apply_vector(EX),
+            vfov,
+            aspect_ratio
+        );
+
+        let aperture_radius = self.float(node, "aperture_radius", 0.0)?;
+        if aperture_radius > 0.0 {
+            return Ok(camera.with_lens(2.0 * aperture_radius, self.float(node, "focus_distance", 1.0)?));
+        }
+
+        Ok(camera)
+    }
+
+    /// The BSDF nested into or referenced from an element.
+    fn nested_bsdf(&self, node: Node) -> Result<Option<Arc<dyn Material>>, LoadError> {
+        for child in elements(node) {
+            match child.tag_name().name() {
+                "bsdf" => return self.bsdf(child).map(Some),
+                "ref" => {
+                    let id = self.required(child, "id")?;
+                    if let Some(material) = self.materials.get(&id) {
+                        return Ok(Some(material.clone()));
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn bsdf(&self, node: Node) -> Result<Arc<dyn Material>, LoadError> {
+        let ty = self.required(node, "type")?;
+        let rough = ty.starts_with("rough");
+        let roughness = |node| -> Result<f32, LoadError> {
+            let alpha = self.float(node, "alpha", if rough { 0.1 } else { 0.0 })?;
+            Ok(alpha.max(0.0).sqrt())
+        };
+        let default = || -> Arc<dyn Material> {
+            Arc::new(Lambertian{ albedo: Arc::new(SolidColor{ color: grey(0.5) }) })
+        };
+
+        let material: Arc<dyn Material> = match ty.as_str() {
+            "twosided" => self.nested_bsdf(node)?.unwrap_or_else(default),
+            "diffuse" => Arc::new(Lambertian{ albedo: self.color_texture(node, "reflectance", grey(0.5))? }),
+            "plastic" | "roughplastic" => Arc::new(Pbr {
+                base_color: self.color_texture(node, "diffuse_reflectance", grey(0.5))?,
+                metallic: 0.0,
+                roughness: roughness(node)?
+            }),
+            "conductor" | "roughconductor" => {
+                let f0 = match self.string(node, "material")? {
+                    Some(name) => named_conductor(&name).ok_or_else(|| {
+                        LoadError::parse(self.line(node), format!("unknown conductor '{}'", name))
+                    })?,
+                    None if self.property(node, "eta").is_some() => {
+                        conductor_f0(self.color(node, "eta", grey(0.0))?, self.color(node, "k", grey(1.0))?)
+                    },
+                    None => named_conductor("Cu").unwrap()
+                };
+
+                let roughness = roughness(node)?;
+                if roughness > 0.0 {
+                    Arc::new(Pbr{ base_color: Arc::new(SolidColor{ color: f0 }), metallic: 1.0, roughness })
+                } else {
+                    Arc::new(Metal::new(f0, 0.0))
+                }
+            },
+            "dielectric" | "thindielectric" | "roughdielectric" => {
+                let ior = |name: &str, default: f32| -> Result<f32, LoadError> {
+                    match self.property(node, name) {
+                        Some(property) if property.has_tag_name("string") => {
+                            let value = self.required(property, "value")?;
+                            named_ior(&value).ok_or_else(|| {
+                                LoadError::parse(self.line(property), format!("unknown material '{}'", value))
+                            })
+                        },
+                        _ => self.float(node, name, default)
+                    }
+                };
+                Arc::new(Dielectric{ refractive_index: ior("int_ior", 1.5046)? / ior("ext_ior", 1.000277)? })
+            },
+            "principled" => Arc::new(Principled {
+                metallic: self.float(node, "metallic", 0.0)?,
+                roughness: self.float(node, "roughness", 0.5)?,
+                specular: self.float(node, "specular", 0.5)?,
+                specular_tint: self.float(node, "spec_tint", 0.0)?,
+                sheen: self.float(node, "sheen", 0.0)?,
+                sheen_tint: self.float(node, "sheen_tint", 0.0)?,
+                clearcoat: self.float(node, "clearcoat", 0.0)?,
+                clearcoat_gloss: self.float(node, "clearcoat_gloss", 0.0)?,
+                ..Principled::new(self.color_texture(node, "base_color", grey(0.5))?)
+            }),
+            "normalmap" => match self.property(node, "normalmap") {
+                Some(map) if map.has_tag_name("texture") => Arc::new(NormalMapped {
+                    material: self.nested_bsdf(node)?.unwrap_or_else(default),
+                    normal_map: self.texture(map)?
+                }),
+                _ => self.nested_bsdf(node)?.unwrap_or_else(default)
+            },
+            "bumpmap" => match elements(node).find(|child| child.has_tag_name("texture")) {
+                Some(map) => Arc::new(BumpMapped {
+                    material: self.nested_bsdf(node)?.unwrap_or_else(default),
+                    height_map: self.texture(map)?,
+                    strength: self.float(node, "scale", 1.0)?
+                }),
+                None => self.nested_bsdf(node)?.unwrap_or_else(default)
+            },
+            _ => default()
+        };
+
+        Ok(material)
+    }
+
+    fn texture(&self, node: Node) -> Result<Arc<dyn Texture>, LoadError> {
+        let texture: Arc<dyn Texture> = match self.required(node, "type")?.as_str() {
+            "bitmap" => {
+                let filename = self.string(node, "filename")?
+                    .ok_or_else(|| LoadError::parse(self.line(node), "bitmap needs a filename"))?;
+                let path = self.path(&filename);
+                if self.boolean(node, "raw")? {
+                    Arc::new(ImageTexture::load_linear(path)?)
+                } else {
+                    Arc::new(ImageTexture::load(path)?)
+                }
+            },
+            "checkerboard" => Arc::new(Checker {
+                even: Arc::new(SolidColor{ color: self.color(node, "color0", grey(0.4))? }),
+                odd: Arc::new(SolidColor{ color: self.color(node, "color1", grey(0.2))? }),
+                size: 0.5,
+                space: CheckerSpace::Uv
+            }),
+            _ => Arc::new(SolidColor{ color: grey(0.5) })
+        };
+
+        Ok(texture)
+    }
+
+    fn shape(&self, node: Node) -> Result<Option<Box<dyn Hittable>>, LoadError> {
+        let to_world = self.transform(node)?;
+        let material: Arc<dyn Material> = match area_emitter(node) {
+            Some(emitter) => Arc::new(Emissive{ radiance: self.color(emitter, "radiance", grey(1.0))? }),
+            None => match self.nested_bsdf(node)? {
+                Some(material) => material,
+                None => Arc::new(Lambertian{ albedo: Arc::new(SolidColor{ color: grey(0.5) }) })
+            }
+        };
+
+        let shape: Box<dyn Hittable> = match self.required(node, "type")?.as_str() {
+            "sphere" => {
+                let center = match self.property(node, "center") {
+                    Some(center) => self.point(center)?,
+                    None => OG
+                };
+                let sphere = Sphere{ center, radius: self.float(node, "radius", 1.0)?, material };
+                Box::new(Instance::new(Arc::new(sphere), to_world))
+            },
+            // The square [-1, 1]² of the xy plane facing z.
+            "rectangle" => Box::new(Quad {
+                corner: to_world.apply_point(Vector{ x: -1.0, y: -1.0, z: 0.0 }),
+                u: to_world.apply_vector(2.0 * EX),
+                v: to_world.apply_vector(2.0 * EY),
+                material
+            }),
+            // The cube [-1, 1]³.
+            "cube" => {
+                let vertices = (0 .. 8)
+                    .map(|k| Vector {
+                        x: if k & 1 == 0 { -1.0 } else { 1.0 },
+                        y: if k & 2 == 0 { -1.0 } else { 1.0 },
+                        z: if k & 4 == 0 { -1.0 } else { 1.0 }
+                    })
+                    .map(|p| to_world.apply_point(p))
+                    .collect();
+                let faces = vec![
+                    [0, 2, 3], [0, 3, 1], // -z
+                    [4, 5, 7], [4, 7, 6], // +z
+                    [0, 4, 6], [0, 6, 2], // -x
+                    [1, 3, 7], [1, 7, 5], // +x
+                    [0, 1, 5], [0, 5, 4], // -y
+                    [2, 6, 7], [2, 7, 3]  // +y
+                ];
+                Box::new(Mesh::new(vertices, faces, material))
+            },
+            "obj" => {
+                let filename = self.string(node, "filename")?
+                    .ok_or_else(|| LoadError::parse(self.line(node), "obj needs a filename"))?;
+                let mut mesh = load_obj(self.path(&filename), material)?;
+                mesh.transform(to_world);
+                Box::new(mesh)
+            },
+            "ply" => {
+                let filename = self.string(node, "filename")?
+                    .ok_or_else(|| LoadError::parse(self.line(node), "ply needs a filename"))?;
+                let mut mesh = load_ply(self.path(&filename), Some(material))?;
+                mesh.transform(to_world);
+                Box::new(mesh)
+            },
+            "instance" => {
+                let id = elements(node)
+                    .find(|child| child.has_tag_name("ref"))
+                    .map(|child| self.required(child, "id"))
+                    .transpose()?
+                    .ok_or_else(|| LoadError::parse(self.line(node), "instance needs a shape group"))?;
+                let group = self.groups.get(&id)
+                    .ok_or_else(|| LoadError::parse(self.line(node), format!("unknown shape group '{}'", id)))?;
+                Box::new(Instance::new(group.clone(), to_world))
+            },
+            _ => return Ok(None)
+        };
+
+        Ok(Some(shape))
+    }
+
+    /// Light sampling the glowing shape, for the rectangles and the
+    /// untransformed spheres with an `area` emitter.
+    fn area_light(&self, node: Node) -> Result<Option<Box<dyn Light>>, LoadError> {
+        let emitter = match area_emitter(node) {
+            Some(emitter) => emitter,
+            None => return Ok(None)
+        };
+        let radiance = self.color(emitter, "radiance", grey(1.0))?;
+        let to_world = self.transform(node)?;
+
+        let light: Box<dyn Light> = match self.required(node, "type")?.as_str() {
+            "sphere" if to_world == Transform::IDENTITY => {
+                let center = match self.property(node, "center") {
+                    Some(center) => self.point(center)?,
+                    None => OG
+                };
+                let radius = self.float(node, "radius", 1.0)?;
+                if radius <= 0.0 {
+                    return Ok(None);
+                }
+                Box::new(SphereLight{ center, radius, radiance })
+            },
+            "rectangle" => Box::new(AreaLight {
+                corner: to_world.apply_point(Vector{ x: -1.0, y: -1.0, z: 0.0 }),
+                u: to_world.apply_vector(2.0 * EX),
+                v: to_world.apply_vector(2.0 * EY),
+                radiance
+            }),
+            _ => return Ok(None)
+        };
+
+        Ok(Some(light))
+    }
+
+    fn emitter(&mut self, node: Node) -> Result<(), LoadError> {
+        match self.required(node, "type")?.as_str() {
+            "point" => {
+                let position = match self.property(node, "position") {
+                    Some(position) => self.point(position)?,
+                    None => self.transform(node)?.apply_point(OG)
+                };
+                self.world.lights.push(Box::new(PointLight {
+                    position,
+                    intensity: self.color(node, "intensity", grey(1.0))?
+                }));
+            },
+            "constant" => {
+                self.world.background = Background::Solid(self.color(node, "radiance", grey(1.0))?);
+            },
+            "envmap" => {
+                let filename = self.string(node, "filename")?
+                    .ok_or_else(|| LoadError::parse(self.line(node), "envmap needs a filename"))?;
+                let map = EnvironmentMap::load(self.path(&filename))?;
+                self.world.background = Background::Environment(Arc::new(map));
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Camera to world transformation of Mitsuba's `lookat`.
+fn look_at(origin: Vector, target: Vector, up: Vector) -> Option<Transform> {
+    let direction = (target - origin).unit();
+    let left = up.unit().cross(direction);
+    if left.is_near_zero() {
+        return None;
+    }
+    let left = left.unit();
+    let up = direction.cross(left);
+
+    let mut m = Mat4::IDENTITY;
+    for (k, column) in [left, up, direction, origin].iter().enumerate() {
+        m.m[0][k] = column.x;
+        m.m[1][k] = column.y;
+        m.m[2][k] = column.z;
+    }
+
+    Transform::from_matrix(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(body: &str) -> String {
+        format!(
+            "<scene version=\"3.0.0\">\n\
+             <sensor type=\"perspective\">\n\
+             <film type=\"hdrfilm\"><integer name=\"width\" value=\"64\"/><integer name=\"height\" value=\"32\"/></film>\n\
+             </sensor>\n{}\n</scene>\n",
+            body
+        )
+    }
+
+    fn error(source: &str) -> LoadError {
+        match parse_mitsuba(source, Path::new(".")) {
+            Ok(_) => panic!("{} loaded", source),
+            Err(err) => err
+        }
+    }
+
+    #[test]
+    fn area_emitters() {
+        let source = scene(
+            "<shape type=\"rectangle\">\n\
+               <transform name=\"to_world\"><scale value=\"0.3\"/><rotate x=\"1\" angle=\"90\"/><translate y=\"2\"/></transform>\n\
+               <emitter type=\"area\"><rgb name=\"radiance\" value=\"10\"/></emitter>\n\
+             </shape>\n\
+             <shape type=\"sphere\"><float name=\"radius\" value=\"0.5\"/></shape>\n\
+             <shape type=\"sphere\">\n\
+               <point name=\"center\" x=\"1\" y=\"0\" z=\"0\"/>\n\
+               <emitter type=\"area\"><rgb name=\"radiance\" value=\"1\"/></emitter>\n\
+             </shape>\n\
+             <shape type=\"sphere\">\n\
+               <transform name=\"to_world\"><scale value=\"2\"/></transform>\n\
+               <emitter type=\"area\"/>\n\
+             </shape>"
+        );
+        let scene = parse_mitsuba(&source, Path::new(".")).unwrap();
+        let world = &scene.world;
+        assert_eq!(world.objects.len(), 4);
+        assert_eq!(world.lights.len(), 2);
+        assert_eq!(world.emitters, HashMap::from([(0, 0), (2, 1)]));
+
+        // The rectangle of 0.6 × 0.6 faces down from 2 units above.
+        let below = OG;
+        let above = Vector{ x: 0.0, y: 4.0, z: 0.0 };
+        let q = Vector{ x: 0.0, y: 2.0, z: 0.0 };
+        assert!((world.lights[0].pdf(below, q) - 4.0 / 0.36).abs() < 1E-3);
+        assert_eq!(world.lights[0].pdf(above, q), 0.0);
+    }
+
+    #[test]
+    fn defaults() {
+        let source = "<scene version=\"0.6.0\">\n\
+                      <default name=\"spp\" value=\"8\"/>\n\
+                      <integrator type=\"path\"><integer name=\"maxDepth\" value=\"3\"/></integrator>\n\
+                      <sensor type=\"perspective\">\n\
+                      <sampler type=\"independent\"><integer name=\"sampleCount\" value=\"$spp\"/></sampler>\n\
+                      </sensor>\n\
+                      </scene>\n";
+        let scene = parse_mitsuba(source, Path::new(".")).unwrap();
+        assert_eq!(scene.settings.samples_per_pixel, 8);
+        assert_eq!(scene.settings.max_depth, 3);
+        assert_eq!((scene.settings.width, scene.settings.height), (768, 576));
+
+        let source = source.replace("<default name=\"spp\" value=\"8\"/>", "");
+        match error(&source) {
+            LoadError::Parse { line, message } => {
+                assert_eq!(line, 5);
+                assert!(message.contains("no default for 'spp'"), "{}", message);
+            },
+            err => panic!("{}", err)
+        }
+    }
+
+    #[test]
+    fn instances() {
+        let source = scene(
+            "<shape type=\"shapegroup\" id=\"pair\">\n\
+               <shape type=\"sphere\"/>\n\
+               <shape type=\"cube\"/>\n\
+             </shape>\n\
+             <shape type=\"instance\"><ref id=\"pair\"/></shape>\n\
+             <shape type=\"instance\"><ref id=\"pair\"/><transform name=\"to_world\"><translate x=\"3\"/></transform></shape>"
+        );
+        assert_eq!(parse_mitsuba(&source, Path::new(".")).unwrap().world.objects.len(), 2);
+
+        let source = scene("<shape type=\"instance\"><ref id=\"pair\"/></shape>");
+        assert!(matches!(error(&source), LoadError::Parse { line: 5, .. }));
+        assert!(matches!(error("<scene><shape/></scene>"), LoadError::Parse { line: 1, .. }));
+        assert!(matches!(error("<film/>"), LoadError::Invalid(_)));
+        assert!(matches!(error("<scene>"), LoadError::Invalid(_)));
+    }
+
+    #[test]
+    fn tiny_film() {
+        let source = scene("").replace("value=\"32\"", "value=\"1\"");
+        assert!(matches!(error(&source), LoadError::Parse { line: 3, .. }));
+    }
+}
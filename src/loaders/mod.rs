@@ -0,0 +1,124 @@
+//! Importers for scene and model files.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use image::ImageError;
+
+use crate::camera::Camera;
+use crate::math::Vector;
+
+pub mod gltf;
+pub mod mitsuba;
+pub mod obj;
+pub mod pbrt;
+pub mod ply;
+pub mod script;
+pub mod stl;
+pub mod vox;
+
+/// Anything that can go wrong while reading a file.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Image(ImageError),
+    Parse { line: usize, message: String },
+    Invalid(String)
+}
+
+impl LoadError {
+    pub fn parse<S: Into<String>>(line: usize, message: S) -> Self {
+        LoadError::Parse { line, message: message.into() }
+    }
+
+    pub fn invalid<S: Into<String>>(message: S) -> Self {
+        LoadError::Invalid(message.into())
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Image(err) => write!(f, "{}", err),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::Invalid(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Image(err) => Some(err),
+            LoadError::Parse { .. } | LoadError::Invalid(_) => None
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<ImageError> for LoadError {
+    fn from(err: ImageError) -> Self {
+        LoadError::Image(err)
+    }
+}
+
+/// Image side the field of view of a camera is measured along.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum FovAxis {
+    X,
+    Y,
+    Diagonal,
+    Smaller,
+    Larger
+}
+
+/// Convert a field of view in degrees measured along `axis` to the
+/// vertical one.
+pub(crate) fn vertical_fov(fov: f32, axis: FovAxis, aspect_ratio: f32) -> f32 {
+    let axis = match axis {
+        FovAxis::Smaller if aspect_ratio >= 1.0 => FovAxis::Y,
+        FovAxis::Smaller => FovAxis::X,
+        FovAxis::Larger if aspect_ratio >= 1.0 => FovAxis::X,
+        FovAxis::Larger => FovAxis::Y,
+        axis => axis
+    };
+
+    let h = (fov.to_radians() / 2.0).tan();
+    let h = match axis {
+        FovAxis::X => h / aspect_ratio,
+        FovAxis::Diagonal => h / (1.0 + aspect_ratio * aspect_ratio).sqrt(),
+        _ => h
+    };
+
+    2.0 * h.atan().to_degrees()
+}
+
+/// Camera at `origin` looking along `direction` whose image x axis
+/// goes along `right`. Other renderers may use left-handed coordinate
+/// systems, where our usual camera would mirror the image.
+pub(crate) fn framed_camera(
+    origin: Vector,
+    direction: Vector,
+    up: Vector,
+    right: Vector,
+    vfov: f32,
+    aspect_ratio: f32
+) -> Camera {
+    let mut camera = Camera::new(origin, origin + direction, up, vfov, aspect_ratio);
+
+    if camera.u.dot(right) < 0.0 {
+        camera.lower_left_corner += camera.horizontal;
+        camera.horizontal = -camera.horizontal;
+        camera.u = -camera.u;
+    }
+
+    camera
+}
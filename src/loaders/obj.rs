@@ -0,0 +1,163 @@
+//! Wavefront OBJ models.
+//!
+//! Only the geometry is read: vertex positions (`v`), normals (`vn`)
+//! and faces (`f`). Polygons with more than three vertices are split
+//! into triangle fans. A position used with different normals, as on
+//! the sharp edges of a model, becomes a vertex of the mesh for each.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::geometry::Mesh;
+use crate::material::Material;
+use crate::math::Vector;
+
+use super::LoadError;
+
+pub fn load_obj<P: AsRef<Path>>(path: P, material: Arc<dyn Material>) -> Result<Mesh, LoadError> {
+    let source = fs::read_to_string(path)?;
+    parse_obj(&source, material)
+}
+
+pub fn parse_obj(source: &str, material: Arc<dyn Material>) -> Result<Mesh, LoadError> {
+    let mut positions = vec![];
+    let mut normals = vec![];
+
+    // Vertices of the mesh by the indices of their position and normal.
+    let mut vertices: HashMap<(usize, Option<usize>), usize> = HashMap::new();
+    let mut mesh = Mesh::new(vec![], vec![], material);
+    let mut has_normals = false;
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some(keyword @ ("v" | "vn")) => {
+                let coords = tokens
+                    .take(3)
+                    .map(|token| token.parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(|err| LoadError::parse(number, err.to_string()))?;
+
+                if coords.len() != 3 {
+                    return Err(LoadError::parse(number, format!("{} needs three coordinates", keyword)));
+                }
+
+                let v = Vector{ x: coords[0], y: coords[1], z: coords[2] };
+                if keyword == "v" { positions.push(v) } else { normals.push(v) }
+            },
+            Some("f") => {
+                let mut indices = vec![];
+                for token in tokens {
+                    let key = parse_element(token, positions.len(), normals.len(), number)?;
+                    has_normals |= key.1.is_some();
+                    let index = *vertices.entry(key).or_insert_with(|| {
+                        mesh.vertices.push(positions[key.0]);
+                        mesh.normals.push(key.1.map_or(Vector{ x: 0.0, y: 0.0, z: 0.0 }, |n| normals[n]));
+                        mesh.vertices.len() - 1
+                    });
+                    indices.push(index);
+                }
+
+                if indices.len() < 3 {
+                    return Err(LoadError::parse(number, "face needs at least three vertices"));
+                }
+
+                for k in 1 .. indices.len() - 1 {
+                    mesh.faces.push([indices[0], indices[k], indices[k + 1]]);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // Vertices of the faces without normals are left with zero ones,
+    // which the rest of the face makes up for.
+    if !has_normals {
+        mesh.normals.clear();
+    }
+
+    Ok(mesh)
+}
+
+/// Resolve a face element such as `3`, `3/1`, `3/1/2` or `-1//2` into
+/// the indices of its position and its normal, if it has one. OBJ
+/// indices start at one and negative ones count from the last position
+/// or normal defined so far.
+fn parse_element(token: &str, positions: usize, normals: usize, number: usize) -> Result<(usize, Option<usize>), LoadError> {
+    let mut parts = token.split('/');
+    let position = resolve(parts.next().unwrap_or(""), positions, token, number)?;
+    let normal = match parts.nth(1) {
+        Some(part) if !part.is_empty() => Some(resolve(part, normals, token, number)?),
+        _ => None
+    };
+    Ok((position, normal))
+}
+
+fn resolve(part: &str, count: usize, token: &str, number: usize) -> Result<usize, LoadError> {
+    let index: i64 = part
+        .parse()
+        .map_err(|_| LoadError::parse(number, format!("bad face index '{}'", token)))?;
+
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(LoadError::parse(number, format!("face index {} out of range", index)));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian{ albedo: Arc::new(SolidColor{ color: Vector{ x: 0.5, y: 0.5, z: 0.5 } }) })
+    }
+
+    #[test]
+    fn quads_become_fans() {
+        let mesh = parse_obj("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n", material()).unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.normals.is_empty());
+    }
+
+    #[test]
+    fn negative_indices_and_normals() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nvn 0 0 -1\nf -3//1 -2//1 -1//1\nf 1//2 2//1 3//1\n";
+        let mesh = parse_obj(source, material()).unwrap();
+        // The first position comes with two normals, so it is two vertices.
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [3, 1, 2]]);
+        assert_eq!(mesh.normals[3], Vector{ x: 0.0, y: 0.0, z: -1.0 });
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        for face in ["f 1 2 4", "f 0 1 2", "f -4 1 2", "f 1//1 2 3"] {
+            let source = format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{}\n", face);
+            match parse_obj(&source, material()) {
+                Err(LoadError::Parse { line, message }) => {
+                    assert_eq!(line, 4);
+                    assert!(message.contains("out of range"), "{}", message);
+                },
+                other => panic!("{} loaded as {:?}", face, other.map(|mesh| mesh.faces))
+            }
+        }
+    }
+
+    #[test]
+    fn bad_lines() {
+        assert!(matches!(parse_obj("v 0 0\n", material()), Err(LoadError::Parse { line: 1, .. })));
+        assert!(matches!(parse_obj("v 0 0 0\nv 1 0 0\nf 1 2\n", material()), Err(LoadError::Parse { line: 3, .. })));
+        assert!(matches!(parse_obj("v 0 0 0\nf 1 x 1\n", material()), Err(LoadError::Parse { line: 2, .. })));
+    }
+}
@@ -0,0 +1,915 @@
+//! PBRT v3 scene files.
+//!
+//! A useful subset of the format is understood:
+//!
+//! - `Film` resolution, `Sampler` pixel samples and `Integrator` maximum
+//!   depth go into the render settings;
+//! - the `perspective` camera with its field of view and thin lens;
+//! - transformations, attributes, named coordinate systems, `Include`
+//!   and object instancing;
+//! - `sphere`, `trianglemesh` and `plymesh` shapes, with `diffuse` area
+//!   lights making them emissive, and sampling spheres and triangle
+//!   meshes as lights;
+//! - `matte`, `plastic`, `uber`, `substrate`, `metal`, `mirror`, `glass`
+//!   and `disney` materials, named or not;
+//! - `constant`, `imagemap` and `checkerboard` color textures;
+//! - `point` and `infinite` lights, the latter becoming the background.
+//!
+//! Spectra given as wavelength-value pairs are averaged into grey. The
+//! rest, such as other shapes, lights and textures or media, is skipped
+//! and unknown materials fall back to `matte`. PBRT uses a left-handed
+//! coordinate system, the camera is set up so that the images are not
+//! mirrored.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::animation::Animation;
+use crate::background::{Background, EnvironmentMap};
+use crate::geometry::{Group, Hittable, Instance, Mesh, Sphere, Triangle, World};
+use crate::light::{Light, PointLight, SphereLight, TriangleLight};
+use crate::material::{Dielectric, Emissive, Lambertian, Material, Metal, Pbr, Principled};
+use crate::math::{Mat4, Transform, Vector, EX, EY, EZ, OG};
+use crate::microfacet::conductor_f0;
+use crate::render::Settings;
+use crate::scene::Scene;
+use crate::texture::{Checker, CheckerSpace, ImageTexture, SolidColor, Texture};
+
+use super::ply::load_ply;
+use super::{framed_camera, vertical_fov, FovAxis, LoadError};
+
+pub fn load_pbrt<P: AsRef<Path>>(path: P) -> Result<Scene, LoadError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut importer = Importer::new(base);
+    importer.includes.push(fs::canonicalize(path)?);
+    importer.run(&source)?;
+    importer.finish()
+}
+
+/// Build a scene from the PBRT source, resolving the relative paths
+/// against `base`.
+pub fn parse_pbrt(source: &str, base: &Path) -> Result<Scene, LoadError> {
+    let mut importer = Importer::new(base);
+    importer.run(source)?;
+    importer.finish()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Num(f32),
+    Open,
+    Close
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, LoadError> {
+    let mut tokens = vec![];
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut chars = line.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            match c {
+                '#' => break,
+                '[' => { chars.next(); tokens.push((number, Token::Open)); },
+                ']' => { chars.next(); tokens.push((number, Token::Close)); },
+                '"' => {
+                    chars.next();
+                    let string: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                    tokens.push((number, Token::Str(string)));
+                },
+                c if c.is_whitespace() => { chars.next(); },
+                _ => {
+                    let mut word = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || "[]\"#".contains(c) {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+
+                    let token = if word.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                        Token::Word(word)
+                    } else {
+                        Token::Num(word.parse().map_err(|_| LoadError::parse(number, format!("bad number '{}'", word)))?)
+                    };
+                    tokens.push((number, token));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Num(f32),
+    Str(String)
+}
+
+/// A directive with its arguments. A bare value and a bracketed list are
+/// both stored as lists.
+struct Directive {
+    name: String,
+    line: usize,
+    args: Vec<Vec<Value>>
+}
+
+fn directives(tokens: Vec<(usize, Token)>) -> Result<Vec<Directive>, LoadError> {
+    let mut result: Vec<Directive> = vec![];
+    let mut tokens = tokens.into_iter();
+
+    while let Some((line, token)) = tokens.next() {
+        let value = match token {
+            Token::Word(name) => {
+                result.push(Directive { name, line, args: vec![] });
+                continue;
+            },
+            Token::Num(x) => vec![Value::Num(x)],
+            Token::Str(s) => vec![Value::Str(s)],
+            Token::Open => {
+                let mut list = vec![];
+                loop {
+                    match tokens.next() {
+                        Some((_, Token::Num(x))) => list.push(Value::Num(x)),
+                        Some((_, Token::Str(s))) => list.push(Value::Str(s)),
+                        Some((_, Token::Close)) => break,
+                        _ => return Err(LoadError::parse(line, "unterminated list"))
+                    }
+                }
+                list
+            },
+            Token::Close => return Err(LoadError::parse(line, "unexpected ']'"))
+        };
+
+        match result.last_mut() {
+            Some(directive) => directive.args.push(value),
+            None => return Err(LoadError::parse(line, "expected a directive"))
+        }
+    }
+
+    Ok(result)
+}
+
+impl Directive {
+    /// All the arguments as numbers, for the transformation directives.
+    fn numbers(&self, count: usize) -> Result<Vec<f32>, LoadError> {
+        let numbers = self.args.iter()
+            .flatten()
+            .map(|value| match value {
+                Value::Num(x) => Ok(*x),
+                Value::Str(_) => Err(LoadError::parse(self.line, format!("{} expects numbers", self.name)))
+            })
+            .collect::<Result<Vec<f32>, _>>()?;
+
+        if numbers.len() != count {
+            return Err(LoadError::parse(self.line, format!("{} expects {} numbers", self.name, count)));
+        }
+
+        Ok(numbers)
+    }
+
+    /// The leading string argument, such as the type of a shape.
+    fn string(&self, index: usize) -> Result<&str, LoadError> {
+        match self.args.get(index).map(|arg| &arg[..]) {
+            Some([Value::Str(s)]) => Ok(s),
+            _ => Err(LoadError::parse(self.line, format!("{} expects a string", self.name)))
+        }
+    }
+
+    /// Parameter list following the first `skip` arguments.
+    fn params(&self, skip: usize) -> Result<Params, LoadError> {
+        let mut list = vec![];
+        let mut args = self.args.iter().skip(skip);
+
+        while let Some(decl) = args.next() {
+            let decl = match &decl[..] {
+                [Value::Str(s)] => s,
+                _ => return Err(LoadError::parse(self.line, "expected a parameter declaration"))
+            };
+
+            let mut words = decl.split_whitespace();
+            let (ty, name) = match (words.next(), words.next()) {
+                (Some(ty), Some(name)) => (ty.to_string(), name.to_string()),
+                _ => return Err(LoadError::parse(self.line, format!("bad parameter '{}'", decl)))
+            };
+            let values = args.next()
+                .ok_or_else(|| LoadError::parse(self.line, format!("parameter '{}' has no value", name)))?
+                .clone();
+
+            list.push(Param { ty, name, values });
+        }
+
+        Ok(Params { line: self.line, list })
+    }
+}
+
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<Value>
+}
+
+struct Params {
+    line: usize,
+    list: Vec<Param>
+}
+
+/// Color parameter, either given in place or naming a texture.
+enum Spectrum {
+    Color(Vector),
+    Texture(String)
+}
+
+impl Params {
+    fn find(&self, name: &str) -> Option<&Param> {
+        self.list.iter().find(|param| param.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Result<Option<Vec<f32>>, LoadError> {
+        let param = match self.find(name) {
+            Some(param) if param.ty != "texture" && param.ty != "string" => param,
+            _ => return Ok(None)
+        };
+
+        param.values.iter()
+            .map(|value| match value {
+                Value::Num(x) => Ok(*x),
+                Value::Str(_) => Err(LoadError::parse(self.line, format!("'{}' expects numbers", name)))
+            })
+            .collect::<Result<Vec<f32>, _>>()
+            .map(Some)
+    }
+
+    /// Single number, or `default` if the parameter is missing or bound
+    /// to a texture.
+    fn float(&self, name: &str, default: f32) -> Result<f32, LoadError> {
+        Ok(self.numbers(name)?.and_then(|x| x.first().copied()).unwrap_or(default))
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.find(name).map(|param| &param.values[..]) {
+            Some([Value::Str(s)]) => Some(s),
+            _ => None
+        }
+    }
+
+    fn point(&self, name: &str, default: Vector) -> Result<Vector, LoadError> {
+        match self.numbers(name)?.as_deref() {
+            Some([x, y, z]) => Ok(Vector{ x: *x, y: *y, z: *z }),
+            Some(_) => Err(LoadError::parse(self.line, format!("'{}' expects three numbers", name))),
+            None => Ok(default)
+        }
+    }
+
+    fn spectrum(&self, name: &str) -> Result<Option<Spectrum>, LoadError> {
+        let param = match self.find(name) {
+            Some(param) => param,
+            None => return Ok(None)
+        };
+
+        if param.ty == "texture" {
+            return match &param.values[..] {
+                [Value::Str(s)] => Ok(Some(Spectrum::Texture(s.clone()))),
+                _ => Err(LoadError::parse(self.line, format!("'{}' expects a texture name", name)))
+            };
+        }
+
+        let values = self.numbers(name)?.unwrap_or_default();
+        let color = match (param.ty.as_str(), &values[..]) {
+            ("rgb" | "color", [r, g, b]) => Vector{ x: *r, y: *g, z: *b },
+            ("float", [x]) => Vector{ x: *x, y: *x, z: *x },
+            ("spectrum", pairs) if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+                let grey = pairs.iter().skip(1).step_by(2).sum::<f32>() / (pairs.len() / 2) as f32;
+                Vector{ x: grey, y: grey, z: grey }
+            },
+            _ => return Err(LoadError::parse(self.line, format!("unsupported spectrum '{}'", name)))
+        };
+
+        Ok(Some(Spectrum::Color(color)))
+    }
+
+    /// Constant color of a parameter that cannot be textured, such as
+    /// the radiance of a light.
+    fn color(&self, name: &str, default: Vector) -> Result<Vector, LoadError> {
+        match self.spectrum(name)? {
+            Some(Spectrum::Color(color)) => Ok(color),
+            Some(Spectrum::Texture(_)) => Err(LoadError::parse(self.line, format!("'{}' cannot be textured", name))),
+            None => Ok(default)
+        }
+    }
+}
+
+fn grey(x: f32) -> Vector {
+    Vector{ x, y: x, z: x }
+}
+
+/// Glowing object of the world and the light sampling it.
+type Emitter = (Box<dyn Hittable>, Box<dyn Light>);
+
+/// Attributes saved and restored by `AttributeBegin` and `AttributeEnd`.
+#[derive(Clone)]
+struct State {
+    transform: Transform,
+    material: Option<Arc<dyn Material>>, // None for the invisible "none"
+    area_light: Option<Vector>
+}
+
+struct Importer {
+    base: PathBuf,
+    includes: Vec<PathBuf>, // Files being read, innermost last, to catch `Include` cycles
+    state: State,
+    stack: Vec<State>,
+    transforms: Vec<Transform>, // Saved by `TransformBegin`
+    coordinate_systems: HashMap<String, Transform>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Option<Arc<dyn Material>>>,
+    instances: HashMap<String, Arc<Group>>,
+    object: Option<(String, Group)>, // Being defined between `ObjectBegin` and `ObjectEnd`
+    camera: (Transform, Params), // Camera to world transformation
+    settings: Settings,
+    world: World
+}
+
+impl Importer {
+    fn new(base: &Path) -> Self {
+        let state = State {
+            transform: Transform::IDENTITY,
+            material: Some(Arc::new(Lambertian{ albedo: Arc::new(SolidColor{ color: grey(0.5) }) })),
+            area_light: None
+        };
+
+        let mut world = World::new();
+        world.background = Background::Solid(grey(0.0));
+
+        Self {
+            base: base.to_path_buf(),
+            includes: vec![],
+            state,
+            stack: vec![],
+            transforms: vec![],
+            coordinate_systems: HashMap::new(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            instances: HashMap::new(),
+            object: None,
+            camera: (Transform::IDENTITY, Params { line: 0, list: vec![] }),
+            settings: Settings {
+                width: 1280,
+                height: 720,
+                samples_per_pixel: 16,
+                max_depth: 5,
+                ..Settings::default()
+            },
+            world
+        }
+    }
+
+    fn run(&mut self, source: &str) -> Result<(), LoadError> {
+        for directive in directives(tokenize(source)?)? {
+            self.directive(&directive)?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, d: &Directive) -> Result<(), LoadError> {
+        match d.name.as_str() {
+            "Include" => {
+                let path = fs::canonicalize(self.base.join(d.string(0)?))?;
+                if self.includes.contains(&path) {
+                    return Err(LoadError::parse(d.line, format!("{} includes itself", path.display())));
+                }
+                let source = fs::read_to_string(&path)?;
+                self.includes.push(path);
+                self.run(&source)?;
+                self.includes.pop();
+            },
+
+            // Transformations
+            "Identity" => self.state.transform = Transform::IDENTITY,
+            "Translate" => {
+                let t = d.numbers(3)?;
+                self.concat(Transform::translate(Vector{ x: t[0], y: t[1], z: t[2] }));
+            },
+            "Scale" => {
+                let s = d.numbers(3)?;
+                self.concat(Transform::scale(Vector{ x: s[0], y: s[1], z: s[2] }));
+            },
+            "Rotate" => {
+                let r = d.numbers(4)?;
+                self.concat(Transform::rotate(Vector{ x: r[1], y: r[2], z: r[3] }, r[0].to_radians()));
+            },
+            "LookAt" => {
+                let l = d.numbers(9)?;
+                let transform = look_at(
+                    Vector{ x: l[0], y: l[1], z: l[2] },
+                    Vector{ x: l[3], y: l[4], z: l[5] },
+                    Vector{ x: l[6], y: l[7], z: l[8] }
+                ).ok_or_else(|| LoadError::parse(d.line, "degenerate LookAt"))?;
+                self.concat(transform);
+            },
+            "Transform" | "ConcatTransform" => {
+                let transform = matrix(&d.numbers(16)?)
+                    .ok_or_else(|| LoadError::parse(d.line, "singular transformation"))?;
+                if d.name == "Transform" {
+                    self.state.transform = transform;
+                } else {
+                    self.concat(transform);
+                }
+            },
+            "CoordinateSystem" => {
+                self.coordinate_systems.insert(d.string(0)?.to_string(), self.state.transform);
+            },
+            "CoordSysTransform" => {
+                let name = d.string(0)?;
+                self.state.transform = *self.coordinate_systems.get(name)
+                    .ok_or_else(|| LoadError::parse(d.line, format!("unknown coordinate system '{}'", name)))?;
+            },
+
+            // Scene-wide options
+            "Camera" => {
+                if d.string(0)? != "perspective" {
+                    return Err(LoadError::parse(d.line, format!("unsupported camera '{}'", d.string(0)?)));
+                }
+                self.camera = (self.state.transform.inverse(), d.params(1)?);
+                self.coordinate_systems.insert("camera".to_string(), self.camera.0);
+            },
+            "Film" => {
+                let params = d.params(1)?;
+                let width = params.float("xresolution", 1280.0)?;
+                let height = params.float("yresolution", 720.0)?;
+                if width < 2.0 || height < 2.0 {
+                    return Err(LoadError::parse(d.line, "the image must be at least 2×2 pixels"));
+                }
+                self.settings.width = width as usize;
+                self.settings.height = height as usize;
+            },
+            "Sampler" => {
+                let params = d.params(1)?;
+                self.settings.samples_per_pixel = params.float("pixelsamples", 16.0)?.max(1.0) as u32;
+            },
+            "Integrator" => {
+                let params = d.params(1)?;
+                self.settings.max_depth = params.float("maxdepth", 5.0)?.clamp(1.0, 255.0) as u8;
+            },
+            "WorldBegin" => {
+                self.state.transform = Transform::IDENTITY;
+                self.coordinate_systems.insert("world".to_string(), Transform::IDENTITY);
+            },
+
+            // Attributes
+            "AttributeBegin" => self.stack.push(self.state.clone()),
+            "AttributeEnd" => {
+                self.state = self.stack.pop()
+                    .ok_or_else(|| LoadError::parse(d.line, "unmatched AttributeEnd"))?;
+            },
+            "TransformBegin" => self.transforms.push(self.state.transform),
+            "TransformEnd" => {
+                self.state.transform = self.transforms.pop()
+                    .ok_or_else(|| LoadError::parse(d.line, "unmatched TransformEnd"))?;
+            },
+            "Texture" => {
+                // Float textures could only drive parameters that are
+                // constant here.
+                let name = d.string(0)?.to_string();
+                if d.string(1)? == "float" {
+                    return Ok(());
+                }
+                if let Some(texture) = self.texture(d.string(2)?, &d.params(3)?)? {
+                    self.textures.insert(name, texture);
+                }
+            },
+            "Material" => self.state.material = self.material(d.string(0)?, &d.params(1)?)?,
+            "MakeNamedMaterial" => {
+                let params = d.params(1)?;
+                let material = self.material(params.string("type").unwrap_or("matte"), &params)?;
+                self.materials.insert(d.string(0)?.to_string(), material);
+            },
+            "NamedMaterial" => {
+                let name = d.string(0)?;
+                self.state.material = self.materials.get(name)
+                    .ok_or_else(|| LoadError::parse(d.line, format!("unknown material '{}'", name)))?
+                    .clone();
+            },
+            "AreaLightSource" => {
+                let params = d.params(1)?;
+                let radiance = params.color("L", grey(1.0))? * params.color("scale", grey(1.0))?;
+                self.state.area_light = Some(radiance);
+            },
+            "LightSource" => self.light(d.string(0)?, &d.params(1)?)?,
+            "Shape" => {
+                if let Some(emitters) = self.emitters(d.string(0)?, &d.params(1)?)? {
+                    for (object, light) in emitters {
+                        self.world.emitters.insert(self.world.objects.len(), self.world.lights.len());
+                        self.world.lights.push(light);
+                        self.world.objects.push(object);
+                    }
+                } else if let Some(shape) = self.shape(d.string(0)?, &d.params(1)?)? {
+                    match &mut self.object {
+                        Some((_, group)) => group.objects.push(shape),
+                        None => self.world.objects.push(shape)
+                    }
+                }
+            },
+
+            // Instancing
+            "ObjectBegin" => {
+                self.stack.push(self.state.clone());
+                self.object = Some((d.string(0)?.to_string(), Group::default()));
+            },
+            "ObjectEnd" => {
+                let (name, group) = self.object.take()
+                    .ok_or_else(|| LoadError::parse(d.line, "unmatched ObjectEnd"))?;
+                self.instances.insert(name, Arc::new(group));
+                self.state = self.stack.pop()
+                    .ok_or_else(|| LoadError::parse(d.line, "unmatched ObjectEnd"))?;
+            },
+            "ObjectInstance" => {
+                let name = d.string(0)?;
+                let group = self.instances.get(name)
+                    .ok_or_else(|| LoadError::parse(d.line, format!("unknown object '{}'", name)))?;
+                self.world.objects.push(Box::new(Instance::new(group.clone(), self.state.transform)));
+            },
+
+            // Media, filters, accelerators and the like.
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Apply a transformation to whatever is defined next.
+    fn concat(&mut self, transform: Transform) {
+        self.state.transform = self.state.transform * transform;
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.base.join(path)
+    }
+
+    fn spectrum_texture(&self, params: &Params, name: &str, default: Vector) -> Result<Arc<dyn Texture>, LoadError> {
+        let color = match params.spectrum(name)? {
+            Some(Spectrum::Color(color)) => color,
+            Some(Spectrum::Texture(texture)) => match self.textures.get(&texture) {
+                Some(texture) => return Ok(texture.clone()),
+                None => default
+            },
+            None => default
+        };
+
+        Ok(Arc::new(SolidColor{ color }))
+    }
+
+    /// Color texture of the given class, or `None` for an unsupported
+    /// one.
+    fn texture(&self, class: &str, params: &Params) -> Result<Option<Arc<dyn Texture>>, LoadError> {
+        let texture: Arc<dyn Texture> = match class {
+            "constant" => self.spectrum_texture(params, "value", grey(1.0))?,
+            "imagemap" => {
+                let filename = params.string("filename")
+                    .ok_or_else(|| LoadError::parse(params.line, "imagemap needs a filename"))?;
+                Arc::new(ImageTexture::load(self.path(filename))?)
+            },
+            "checkerboard" => Arc::new(Checker {
+                even: self.spectrum_texture(params, "tex1", grey(1.0))?,
+                odd: self.spectrum_texture(params, "tex2", grey(0.0))?,
+                size: 1.0 / params.float("uscale", 1.0)?,
+                space: CheckerSpace::Uv
+            }),
+            _ => return Ok(None)
+        };
+
+        Ok(Some(texture))
+    }
+
+    fn material(&self, ty: &str, params: &Params) -> Result<Option<Arc<dyn Material>>, LoadError> {
+        let roughness = |default: f32| -> Result<f32, LoadError> {
+            let u = params.float("uroughness", params.float("roughness", default)?)?;
+            let v = params.float("vroughness", params.float("roughness", default)?)?;
+            Ok(0.5 * (u + v))
+        };
+
+        let material: Arc<dyn Material> = match ty {
+            "" | "none" => return Ok(None),
+            "plastic" | "uber" | "substrate" => Arc::new(Pbr {
+                base_color: self.spectrum_texture(params, "Kd", grey(0.25))?,
+                metallic: 0.0,
+                roughness: roughness(0.1)?
+            }),
+            "metal" => {
+                // Reflectance at normal incidence from the complex index
+                // of refraction, copper by default.
+                let eta = params.color("eta", Vector{ x: 0.2, y: 0.92, z: 1.1 })?;
+                let k = params.color("k", Vector{ x: 3.9, y: 2.45, z: 2.14 })?;
+                Arc::new(Pbr {
+                    base_color: Arc::new(SolidColor{ color: conductor_f0(eta, k) }),
+                    metallic: 1.0,
+                    roughness: roughness(0.01)?
+                })
+            },
+            "mirror" => Arc::new(Metal::new(params.color("Kr", grey(0.9))?, 0.0)),
+            "glass" => Arc::new(Dielectric{ refractive_index: params.float("eta", params.float("index", 1.5)?)? }),
+            "disney" => Arc::new(Principled {
+                metallic: params.float("metallic", 0.0)?,
+                roughness: params.float("roughness", 0.5)?,
+                specular_tint: params.float("speculartint", 0.0)?,
+                sheen: params.float("sheen", 0.0)?,
+                sheen_tint: params.float("sheentint", 0.5)?,
+                clearcoat: params.float("clearcoat", 0.0)?,
+                clearcoat_gloss: params.float("clearcoatgloss", 1.0)?,
+                ..Principled::new(self.spectrum_texture(params, "color", grey(0.5))?)
+            }),
+            _ => Arc::new(Lambertian{ albedo: self.spectrum_texture(params, "Kd", grey(0.5))? })
+        };
+
+        Ok(Some(material))
+    }
+
+    fn light(&mut self, ty: &str, params: &Params) -> Result<(), LoadError> {
+        let scale = params.color("scale", grey(1.0))?;
+
+        match ty {
+            "point" => {
+                let from = params.point("from", OG)?;
+                self.world.lights.push(Box::new(PointLight {
+                    position: self.state.transform.apply_point(from),
+                    intensity: scale * params.color("I", grey(1.0))?
+                }));
+            },
+            "infinite" => {
+                self.world.background = match params.string("mapname") {
+                    Some(map) => Background::Environment(Arc::new(EnvironmentMap::load(self.path(map))?)),
+                    None => Background::Solid(scale * params.color("L", grey(1.0))?)
+                };
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Glowing shapes of the world under an area light together with
+    /// the lights sampling them: spheres that are not squashed, and the
+    /// triangles of meshes one by one. `None` for the rest, which are
+    /// only found by the rays that happen to hit them.
+    fn emitters(&self, ty: &str, params: &Params) -> Result<Option<Vec<Emitter>>, LoadError> {
+        let radiance = match (self.state.area_light, &self.object) {
+            (Some(radiance), None) => radiance,
+            _ => return Ok(None)
+        };
+        let transform = self.state.transform;
+        let material: Arc<dyn Material> = Arc::new(Emissive{ radiance });
+
+        let emitters: Vec<Emitter> = match ty {
+            "sphere" => {
+                // A sphere stays one if the axes are stretched alike
+                // and kept square to each other.
+                let (x, y, z) = (transform.apply_vector(EX), transform.apply_vector(EY), transform.apply_vector(EZ));
+                let eps = 1E-4 * x.sqnorm();
+                let alike = (y.sqnorm() - x.sqnorm()).abs() < eps && (z.sqnorm() - x.sqnorm()).abs() < eps;
+                let square = x.dot(y).abs() < eps && y.dot(z).abs() < eps && z.dot(x).abs() < eps;
+                let radius = x.norm() * params.float("radius", 1.0)?;
+                if !alike || !square || radius <= 0.0 {
+                    return Ok(None);
+                }
+
+                let center = transform.apply_point(OG);
+                vec![(
+                    Box::new(Sphere{ center, radius, material }),
+                    Box::new(SphereLight{ center, radius, radiance })
+                )]
+            },
+            "trianglemesh" => {
+                let (vertices, faces) = self.triangle_mesh(params)?;
+                faces.iter()
+                    .map(|&[i, j, k]| -> Emitter {
+                        let (a, b, c) = (vertices[i], vertices[j], vertices[k]);
+                        (
+                            Box::new(Triangle{ a, b, c, material: material.clone() }),
+                            Box::new(TriangleLight{ a, b, c, radiance })
+                        )
+                    })
+                    .collect()
+            },
+            _ => return Ok(None)
+        };
+
+        Ok(Some(emitters))
+    }
+
+    fn shape(&self, ty: &str, params: &Params) -> Result<Option<Box<dyn Hittable>>, LoadError> {
+        let material: Arc<dyn Material> = match (self.state.area_light, &self.state.material) {
+            (Some(radiance), _) => Arc::new(Emissive{ radiance }),
+            (None, Some(material)) => material.clone(),
+            (None, None) => return Ok(None)
+        };
+
+        let shape: Box<dyn Hittable> = match ty {
+            "sphere" => {
+                let sphere = Sphere {
+                    center: OG,
+                    radius: params.float("radius", 1.0)?,
+                    material
+                };
+                Box::new(Instance::new(Arc::new(sphere), self.state.transform))
+            },
+            "trianglemesh" => {
+                let (vertices, faces) = self.triangle_mesh(params)?;
+
+                let uv = match params.numbers("uv")? {
+                    Some(uv) => Some(uv),
+                    None => params.numbers("st")?
+                };
+                let texcoords = match uv {
+                    Some(uv) if uv.len() == 2 * vertices.len() => uv.chunks_exact(2).map(|t| [t[0], t[1]]).collect(),
+                    _ => vec![]
+                };
+
+                let normals = match params.numbers("N")? {
+                    Some(n) if n.len() == 3 * vertices.len() => n.chunks_exact(3)
+                        .map(|n| self.state.transform.apply_normal(Vector{ x: n[0], y: n[1], z: n[2] }))
+                        .collect(),
+                    _ => vec![]
+                };
+
+                Box::new(Mesh { texcoords, normals, ..Mesh::new(vertices, faces, material) })
+            },
+            "plymesh" => {
+                let filename = params.string("filename")
+                    .ok_or_else(|| LoadError::parse(params.line, "plymesh needs a filename"))?;
+                let mut mesh = load_ply(self.path(filename), Some(material))?;
+                mesh.transform(self.state.transform);
+                Box::new(mesh)
+            },
+            _ => return Ok(None)
+        };
+
+        Ok(Some(shape))
+    }
+
+    /// Vertices of a `trianglemesh`, moved to the world space right
+    /// away, and its faces.
+    fn triangle_mesh(&self, params: &Params) -> Result<(Vec<Vector>, Vec<[usize; 3]>), LoadError> {
+        let points = params.numbers("P")?.unwrap_or_default();
+        let indices = params.numbers("indices")?.unwrap_or_default();
+        if points.len() % 3 != 0 || indices.len() % 3 != 0 {
+            return Err(LoadError::parse(params.line, "trianglemesh needs triples of points and indices"));
+        }
+
+        let vertices: Vec<Vector> = points.chunks_exact(3)
+            .map(|p| self.state.transform.apply_point(Vector{ x: p[0], y: p[1], z: p[2] }))
+            .collect();
+        let faces: Vec<[usize; 3]> = indices.chunks_exact(3)
+            .map(|f| [f[0] as usize, f[1] as usize, f[2] as usize])
+            .collect();
+        if indices.iter().any(|&i| i < 0.0) || faces.iter().flatten().any(|&i| i >= vertices.len()) {
+            return Err(LoadError::parse(params.line, "trianglemesh index out of range"));
+        }
+
+        Ok((vertices, faces))
+    }
+
+    fn finish(self) -> Result<Scene, LoadError> {
+        let (camera_to_world, params) = &self.camera;
+        let settings = self.settings;
+        let aspect_ratio = settings.aspect_ratio();
+
+        // The field of view is the one of the shorter image side and
+        // PBRT cameras look along z with y up.
+        let vfov = vertical_fov(params.float("fov", 90.0)?, FovAxis::Smaller, aspect_ratio);
+        let mut camera = framed_camera(
+            camera_to_world.apply_point(OG),
+            camera_to_world.apply_vector(EZ),
+            camera_to_world.apply_vector(EY),
+            camera_to_world.apply_vector(EX),
+            vfov,
+            aspect_ratio
+        );
+
+        let lens_radius = params.float("lensradius", 0.0)?;
+        if lens_radius > 0.0 {
+            camera = camera.with_lens(2.0 * lens_radius, params.float("focaldistance", 1E6)?);
+        }
+
+        Ok(Scene { settings, camera, world: self.world, colors: vec![], animation: Animation::default() })
+    }
+}
+
+/// World to camera transformation of PBRT's `LookAt`.
+fn look_at(eye: Vector, target: Vector, up: Vector) -> Option<Transform> {
+    let direction = (target - eye).unit();
+    let right = up.unit().cross(direction);
+    if right.is_near_zero() {
+        return None;
+    }
+    let right = right.unit();
+    let up = direction.cross(right);
+
+    let mut m = Mat4::IDENTITY;
+    for (k, column) in [right, up, direction, eye].iter().enumerate() {
+        m.m[0][k] = column.x;
+        m.m[1][k] = column.y;
+        m.m[2][k] = column.z;
+    }
+
+    Transform::from_matrix(m).map(|camera_to_world| camera_to_world.inverse())
+}
+
+/// Transformation from the 16 matrix elements, listed column by column.
+fn matrix(elements: &[f32]) -> Option<Transform> {
+    let mut m = Mat4::IDENTITY;
+    for (k, &x) in elements.iter().enumerate() {
+        m.m[k % 4][k / 4] = x;
+    }
+    Transform::from_matrix(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(shape: &str) -> String {
+        format!(
+            "LookAt 0 0 -5  0 0 0  0 1 0\nCamera \"perspective\" \"float fov\" [45]\n\
+             Film \"image\" \"integer xresolution\" [64] \"integer yresolution\" [32]\n\
+             WorldBegin\n{}\nWorldEnd\n",
+            shape
+        )
+    }
+
+    fn error(source: &str) -> LoadError {
+        match parse_pbrt(source, Path::new(".")) {
+            Ok(_) => panic!("{} loaded", source),
+            Err(err) => err
+        }
+    }
+
+    #[test]
+    fn triangle_mesh() {
+        let source = scene("Shape \"trianglemesh\" \"point P\" [0 0 0  1 0 0  0 1 0  1 1 0] \"integer indices\" [0 1 2  2 1 3]");
+        let scene = parse_pbrt(&source, Path::new(".")).unwrap();
+        assert_eq!((scene.settings.width, scene.settings.height), (64, 32));
+        assert_eq!(scene.world.objects.len(), 1);
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        for indices in ["0 1 4", "0 -1 2"] {
+            let shape = format!("Shape \"trianglemesh\" \"point P\" [0 0 0  1 0 0  0 1 0  1 1 0] \"integer indices\" [{}]", indices);
+            match error(&scene(&shape)) {
+                LoadError::Parse { line, message } => {
+                    assert_eq!(line, 5);
+                    assert!(message.contains("out of range"), "{}", message);
+                },
+                err => panic!("{}", err)
+            }
+        }
+    }
+
+    #[test]
+    fn bad_triangle_mesh() {
+        let shape = "Shape \"trianglemesh\" \"point P\" [0 0 0  1 0] \"integer indices\" [0 1 2]";
+        assert!(matches!(error(&scene(shape)), LoadError::Parse { line: 5, .. }));
+    }
+
+    #[test]
+    fn tiny_film() {
+        let source = "Film \"image\" \"integer xresolution\" [64]\n  \"integer yresolution\" [1]\nWorldBegin\n";
+        assert!(matches!(error(source), LoadError::Parse { line: 1, .. }));
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = std::env::temp_dir();
+        let name = |file: &str| format!("rtrace-{}-{}.pbrt", std::process::id(), file);
+        fs::write(dir.join(name("a")), format!("Include \"{}\"\n", name("b"))).unwrap();
+        fs::write(dir.join(name("b")), format!("WorldBegin\nInclude \"{}\"\n", name("a"))).unwrap();
+
+        let result = load_pbrt(dir.join(name("a")));
+        fs::remove_file(dir.join(name("a"))).unwrap();
+        fs::remove_file(dir.join(name("b"))).unwrap();
+        match result {
+            Err(LoadError::Parse { line, message }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("includes itself"), "{}", message);
+            },
+            Err(err) => panic!("{}", err),
+            Ok(_) => panic!("the cycle loaded")
+        }
+    }
+
+    #[test]
+    fn bad_syntax() {
+        assert!(matches!(error("WorldBegin\nShape \"sphere\" \"float radius\" [1\n"), LoadError::Parse { line: 2, .. }));
+        assert!(matches!(error("WorldBegin\nAttributeEnd\n"), LoadError::Parse { line: 2, .. }));
+        assert!(matches!(error("WorldBegin\n]\n"), LoadError::Parse { line: 2, .. }));
+    }
+}
@@ -0,0 +1,373 @@
+//! PLY models, in the ASCII and both binary encodings.
+//!
+//! Read are the vertex positions (`x`, `y`, `z`), normals (`nx`, `ny`,
+//! `nz`), texture coordinates (`u`, `v` or `s`, `t`) and colors (`red`,
+//! `green`, `blue`), and the faces (`vertex_indices` or `vertex_index`).
+//! Polygons with more than three vertices are split into triangle fans.
+//! Other elements and properties are skipped.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::color::srgb_decode;
+use crate::geometry::Mesh;
+use crate::material::{Lambertian, Material};
+use crate::math::Vector;
+use crate::texture::SolidColor;
+
+use super::LoadError;
+
+/// Load a mesh with the given material. Without one, a white matte
+/// material lets the vertex colors show as they are, or a light grey
+/// one is used if there are no colors.
+pub fn load_ply<P: AsRef<Path>>(path: P, material: Option<Arc<dyn Material>>) -> Result<Mesh, LoadError> {
+    let data = fs::read(path)?;
+    parse_ply(&data, material)
+}
+
+pub fn parse_ply(data: &[u8], material: Option<Arc<dyn Material>>) -> Result<Mesh, LoadError> {
+    let (header, body) = header(data)?;
+    let mut reader: Box<dyn Reader> = match header.format {
+        Format::Ascii => {
+            let source = std::str::from_utf8(body)
+                .map_err(|_| LoadError::invalid("ASCII PLY body is not text"))?;
+            Box::new(AsciiReader{ tokens: source.split_whitespace() })
+        },
+        Format::BinaryLittleEndian => Box::new(BinaryReader{ data: body, little_endian: true }),
+        Format::BinaryBigEndian => Box::new(BinaryReader{ data: body, little_endian: false })
+    };
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut texcoords = vec![];
+    let mut colors = vec![];
+    let mut faces = vec![];
+
+    for element in &header.elements {
+        let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()));
+
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let uv = [find(&["u", "s", "texture_u"]), find(&["v", "t", "texture_v"])];
+        let color = [find(&["red", "r"]), find(&["green", "g"]), find(&["blue", "b"])];
+        let indices = find(&["vertex_indices", "vertex_index"]);
+
+        for _ in 0 .. element.count {
+            let mut row = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                row.push(match property.kind {
+                    Kind::Scalar(ty) => vec![reader.read(ty)?],
+                    Kind::List(count, ty) => {
+                        let count = reader.read(count)? as usize;
+                        (0 .. count).map(|_| reader.read(ty)).collect::<Result<Vec<f64>, _>>()?
+                    }
+                });
+            }
+            let scalar = |k: usize| row[k].first().copied().unwrap_or(0.0) as f32;
+
+            if element.name == "vertex" {
+                let [x, y, z] = position.map(|k| k.map_or(0.0, scalar));
+                vertices.push(Vector{ x, y, z });
+
+                if let [Some(nx), Some(ny), Some(nz)] = normal {
+                    normals.push(Vector{ x: scalar(nx), y: scalar(ny), z: scalar(nz) });
+                }
+                if let [Some(u), Some(v)] = uv {
+                    texcoords.push([scalar(u), scalar(v)]);
+                }
+                if let [Some(r), Some(g), Some(b)] = color {
+                    // Integer colors span the whole range of their type.
+                    let decode = |k: usize| {
+                        let c = scalar(k) / element.properties[k].kind.scale();
+                        srgb_decode(c.clamp(0.0, 1.0))
+                    };
+                    colors.push(Vector{ x: decode(r), y: decode(g), z: decode(b) });
+                }
+            } else if element.name == "face" {
+                if let Some(k) = indices {
+                    if row[k].iter().any(|&i| i < 0.0) {
+                        return Err(LoadError::invalid("PLY face index out of range"));
+                    }
+                    let polygon: Vec<usize> = row[k].iter().map(|&i| i as usize).collect();
+                    for k in 1 .. polygon.len().saturating_sub(1) {
+                        faces.push([polygon[0], polygon[k], polygon[k + 1]]);
+                    }
+                }
+            }
+        }
+    }
+
+    if faces.iter().flatten().any(|&i| i >= vertices.len()) {
+        return Err(LoadError::invalid("PLY face index out of range"));
+    }
+
+    let material = material.unwrap_or_else(|| {
+        let grey = if colors.is_empty() { 0.8 } else { 1.0 };
+        Arc::new(Lambertian{ albedo: Arc::new(SolidColor{ color: Vector{ x: grey, y: grey, z: grey } }) })
+    });
+
+    Ok(Mesh { texcoords, normals, colors, ..Mesh::new(vertices, faces, material) })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Type {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Type> {
+        let ty = match name {
+            "char" | "int8" => Type::I8,
+            "uchar" | "uint8" => Type::U8,
+            "short" | "int16" => Type::I16,
+            "ushort" | "uint16" => Type::U16,
+            "int" | "int32" => Type::I32,
+            "uint" | "uint32" => Type::U32,
+            "float" | "float32" => Type::F32,
+            "double" | "float64" => Type::F64,
+            _ => return None
+        };
+        Some(ty)
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 | Type::F32 => 4,
+            Type::F64 => 8
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    Scalar(Type),
+    List(Type, Type) // Types of the count and of the items
+}
+
+impl Kind {
+    /// Value standing for the full intensity of a color channel.
+    fn scale(self) -> f32 {
+        match self {
+            Kind::Scalar(Type::U8) => 255.0,
+            Kind::Scalar(Type::U16) => 65535.0,
+            _ => 1.0
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Kind
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>
+}
+
+/// Parse the header and split off the body following it.
+fn header(data: &[u8]) -> Result<(Header, &[u8]), LoadError> {
+    let marker = b"end_header";
+    let end = data.windows(marker.len())
+        .position(|window| window == marker)
+        .ok_or_else(|| LoadError::invalid("PLY header has no end"))?;
+
+    // The body starts on the line after the marker.
+    let mut start = end + marker.len();
+    while start < data.len() && data[start] != b'\n' {
+        start += 1;
+    }
+    let body = &data[(start + 1).min(data.len()) ..];
+
+    let text = std::str::from_utf8(&data[.. end])
+        .map_err(|_| LoadError::invalid("PLY header is not text"))?;
+    let mut lines = text.lines().enumerate().map(|(number, line)| (number + 1, line));
+
+    if lines.next().map(|(_, line)| line.trim()) != Some("ply") {
+        return Err(LoadError::parse(1, "not a PLY file"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+
+    for (number, line) in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(LoadError::parse(number, format!("unknown format '{}'", name)))
+                });
+            },
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| LoadError::parse(number, "bad element count"))?,
+                properties: vec![]
+            }),
+            ["property", "list", count, item, name] => {
+                let kind = match (Type::parse(count), Type::parse(item)) {
+                    (Some(count), Some(item)) => Kind::List(count, item),
+                    _ => return Err(LoadError::parse(number, "unknown property type"))
+                };
+                let element = elements.last_mut()
+                    .ok_or_else(|| LoadError::parse(number, "property outside of an element"))?;
+                element.properties.push(Property{ name: name.to_string(), kind });
+            },
+            ["property", ty, name] => {
+                let ty = Type::parse(ty)
+                    .ok_or_else(|| LoadError::parse(number, format!("unknown property type '{}'", ty)))?;
+                let element = elements.last_mut()
+                    .ok_or_else(|| LoadError::parse(number, "property outside of an element"))?;
+                element.properties.push(Property{ name: name.to_string(), kind: Kind::Scalar(ty) });
+            },
+            [] | ["comment", ..] | ["obj_info", ..] => {},
+            _ => return Err(LoadError::parse(number, format!("unexpected '{}'", line.trim())))
+        }
+    }
+
+    let format = format.ok_or_else(|| LoadError::invalid("PLY header has no format"))?;
+    Ok((Header{ format, elements }, body))
+}
+
+/// Source of the property values of the body.
+trait Reader {
+    fn read(&mut self, ty: Type) -> Result<f64, LoadError>;
+}
+
+struct AsciiReader<'a> {
+    tokens: std::str::SplitWhitespace<'a>
+}
+
+impl Reader for AsciiReader<'_> {
+    fn read(&mut self, _ty: Type) -> Result<f64, LoadError> {
+        let token = self.tokens.next().ok_or_else(|| LoadError::invalid("PLY body ends too early"))?;
+        token.parse().map_err(|_| LoadError::invalid(format!("bad PLY value '{}'", token)))
+    }
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    little_endian: bool
+}
+
+impl Reader for BinaryReader<'_> {
+    fn read(&mut self, ty: Type) -> Result<f64, LoadError> {
+        let size = ty.size();
+        if self.data.len() < size {
+            return Err(LoadError::invalid("PLY body ends too early"));
+        }
+
+        let mut bytes = [0; 8];
+        bytes[.. size].copy_from_slice(&self.data[.. size]);
+        if self.little_endian != cfg!(target_endian = "little") {
+            bytes[.. size].reverse();
+        }
+        self.data = &self.data[size ..];
+
+        let [b0, b1, b2, b3, ..] = bytes;
+        let value = match ty {
+            Type::I8 => b0 as i8 as f64,
+            Type::U8 => b0 as f64,
+            Type::I16 => i16::from_ne_bytes([b0, b1]) as f64,
+            Type::U16 => u16::from_ne_bytes([b0, b1]) as f64,
+            Type::I32 => i32::from_ne_bytes([b0, b1, b2, b3]) as f64,
+            Type::U32 => u32::from_ne_bytes([b0, b1, b2, b3]) as f64,
+            Type::F32 => f32::from_ne_bytes([b0, b1, b2, b3]) as f64,
+            Type::F64 => f64::from_ne_bytes(bytes)
+        };
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                          element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+    const VERTICES: &str = "0 0 0\n1 0 0\n1 1 0\n0 1 0\n";
+
+    fn ascii(face: &str) -> Vec<u8> {
+        format!("{}{}{}\n", HEADER, VERTICES, face).into_bytes()
+    }
+
+    #[test]
+    fn ascii_quad() {
+        let mesh = parse_ply(&ascii("4 0 1 2 3"), None).unwrap();
+        assert_eq!(mesh.vertices[2], Vector{ x: 1.0, y: 1.0, z: 0.0 });
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        for (format, little_endian) in [("binary_little_endian", true), ("binary_big_endian", false)] {
+            let mut data = HEADER.replace("ascii", format).into_bytes();
+            for v in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]] {
+                for c in v {
+                    data.extend(if little_endian { c.to_le_bytes() } else { c.to_be_bytes() });
+                }
+            }
+            data.push(4);
+            for i in [0i32, 1, 2, 3] {
+                data.extend(if little_endian { i.to_le_bytes() } else { i.to_be_bytes() });
+            }
+
+            let mesh = parse_ply(&data, None).unwrap();
+            let expected = parse_ply(&ascii("4 0 1 2 3"), None).unwrap();
+            assert_eq!(mesh.vertices, expected.vertices);
+            assert_eq!(mesh.faces, expected.faces);
+        }
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        for face in ["3 0 1 4", "3 0 -1 2", "4 0 1 2 7"] {
+            match parse_ply(&ascii(face), None) {
+                Err(LoadError::Invalid(message)) => assert!(message.contains("out of range"), "{}", message),
+                other => panic!("{} loaded as {:?}", face, other.map(|mesh| mesh.faces))
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_body() {
+        let data = ascii("4 0 1 2");
+        assert!(matches!(parse_ply(&data, None), Err(LoadError::Invalid(_))));
+        let data = HEADER.replace("ascii", "binary_little_endian").into_bytes();
+        assert!(matches!(parse_ply(&data, None), Err(LoadError::Invalid(_))));
+    }
+
+    #[test]
+    fn bad_header() {
+        assert!(matches!(parse_ply(b"obj\nend_header\n", None), Err(LoadError::Parse { line: 1, .. })));
+        assert!(matches!(parse_ply(b"ply\nformat ascii 1.0\n", None), Err(LoadError::Invalid(_))));
+        let data = HEADER.replace("float x", "quad x");
+        assert!(matches!(parse_ply(data.as_bytes(), None), Err(LoadError::Parse { line: 4, .. })));
+    }
+}
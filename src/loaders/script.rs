@@ -0,0 +1,1182 @@
+//! Scene scripts: scenes generated by a program rather than listed, such
+//! as grids of spheres and random layouts.
+//!
+//! The language is a small part of Rhai, with its syntax: `let` and
+//! `const`, integers and floats, strings, booleans, arrays `[1, 2]` and
+//! object maps `#{ radius: 0.5 }`, the usual operators, `if`/`else`,
+//! `while`, `loop`, `for x in 0..10` (or `0..=10`, or over an array),
+//! `break`, `continue`, and functions `fn name(a, b) { ... }` that see
+//! their parameters only and give the value of their last expression or
+//! `return`. Comments are `//` and `/* */`. The variable `frame` holds
+//! the frame of the animation being rendered, counting from 1.
+//!
+//! A script builds the tables a TOML scene file would have, with the
+//! same keys and values:
+//!
+//! - `render(#{...})`, `camera(#{...})`, `background(#{...})` and
+//!   `animation(#{...})` set the keys of those tables;
+//! - `texture(name, #{...})` and `material(name, #{...})` add named ones
+//!   and give the name back;
+//! - `add(#{...})` adds an object and `light(#{...})` a light;
+//! - `sphere(center, radius, material)` and `plane(point, normal,
+//!   material)` add those objects in short.
+//!
+//! There are also `sin`, `cos`, `tan`, `atan(y, x)`, `sqrt`, `abs`,
+//! `floor`, `ceil`, `pow`, `min`, `max`, `PI()`, `to_int`, `to_float`,
+//! `len`, `print`, and `rand()`, a random float in 0 .. 1, always the
+//! same sequence unless `seed(n)` starts another one.
+
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::LoadError;
+
+/// Most statements a script may run, so that a loop that never ends
+/// fails instead of hanging.
+const MAX_OPERATIONS: u64 = 100_000_000;
+
+/// Deepest the function calls may nest.
+const MAX_CALL_DEPTH: usize = 64;
+
+/// Run the script for the `frame` of the animation and return the scene
+/// it builds, as the table a TOML scene file would parse to.
+pub fn run_script(source: &str, frame: u32) -> Result<toml::Table, LoadError> {
+    let tokens = lex(source)?;
+    let mut parser = Parser { tokens, position: 0, functions: HashMap::new() };
+    let program = parser.program()?;
+
+    let mut interpreter = Interpreter {
+        functions: parser.functions,
+        scopes: vec![HashMap::new()],
+        scene: toml::Table::new(),
+        random: SEED,
+        operations: 0,
+        depth: 0,
+        line: 0
+    };
+    interpreter.scopes[0].insert("frame".to_string(), Value::Int(frame as i64));
+    match interpreter.block(&program) {
+        Ok(_) => Ok(interpreter.scene),
+        Err(message) => Err(LoadError::parse(interpreter.line, message))
+    }
+}
+
+/// State of `rand` at the start, and after `seed(0)`.
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Punct(&'static str)
+}
+
+/// Operators and punctuation, the longer ones first so that they win.
+const PUNCTUATION: [&str; 32] = [
+    "..=", "#{", "..", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=",
+    "(", ")", "[", "]", "{", "}", ",", ";", ":", ".", "=", "<", ">", "+", "-", "*", "/", "%"
+];
+
+fn lex(source: &str) -> Result<Vec<(usize, Token)>, LoadError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let (mut i, mut line) = (0, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                line += (chars[i] == '\n') as usize;
+                i += 1;
+            }
+            i += 2;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+                i += 1;
+            }
+            // A dot followed by a digit makes a float, two of them a range.
+            let float = chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(char::is_ascii_digit);
+            if float {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if matches!(chars.get(i), Some('e') | Some('E')) {
+                let sign = matches!(chars.get(i + 1), Some('+') | Some('-')) as usize;
+                if chars.get(i + 1 + sign).is_some_and(char::is_ascii_digit) {
+                    i += 1 + sign;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start .. i].iter().filter(|&&c| c != '_').collect();
+            let token = match text.parse::<i64>() {
+                Ok(n) => Token::Int(n),
+                Err(_) => Token::Float(text.parse().map_err(|_| LoadError::parse(line, format!("bad number {}", text)))?)
+            };
+            tokens.push((line, token));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((line, Token::Ident(chars[start .. i].iter().collect())));
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(LoadError::parse(line, "unterminated string")),
+                    Some('"') => break,
+                    Some('\\') => {
+                        text.push(match chars.get(i + 1) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(&c) => c,
+                            None => return Err(LoadError::parse(line, "unterminated string"))
+                        });
+                        i += 2;
+                    },
+                    Some(&c) => {
+                        line += (c == '\n') as usize;
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push((line, Token::Str(text)));
+        } else {
+            let rest: String = chars[i .. (i + 3).min(chars.len())].iter().collect();
+            let punct = PUNCTUATION.iter()
+                .chain(&["!"])
+                .find(|p| rest.starts_with(*p))
+                .ok_or_else(|| LoadError::parse(line, format!("unexpected {:?}", c)))?;
+            tokens.push((line, Token::Punct(punct)));
+            i += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Value(Value),
+    Var(String),
+    Array(Vec<Expr>),
+    Map(Vec<(String, Expr)>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Field(Box<Expr>, String),
+    Call(String, Vec<Expr>), // A method call `x.f(a)` is `f(x, a)`
+    If(Vec<(Expr, Block)>, Option<Block>),
+    Block(Block)
+}
+
+type Block = Vec<(usize, Stmt)>; // Statements with their lines
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(Place, &'static str, Expr), // `=` or one of `+=` and the like
+    Expr(Expr),
+    For(String, Iteration, Block),
+    While(Option<Expr>, Block), // `loop` has no condition
+    Break,
+    Continue,
+    Return(Option<Expr>)
+}
+
+#[derive(Debug, Clone)]
+enum Iteration {
+    Range(Expr, Expr, bool), // Whether the end is included
+    Each(Expr)
+}
+
+/// Variable, or an element or a field of one, that can be assigned to.
+#[derive(Debug, Clone)]
+struct Place {
+    name: String,
+    path: Vec<Access>
+}
+
+#[derive(Debug, Clone)]
+enum Access {
+    Index(Expr),
+    Field(String)
+}
+
+struct Function {
+    parameters: Vec<String>,
+    body: Block
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    functions: HashMap<String, Function>
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens.get(self.position).or_else(|| self.tokens.last()).map_or(1, |t| t.0)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, LoadError> {
+        Err(LoadError::parse(self.line(), message))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|t| &t.1)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).map(|t| t.1.clone());
+        self.position += 1;
+        token
+    }
+
+    fn is(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(name)) if name == keyword)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.is(punct);
+        self.position += found as usize;
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), LoadError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{}`", punct))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, LoadError> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            _ => {
+                self.position -= 1;
+                self.error("expected a name")
+            }
+        }
+    }
+
+    fn program(&mut self) -> Result<Block, LoadError> {
+        let mut block = vec![];
+        while self.peek().is_some() {
+            if self.is_keyword("fn") {
+                self.function()?;
+            } else if let Some(stmt) = self.statement()? {
+                block.push(stmt);
+            }
+        }
+        Ok(block)
+    }
+
+    fn function(&mut self) -> Result<(), LoadError> {
+        self.next();
+        let name = self.ident()?;
+        self.expect("(")?;
+        let mut parameters = vec![];
+        while !self.eat(")") {
+            parameters.push(self.ident()?);
+            if !self.is(")") {
+                self.expect(",")?;
+            }
+        }
+        let body = self.block()?;
+        self.functions.insert(name, Function { parameters, body });
+        Ok(())
+    }
+
+    fn block(&mut self) -> Result<Block, LoadError> {
+        self.expect("{")?;
+        let mut block = vec![];
+        while !self.eat("}") {
+            if self.peek().is_none() {
+                return self.error("expected `}`");
+            }
+            if let Some(stmt) = self.statement()? {
+                block.push(stmt);
+            }
+        }
+        Ok(block)
+    }
+
+    /// Next statement, or none for a lone `;`.
+    fn statement(&mut self) -> Result<Option<(usize, Stmt)>, LoadError> {
+        let line = self.line();
+        if self.eat(";") {
+            return Ok(None);
+        }
+        let keyword = match self.peek() {
+            Some(Token::Ident(name)) => name.clone(),
+            _ => String::new()
+        };
+        let stmt = match keyword.as_str() {
+            "let" | "const" => {
+                self.next();
+                let name = self.ident()?;
+                self.expect("=")?;
+                let value = self.expression()?;
+                self.end()?;
+                Stmt::Let(name, value)
+            },
+            "for" => {
+                self.next();
+                let name = self.ident()?;
+                if !self.is_keyword("in") {
+                    return self.error("expected `in`");
+                }
+                self.next();
+                let from = self.expression()?;
+                let iteration = if self.eat("..") {
+                    Iteration::Range(from, self.expression()?, false)
+                } else if self.eat("..=") {
+                    Iteration::Range(from, self.expression()?, true)
+                } else {
+                    Iteration::Each(from)
+                };
+                Stmt::For(name, iteration, self.block()?)
+            },
+            "while" => {
+                self.next();
+                let condition = self.expression()?;
+                Stmt::While(Some(condition), self.block()?)
+            },
+            "loop" => {
+                self.next();
+                Stmt::While(None, self.block()?)
+            },
+            "break" | "continue" => {
+                self.next();
+                self.end()?;
+                if keyword == "break" { Stmt::Break } else { Stmt::Continue }
+            },
+            "return" => {
+                self.next();
+                let value = if self.is(";") || self.is("}") { None } else { Some(self.expression()?) };
+                self.end()?;
+                Stmt::Return(value)
+            },
+            _ => {
+                let expr = self.expression()?;
+                let op = ["=", "+=", "-=", "*=", "/=", "%="].iter().find(|op| self.is(op)).copied();
+                match op {
+                    Some(op) => {
+                        self.next();
+                        let place = match place(expr) {
+                            Some(place) => place,
+                            None => return self.error("cannot assign to this")
+                        };
+                        let value = self.expression()?;
+                        self.end()?;
+                        Stmt::Assign(place, op, value)
+                    },
+                    None => {
+                        // Blocks and `if` need no `;` after them.
+                        if !matches!(expr, Expr::If(..) | Expr::Block(_)) {
+                            self.end()?;
+                        }
+                        Stmt::Expr(expr)
+                    }
+                }
+            }
+        };
+        Ok(Some((line, stmt)))
+    }
+
+    /// End of a statement: a `;`, or the `}` closing the block, which
+    /// makes it the value of the block.
+    fn end(&mut self) -> Result<(), LoadError> {
+        if self.eat(";") || self.is("}") || self.peek().is_none() {
+            Ok(())
+        } else {
+            self.error("expected `;`")
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, LoadError> {
+        self.binary(0)
+    }
+
+    /// Binary operators of the given precedence and higher.
+    fn binary(&mut self, level: usize) -> Result<Expr, LoadError> {
+        const LEVELS: [&[&str]; 5] = [&["||"], &["&&"], &["==", "!=", "<", "<=", ">", ">="], &["+", "-"], &["*", "/", "%"]];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = LEVELS[level].iter().find(|op| self.is(op)).copied() {
+            self.next();
+            let right = Box::new(self.binary(level + 1)?);
+            left = match op {
+                "||" => Expr::Or(Box::new(left), right),
+                "&&" => Expr::And(Box::new(left), right),
+                _ => Expr::Binary(op, Box::new(left), right)
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, LoadError> {
+        if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else if self.eat("!") {
+            Ok(Expr::Not(Box::new(self.unary()?)))
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, LoadError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.expression()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.eat(".") {
+                let name = self.ident()?;
+                if self.eat("(") {
+                    let mut args = vec![expr];
+                    args.extend(self.arguments()?);
+                    expr = Expr::Call(name, args);
+                } else {
+                    expr = Expr::Field(Box::new(expr), name);
+                }
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Arguments of a call, after the `(`.
+    fn arguments(&mut self) -> Result<Vec<Expr>, LoadError> {
+        let mut args = vec![];
+        while !self.eat(")") {
+            args.push(self.expression()?);
+            if !self.is(")") {
+                self.expect(",")?;
+            }
+        }
+        Ok(args)
+    }
+
+    fn primary(&mut self) -> Result<Expr, LoadError> {
+        match self.next() {
+            Some(Token::Int(n)) => Ok(Expr::Value(Value::Int(n))),
+            Some(Token::Float(x)) => Ok(Expr::Value(Value::Float(x))),
+            Some(Token::Str(s)) => Ok(Expr::Value(Value::Str(s))),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Value(Value::Bool(true))),
+                "false" => Ok(Expr::Value(Value::Bool(false))),
+                "if" => self.conditional(),
+                _ if self.eat("(") => Ok(Expr::Call(name, self.arguments()?)),
+                _ => Ok(Expr::Var(name))
+            },
+            Some(Token::Punct("(")) => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            Some(Token::Punct("[")) => {
+                let mut items = vec![];
+                while !self.eat("]") {
+                    items.push(self.expression()?);
+                    if !self.is("]") {
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Array(items))
+            },
+            Some(Token::Punct("#{")) => {
+                let mut fields = vec![];
+                while !self.eat("}") {
+                    let key = match self.next() {
+                        Some(Token::Ident(key)) | Some(Token::Str(key)) => key,
+                        _ => {
+                            self.position -= 1;
+                            return self.error("expected a key");
+                        }
+                    };
+                    self.expect(":")?;
+                    fields.push((key, self.expression()?));
+                    if !self.is("}") {
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Map(fields))
+            },
+            Some(Token::Punct("{")) => {
+                self.position -= 1;
+                Ok(Expr::Block(self.block()?))
+            },
+            _ => {
+                self.position -= 1;
+                self.error("expected an expression")
+            }
+        }
+    }
+
+    /// `if` with its branches, after the `if`.
+    fn conditional(&mut self) -> Result<Expr, LoadError> {
+        let mut branches = vec![(self.expression()?, self.block()?)];
+        while self.is_keyword("else") {
+            self.next();
+            if self.is_keyword("if") {
+                self.next();
+                branches.push((self.expression()?, self.block()?));
+            } else {
+                return Ok(Expr::If(branches, Some(self.block()?)));
+            }
+        }
+        Ok(Expr::If(branches, None))
+    }
+}
+
+/// Place the expression stands for, if it can be assigned to.
+fn place(expr: Expr) -> Option<Place> {
+    match expr {
+        Expr::Var(name) => Some(Place { name, path: vec![] }),
+        Expr::Index(base, index) => {
+            let mut place = place(*base)?;
+            place.path.push(Access::Index(*index));
+            Some(place)
+        },
+        Expr::Field(base, name) => {
+            let mut place = place(*base)?;
+            place.path.push(Access::Field(name));
+            Some(place)
+        },
+        _ => None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Value>),
+    Map(BTreeMap<String, Value>)
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "()",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map"
+        }
+    }
+
+    fn float(&self) -> Result<f64, String> {
+        match *self {
+            Value::Int(n) => Ok(n as f64),
+            Value::Float(x) => Ok(x),
+            _ => Err(format!("expected a number, not {}", self.type_name()))
+        }
+    }
+
+    fn int(&self) -> Result<i64, String> {
+        match *self {
+            Value::Int(n) => Ok(n),
+            _ => Err(format!("expected an int, not {}", self.type_name()))
+        }
+    }
+
+    fn bool(&self) -> Result<bool, String> {
+        match *self {
+            Value::Bool(b) => Ok(b),
+            _ => Err(format!("expected a bool, not {}", self.type_name()))
+        }
+    }
+
+    fn to_toml(&self) -> Result<toml::Value, String> {
+        Ok(match self {
+            Value::Bool(b) => toml::Value::Boolean(*b),
+            Value::Int(n) => toml::Value::Integer(*n),
+            Value::Float(x) => toml::Value::Float(*x),
+            Value::Str(s) => toml::Value::String(s.clone()),
+            Value::Array(items) => toml::Value::Array(items.iter().map(Value::to_toml).collect::<Result<_, _>>()?),
+            Value::Map(map) => toml::Value::Table(
+                map.iter().map(|(k, v)| Ok((k.clone(), v.to_toml()?))).collect::<Result<_, String>>()?
+            ),
+            Value::Unit => return Err("() has no place in a scene".to_string())
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            Value::Map(map) => {
+                let fields: Vec<String> = map.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "#{{{}}}", fields.join(", "))
+            }
+        }
+    }
+}
+
+/// How a statement left off.
+enum Flow {
+    Normal(Value), // The value of the expression statement, or ()
+    Break,
+    Continue,
+    Return(Value)
+}
+
+struct Interpreter {
+    functions: HashMap<String, Function>,
+    scopes: Vec<HashMap<String, Value>>, // Innermost last
+    scene: toml::Table,
+    random: u64,
+    operations: u64,
+    depth: usize,
+    line: usize // Of the statement running, for the errors
+}
+
+impl Interpreter {
+    /// Run the statements in a scope of their own.
+    fn block(&mut self, block: &Block) -> Result<Flow, String> {
+        self.scopes.push(HashMap::new());
+        let mut flow = Ok(Flow::Normal(Value::Unit));
+        for (line, stmt) in block {
+            self.line = *line;
+            flow = self.statement(stmt);
+            if !matches!(flow, Ok(Flow::Normal(_))) {
+                break;
+            }
+        }
+        self.scopes.pop();
+        flow
+    }
+
+    /// Count an operation against the limit.
+    fn tick(&mut self) -> Result<(), String> {
+        self.operations += 1;
+        if self.operations > MAX_OPERATIONS {
+            return Err("the script runs for too long".to_string());
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<Flow, String> {
+        self.tick()?;
+        match stmt {
+            Stmt::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                self.scopes.last_mut().unwrap().insert(name.clone(), value);
+            },
+            Stmt::Assign(place, op, expr) => {
+                let value = self.eval(expr)?;
+                let indices = place.path.iter()
+                    .map(|access| match access {
+                        Access::Index(expr) => self.eval(expr).map(Some),
+                        Access::Field(_) => Ok(None)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let target = self.place(place, &indices)?;
+                *target = match *op {
+                    "=" => value,
+                    op => binary(&op[.. 1], target, &value)?
+                };
+            },
+            // As statements, `if` and blocks pass `break`, `continue`
+            // and `return` on.
+            Stmt::Expr(Expr::If(branches, otherwise)) => {
+                for (condition, body) in branches {
+                    if self.eval(condition)?.bool()? {
+                        return self.block(body);
+                    }
+                }
+                if let Some(body) = otherwise {
+                    return self.block(body);
+                }
+            },
+            Stmt::Expr(Expr::Block(body)) => return self.block(body),
+            Stmt::Expr(expr) => return Ok(Flow::Normal(self.eval(expr)?)),
+            Stmt::For(name, iteration, body) => {
+                let items: Box<dyn Iterator<Item = Value>> = match iteration {
+                    Iteration::Range(from, to, inclusive) => {
+                        let (from, to) = (self.eval(from)?.int()?, self.eval(to)?.int()?);
+                        if *inclusive {
+                            Box::new((from ..= to).map(Value::Int))
+                        } else {
+                            Box::new((from .. to).map(Value::Int))
+                        }
+                    },
+                    Iteration::Each(expr) => match self.eval(expr)? {
+                        Value::Array(items) => Box::new(items.into_iter()),
+                        value => return Err(format!("cannot loop over {}", value.type_name()))
+                    }
+                };
+                for item in items {
+                    self.tick()?;
+                    self.scopes.push(HashMap::from([(name.clone(), item)]));
+                    let flow = self.block(body);
+                    self.scopes.pop();
+                    match flow? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        _ => {}
+                    }
+                }
+            },
+            Stmt::While(condition, body) => loop {
+                self.tick()?;
+                if let Some(condition) = condition {
+                    if !self.eval(condition)?.bool()? {
+                        break;
+                    }
+                }
+                match self.block(body)? {
+                    Flow::Break => break,
+                    Flow::Return(value) => return Ok(Flow::Return(value)),
+                    _ => {}
+                }
+            },
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+            Stmt::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.eval(expr)?,
+                    None => Value::Unit
+                };
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Normal(Value::Unit))
+    }
+
+    /// The value at the place, with the indices along its path already
+    /// evaluated.
+    fn place(&mut self, place: &Place, indices: &[Option<Value>]) -> Result<&mut Value, String> {
+        let mut value = self.scopes.iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&place.name))
+            .ok_or_else(|| format!("no variable `{}`", place.name))?;
+        for (access, index) in place.path.iter().zip(indices) {
+            value = match (access, index, value) {
+                (Access::Field(name), _, Value::Map(map)) => {
+                    map.entry(name.clone()).or_insert(Value::Unit)
+                },
+                (Access::Index(_), Some(Value::Int(i)), Value::Array(items)) => {
+                    let len = items.len();
+                    items.get_mut(*i as usize).ok_or_else(|| format!("index {} out of 0 .. {}", i, len))?
+                },
+                (Access::Index(_), Some(Value::Str(key)), Value::Map(map)) => {
+                    map.entry(key.clone()).or_insert(Value::Unit)
+                },
+                (_, _, value) => return Err(format!("cannot index {}", value.type_name()))
+            };
+        }
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Value(value) => value.clone(),
+            Expr::Var(name) => self.scopes.iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .cloned()
+                .ok_or_else(|| format!("no variable `{}`", name))?,
+            Expr::Array(items) => Value::Array(items.iter().map(|e| self.eval(e)).collect::<Result<_, _>>()?),
+            Expr::Map(fields) => Value::Map(
+                fields.iter().map(|(k, e)| Ok((k.clone(), self.eval(e)?))).collect::<Result<_, String>>()?
+            ),
+            Expr::Neg(expr) => match self.eval(expr)? {
+                Value::Int(n) => Value::Int(n.wrapping_neg()),
+                value => Value::Float(-value.float()?)
+            },
+            Expr::Not(expr) => Value::Bool(!self.eval(expr)?.bool()?),
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                binary(op, &left, &self.eval(right)?)?
+            },
+            Expr::And(left, right) => Value::Bool(self.eval(left)?.bool()? && self.eval(right)?.bool()?),
+            Expr::Or(left, right) => Value::Bool(self.eval(left)?.bool()? || self.eval(right)?.bool()?),
+            Expr::Index(base, index) => match (self.eval(base)?, self.eval(index)?) {
+                (Value::Array(items), Value::Int(i)) => {
+                    let len = items.len();
+                    items.into_iter().nth(i as usize).ok_or_else(|| format!("index {} out of 0 .. {}", i, len))?
+                },
+                (Value::Map(mut map), Value::Str(key)) => map.remove(&key).unwrap_or(Value::Unit),
+                (value, _) => return Err(format!("cannot index {}", value.type_name()))
+            },
+            Expr::Field(base, name) => match self.eval(base)? {
+                Value::Map(mut map) => map.remove(name).unwrap_or(Value::Unit),
+                value => return Err(format!("{} has no field `{}`", value.type_name(), name))
+            },
+            Expr::Call(name, args) => {
+                let args = args.iter().map(|e| self.eval(e)).collect::<Result<Vec<_>, _>>()?;
+                self.call(name, args)?
+            },
+            Expr::If(branches, otherwise) => {
+                for (condition, body) in branches {
+                    if self.eval(condition)?.bool()? {
+                        return self.value_of(body);
+                    }
+                }
+                match otherwise {
+                    Some(body) => self.value_of(body)?,
+                    None => Value::Unit
+                }
+            },
+            Expr::Block(body) => self.value_of(body)?
+        })
+    }
+
+    /// Value of a block used within an expression, where `break` and
+    /// `continue` have nowhere to go. A `return` gives the value.
+    fn value_of(&mut self, body: &Block) -> Result<Value, String> {
+        match self.block(body)? {
+            Flow::Normal(value) => Ok(value),
+            Flow::Return(value) => Ok(value),
+            Flow::Break | Flow::Continue => Err("`break` or `continue` within an expression".to_string())
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        if let Some(function) = self.functions.get(name) {
+            if args.len() != function.parameters.len() {
+                return Err(format!("`{}` takes {} arguments, not {}", name, function.parameters.len(), args.len()));
+            }
+            if self.depth == MAX_CALL_DEPTH {
+                return Err("the calls nest too deep".to_string());
+            }
+            let scope = function.parameters.iter().cloned().zip(args).collect();
+            let body = function.body.clone();
+
+            // The function sees its parameters only.
+            let scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+            let line = self.line;
+            self.depth += 1;
+            let result = self.block(&body);
+            self.depth -= 1;
+            self.scopes = scopes;
+            let value = match result? {
+                Flow::Normal(value) | Flow::Return(value) => value,
+                Flow::Break | Flow::Continue => return Err("`break` or `continue` outside of a loop".to_string())
+            };
+            self.line = line;
+            return Ok(value);
+        }
+        self.builtin(name, args)
+    }
+
+    fn builtin(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let arity = match name {
+            "PI" | "rand" => 0,
+            "atan" | "pow" | "min" | "max" | "texture" | "material" => 2,
+            "sphere" | "plane" => 3,
+            _ => 1
+        };
+        if args.len() != arity {
+            return Err(format!("`{}` takes {} arguments, not {}", name, arity, args.len()));
+        }
+        let float = |i: usize| args[i].float();
+        Ok(match name {
+            "sin" => Value::Float(float(0)?.sin()),
+            "cos" => Value::Float(float(0)?.cos()),
+            "tan" => Value::Float(float(0)?.tan()),
+            "atan" => Value::Float(float(0)?.atan2(float(1)?)),
+            "sqrt" => Value::Float(float(0)?.sqrt()),
+            "abs" => match args[0] {
+                Value::Int(n) => Value::Int(n.wrapping_abs()),
+                _ => Value::Float(float(0)?.abs())
+            },
+            "floor" => Value::Float(float(0)?.floor()),
+            "ceil" => Value::Float(float(0)?.ceil()),
+            "pow" => Value::Float(float(0)?.powf(float(1)?)),
+            "min" | "max" => match (&args[0], &args[1]) {
+                (Value::Int(a), Value::Int(b)) => Value::Int(if name == "min" { *a.min(b) } else { *a.max(b) }),
+                _ => Value::Float(if name == "min" { float(0)?.min(float(1)?) } else { float(0)?.max(float(1)?) })
+            },
+            "PI" => Value::Float(PI),
+            "to_int" => Value::Int(float(0)? as i64),
+            "to_float" => Value::Float(float(0)?),
+            "len" => match &args[0] {
+                Value::Array(items) => Value::Int(items.len() as i64),
+                Value::Map(map) => Value::Int(map.len() as i64),
+                Value::Str(s) => Value::Int(s.chars().count() as i64),
+                value => return Err(format!("{} has no length", value.type_name()))
+            },
+            "print" => {
+                println!("{}", args[0]);
+                Value::Unit
+            },
+            "rand" => {
+                // xorshift64*
+                self.random ^= self.random >> 12;
+                self.random ^= self.random << 25;
+                self.random ^= self.random >> 27;
+                let bits = self.random.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+                Value::Float(bits as f64 / (1u64 << 53) as f64)
+            },
+            "seed" => {
+                self.random = SEED ^ args[0].int()? as u64;
+                if self.random == 0 {
+                    self.random = SEED;
+                }
+                Value::Unit
+            },
+            "render" | "camera" | "background" | "animation" => {
+                let table = self.scene.entry(name).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+                if let (toml::Value::Table(table), toml::Value::Table(keys)) = (table, map(&args[0])?) {
+                    table.extend(keys);
+                }
+                Value::Unit
+            },
+            "texture" | "material" => {
+                let key = match &args[0] {
+                    Value::Str(key) => key.clone(),
+                    value => return Err(format!("expected a name, not {}", value.type_name()))
+                };
+                let tables = self.scene.entry(format!("{}s", name)).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+                if let toml::Value::Table(tables) = tables {
+                    tables.insert(key.clone(), map(&args[1])?);
+                }
+                Value::Str(key)
+            },
+            "add" => self.push("objects", map(&args[0])?),
+            "light" => self.push("lights", map(&args[0])?),
+            "sphere" => self.push("objects", Value::Map(BTreeMap::from([
+                ("type".to_string(), Value::Str("sphere".to_string())),
+                ("center".to_string(), args[0].clone()),
+                ("radius".to_string(), args[1].clone()),
+                ("material".to_string(), args[2].clone())
+            ])).to_toml()?),
+            "plane" => self.push("objects", Value::Map(BTreeMap::from([
+                ("type".to_string(), Value::Str("plane".to_string())),
+                ("point".to_string(), args[0].clone()),
+                ("normal".to_string(), args[1].clone()),
+                ("material".to_string(), args[2].clone())
+            ])).to_toml()?),
+            _ => return Err(format!("no function `{}`", name))
+        })
+    }
+
+    /// Append the table to the array of tables under `key`.
+    fn push(&mut self, key: &str, table: toml::Value) -> Value {
+        let array = self.scene.entry(key).or_insert_with(|| toml::Value::Array(vec![]));
+        if let toml::Value::Array(array) = array {
+            array.push(table);
+        }
+        Value::Unit
+    }
+}
+
+/// The object map as a TOML table.
+fn map(value: &Value) -> Result<toml::Value, String> {
+    match value {
+        Value::Map(_) => value.to_toml(),
+        value => Err(format!("expected a map, not {}", value.type_name()))
+    }
+}
+
+fn binary(op: &str, left: &Value, right: &Value) -> Result<Value, String> {
+    use Value::*;
+    Ok(match (op, left, right) {
+        ("==", a, b) => Bool(a == b || matches!((a, b), (Int(_), Float(_)) | (Float(_), Int(_))) && a.float()? == b.float()?),
+        ("!=", a, b) => Bool(!binary("==", a, b)?.bool()?),
+        ("+", Str(a), b) => Str(format!("{}{}", a, b)),
+        ("+", a, Str(b)) => Str(format!("{}{}", a, b)),
+        ("+", Array(a), Array(b)) => Array(a.iter().chain(b).cloned().collect()),
+        ("+", Int(a), Int(b)) => Int(a.wrapping_add(*b)),
+        ("-", Int(a), Int(b)) => Int(a.wrapping_sub(*b)),
+        ("*", Int(a), Int(b)) => Int(a.wrapping_mul(*b)),
+        ("/", Int(_), Int(0)) | ("%", Int(_), Int(0)) => return Err("division by zero".to_string()),
+        ("/", Int(a), Int(b)) => Int(a.checked_div(*b).ok_or_else(|| "integer overflow".to_string())?),
+        ("%", Int(a), Int(b)) => Int(a.checked_rem(*b).ok_or_else(|| "integer overflow".to_string())?),
+        ("<", Int(a), Int(b)) => Bool(a < b),
+        ("<=", Int(a), Int(b)) => Bool(a <= b),
+        (">", Int(a), Int(b)) => Bool(a > b),
+        (">=", Int(a), Int(b)) => Bool(a >= b),
+        ("<", Str(a), Str(b)) => Bool(a < b),
+        (">", Str(a), Str(b)) => Bool(a > b),
+        (op, a, b) => {
+            let (a, b) = match (a.float(), b.float()) {
+                (Ok(a), Ok(b)) => (a, b),
+                _ => return Err(format!("cannot apply `{}` to {} and {}", op, a.type_name(), b.type_name()))
+            };
+            match op {
+                "+" => Float(a + b),
+                "-" => Float(a - b),
+                "*" => Float(a * b),
+                "/" => Float(a / b),
+                "%" => Float(a % b),
+                "<" => Bool(a < b),
+                "<=" => Bool(a <= b),
+                ">" => Bool(a > b),
+                ">=" => Bool(a >= b),
+                _ => unreachable!()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Value of the expression, after the statements before it.
+    fn value(source: &str) -> toml::Value {
+        let (statements, expression) = source.rsplit_once('\n').unwrap_or(("", source));
+        let scene = run_script(&format!("{}\nrender(#{{ x: {} }});", statements, expression), 1)
+            .unwrap_or_else(|err| panic!("{}: {}", source, err));
+        scene["render"]["x"].clone()
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        match run_script(source, 1) {
+            Ok(_) => panic!("{} ran", source),
+            Err(LoadError::Parse { line, message }) => (line, message),
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("1 + 2 * 3"), toml::Value::Integer(7));
+        assert_eq!(value("(1 + 2) * 3"), toml::Value::Integer(9));
+        assert_eq!(value("10 - 4 - 3"), toml::Value::Integer(3));
+        assert_eq!(value("-2 * 3 + 7 % 4"), toml::Value::Integer(-3));
+        assert_eq!(value("7 / 2"), toml::Value::Integer(3));
+        assert_eq!(value("7 / 2.0"), toml::Value::Float(3.5));
+        assert_eq!(value("1 < 2 && 2 < 1 || 1 == 1.0"), toml::Value::Boolean(true));
+        assert_eq!(value("!true || false"), toml::Value::Boolean(false));
+        assert_eq!(value("\"ball_\" + 1 + 2"), toml::Value::String("ball_12".to_string()));
+    }
+
+    #[test]
+    fn scopes() {
+        assert_eq!(value("let x = 1;\n{ let x = 2; }\nx"), toml::Value::Integer(1));
+        assert_eq!(value("let x = 1;\nif true { x = 2; }\nx"), toml::Value::Integer(2));
+        assert_eq!(value("let a = [1, #{ b: 2 }];\na[1].b += 3;\na[1].b"), toml::Value::Integer(5));
+        assert_eq!(error("let a = 1;\nfn f() { a }\nf();").1, "no variable `a`");
+        assert_eq!(error("{ let y = 1; }\nlet z = y;").0, 2);
+    }
+
+    #[test]
+    fn functions() {
+        let fact = "fn fact(n) { if n <= 1 { return 1; } n * fact(n - 1) }\n";
+        assert_eq!(value(&format!("{}fact(10)", fact)), toml::Value::Integer(3_628_800));
+        assert_eq!(value("fn twice(x) { 2 * x }\n[1.5].len().twice()"), toml::Value::Integer(2));
+        assert_eq!(error("fn f(a, b) { a }\nf(1);").1, "`f` takes 2 arguments, not 1");
+        assert_eq!(error("sqrt(1, 2);").1, "`sqrt` takes 1 arguments, not 2");
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(value("let s = 0;\nfor i in 0..10 { s += i; }\ns"), toml::Value::Integer(45));
+        assert_eq!(value("let s = 0;\nfor i in 0..=10 { s += i; }\ns"), toml::Value::Integer(55));
+        assert_eq!(value("let s = 0;\nfor i in 5..0 { s += i; }\ns"), toml::Value::Integer(0));
+        assert_eq!(value("let s = \"\";\nfor c in [\"a\", 1, 2.5] { s += c; }\ns"), toml::Value::String("a12.5".to_string()));
+        assert_eq!(
+            value("let s = 0;\nfor i in 0..100 { if i % 2 == 0 { continue; } if i > 10 { break; } s += i; }\ns"),
+            toml::Value::Integer(25)
+        );
+        assert_eq!(value("let n = 0;\nwhile n < 5 { n += 1; }\nn"), toml::Value::Integer(5));
+        assert_eq!(value("let n = 0;\nfor i in 9223372036854775806..=9223372036854775807 { n += 1; }\nn"), toml::Value::Integer(2));
+    }
+
+    #[test]
+    fn limits() {
+        // Close to the limit already, not to run a hundred million of
+        // them.
+        let mut parser = Parser { tokens: lex("let n = 0;\nloop { n += 1; }").unwrap(), position: 0, functions: HashMap::new() };
+        let program = parser.program().unwrap();
+        let mut interpreter = Interpreter {
+            functions: parser.functions,
+            scopes: vec![HashMap::new()],
+            scene: toml::Table::new(),
+            random: SEED,
+            operations: MAX_OPERATIONS - 1000,
+            depth: 0,
+            line: 0
+        };
+        assert_eq!(interpreter.block(&program).err().as_deref(), Some("the script runs for too long"));
+        assert_eq!(interpreter.line, 2);
+
+        assert_eq!(error("fn f(n) { f(n + 1) }\nf(0);").1, "the calls nest too deep");
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let min = "let m = -9223372036854775807 - 1;\n";
+        assert_eq!(value(&format!("{}-m == m", min)), toml::Value::Boolean(true));
+        assert_eq!(value(&format!("{}abs(m) == m", min)), toml::Value::Boolean(true));
+        assert_eq!(value(&format!("{}m * 2", min)), toml::Value::Integer(0));
+        assert_eq!(error(&format!("{}m / -1;", min)), (2, "integer overflow".to_string()));
+        assert_eq!(error(&format!("{}m % -1;", min)), (2, "integer overflow".to_string()));
+        assert_eq!(error("1 % 0;").1, "division by zero");
+    }
+
+    #[test]
+    fn scene() {
+        let source = "camera(#{ origin: [0.0, 1.0, 2.0], vfov: 40.0 });\n\
+                      let m = material(\"red\", #{ type: \"lambertian\", albedo: [0.8, 0.1, 0.1] });\n\
+                      add(#{ type: \"sphere\", center: [0, 0, -1], radius: 0.5, material: m });\n\
+                      sphere([1.0, 0.0, -1.0], 0.25, m);\n\
+                      render(#{ width: 16 * frame });";
+        let expected: toml::Table = "[render]\nwidth = 48\n\
+                                     [camera]\norigin = [0.0, 1.0, 2.0]\nvfov = 40.0\n\
+                                     [materials.red]\ntype = \"lambertian\"\nalbedo = [0.8, 0.1, 0.1]\n\
+                                     [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, -1]\nradius = 0.5\nmaterial = \"red\"\n\
+                                     [[objects]]\ntype = \"sphere\"\ncenter = [1.0, 0.0, -1.0]\nradius = 0.25\nmaterial = \"red\"\n"
+            .parse()
+            .unwrap();
+        assert_eq!(run_script(source, 3).unwrap(), expected);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("let x = 1;\nlet y = ;").0, 2);
+        assert_eq!(error("let s = \"open;").0, 1);
+        assert_eq!(error("let x = 1;\n\nfor i in 0..3 {").0, 3);
+    }
+}
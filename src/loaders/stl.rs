@@ -0,0 +1,189 @@
+//! STL models, in both the binary and the ASCII flavour.
+//!
+//! STL stores every triangle with its own copy of the vertices, the
+//! coincident ones are merged back. The stored face normals only fix
+//! the winding of the faces, as the geometric normals are used anyway.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::geometry::Mesh;
+use crate::material::{Lambertian, Material};
+use crate::math::Vector;
+use crate::texture::SolidColor;
+
+use super::LoadError;
+
+/// Load a mesh with the given material, or with a light grey matte one
+/// if there is none.
+pub fn load_stl<P: AsRef<Path>>(path: P, material: Option<Arc<dyn Material>>) -> Result<Mesh, LoadError> {
+    let data = fs::read(path)?;
+    parse_stl(&data, material)
+}
+
+pub fn parse_stl(data: &[u8], material: Option<Arc<dyn Material>>) -> Result<Mesh, LoadError> {
+    let material = material.unwrap_or_else(|| {
+        Arc::new(Lambertian{ albedo: Arc::new(SolidColor{ color: Vector{ x: 0.8, y: 0.8, z: 0.8 } }) })
+    });
+
+    // Binary files may start with "solid" too, their size tells them
+    // apart: an 80 byte header, the triangle count and 50 bytes per
+    // triangle.
+    let binary = data.len() >= 84 && {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        data.len() == 84 + 50 * count
+    };
+
+    let triangles = if binary {
+        binary_triangles(data)
+    } else {
+        let source = std::str::from_utf8(data)
+            .map_err(|_| LoadError::invalid("STL file is neither binary nor text"))?;
+        ascii_triangles(source)?
+    };
+
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    let mut indices: HashMap<[u32; 3], usize> = HashMap::new();
+
+    for [normal, a, b, c] in triangles {
+        let mut face = [a, b, c].map(|p| {
+            *indices.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert_with(|| {
+                vertices.push(p);
+                vertices.len() - 1
+            })
+        });
+
+        if face[0] == face[1] || face[1] == face[2] || face[2] == face[0] {
+            continue;
+        }
+
+        if (b - a).cross(c - a).dot(normal) < 0.0 {
+            face.swap(1, 2);
+        }
+        faces.push(face);
+    }
+
+    Ok(Mesh::new(vertices, faces, material))
+}
+
+/// Normal and vertices of every triangle of a binary file.
+fn binary_triangles(data: &[u8]) -> Vec<[Vector; 4]> {
+    let float = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let vector = |bytes: &[u8]| Vector{ x: float(&bytes[0 ..]), y: float(&bytes[4 ..]), z: float(&bytes[8 ..]) };
+
+    data[84 ..]
+        .chunks_exact(50)
+        .map(|record| [
+            vector(&record[0 ..]),
+            vector(&record[12 ..]),
+            vector(&record[24 ..]),
+            vector(&record[36 ..])
+        ])
+        .collect()
+}
+
+/// Normal and vertices of every triangle of an ASCII file:
+///
+/// ```text
+/// solid name
+///   facet normal nx ny nz
+///     outer loop
+///       vertex x y z
+///       vertex x y z
+///       vertex x y z
+///     endloop
+///   endfacet
+/// endsolid name
+/// ```
+fn ascii_triangles(source: &str) -> Result<Vec<[Vector; 4]>, LoadError> {
+    let mut triangles = vec![];
+    let mut current = vec![];
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("facet") => {
+                // Skip the "normal" keyword.
+                current.clear();
+                tokens.next();
+            },
+            Some("vertex") => {},
+            Some("endfacet") => {
+                if current.len() != 4 {
+                    return Err(LoadError::parse(number, "facet needs a normal and three vertices"));
+                }
+                triangles.push([current[0], current[1], current[2], current[3]]);
+                continue;
+            },
+            _ => continue
+        }
+
+        let coords = tokens
+            .map(|token| token.parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|err| LoadError::parse(number, err.to_string()))?;
+        match coords[..] {
+            [x, y, z] => current.push(Vector{ x, y, z }),
+            _ => return Err(LoadError::parse(number, "expected three coordinates"))
+        }
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Binary file of the triangles, each a normal and three vertices,
+    /// with a header starting with "solid" to make it look like text.
+    fn binary(triangles: &[[[f32; 3]; 4]]) -> Vec<u8> {
+        let mut data = b"solid but binary".to_vec();
+        data.resize(80, 0);
+        data.extend((triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            for x in triangle.iter().flatten() {
+                data.extend(x.to_le_bytes());
+            }
+            data.extend([0, 0]);
+        }
+        data
+    }
+
+    #[test]
+    fn binary_vertices_are_merged() {
+        let data = binary(&[
+            [[0.0, 0.0, 1.0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]
+        ]);
+        let mesh = parse_stl(&data, None).unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [1, 3, 2]]);
+    }
+
+    #[test]
+    fn ascii_winding_follows_the_normals() {
+        let source = "solid square\n\
+                      facet normal 0 0 -1\n outer loop\n  vertex 0 0 0\n  vertex 1 0 0\n  vertex 0 1 0\n endloop\nendfacet\n\
+                      facet normal 0 0 1\n outer loop\n  vertex 0 0 0\n  vertex 0 0 0\n  vertex 0 1 0\n endloop\nendfacet\n\
+                      endsolid square\n";
+        let mesh = parse_stl(source.as_bytes(), None).unwrap();
+        // The first face is turned to face down, the degenerate one dropped.
+        assert_eq!(mesh.faces, vec![[0, 2, 1]]);
+    }
+
+    #[test]
+    fn bad_ascii() {
+        let facet = |vertices: &str| format!("solid\nfacet normal 0 0 1\nouter loop\n{}endloop\nendfacet\nendsolid\n", vertices);
+        let short = facet("vertex 0 0 0\nvertex 1 0 0\n");
+        assert!(matches!(parse_stl(short.as_bytes(), None), Err(LoadError::Parse { line: 7, .. })));
+        let bad = facet("vertex 0 0 0\nvertex 1 x 0\nvertex 0 1 0\n");
+        assert!(matches!(parse_stl(bad.as_bytes(), None), Err(LoadError::Parse { line: 5, .. })));
+        assert!(matches!(parse_stl(&[0xff; 10], None), Err(LoadError::Invalid(_))));
+    }
+}
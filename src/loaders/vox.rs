@@ -0,0 +1,404 @@
+//! MagicaVoxel `.vox` scenes.
+//!
+//! Every model becomes a `VoxelGrid` placed by the scene graph of the
+//! file, if there is one, with the voxel edge being one unit long.
+//! MagicaVoxel has z pointing up, it is turned to point along y here.
+//! Colors come from the palette of the file, or are grey without one,
+//! and the `MATL` chunks make them metallic, glassy or emissive.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::color::srgb_decode;
+use crate::geometry::{Group, Instance, VoxelGrid};
+use crate::material::{Dielectric, Emissive, Lambertian, Material, Pbr};
+use crate::math::{Mat4, Transform, Vector};
+use crate::texture::SolidColor;
+
+use super::LoadError;
+
+pub fn load_vox<P: AsRef<Path>>(path: P) -> Result<Group, LoadError> {
+    let data = fs::read(path)?;
+    parse_vox(&data)
+}
+
+pub fn parse_vox(data: &[u8]) -> Result<Group, LoadError> {
+    if data.len() < 8 || &data[.. 4] != b"VOX " {
+        return Err(LoadError::invalid("not a MagicaVoxel file"));
+    }
+
+    let mut file = VoxFile::default();
+    let main = Cursor{ data: &data[8 ..] }.chunk()?
+        .ok_or_else(|| LoadError::invalid("vox file has no MAIN chunk"))?;
+    let mut children = Cursor{ data: main.children };
+    while let Some(chunk) = children.chunk()? {
+        file.read(chunk)?;
+    }
+
+    let materials: Vec<Arc<dyn Material>> = (0 .. 256)
+        .map(|index| file.material(index as u8))
+        .collect();
+
+    let grids: Vec<Arc<VoxelGrid>> = file.models.iter()
+        .map(|model| {
+            let [nx, ny, nz] = model.size;
+            let mut voxels = vec![0; nx * ny * nz];
+            for &[x, y, z, value] in &model.voxels {
+                let (x, y, z) = (x as usize, y as usize, z as usize);
+                if x < nx && y < ny && z < nz {
+                    voxels[(z * ny + y) * nx + x] = value;
+                }
+            }
+            Arc::new(VoxelGrid{ nx, ny, nz, voxels, materials: materials.clone() })
+        })
+        .collect();
+
+    // A quarter turn around x brings z up to where y was.
+    let z_up = Transform::rotate(Vector{ x: 1.0, y: 0.0, z: 0.0 }, -std::f32::consts::FRAC_PI_2);
+
+    let mut group = Group::default();
+    let mut placements = vec![];
+    if file.nodes.is_empty() {
+        placements.extend((0 .. grids.len()).map(|model| (model, Transform::IDENTITY)));
+    } else {
+        file.place(0, Transform::IDENTITY, &mut placements, 0)?;
+    }
+
+    for (model, transform) in placements {
+        let grid = grids.get(model)
+            .ok_or_else(|| LoadError::invalid(format!("vox scene refers to a missing model {}", model)))?;
+
+        // Models are centered at their node.
+        let [nx, ny, nz] = file.models[model].size;
+        let center = Vector{ x: (nx / 2) as f32, y: (ny / 2) as f32, z: (nz / 2) as f32 };
+        let transform = z_up * transform * Transform::translate(-center);
+        group.objects.push(Box::new(Instance::new(grid.clone(), transform)));
+    }
+
+    Ok(group)
+}
+
+struct Chunk<'a> {
+    id: [u8; 4],
+    content: &'a [u8],
+    children: &'a [u8]
+}
+
+struct Cursor<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], LoadError> {
+        if self.data.len() < count {
+            return Err(LoadError::invalid("vox file ends too early"));
+        }
+        let (head, tail) = self.data.split_at(count);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn int(&mut self) -> Result<i32, LoadError> {
+        let b = self.bytes(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn count(&mut self) -> Result<usize, LoadError> {
+        usize::try_from(self.int()?).map_err(|_| LoadError::invalid("negative count in vox file"))
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let length = self.count()?;
+        Ok(String::from_utf8_lossy(self.bytes(length)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>, LoadError> {
+        let count = self.count()?;
+        (0 .. count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+
+    fn chunk(&mut self) -> Result<Option<Chunk<'a>>, LoadError> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+
+        let id = self.bytes(4)?;
+        let content = self.count()?;
+        let children = self.count()?;
+        Ok(Some(Chunk {
+            id: [id[0], id[1], id[2], id[3]],
+            content: self.bytes(content)?,
+            children: self.bytes(children)?
+        }))
+    }
+}
+
+#[derive(Default)]
+struct Model {
+    size: [usize; 3],
+    voxels: Vec<[u8; 4]> // Coordinates and color index
+}
+
+enum Node {
+    Transform { child: usize, translation: Vector, rotation: Transform },
+    Group { children: Vec<usize> },
+    Shape { models: Vec<usize> }
+}
+
+#[derive(Default)]
+struct VoxFile {
+    models: Vec<Model>,
+    palette: Option<Vec<Vector>>,
+    materials: HashMap<u8, HashMap<String, String>>,
+    nodes: HashMap<usize, Node>
+}
+
+impl VoxFile {
+    fn read(&mut self, chunk: Chunk) -> Result<(), LoadError> {
+        let mut cursor = Cursor{ data: chunk.content };
+
+        match &chunk.id {
+            b"SIZE" => {
+                let size = [cursor.count()?, cursor.count()?, cursor.count()?];
+                self.models.push(Model{ size, voxels: vec![] });
+            },
+            b"XYZI" => {
+                let model = self.models.last_mut()
+                    .ok_or_else(|| LoadError::invalid("vox XYZI chunk before SIZE"))?;
+                let count = cursor.count()?;
+                model.voxels = cursor.bytes(4 * count)?
+                    .chunks_exact(4)
+                    .map(|v| [v[0], v[1], v[2], v[3]])
+                    .collect();
+            },
+            b"RGBA" => {
+                // The colors are listed starting from the index one.
+                let colors = cursor.bytes(4 * 256)?
+                    .chunks_exact(4)
+                    .map(|c| Vector {
+                        x: srgb_decode(c[0] as f32 / 255.0),
+                        y: srgb_decode(c[1] as f32 / 255.0),
+                        z: srgb_decode(c[2] as f32 / 255.0)
+                    });
+                let grey = Vector{ x: 0.5, y: 0.5, z: 0.5 };
+                self.palette = Some(std::iter::once(grey).chain(colors).take(256).collect());
+            },
+            b"MATL" => {
+                let id = cursor.count()?;
+                let properties = cursor.dict()?;
+                if let Ok(id) = u8::try_from(id) {
+                    self.materials.insert(id, properties);
+                }
+            },
+            b"nTRN" => {
+                let id = cursor.count()?;
+                cursor.dict()?;
+                let child = cursor.count()?;
+                cursor.int()?; // Reserved
+                cursor.int()?; // Layer
+                let frames = cursor.count()?;
+                let frame = if frames > 0 { cursor.dict()? } else { HashMap::new() };
+
+                let translation = frame.get("_t")
+                    .map(|t| t.split_whitespace().filter_map(|x| x.parse().ok()).collect::<Vec<f32>>())
+                    .and_then(|t| match t[..] {
+                        [x, y, z] => Some(Vector{ x, y, z }),
+                        _ => None
+                    })
+                    .unwrap_or(Vector{ x: 0.0, y: 0.0, z: 0.0 });
+                let rotation = frame.get("_r")
+                    .and_then(|r| r.parse().ok())
+                    .map(rotation)
+                    .unwrap_or(Transform::IDENTITY);
+
+                self.nodes.insert(id, Node::Transform{ child, translation, rotation });
+            },
+            b"nGRP" => {
+                let id = cursor.count()?;
+                cursor.dict()?;
+                let count = cursor.count()?;
+                let children = (0 .. count).map(|_| cursor.count()).collect::<Result<_, _>>()?;
+                self.nodes.insert(id, Node::Group{ children });
+            },
+            b"nSHP" => {
+                let id = cursor.count()?;
+                cursor.dict()?;
+                let count = cursor.count()?;
+                let mut models = vec![];
+                for _ in 0 .. count {
+                    models.push(cursor.count()?);
+                    cursor.dict()?;
+                }
+                self.nodes.insert(id, Node::Shape{ models });
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Collect the models under a node of the scene graph along with
+    /// their transformations.
+    fn place(&self, id: usize, parent: Transform, placements: &mut Vec<(usize, Transform)>, depth: usize) -> Result<(), LoadError> {
+        if depth > self.nodes.len() {
+            return Err(LoadError::invalid("vox scene graph has a cycle"));
+        }
+
+        match self.nodes.get(&id) {
+            Some(Node::Transform{ child, translation, rotation }) => {
+                let transform = parent * Transform::translate(*translation) * *rotation;
+                self.place(*child, transform, placements, depth + 1)?;
+            },
+            Some(Node::Group{ children }) => {
+                for &child in children {
+                    self.place(child, parent, placements, depth + 1)?;
+                }
+            },
+            Some(Node::Shape{ models }) => {
+                placements.extend(models.iter().map(|&model| (model, parent)));
+            },
+            None => return Err(LoadError::invalid(format!("vox scene graph has no node {}", id)))
+        }
+
+        Ok(())
+    }
+
+    fn material(&self, index: u8) -> Arc<dyn Material> {
+        let color = match &self.palette {
+            Some(palette) => palette[index as usize],
+            None => Vector{ x: 0.5, y: 0.5, z: 0.5 }
+        };
+
+        let properties = self.materials.get(&index);
+        let property = |name: &str, default: f32| {
+            properties
+                .and_then(|p| p.get(name))
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+
+        match properties.and_then(|p| p.get("_type")).map(String::as_str) {
+            Some("_metal") => Arc::new(Pbr {
+                base_color: Arc::new(SolidColor{ color }),
+                metallic: property("_metal", 1.0),
+                roughness: property("_rough", 0.1)
+            }),
+            // The refractive index is stored less one.
+            Some("_glass") => Arc::new(Dielectric{ refractive_index: 1.0 + property("_ior", 0.5) }),
+            Some("_emit") => Arc::new(Emissive {
+                radiance: property("_emit", 1.0) * (1.0 + property("_flux", 0.0)) * color
+            }),
+            _ => Arc::new(Lambertian{ albedo: Arc::new(SolidColor{ color }) })
+        }
+    }
+}
+
+/// Rotation packed into a byte: bits 0-1 and 2-3 are the columns of the
+/// nonzero entries of the first two rows, bits 4-6 are the signs of the
+/// three rows.
+fn rotation(packed: u8) -> Transform {
+    let first = (packed & 3) as usize;
+    let second = ((packed >> 2) & 3) as usize;
+    let third = 3usize.saturating_sub(first + second);
+
+    let mut m = Mat4::IDENTITY;
+    for (row, &column) in [first, second, third].iter().enumerate() {
+        m.m[row] = [0.0; 4];
+        m.m[row][column.min(2)] = if packed & (16 << row) != 0 { -1.0 } else { 1.0 };
+    }
+    m.m[3] = [0.0, 0.0, 0.0, 1.0];
+
+    Transform::from_matrix(m).unwrap_or(Transform::IDENTITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Hittable, Ray};
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.extend((content.len() as u32).to_le_bytes());
+        data.extend((children.len() as u32).to_le_bytes());
+        data.extend(content);
+        data.extend(children);
+        data
+    }
+
+    fn ints(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// File of the chunks, put under MAIN.
+    fn vox(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"VOX ".to_vec();
+        data.extend(150u32.to_le_bytes());
+        data.extend(chunk(b"MAIN", &[], &chunks.concat()));
+        data
+    }
+
+    /// A 2×3×4 model with one voxel at its origin.
+    fn model() -> Vec<Vec<u8>> {
+        vec![
+            chunk(b"SIZE", &ints(&[2, 3, 4]), &[]),
+            chunk(b"XYZI", &[ints(&[1]), vec![0, 0, 0, 1]].concat(), &[])
+        ]
+    }
+
+    fn hit(group: &Group, x: f32, y: f32) -> Option<f32> {
+        let ray = Ray::new(Vector{ x, y, z: 5.0 }, Vector{ x: 0.0, y: 0.0, z: -1.0 });
+        group.hit(&ray).map(|hit| hit.t)
+    }
+
+    #[test]
+    fn model_is_centered_with_z_up() {
+        // The voxel spans [-1, 0] along x, [-2, -1] along z before z is
+        // turned up, which makes it [-2, -1] along y and [0, 1] along z.
+        let group = parse_vox(&vox(&model())).unwrap();
+        assert_eq!(group.objects.len(), 1);
+        assert!((hit(&group, -0.5, -1.5).unwrap() - 4.0).abs() < 1E-4);
+        assert_eq!(hit(&group, 0.5, -1.5), None);
+        assert_eq!(hit(&group, -0.5, -0.5), None);
+    }
+
+    #[test]
+    fn scene_graph() {
+        let dict = |pairs: &[(&str, &str)]| -> Vec<u8> {
+            let mut data = ints(&[pairs.len() as u32]);
+            for (key, value) in pairs {
+                data.extend(ints(&[key.len() as u32]));
+                data.extend(key.as_bytes());
+                data.extend(ints(&[value.len() as u32]));
+                data.extend(value.as_bytes());
+            }
+            data
+        };
+        let transform = |id: u32, child: u32| {
+            let content = [ints(&[id]), dict(&[]), ints(&[child, 0, 0, 1]), dict(&[("_t", "10 0 0")])].concat();
+            chunk(b"nTRN", &content, &[])
+        };
+        let shape = chunk(b"nSHP", &[ints(&[1]), dict(&[]), ints(&[1, 0]), dict(&[])].concat(), &[]);
+
+        let group = parse_vox(&vox(&[model(), vec![transform(0, 1), shape.clone()]].concat())).unwrap();
+        assert!((hit(&group, 9.5, -1.5).unwrap() - 4.0).abs() < 1E-4);
+        assert_eq!(hit(&group, -0.5, -1.5), None);
+
+        let cycle = parse_vox(&vox(&[model(), vec![transform(0, 0)]].concat()));
+        assert!(matches!(cycle, Err(LoadError::Invalid(message)) if message.contains("cycle")));
+        let missing = parse_vox(&vox(&[model(), vec![transform(0, 2), shape]].concat()));
+        assert!(matches!(missing, Err(LoadError::Invalid(message)) if message.contains("no node 2")));
+    }
+
+    #[test]
+    fn bad_files() {
+        assert!(matches!(parse_vox(b"PNG 1234"), Err(LoadError::Invalid(_))));
+        let mut data = vox(&model());
+        data.truncate(data.len() - 2);
+        assert!(matches!(parse_vox(&data), Err(LoadError::Invalid(message)) if message.contains("too early")));
+        let data = vox(&[model()[1].clone()]);
+        assert!(matches!(parse_vox(&data), Err(LoadError::Invalid(message)) if message.contains("before SIZE")));
+    }
+}
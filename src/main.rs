@@ -1,399 +1,790 @@
-/// Basic vector arithmetics.
-
-use std::ops::Add;
-use std::ops::AddAssign;
-use std::ops::Div;
-use std::ops::Mul;
-use std::ops::Sub;
+use std::f32::consts::PI;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+
+use rtrace::animation::Animation;
+use rtrace::camera::{Camera, Exposure, Projection};
+use rtrace::checkpoint::Checkpoint;
+use rtrace::demo::Demo;
+use rtrace::denoise::denoise;
+use rtrace::distributed;
+use rtrace::filter::Filter;
+use rtrace::geometry::{AcceleratorKind, Hittable, Split};
+use rtrace::integrator::IntegratorKind;
+use rtrace::math::{Transform, EY};
+use rtrace::output::{save_aovs, save_exr, save_image, unpremultiply, Format, Precision};
+use rtrace::photon::PhotonMap;
+use rtrace::render::{Aovs, Image, RenderThread, Settings};
+use rtrace::sampler::SamplerKind;
+use rtrace::scene::Scene;
+use rtrace::server;
+use rtrace::tonemap::ToneMap;
+use rtrace::video::Video;
+
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+use {
+    std::thread,
+    std::time::{Instant, SystemTime},
+    clap::ValueEnum,
+    rtrace::display::{Display, Event, FlyControls, Frame, Key},
+    rtrace::geometry::World,
+    rtrace::output::timestamped_name
+};
+
+/// Where the image goes once sampling finishes, unless told otherwise.
+/// Snapshots taken with P get a timestamp added to the name.
+const OUTPUT_PATH: &str = "render.png";
+
+/// Stops of exposure added or taken away by every press of + or -.
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+const EXPOSURE_STEP: f32 = 0.5;
+
+/// Windowing libraries the binary was built with.
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    #[cfg(feature = "sdl2")]
+    Sdl,
+    #[cfg(feature = "minifb")]
+    Minifb
+}
 
-use rand;
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+impl Backend {
+    /// The first one that is compiled in.
+    fn default() -> Backend {
+        Backend::value_variants()[0]
+    }
+}
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Vector {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
+/// A toy ray tracer.
+#[derive(Parser)]
+struct Args {
+    /// Scene file to render. Renders a built-in scene if omitted, see
+    /// --demo.
+    scene: Option<PathBuf>,
+
+    /// Built-in scene to render without a scene file: balls for three
+    /// balls on a checkered floor, or final-scene for the random balls of
+    /// the cover of "Ray Tracing in One Weekend", laid out by the seed.
+    #[arg(long, conflicts_with = "scene")]
+    demo: Option<Demo>,
+
+    /// Render without opening a window and only write the image file.
+    #[arg(long)]
+    headless: bool,
+
+    /// How the image maps to the directions of the camera rays:
+    /// perspective, cubemap for six views along the axes side by side,
+    /// equirectangular for a 360° panorama, equidistant or equisolid for
+    /// a fisheye lens, orthographic for parallel rays, or realistic for a
+    /// 50 mm double Gauss lens of real elements. Overrides the scene.
+    #[arg(long)]
+    projection: Option<Projection>,
+
+    /// Degrees a fisheye lens sees across the height of the image, up to
+    /// 360. Overrides the scene.
+    #[arg(long)]
+    fisheye_fov: Option<f32>,
+
+    /// Render for both eyes this far apart, in the units of the scene,
+    /// side by side: the left half of the image for the left eye and the
+    /// right half for the right one. Overrides the scene.
+    #[arg(long)]
+    eye_separation: Option<f32>,
+
+    /// Save the halves of a stereo image to files of their own, with
+    /// -left and -right added to the names.
+    #[arg(long)]
+    split_eyes: bool,
+
+    /// How much of the scene is seen from bottom to top, in the units of
+    /// the scene, for the orthographic projection. Overrides the scene.
+    #[arg(long)]
+    view_height: Option<f32>,
+
+    /// Image file to write: a PNG file, or a PPM file of 8-bit colors or
+    /// a PFM file of floats if the name ends with .ppm or .pfm.
+    #[arg(long, short, default_value = OUTPUT_PATH)]
+    output: String,
+
+    /// Render only this frame of an animated scene, counting from 1.
+    /// Without it, an animation is rendered headless frame by frame to
+    /// files numbered after the image, such as render_0001.png, and the
+    /// window shows the first frame.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    frame: Option<u32>,
+
+    /// Render every frame of the animation and pipe them to ffmpeg,
+    /// which encodes them into this video file, such as out.mp4, by its
+    /// extension. Needs ffmpeg on the PATH or named by FFMPEG.
+    #[arg(long, conflicts_with_all = ["frame", "coordinator", "worker"])]
+    video: Option<String>,
+
+    /// Turn the scene into an animation of this many frames, the camera
+    /// going once round the point it is focused on, about the vertical
+    /// axis, to show off a model from every side.
+    #[arg(long, value_name = "FRAMES")]
+    turntable: Option<u32>,
+
+    /// Algorithm finding the light: path, bdpt for bidirectional path
+    /// tracing, mlt for Metropolis light transport, direct for the
+    /// light straight from the lights only, ao for ambient occlusion, or
+    /// normals, depth or uvs to show the shading normals, the distances
+    /// or the texture coordinates of the surfaces. Overrides the
+    /// integrator of the scene.
+    #[arg(long)]
+    integrator: Option<IntegratorKind>,
+
+    /// Save the albedo, the normals and the distances of what the camera
+    /// sees next to the image, for denoisers and compositing.
+    #[arg(long)]
+    aovs: bool,
+
+    /// Filter the noise out of the saved images, with the help of the
+    /// albedo, the normals and the distances of what the camera sees.
+    #[arg(long)]
+    denoise: bool,
+
+    /// Leave the background out, with an alpha channel in the PNG and
+    /// OpenEXR files, for compositing the render over other images.
+    #[arg(long)]
+    transparent: bool,
+
+    /// Also save the image, with the AOVs, as an OpenEXR file of half or
+    /// float precision, half if not given.
+    #[arg(long, num_args = 0 ..= 1, default_missing_value = "half")]
+    exr: Option<Precision>,
+
+    /// Save the state of the render every so many seconds to a checkpoint
+    /// file named after the image, with the .checkpoint extension, so
+    /// that it can be resumed if it gets interrupted.
+    #[arg(long)]
+    checkpoint_interval: Option<f32>,
+
+    /// Render on the workers that connect to this address, such as
+    /// 0.0.0.0:7878, instead of here, and write the image once they are
+    /// done.
+    #[arg(long, conflicts_with = "worker")]
+    coordinator: Option<String>,
+
+    /// Take the jobs of the coordinator at this address, such as
+    /// render-box:7878, till the image is done. The scene and the options
+    /// are to be the same as those of the coordinator.
+    #[arg(long)]
+    worker: Option<String>,
+
+    /// Serve the rendering over HTTP on this address, such as
+    /// 0.0.0.0:8080, instead: scene files posted to /jobs are rendered in
+    /// turn, and their progress and images can be fetched from there.
+    #[arg(long, conflicts_with_all = ["coordinator", "worker"])]
+    serve: Option<String>,
+
+    /// Carry on from the checkpoint file named after the image, if there
+    /// is one, instead of starting over. The scene and the settings are
+    /// to be the same as those of the render that saved it.
+    #[arg(long)]
+    resume: bool,
+
+    /// Farthest an object can be to occlude a point, with `--integrator
+    /// ao`. Overrides the ao_distance of the scene.
+    #[arg(long)]
+    ao_distance: Option<f32>,
+
+    /// Photons to shoot from the lights for the caustics. Overrides the
+    /// photons of the scene.
+    #[arg(long)]
+    photons: Option<u32>,
+
+    /// Stop sampling a pixel once the error of its average drops below
+    /// this fraction of it. Overrides the threshold of the scene.
+    #[arg(long)]
+    threshold: Option<f32>,
+
+    /// Where the random numbers of the samples come from: random or
+    /// halton. Overrides the sampler of the scene.
+    #[arg(long)]
+    sampler: Option<SamplerKind>,
+
+    /// Shift the Halton sequences of the pixels by a blue noise mask, so
+    /// that the noise looks finer at a few samples per pixel.
+    #[arg(long)]
+    blue_noise: bool,
+
+    /// Seed of the random numbers: the same seed renders the same image.
+    /// Overrides the seed of the scene.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Reconstruction filter: box, tent, gaussian or mitchell. Overrides
+    /// the filter of the scene.
+    #[arg(long)]
+    filter: Option<Filter>,
+
+    /// Dim the samples brighter than this, to get rid of the speckles
+    /// of light paths too rare to average out. Overrides the scene.
+    #[arg(long)]
+    max_radiance: Option<f32>,
+
+    /// How the light is squeezed into the colors of the window and the
+    /// PNG files: clamp, reinhard or aces. Overrides the scene.
+    #[arg(long)]
+    tone_map: Option<ToneMap>,
+
+    /// Stops to make the image brighter by before the tone mapping, or
+    /// darker if negative. Overrides the scene.
+    #[arg(long, allow_negative_numbers = true)]
+    exposure: Option<f32>,
+
+    /// Film sensitivity of a physical camera, which exposes the image for
+    /// the light in physical units. The shutter speed and the f-stop are
+    /// those of the "sunny 16" rule unless given. Overrides the scene.
+    #[arg(long)]
+    iso: Option<f32>,
+
+    /// Seconds the shutter of a physical camera is open for. Overrides the
+    /// scene.
+    #[arg(long)]
+    shutter_speed: Option<f32>,
+
+    /// F-number of the aperture of a physical camera. Overrides the scene.
+    #[arg(long)]
+    f_stop: Option<f32>,
+
+    /// Structure finding what the rays hit: bvh, bounding volume
+    /// hierarchies, or kdtree. Overrides the scene.
+    #[arg(long)]
+    accelerator: Option<AcceleratorKind>,
+
+    /// How the nodes of the BVHs are split: sah, the surface area
+    /// heuristic, or median, quicker to build but slower to trace.
+    /// Overrides the scene.
+    #[arg(long)]
+    bvh: Option<Split>,
+
+    /// Windowing library to show the image with.
+    #[cfg(any(feature = "sdl2", feature = "minifb"))]
+    #[arg(long, value_enum)]
+    display: Option<Backend>,
+
+    /// How fast the camera flies, in scene units per second.
+    #[cfg(any(feature = "sdl2", feature = "minifb"))]
+    #[arg(long, default_value_t = 1.0)]
+    speed: f32
 }
 
-impl Vector {
-    pub fn dot(self, other: Self) -> f32 {
-        self.x * other.x + self.y * other.y + self.z * other.z
+/// Save the image to `path`, in the format its extension tells and tone
+/// mapped as the settings ask unless the format keeps the full range of
+/// the light, denoised if they ask for it and with an alpha channel if
+/// they ask for a transparent background, along with the arbitrary
+/// output variables if they ask for them, and an OpenEXR file of the
+/// given precision if asked for, named alike. If the settings ask to
+/// split the eyes of a stereo image, the halves are saved instead, with
+/// `-left` and `-right` added to the names. The physical exposure of the
+/// camera adds to the one of the settings.
+fn save(image: &Image, aovs: Option<Aovs>, settings: &Settings, camera: &Camera, exr: Option<Precision>, path: &str) {
+    let settings = &Settings { exposure: settings.exposure + camera.exposure_stops(), ..*settings };
+    let image = match &aovs {
+        Some(aovs) if settings.denoise => denoise(image, aovs),
+        _ => image.clone()
+    };
+    let alpha = aovs.as_ref().filter(|_| settings.transparent).map(|aovs| aovs.alpha.clone());
+    let aovs = aovs.filter(|_| settings.aovs);
+
+    if !settings.split_eyes {
+        write(&image, alpha.as_ref(), aovs.as_ref(), settings, exr, path);
+        return;
     }
-
-    pub fn sqnorm(self) -> f32 {
-        self.dot(self)
+    let half = image.width / 2;
+    for (eye, j0, j1) in [("left", 0, half), ("right", half, image.width)] {
+        let path = format!("{}-{}.{}", stem(path), eye, Format::of(path).extension());
+        let alpha = alpha.as_ref().map(|alpha| alpha.columns(j0, j1));
+        let aovs = aovs.as_ref().map(|aovs| aovs.columns(j0, j1));
+        write(&image.columns(j0, j1), alpha.as_ref(), aovs.as_ref(), settings, exr, &path);
     }
+}
 
-    pub fn norm(self) -> f32 {
-        self.sqnorm().sqrt()
+/// Write the files `save` saves, for the averaged image, alpha and AOVs.
+fn write(image: &Image, alpha: Option<&Image>, aovs: Option<&Aovs>, settings: &Settings, exr: Option<Precision>, path: &str) {
+    let stem = stem(path);
+    let format = Format::of(path);
+
+    // The tone mapping goes by the colors themselves, not the ones
+    // multiplied by the alpha.
+    let mapped = match alpha {
+        _ if format.is_hdr() => image.clone(),
+        Some(alpha) => settings.tone_map.map_image(&unpremultiply(image, alpha), 1, settings.exposure),
+        None => settings.tone_map.map_image(image, 1, settings.exposure)
+    };
+    match save_image(&mapped, 1, alpha, format, path) {
+        Ok(()) => println!("Saved {}", path),
+        Err(err) => eprintln!("Failed to save {}: {}", path, err)
     }
-
-    pub fn unit(self) -> Self {
-        self / self.norm()
+    if let Some(aovs) = aovs {
+        match save_aovs(aovs, stem, format) {
+            Ok(paths) => println!("Saved {}", paths.join(", ")),
+            Err(err) => eprintln!("Failed to save the AOVs of {}: {}", path, err)
+        }
     }
-
-    pub fn random_unit() -> Self {
-        loop {
-            let v = Vector{
-                x: rand::random::<f32>(),
-                y: rand::random::<f32>(),
-                z: rand::random::<f32>()
-            };
-
-            if v.sqnorm() >= 1.0 {
-                continue
-            }
-
-            return v.unit();
+    if let Some(precision) = exr {
+        let path = format!("{}.exr", stem);
+        match save_exr(image, 1, alpha, aovs, precision, &path) {
+            Ok(()) => println!("Saved {}", path),
+            Err(err) => eprintln!("Failed to save {}: {}", path, err)
         }
     }
 }
 
-impl Add<Vector> for Vector {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self {
-        Self {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z
-        }
-    }
+/// Checkpoint file of the render of the image `path`.
+fn checkpoint_path(path: &str) -> String {
+    format!("{}.checkpoint", stem(path))
 }
 
-impl AddAssign<Vector> for Vector {
-    fn add_assign(&mut self, other: Self) {
-        self.x += other.x;
-        self.y += other.y;
-        self.z += other.z;
+/// Carry on from the checkpoint at `path`, or tell why not and go on
+/// from the start.
+fn resume(renderer: &RenderThread, path: &str) {
+    let resumed = Checkpoint::load(path)
+        .map_err(|err| err.to_string())
+        .and_then(|checkpoint| renderer.resume(checkpoint));
+    match resumed {
+        Ok(()) => println!("Resumed from {}", path),
+        Err(err) => eprintln!("Failed to resume from {}, starting over: {}", path, err)
     }
 }
 
-impl Add<f32> for Vector {
-    type Output = Self;
-
-    fn add(self, other: f32) -> Self {
-        Self {
-            x: self.x + other,
-            y: self.y + other,
-            z: self.z + other
-        }
+fn save_checkpoint(renderer: &RenderThread, path: &str) {
+    match renderer.checkpoint().save(path) {
+        Ok(()) => println!("Saved {}", path),
+        Err(err) => eprintln!("Failed to save {}: {}", path, err)
     }
 }
 
-impl Add<Vector> for f32 {
-    type Output = Vector;
-
-    fn add(self, other: Vector) -> Vector {
-        Vector {
-            x: self + other.x,
-            y: self + other.y,
-            z: self + other.z
-        }
+/// Render every frame of the animation to the video file `path`.
+fn render_video(scene: Scene, args: &Args, path: &str) {
+    let fail = |err: io::Error| -> ! {
+        eprintln!("Failed to write {}: {}", path, err);
+        process::exit(1);
+    };
+    let Animation { frames, fps, .. } = scene.animation;
+    let mut video = Video::open(path, scene.settings.width, scene.settings.height, fps).unwrap_or_else(|err| fail(err));
+
+    let mut first = Some(scene);
+    for frame in 1 ..= frames {
+        let Scene { settings, camera, world, .. } = first.take().unwrap_or_else(|| load_scene(args, frame));
+        println!("Frame {} of {}", frame, frames);
+        let renderer = RenderThread::spawn(settings, camera, Arc::new(world));
+        let (image, _) = renderer.wait();
+
+        // As `save` would write it to a PNG file, but for the alpha.
+        let image = match renderer.aovs() {
+            Some(aovs) if settings.denoise => denoise(&image, &aovs),
+            _ => image
+        };
+        let mapped = settings.tone_map.map_image(&image, 1, settings.exposure + camera.exposure_stops());
+        video.write_frame(&mapped).unwrap_or_else(|err| fail(err));
     }
+    video.finish().unwrap_or_else(|err| fail(err));
+    println!("Saved {}", path);
 }
 
-impl Div<f32> for Vector {
-    type Output = Self;
+/// Name of the file of the `frame` of an animation, such as
+/// render_0001.png for render.png.
+fn frame_name(path: &str, frame: u32) -> String {
+    format!("{}_{:04}.{}", stem(path), frame, Format::of(path).extension())
+}
 
-    fn div(self, other: f32) -> Self {
-        Self {
-            x: self.x / other,
-            y: self.y / other,
-            z: self.z / other
-        }
+/// File name without the extension.
+fn stem(path: &str) -> &str {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(extension) => &path[.. path.len() - extension.len() - 1],
+        None => path
     }
 }
 
-impl Mul<Vector> for f32 {
-    type Output = Vector;
+/// Load the scene as it is at the `frame` of its animation, with the
+/// options overriding it.
+fn load_scene(args: &Args, frame: u32) -> Scene {
+    let mut scene = match &args.scene {
+        Some(path) => Scene::load_frame(path, frame).unwrap_or_else(|err| {
+            eprintln!("Failed to load {}: {}", path.display(), err);
+            process::exit(1);
+        }),
+        None => args.demo.unwrap_or_default().scene(args.seed.unwrap_or_default())
+    };
+    configure(&mut scene, args, frame);
+    scene
+}
 
-    fn mul(self, other: Vector) -> Vector {
-        Vector {
-            x: self * other.x,
-            y: self * other.y,
-            z: self * other.z
-        }
+/// Apply the options overriding the scene, as it is at the `frame` of
+/// its animation.
+fn configure(scene: &mut Scene, args: &Args, frame: u32) {
+    if let Some(frames) = args.turntable.filter(|&frames| frames > 0) {
+        scene.animation.frames = frames;
+        let camera = scene.camera;
+        let center = camera.origin + camera.focus_distance * camera.forward();
+        let angle = 2.0 * PI * frame.saturating_sub(1) as f32 / frames as f32;
+        scene.camera = camera.orbited(center, Transform::rotate(EY, angle));
+    }
+    if let Some(projection) = args.projection {
+        scene.camera = scene.camera.with_projection(projection);
+    }
+    if let (Some(fov), Projection::Fisheye { mapping, .. }) = (args.fisheye_fov, scene.camera.projection) {
+        scene.camera = scene.camera.with_projection(Projection::Fisheye { mapping, fov });
+    }
+    if let Some(height) = args.view_height {
+        scene.camera = scene.camera.with_view_height(height);
+    }
+    if let Some(separation) = args.eye_separation {
+        scene.camera = scene.camera.with_stereo(separation);
+    }
+    scene.settings.split_eyes |= args.split_eyes;
+    if let Some(integrator) = args.integrator {
+        scene.settings.integrator = integrator;
+    }
+    scene.settings.aovs |= args.aovs;
+    scene.settings.denoise |= args.denoise;
+    scene.settings.transparent |= args.transparent;
+    if let Some(distance) = args.ao_distance {
+        scene.settings.ao_distance = distance;
+    }
+    if args.accelerator.is_some() || args.bvh.is_some() {
+        scene.settings.accelerator = args.accelerator.unwrap_or(scene.settings.accelerator);
+        scene.settings.bvh = args.bvh.unwrap_or(scene.settings.bvh);
+        scene.world.prepare(scene.settings.accelerator, scene.settings.bvh);
+    }
+    if let Some(photons) = args.photons {
+        scene.settings.photons = photons;
+        scene.world.caustics = PhotonMap::build(&scene.world, &scene.settings);
+    }
+    if args.threshold.is_some() {
+        scene.settings.threshold = args.threshold;
+    }
+    if let Some(sampler) = args.sampler {
+        scene.settings.sampler = sampler;
+    }
+    scene.settings.blue_noise |= args.blue_noise;
+    if let Some(seed) = args.seed {
+        scene.settings.seed = seed;
+    }
+    if let Some(filter) = args.filter {
+        scene.settings.filter = filter;
+    }
+    if args.max_radiance.is_some() {
+        scene.settings.max_radiance = args.max_radiance;
+    }
+    if let Some(tone_map) = args.tone_map {
+        scene.settings.tone_map = tone_map;
+    }
+    if let Some(exposure) = args.exposure {
+        scene.settings.exposure = exposure;
+    }
+    if args.iso.is_some() || args.shutter_speed.is_some() || args.f_stop.is_some() {
+        let exposure = scene.camera.exposure.unwrap_or_default();
+        scene.camera = scene.camera.with_exposure(Exposure {
+            iso: args.iso.unwrap_or(exposure.iso),
+            shutter: args.shutter_speed.unwrap_or(exposure.shutter),
+            f_stop: args.f_stop.unwrap_or(exposure.f_stop)
+        });
     }
 }
 
-impl Mul<f32> for Vector {
-    type Output = Self;
-
-    fn mul(self, other: f32) -> Self {
-        Self {
-            x: other * self.x,
-            y: other * self.y,
-            z: other * self.z
+fn render_headless(scene: Scene, args: &Args, output: &str) {
+    let Scene { settings, camera, world, .. } = scene;
+    let renderer = RenderThread::spawn(settings, camera, Arc::new(world));
+    let checkpoint = checkpoint_path(output);
+    if args.resume {
+        resume(&renderer, &checkpoint);
+    }
+    if let Some(interval) = args.checkpoint_interval {
+        while !renderer.wait_timeout(Duration::from_secs_f32(interval)) {
+            save_checkpoint(&renderer, &checkpoint);
         }
     }
-}
 
-impl Sub<Vector> for Vector {
-    type Output = Self;
+    let (image, _) = renderer.wait();
+    save(&image, renderer.aovs(), &settings, &camera, args.exr, output);
 
-    fn sub(self, other: Self) -> Self {
-        Self {
-            x: self.x - other.x,
-            y: self.y - other.y,
-            z: self.z - other.z
-        }
+    // The render is done, there is nothing to resume.
+    if args.checkpoint_interval.is_some() {
+        let _ = fs::remove_file(&checkpoint);
     }
 }
 
-/// Minimal ray abstraction.
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Ray {
-    pub origin: Vector,
-    pub direction: Vector
+/// Render every frame of the animation to files numbered after the image.
+fn render_animation(scene: Scene, args: &Args) {
+    let frames = scene.animation.frames;
+    let mut first = Some(scene);
+    for frame in 1 ..= frames {
+        let scene = first.take().unwrap_or_else(|| load_scene(args, frame));
+        println!("Frame {} of {}", frame, frames);
+        render_headless(scene, args, &frame_name(&args.output, frame));
+    }
 }
 
-impl Ray {
-    pub fn new(origin: Vector, direction: Vector) -> Self {
-        Self {
-            origin: origin,
-            direction: direction.unit()
+fn render_distributed(scene: Scene, address: &str, args: &Args) {
+    let Scene { settings, camera, .. } = scene;
+    match distributed::coordinate(address, &settings) {
+        Ok((image, aovs)) => save(&image, aovs, &settings, &camera, args.exr, &args.output),
+        Err(err) => {
+            eprintln!("Failed to coordinate on {}: {}", address, err);
+            process::exit(1);
         }
     }
+}
 
-    pub fn at(self, t: f32) -> Vector {
-        self.origin + t * self.direction
+fn work(scene: &Scene, address: &str) {
+    println!("Working for {}", address);
+    if let Err(err) = distributed::work(address, &scene.settings, &scene.camera, &scene.world) {
+        eprintln!("Failed to work for {}: {}", address, err);
+        process::exit(1);
     }
 }
 
-/// Geometry
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Hit {
-    pub t: f32,    // Distance along the ray to the intersection with the shape
-    pub p: Vector, // Cartesian coordinates of the intersection
-    pub n: Vector, // Outer surface normal at the intersection
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+fn open_display(backend: Backend, settings: &Settings) -> Result<Box<dyn Display>, String> {
+    let title = "Raytracer Demo";
+    let display: Box<dyn Display> = match backend {
+        #[cfg(feature = "sdl2")]
+        Backend::Sdl => Box::new(rtrace::display::SdlDisplay::new(title, settings.width, settings.height)?),
+        #[cfg(feature = "minifb")]
+        Backend::Minifb => Box::new(rtrace::display::MinifbDisplay::new(title, settings.width, settings.height)?)
+    };
+    Ok(display)
 }
 
-impl Hit {
-    pub fn new(t: f32, p: Vector, n: Vector) -> Self {
-        Self {
-            t: t,
-            p: p,
-            n: n.unit()
-        }
+/// Tell what is seen at the pixel (x, y) counting from the top left
+/// corner.
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+fn pick(world: &World, camera: &Camera, settings: &Settings, x: i32, y: i32) {
+    let u = x as f32 / (settings.width as f32 - 1.0);
+    let v = 1.0 - y as f32 / (settings.height as f32 - 1.0);
+    match world.pick(camera, u, v) {
+        Some(hit) => println!(
+            "Object {} at ({}, {}): {} at distance {:.3}",
+            hit.object, x, y, hit.material.name(), hit.t
+        ),
+        None => println!("Nothing at ({}, {})", x, y)
     }
 }
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray) -> Option<Hit>;
+/// Tells when a file is saved, by the time it was last modified.
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+struct FileWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Sphere {
-    pub center: Vector,
-    pub radius: f32
-}
-
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray) -> Option<Hit> {
-        let eps = 1E-3;
-
-        let o = ray.origin - self.center;
-        let b = ray.direction.dot(o);
-        let c = o.sqnorm() - self.radius * self.radius;
-        let discriminant = b * b - c;
-
-        if discriminant < 0.0 {
-            return None;
-        }
-
-        let d = discriminant.sqrt();
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+impl FileWatch {
+    fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), modified: Self::modified(path) }
+    }
 
-        let t1 = - b + d;
-        let t2 = - b - d;
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
 
-        if t1 < eps && t2 < eps {
-            return None;
+    /// Whether the file was modified since it was last asked.
+    fn changed(&mut self) -> bool {
+        let modified = Self::modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
         }
-
-        let t: f32 = match (t1 >= eps, t2 >= eps) {
-            (false, true) => t2,
-            (true, false) => t1,
-            (true, true)  => t1.min(t2),
-            _ => unreachable!()
-        };
-
-        let p = ray.at(t);
-        let n = p - self.center;
-
-        Some(Hit::new(t, p, n))
+        self.modified = modified;
+        true
     }
 }
 
-pub struct World {
-    pub objects: Vec<Box<dyn Hittable>>
-}
-
-impl World {
-    pub fn new() -> World {
-        World {
-            objects: vec![]
-        }
+/// Load the scene file at `path` again after it was edited, unless it
+/// fails to load or the size of the image changed, which needs another
+/// window.
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+fn reload(path: &Path, args: &Args, settings: &Settings) -> Option<Scene> {
+    let frame = args.frame.unwrap_or(1);
+    let mut scene = Scene::load_frame(path, frame)
+        .map_err(|err| eprintln!("Failed to reload {}: {}", path.display(), err))
+        .ok()?;
+    configure(&mut scene, args, frame);
+    if (scene.settings.width, scene.settings.height) != (settings.width, settings.height) {
+        eprintln!("The size of the image in {} changed, restart to see it", path.display());
+        return None;
     }
+    println!("Reloaded {}", path.display());
+    Some(scene)
 }
 
-impl Hittable for World {
-    fn hit(&self, ray: &Ray) -> Option<Hit> {
-        let hits: Vec<Hit> = self.objects.iter()
-            .map(|obj| obj.hit(&ray))
-            .filter(|hit| hit.is_some())
-            .map(|hit| hit.unwrap())
-            .collect();
+#[cfg(any(feature = "sdl2", feature = "minifb"))]
+fn render_window(scene: Scene, args: &Args) {
+    let backend = args.display.unwrap_or_else(Backend::default);
+    let mut display = open_display(backend, &scene.settings).unwrap_or_else(|err| {
+        eprintln!("Failed to open a window: {}", err);
+        process::exit(1);
+    });
+
+    let mut settings = scene.settings;
+    let mut camera = scene.camera;
+    let mut world = Arc::new(scene.world);
+    #[cfg(feature = "egui")]
+    let mut colors = scene.colors;
+
+    // Sampling goes on in the background, this loop only shows the
+    // samples taken so far and handles the input.
+    let mut renderer = RenderThread::spawn(settings, camera, world.clone());
+    let mut saved = false;
+    let checkpoint = checkpoint_path(&args.output);
+    if args.resume {
+        resume(&renderer, &checkpoint);
+    }
+    let mut checkpointed = Instant::now();
+
+    // The camera as the scene file places it, to tell whether an edit
+    // moved it.
+    let mut loaded_camera = camera;
+    let mut watch = args.scene.as_deref().map(FileWatch::new);
+
+    let mut controls = FlyControls::new(args.speed);
+    #[cfg(feature = "egui")]
+    let mut panel = rtrace::display::Panel::new();
+
+    loop {
+        let mut restart = false;
+
+        for event in display.poll_events() {
+            // Clicks on the panel don't turn the camera.
+            #[cfg(feature = "egui")]
+            {
+                panel.handle(event);
+                if matches!(event, Event::MouseDown {..}) && panel.wants_mouse() {
+                    continue;
+                }
+            }
+            controls.handle(event);
 
-        if hits.is_empty() {
-            return None
+            match event {
+                Event::Quit => return,
+                // The sampling goes on while the snapshot is saved.
+                Event::KeyDown(Key::P) => {
+                    let (image, _) = renderer.snapshot();
+                    let path = timestamped_name(stem(&args.output), Format::of(&args.output).extension());
+                    save(&image, renderer.aovs(), &settings, &camera, args.exr, &path);
+                },
+                Event::KeyDown(Key::Space) => renderer.set_paused(!renderer.is_paused()),
+                Event::KeyDown(Key::R) => restart = true,
+                // Only the image shown changes, the samples are kept.
+                Event::KeyDown(Key::T) => {
+                    settings.tone_map = settings.tone_map.next();
+                    println!("Tone mapping: {:?}", settings.tone_map);
+                },
+                Event::KeyDown(Key::Plus) => {
+                    settings.exposure += EXPOSURE_STEP;
+                    println!("Exposure: {:+.1} EV", settings.exposure);
+                },
+                Event::KeyDown(Key::Minus) => {
+                    settings.exposure -= EXPOSURE_STEP;
+                    println!("Exposure: {:+.1} EV", settings.exposure);
+                },
+                Event::MouseDown { x, y } => pick(&world, &camera, &settings, x, y),
+                _ => {}
+            }
         }
 
-        let nearest_hit = hits.iter().fold(hits[0], |a, b| {
-            if a.t > b.t { *b } else { a }
-        });
-
-        return Some(nearest_hit);
-    }
-}
+        // Saving the scene file rebuilds the world and starts over. The
+        // camera flown elsewhere stays there, unless the edit moves it.
+        let edited = watch.as_mut().is_some_and(FileWatch::changed);
+        let reloaded = match &watch {
+            Some(watch) if edited => reload(&watch.path, args, &settings),
+            _ => None
+        };
+        if let Some(scene) = reloaded {
+            if scene.camera != loaded_camera {
+                camera = scene.camera;
+                loaded_camera = camera;
+            }
+            settings = scene.settings;
+            world = Arc::new(scene.world);
+            #[cfg(feature = "egui")]
+            {
+                colors = scene.colors;
+            }
 
-/// Ray tracing algorithm.
-pub fn background_color(ray: &Ray) -> Vector {
-    let y = ray.direction.y;
-    let t = 0.5 * (y + 1.0);
-    let blue  = Vector {x: 0.5, y: 0.7, z: 1.0};
-    let white = Vector {x: 1.0, y: 1.0, z: 1.0};
+            let paused = renderer.is_paused();
+            renderer = RenderThread::spawn(settings, camera, world.clone());
+            renderer.set_paused(paused);
+            saved = false;
+        }
 
-    (1.0 - t) * white + t * blue
-}
+        // Moving or changing any of the parameters starts the sampling
+        // over.
+        restart |= controls.update(&mut camera);
+        #[cfg(feature = "egui")]
+        {
+            restart |= panel.update(&mut settings, &mut camera, &colors);
+        }
+        if restart {
+            renderer.restart(settings, camera);
+            saved = false;
+        }
 
-pub fn ray_color(ray: &Ray, world: &World, depth: u8) -> Vector {
-    if depth == 0 {
-        return Vector {x: 0.0, y: 0.0, z: 0.0};
-    }
+        let finished = renderer.is_finished();
+        let (image, _) = renderer.snapshot();
+        if finished && !saved {
+            save(&image, renderer.aovs(), &settings, &camera, args.exr, &args.output);
+            saved = true;
+        }
+        if let Some(interval) = args.checkpoint_interval {
+            if !finished && checkpointed.elapsed().as_secs_f32() >= interval {
+                save_checkpoint(&renderer, &checkpoint);
+                checkpointed = Instant::now();
+            }
+        }
 
-    let hit = world.hit(ray);
+        #[cfg_attr(not(feature = "egui"), allow(unused_mut))]
+        let mut frame = Frame::from_image(
+            &settings.tone_map.map_image(&image, 1, settings.exposure + camera.exposure_stops()), 1
+        );
+        #[cfg(feature = "egui")]
+        panel.paint(&mut frame);
+        display.show(&frame);
 
-    if !hit.is_none() {
-        let h = hit.unwrap();
-        let d = h.n + Vector::random_unit();
-        return 0.5 * ray_color(&Ray{origin: h.p, direction: d}, world, depth - 1);
+        thread::sleep(Duration::from_millis(16));
     }
-
-    background_color(ray)
 }
 
-/// Window and viewport related setup.
-const IMAGE_WIDTH:  usize = 500;
-const IMAGE_HEIGHT: usize = 500;
-
-const ASPECT_RATIO: f32 = IMAGE_WIDTH as f32 / IMAGE_HEIGHT as f32;
-
-const VIEWPORT_WIDTH: f32 = 2.0;
-const VIEWPORT_HEIGHT: f32 = VIEWPORT_WIDTH / ASPECT_RATIO;
-const VIEWPORT_FOCUS_DISTANCE: f32 = 1.0;
-
-/// Rendering algorithm parameters.
-const SAMPLES_PER_PIXEL: u32 = 100;
-const RECURSION_DEPTH: u8 = 7;
-
-/// Basic geometric constants.
-const OG: Vector = Vector{x: 0.0, y: 0.0, z: 0.0};
-const EX: Vector = Vector{x: 1.0, y: 0.0, z: 0.0};
-const EY: Vector = Vector{x: 0.0, y: 1.0, z: 0.0};
-const EZ: Vector = Vector{x: 0.0, y: 0.0, z: 1.0};
-
-/// Auxiliary functions.
-use sdl2;
-use sdl2::pixels::Color;
-use sdl2::event::Event;
-use sdl2::rect::Point;
-use sdl2::render::{Canvas, RenderTarget};
-
-pub fn to_rgb(vec: Vector) -> Color {
-    Color::RGB(
-        (255.0 * vec.x.sqrt()) as u8,
-        (255.0 * vec.y.sqrt()) as u8,
-        (255.0 * vec.z.sqrt()) as u8
-    )
-}
-
-pub fn render_image<T: RenderTarget>(image: &[[Vector; IMAGE_WIDTH]; IMAGE_HEIGHT], canvas: &mut Canvas<T>) {
-    for i in 0 .. IMAGE_HEIGHT {
-        for j in 0 .. IMAGE_WIDTH {
-            let vector = image[i][j] / (SAMPLES_PER_PIXEL as f32);
-            canvas.set_draw_color(to_rgb(vector));
-            canvas.draw_point(Point::new(j as i32, IMAGE_HEIGHT as i32 - i as i32)).unwrap();
-        }
-    }
-    canvas.present();
+#[cfg(not(any(feature = "sdl2", feature = "minifb")))]
+fn render_window(_scene: Scene, _args: &Args) {
+    eprintln!("Built without a display backend, pass --headless");
+    process::exit(1);
 }
 
 fn main() {
-    // Initialize the window.
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let window = video_subsystem.window("Raytracer Demo", IMAGE_WIDTH as u32, IMAGE_HEIGHT as u32)
-        .position_centered()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas()
-        .present_vsync()
-        .build()
-        .unwrap();
-
-    let black = Vector{x: 0.0, y: 0.0, z: 0.0};
-    let mut image = [[black; IMAGE_WIDTH]; IMAGE_HEIGHT];
-
-    let mut world = World::new();
-    world.objects.push(Box::new(
-        Sphere{
-            center: Vector{ x: 0.0, y: 0.0, z: -1.0},
-            radius: 0.5
-        }
-    ));
-    world.objects.push(Box::new(
-        Sphere{
-            center: Vector{ x: 0.0, y: -100.5, z: -1.0},
-            radius: 100.0
-        }
-    ));
-
-    // For each pixel we cast a ray.
-    for n in 0 .. SAMPLES_PER_PIXEL {
-        for i in 0 .. IMAGE_HEIGHT {
-            for j in 0 .. IMAGE_WIDTH {
-                // Calculate coordinates of the point relative to the
-                // viewport.
-                let u = (j as f32 + rand::random::<f32>()) / (IMAGE_WIDTH  as f32 - 1.0);
-                let v = (i as f32 + rand::random::<f32>()) / (IMAGE_HEIGHT as f32 - 1.0);
-
-                let x = (u - 0.5) * VIEWPORT_WIDTH;
-                let y = (v - 0.5) * VIEWPORT_HEIGHT;
-
-                // Construct a ray going through the point on the
-                // viewport.
-                let ray = Ray::new(OG, x * EX + y * EY - VIEWPORT_FOCUS_DISTANCE * EZ - OG);
-
-                // Perform ray tracing and see what color the ray should
-                // be.
-                let color = ray_color(&ray, &world, RECURSION_DEPTH);
-                image[i][j] += color;
-            }
+    let args = Args::parse();
+
+    if let Some(address) = &args.serve {
+        if let Err(err) = server::serve(address) {
+            eprintln!("Failed to serve on {}: {}", address, err);
+            process::exit(1);
         }
-        println!("{:?}", n);
-        render_image(&image, &mut canvas);
+        return;
     }
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    'main: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit {..} => break 'main,
-                _ => {}
-            }
-        }
+    let scene = load_scene(&args, args.frame.unwrap_or(1));
+    if let Some(address) = &args.worker {
+        work(&scene, address);
+    } else if let Some(address) = &args.coordinator {
+        render_distributed(scene, address, &args);
+    } else if let Some(path) = &args.video {
+        render_video(scene, &args, path);
+    } else if args.headless && scene.animation.is_animated() && args.frame.is_none() {
+        render_animation(scene, &args);
+    } else if args.headless {
+        render_headless(scene, &args, &args.output);
+    } else {
+        render_window(scene, &args);
     }
 }
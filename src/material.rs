@@ -0,0 +1,625 @@
+//! Surface materials: how a ray scatters off the point it hits.
+
+use std::f32::consts::{FRAC_1_PI, PI};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::geometry::{Hit, Ray};
+use crate::math::Vector;
+use crate::microfacet::{
+    fresnel_schlick, ggx_d, ggx_pdf, gtr1_d, gtr1_pdf, sample_cosine, sample_ggx, sample_gtr1, smith_g
+};
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+
+/// Outcome of a ray scattering off a surface: the new ray and the
+/// fraction of its color that makes it back along the incoming ray.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Scatter {
+    pub ray: Ray,
+    pub attenuation: Vector
+}
+
+pub trait Material: Debug + Send + Sync {
+    /// Scatter the incoming ray at the hit point. Returns `None` if the
+    /// ray gets absorbed.
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter>;
+
+    /// Fraction of the light coming from `direction` that leaves back
+    /// along the incoming ray: the BRDF (or phase function) already
+    /// multiplied by the cosine factor. Used for direct lighting, which
+    /// purely specular materials never receive, hence zero by default.
+    fn eval(&self, _ray: &Ray, _hit: &Hit, _direction: Vector) -> Vector {
+        Vector{x: 0.0, y: 0.0, z: 0.0}
+    }
+
+    /// Density per unit solid angle with which `scatter` sends the ray
+    /// to `direction`. Zero for purely specular materials, whose
+    /// directions can't be picked any other way.
+    fn pdf(&self, _ray: &Ray, _hit: &Hit, _direction: Vector) -> f32 {
+        0.0
+    }
+
+    /// Light emitted by the surface back along the incoming ray.
+    fn emitted(&self, _ray: &Ray, _hit: &Hit) -> Vector {
+        Vector{x: 0.0, y: 0.0, z: 0.0}
+    }
+
+    /// Whether the material only reflects or refracts in the directions
+    /// it scatters to, so that sampling the lights does it no good.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Outer normal the surface is shaded with, which the normal and
+    /// bump maps tilt away from the normal of the geometry.
+    fn shading_normal(&self, hit: &Hit) -> Vector {
+        hit.n
+    }
+
+    /// Color of the surface: the fraction of the light it reflects in
+    /// all, for the denoisers to tell the textures from the noise. White
+    /// for what has no color of its own, such as glass.
+    fn albedo(&self, _hit: &Hit) -> Vector {
+        Vector{x: 1.0, y: 1.0, z: 1.0}
+    }
+
+    /// Name of the kind of the material, for the humans.
+    fn name(&self) -> &'static str {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").next().unwrap_or(path)
+    }
+}
+
+/// Ideal diffuse surface.
+#[derive(Debug, Clone)]
+pub struct Lambertian {
+    pub albedo: Arc<dyn Texture>
+}
+
+impl Lambertian {
+    fn albedo_at(&self, hit: &Hit) -> Vector {
+        hit.color * self.albedo.value(hit.u, hit.v, hit.p)
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        // Off the side the ray came from, even of the back of a surface.
+        let n = hit.facing_normal(ray);
+        let mut direction = n + sampler.unit_vector();
+
+        // The random vector may happen to be opposite to the normal.
+        if direction.is_near_zero() {
+            direction = n;
+        }
+
+        Some(Scatter {
+            ray: Ray::new(hit.p, direction).with_time(ray.time),
+            attenuation: self.albedo_at(hit)
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: Vector) -> Vector {
+        // The surface is lit from the side the ray came from.
+        let n = hit.facing_normal(ray);
+        let cos = n.dot(direction).max(0.0);
+        (cos * FRAC_1_PI) * self.albedo_at(hit)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: Vector) -> f32 {
+        hit.facing_normal(ray).dot(direction).max(0.0) * FRAC_1_PI
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector {
+        self.albedo_at(hit)
+    }
+}
+
+/// Reflective surface. Non-zero `fuzz` randomly perturbs the reflected
+/// ray, from a perfect mirror at 0 to a brushed look around 0.3 and
+/// almost diffuse at 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Metal {
+    pub albedo: Vector,
+    pub fuzz: f32
+}
+
+impl Metal {
+    pub fn new(albedo: Vector, fuzz: f32) -> Self {
+        Self {
+            albedo,
+            fuzz: fuzz.clamp(0.0, 1.0)
+        }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        // The reflection happens on whatever side the ray came from. The
+        // fuzz moves the reflected direction to a random point of a
+        // sphere around its tip.
+        let n = hit.facing_normal(ray);
+        let reflected = ray.direction.reflect(n);
+        let direction = reflected + self.fuzz * sampler.unit_vector();
+
+        // Perturbed below the surface, the ray gets absorbed.
+        if direction.dot(n) <= 0.0 {
+            return None;
+        }
+
+        Some(Scatter {
+            ray: Ray::new(hit.p, direction).with_time(ray.time),
+            attenuation: self.albedo
+        })
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn albedo(&self, _hit: &Hit) -> Vector {
+        self.albedo
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance for light hitting
+/// the surface with the given cosine of the angle of incidence, where
+/// `eta` is the ratio of the refractive indices.
+pub fn schlick(cos: f32, eta: f32) -> f32 {
+    let r0 = ((1.0 - eta) / (1.0 + eta)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+/// Transparent surface, such as glass or water, that refracts the rays
+/// passing through it. Put a sphere with a negative radius inside a
+/// glass one to get a hollow glass ball.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Dielectric {
+    pub refractive_index: f32
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        // The normal always points outwards, so facing it tells that we
+        // are entering the body rather than leaving it.
+        let n = hit.facing_normal(ray);
+        let eta = if n == hit.n {
+            1.0 / self.refractive_index
+        } else {
+            self.refractive_index
+        };
+
+        // Part of the light is always reflected, the more so the more
+        // grazing the angle is. Beyond the critical angle all of it is.
+        let cos = -ray.direction.dot(n);
+        let direction = match ray.direction.refract(n, eta) {
+            Some(refracted) if sampler.next_1d() >= schlick(cos, eta) => refracted,
+            _ => ray.direction.reflect(n)
+        };
+
+        Some(Scatter {
+            ray: Ray::new(hit.p, direction).with_time(ray.time),
+            attenuation: Vector{x: 1.0, y: 1.0, z: 1.0}
+        })
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+/// Glowing surface that emits `radiance` from its outer side and
+/// reflects nothing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Emissive {
+    pub radiance: Vector
+}
+
+impl Material for Emissive {
+    fn scatter(&self, _ray: &Ray, _hit: &Hit, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &Hit) -> Vector {
+        if ray.direction.dot(hit.n) < 0.0 {
+            self.radiance
+        } else {
+            Vector{x: 0.0, y: 0.0, z: 0.0}
+        }
+    }
+}
+
+/// Phase function of a participating medium that scatters light equally
+/// in all directions.
+#[derive(Debug, Clone)]
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        Some(Scatter {
+            ray: Ray::new(hit.p, sampler.unit_vector()).with_time(ray.time),
+            attenuation: self.albedo.value(hit.u, hit.v, hit.p)
+        })
+    }
+
+    fn eval(&self, _ray: &Ray, hit: &Hit, _direction: Vector) -> Vector {
+        self.albedo.value(hit.u, hit.v, hit.p) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _ray: &Ray, _hit: &Hit, _direction: Vector) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector {
+        self.albedo.value(hit.u, hit.v, hit.p)
+    }
+}
+
+/// Physically based material of the metallic-roughness workflow: a
+/// GGX microfacet specular lobe with Smith shadowing over a Lambertian
+/// base. Metals tint their reflections with the base color and have no
+/// diffuse part, dielectrics reflect about 4% at normal incidence.
+#[derive(Debug, Clone)]
+pub struct Pbr {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: f32,
+    pub roughness: f32
+}
+
+impl Pbr {
+    fn alpha(&self) -> f32 {
+        // Perfectly smooth surfaces make the distribution singular.
+        (self.roughness * self.roughness).max(1E-3)
+    }
+
+    /// Probability of sampling the specular lobe rather than the
+    /// diffuse one.
+    fn specular_probability(&self) -> f32 {
+        0.5 + 0.5 * self.metallic
+    }
+
+    /// BRDF times the cosine for the light coming from `wi` and leaving
+    /// towards `wo`, both pointing away from the surface.
+    fn reflectance(&self, base: Vector, n: Vector, wo: Vector, wi: Vector) -> Vector {
+        let n_dot_l = n.dot(wi);
+        let n_dot_v = n.dot(wo);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return Vector{x: 0.0, y: 0.0, z: 0.0};
+        }
+
+        let alpha = self.alpha();
+        let h = (wi + wo).unit();
+
+        let dielectric = Vector{x: 0.04, y: 0.04, z: 0.04};
+        let f0 = (1.0 - self.metallic) * dielectric + self.metallic * base;
+        let f = fresnel_schlick(wi.dot(h), f0);
+
+        let d = ggx_d(n.dot(h).max(0.0), alpha);
+        let g = smith_g(n_dot_l, n_dot_v, alpha);
+        let specular = (d * g / (4.0 * n_dot_l * n_dot_v)) * f;
+
+        let kd = (1.0 - self.metallic) * (1.0 - f);
+        let diffuse = FRAC_1_PI * (kd * base);
+
+        n_dot_l * (specular + diffuse)
+    }
+
+    fn density(&self, n: Vector, wo: Vector, wi: Vector) -> f32 {
+        let p = self.specular_probability();
+        let cos = n.dot(wi).max(0.0);
+        p * ggx_pdf(n, wo, wi, self.alpha()) + (1.0 - p) * cos * FRAC_1_PI
+    }
+}
+
+impl Material for Pbr {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let n = hit.facing_normal(ray);
+        let wo = -ray.direction;
+
+        let wi = if sampler.next_1d() < self.specular_probability() {
+            let h = sample_ggx(n, self.alpha(), sampler);
+            ray.direction.reflect(h)
+        } else {
+            sample_cosine(n, sampler)
+        };
+
+        let pdf = self.density(n, wo, wi);
+        if n.dot(wi) <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+
+        let base = hit.color * self.base_color.value(hit.u, hit.v, hit.p);
+        Some(Scatter {
+            ray: Ray::new(hit.p, wi).with_time(ray.time),
+            attenuation: self.reflectance(base, n, wo, wi) / pdf
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: Vector) -> Vector {
+        let base = hit.color * self.base_color.value(hit.u, hit.v, hit.p);
+        self.reflectance(base, hit.facing_normal(ray), -ray.direction, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: Vector) -> f32 {
+        self.density(hit.facing_normal(ray), -ray.direction, direction)
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector {
+        hit.color * self.base_color.value(hit.u, hit.v, hit.p)
+    }
+}
+
+/// Disney's principled BSDF (the reflective part of it): a single
+/// material that covers most opaque looks with artist-friendly
+/// parameters, all in the [0, 1] range.
+///
+/// * `metallic` blends between a dielectric and a metal;
+/// * `roughness` widens the specular highlight and flattens the diffuse
+///   retro-reflection;
+/// * `specular` sets the dielectric reflectance (0.5 is 4%), and
+///   `specular_tint` tints it towards the base color;
+/// * `sheen` adds the grazing-angle glow of cloth, `sheen_tint` tints it;
+/// * `clearcoat` adds a second glossy layer on top, as on car paint, with
+///   `clearcoat_gloss` going from satin to glossy.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub specular: f32,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_gloss: f32
+}
+
+impl Principled {
+    /// Plastic-like defaults in the spirit of the DCC tools.
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0
+        }
+    }
+
+    fn alpha(&self) -> f32 {
+        (self.roughness * self.roughness).max(1E-3)
+    }
+
+    fn clearcoat_alpha(&self) -> f32 {
+        0.1 + (0.001 - 0.1) * self.clearcoat_gloss
+    }
+
+    /// Probabilities of sampling the diffuse, the specular and the
+    /// clearcoat lobes.
+    fn lobe_probabilities(&self) -> (f32, f32, f32) {
+        let diffuse = 0.5 * (1.0 - self.metallic);
+        let specular = 1.0;
+        let clearcoat = 0.25 * self.clearcoat;
+        let total = diffuse + specular + clearcoat;
+        (diffuse / total, specular / total, clearcoat / total)
+    }
+
+    fn reflectance(&self, base: Vector, n: Vector, wo: Vector, wi: Vector) -> Vector {
+        let n_dot_l = n.dot(wi);
+        let n_dot_v = n.dot(wo);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return Vector{x: 0.0, y: 0.0, z: 0.0};
+        }
+
+        let h = (wi + wo).unit();
+        let n_dot_h = n.dot(h).max(0.0);
+        let l_dot_h = wi.dot(h).max(0.0);
+
+        let one = Vector{x: 1.0, y: 1.0, z: 1.0};
+        let luminance = 0.3 * base.x + 0.6 * base.y + 0.1 * base.z;
+        let tint = if luminance > 0.0 { base / luminance } else { one };
+
+        // Diffuse with the roughness-dependent retro-reflection.
+        let schlick_weight = |cos: f32| (1.0 - cos).clamp(0.0, 1.0).powi(5);
+        let fl = schlick_weight(n_dot_l);
+        let fv = schlick_weight(n_dot_v);
+        let fd90 = 0.5 + 2.0 * self.roughness * l_dot_h * l_dot_h;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+        let diffuse = (FRAC_1_PI * fd) * base;
+
+        // Sheen.
+        let sheen_color = (1.0 - self.sheen_tint) * one + self.sheen_tint * tint;
+        let sheen = (self.sheen * schlick_weight(l_dot_h)) * sheen_color;
+
+        // Specular.
+        let alpha = self.alpha();
+        let specular_color = (1.0 - self.specular_tint) * one + self.specular_tint * tint;
+        let f0 = (1.0 - self.metallic) * (0.08 * self.specular * specular_color) + self.metallic * base;
+        let fs = fresnel_schlick(l_dot_h, f0);
+        let ds = ggx_d(n_dot_h, alpha);
+        let gs = smith_g(n_dot_l, n_dot_v, alpha);
+        let specular = (ds * gs / (4.0 * n_dot_l * n_dot_v)) * fs;
+
+        // Clearcoat.
+        let fc = 0.04 + 0.96 * schlick_weight(l_dot_h);
+        let dc = gtr1_d(n_dot_h, self.clearcoat_alpha());
+        let gc = smith_g(n_dot_l, n_dot_v, 0.25);
+        let clearcoat = 0.25 * self.clearcoat * dc * gc * fc / (4.0 * n_dot_l * n_dot_v);
+
+        n_dot_l * ((1.0 - self.metallic) * (diffuse + sheen) + specular + clearcoat)
+    }
+
+    fn density(&self, n: Vector, wo: Vector, wi: Vector) -> f32 {
+        let (pd, ps, pc) = self.lobe_probabilities();
+        let cos = n.dot(wi).max(0.0);
+
+        pd * cos * FRAC_1_PI
+            + ps * ggx_pdf(n, wo, wi, self.alpha())
+            + pc * gtr1_pdf(n, wo, wi, self.clearcoat_alpha())
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let n = hit.facing_normal(ray);
+        let wo = -ray.direction;
+
+        let (pd, ps, _) = self.lobe_probabilities();
+        let xi = sampler.next_1d();
+        let wi = if xi < pd {
+            sample_cosine(n, sampler)
+        } else if xi < pd + ps {
+            ray.direction.reflect(sample_ggx(n, self.alpha(), sampler))
+        } else {
+            ray.direction.reflect(sample_gtr1(n, self.clearcoat_alpha(), sampler))
+        };
+
+        let pdf = self.density(n, wo, wi);
+        if n.dot(wi) <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+
+        let base = hit.color * self.base_color.value(hit.u, hit.v, hit.p);
+        Some(Scatter {
+            ray: Ray::new(hit.p, wi).with_time(ray.time),
+            attenuation: self.reflectance(base, n, wo, wi) / pdf
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: Vector) -> Vector {
+        let base = hit.color * self.base_color.value(hit.u, hit.v, hit.p);
+        self.reflectance(base, hit.facing_normal(ray), -ray.direction, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: Vector) -> f32 {
+        self.density(hit.facing_normal(ray), -ray.direction, direction)
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector {
+        hit.color * self.base_color.value(hit.u, hit.v, hit.p)
+    }
+}
+
+/// Wrapper perturbing the shading normal of another material with a
+/// tangent-space normal map, in the usual encoding where the color
+/// channels store the (x, y, z) components remapped from [-1, 1] to
+/// [0, 1] and z points away from the surface. Load the map with
+/// `ImageTexture::load_linear`.
+#[derive(Debug, Clone)]
+pub struct NormalMapped {
+    pub material: Arc<dyn Material>,
+    pub normal_map: Arc<dyn Texture>
+}
+
+impl NormalMapped {
+    fn perturb<'a>(&self, hit: &Hit<'a>) -> Hit<'a> {
+        let c = self.normal_map.value(hit.u, hit.v, hit.p);
+        let (t, b, n) = hit.frame();
+        let normal = (2.0 * c.x - 1.0) * t + (2.0 * c.y - 1.0) * b + (2.0 * c.z - 1.0) * n;
+
+        if normal.is_near_zero() {
+            return *hit;
+        }
+
+        Hit { n: normal.unit(), ..*hit }.with_tangent(t)
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        self.material.scatter(ray, &self.perturb(hit), sampler)
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: Vector) -> Vector {
+        self.material.eval(ray, &self.perturb(hit), direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: Vector) -> f32 {
+        self.material.pdf(ray, &self.perturb(hit), direction)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &Hit) -> Vector {
+        self.material.emitted(ray, hit)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn shading_normal(&self, hit: &Hit) -> Vector {
+        self.material.shading_normal(&self.perturb(hit))
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector {
+        self.material.albedo(hit)
+    }
+}
+
+/// Wrapper perturbing the shading normal of another material according
+/// to the slopes of a grayscale height map. The `strength` scales the
+/// heights, making the bumps more or less pronounced. Load the map with
+/// `ImageTexture::load_linear`.
+#[derive(Debug, Clone)]
+pub struct BumpMapped {
+    pub material: Arc<dyn Material>,
+    pub height_map: Arc<dyn Texture>,
+    pub strength: f32
+}
+
+impl BumpMapped {
+    fn height(&self, u: f32, v: f32, p: Vector) -> f32 {
+        let c = self.height_map.value(u, v, p);
+        (c.x + c.y + c.z) / 3.0
+    }
+
+    fn perturb<'a>(&self, hit: &Hit<'a>) -> Hit<'a> {
+        // Slopes of the height field by finite differences in the
+        // texture coordinates.
+        let delta = 1E-3;
+        let h = self.height(hit.u, hit.v, hit.p);
+        let dhdu = (self.height(hit.u + delta, hit.v, hit.p) - h) / delta;
+        let dhdv = (self.height(hit.u, hit.v + delta, hit.p) - h) / delta;
+
+        let (t, b, n) = hit.frame();
+        let normal = n - self.strength * (dhdu * t + dhdv * b);
+
+        Hit { n: normal.unit(), ..*hit }.with_tangent(t)
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        self.material.scatter(ray, &self.perturb(hit), sampler)
+    }
+
+    fn eval(&self, ray: &Ray, hit: &Hit, direction: Vector) -> Vector {
+        self.material.eval(ray, &self.perturb(hit), direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: Vector) -> f32 {
+        self.material.pdf(ray, &self.perturb(hit), direction)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &Hit) -> Vector {
+        self.material.emitted(ray, hit)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn shading_normal(&self, hit: &Hit) -> Vector {
+        self.material.shading_normal(&self.perturb(hit))
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector {
+        self.material.albedo(hit)
+    }
+}
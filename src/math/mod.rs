@@ -0,0 +1,224 @@
+//! Basic vector arithmetics.
+
+use std::ops::Add;
+use std::ops::AddAssign;
+use std::ops::Div;
+use std::ops::Mul;
+use std::ops::Neg;
+use std::ops::Sub;
+
+mod quaternion;
+mod transform;
+
+pub use quaternion::Quaternion;
+pub use transform::{Mat4, Transform};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vector {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Basic geometric constants.
+pub const OG: Vector = Vector{x: 0.0, y: 0.0, z: 0.0};
+pub const EX: Vector = Vector{x: 1.0, y: 0.0, z: 0.0};
+pub const EY: Vector = Vector{x: 0.0, y: 1.0, z: 0.0};
+pub const EZ: Vector = Vector{x: 0.0, y: 0.0, z: 1.0};
+
+impl Vector {
+    pub fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn sqnorm(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn norm(self) -> f32 {
+        self.sqnorm().sqrt()
+    }
+
+    pub fn unit(self) -> Self {
+        self / self.norm()
+    }
+
+    pub fn cross(self, other: Self) -> Self {
+        Self {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x
+        }
+    }
+
+    /// Mirror reflection of the vector about the normal `n`.
+    pub fn reflect(self, n: Self) -> Self {
+        self - 2.0 * self.dot(n) * n
+    }
+
+    /// Refraction of a unit vector through a surface with unit normal
+    /// `n` facing against it, where `eta` is the ratio of the refractive
+    /// indices. Returns `None` in case of total internal reflection.
+    pub fn refract(self, n: Self, eta: f32) -> Option<Self> {
+        let cos_i = -self.dot(n);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+
+        if sin2_t > 1.0 {
+            return None;
+        }
+
+        let cos_t = (1.0 - sin2_t).sqrt();
+        Some(eta * self + (eta * cos_i - cos_t) * n)
+    }
+
+    /// Two unit vectors that together with this unit vector form a
+    /// right-handed orthonormal basis.
+    pub fn basis(self) -> (Self, Self) {
+        // Duff et al., "Building an Orthonormal Basis, Revisited".
+        let sign = 1.0_f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+
+        let t = Vector{ x: 1.0 + sign * self.x * self.x * a, y: sign * b, z: -sign * self.x };
+        let s = Vector{ x: b, y: sign + self.y * self.y * a, z: -self.y };
+        (t, s)
+    }
+
+    pub fn is_near_zero(self) -> bool {
+        let eps = 1E-6;
+        self.x.abs() < eps && self.y.abs() < eps && self.z.abs() < eps
+    }
+
+    pub fn max_component(self) -> f32 {
+        self.x.max(self.y).max(self.z)
+    }
+}
+
+impl Add<Vector> for Vector {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z
+        }
+    }
+}
+
+impl AddAssign<Vector> for Vector {
+    fn add_assign(&mut self, other: Self) {
+        self.x += other.x;
+        self.y += other.y;
+        self.z += other.z;
+    }
+}
+
+impl Add<f32> for Vector {
+    type Output = Self;
+
+    fn add(self, other: f32) -> Self {
+        Self {
+            x: self.x + other,
+            y: self.y + other,
+            z: self.z + other
+        }
+    }
+}
+
+impl Add<Vector> for f32 {
+    type Output = Vector;
+
+    fn add(self, other: Vector) -> Vector {
+        Vector {
+            x: self + other.x,
+            y: self + other.y,
+            z: self + other.z
+        }
+    }
+}
+
+impl Div<f32> for Vector {
+    type Output = Self;
+
+    fn div(self, other: f32) -> Self {
+        Self {
+            x: self.x / other,
+            y: self.y / other,
+            z: self.z / other
+        }
+    }
+}
+
+impl Mul<Vector> for f32 {
+    type Output = Vector;
+
+    fn mul(self, other: Vector) -> Vector {
+        Vector {
+            x: self * other.x,
+            y: self * other.y,
+            z: self * other.z
+        }
+    }
+}
+
+impl Mul<f32> for Vector {
+    type Output = Self;
+
+    fn mul(self, other: f32) -> Self {
+        Self {
+            x: other * self.x,
+            y: other * self.y,
+            z: other * self.z
+        }
+    }
+}
+
+/// Component-wise product, used to attenuate colors.
+impl Mul<Vector> for Vector {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            x: self.x * other.x,
+            y: self.y * other.y,
+            z: self.z * other.z
+        }
+    }
+}
+
+impl Neg for Vector {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z
+        }
+    }
+}
+
+impl Sub<Vector> for Vector {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z
+        }
+    }
+}
+
+impl Sub<Vector> for f32 {
+    type Output = Vector;
+
+    fn sub(self, other: Vector) -> Vector {
+        Vector {
+            x: self - other.x,
+            y: self - other.y,
+            z: self - other.z
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::ops::Mul;
+
+use super::{Mat4, Transform, Vector};
+
+/// Unit quaternions represent rotations and interpolate between them
+/// smoothly, without the gimbal lock of Euler angles.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion{ w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    /// Rotation about the `axis` by `angle` radians, counter-clockwise
+    /// when looking against the axis.
+    pub fn from_axis_angle(axis: Vector, angle: f32) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        Self { w: cos, x: sin * a.x, y: sin * a.y, z: sin * a.z }
+    }
+
+    pub fn dot(self, other: Self) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn norm(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn unit(self) -> Self {
+        let n = self.norm();
+        Self { w: self.w / n, x: self.x / n, y: self.y / n, z: self.z / n }
+    }
+
+    pub fn conjugate(self) -> Self {
+        Self { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    /// Rotate a vector by a unit quaternion.
+    pub fn rotate(self, v: Vector) -> Vector {
+        let u = Vector{ x: self.x, y: self.y, z: self.z };
+        let t = 2.0 * u.cross(v);
+        v + self.w * t + u.cross(t)
+    }
+
+    /// Spherical linear interpolation between two rotations, turning with
+    /// a constant angular velocity as `t` goes from 0 to 1 and always
+    /// taking the shorter way around.
+    pub fn slerp(self, other: Self, t: f32) -> Self {
+        let mut other = other;
+        let mut cos = self.dot(other);
+
+        // q and -q are the same rotation; pick the closer one.
+        if cos < 0.0 {
+            other = Self { w: -other.w, x: -other.x, y: -other.y, z: -other.z };
+            cos = -cos;
+        }
+
+        // Nearly identical rotations: fall back to a linear blend to avoid
+        // dividing by a vanishing sine.
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Self {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z
+        }.unit()
+    }
+
+    pub fn to_transform(self) -> Transform {
+        let Quaternion { w, x, y, z } = self.unit();
+
+        let mut matrix = Mat4::IDENTITY;
+        matrix.m[0] = [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0];
+        matrix.m[1] = [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0];
+        matrix.m[2] = [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0];
+
+        Transform { matrix, inverse: matrix.transpose() }
+    }
+
+    /// Rotation part of a transform, assuming it has no scaling or shear.
+    pub fn from_transform(transform: &Transform) -> Self {
+        let m = &transform.matrix.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        // Pick the largest of the components to divide by for numerical
+        // stability.
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Self {
+                w: 0.25 * s,
+                x: (m[2][1] - m[1][2]) / s,
+                y: (m[0][2] - m[2][0]) / s,
+                z: (m[1][0] - m[0][1]) / s
+            }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Self {
+                w: (m[2][1] - m[1][2]) / s,
+                x: 0.25 * s,
+                y: (m[0][1] + m[1][0]) / s,
+                z: (m[0][2] + m[2][0]) / s
+            }
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Self {
+                w: (m[0][2] - m[2][0]) / s,
+                x: (m[0][1] + m[1][0]) / s,
+                y: 0.25 * s,
+                z: (m[1][2] + m[2][1]) / s
+            }
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Self {
+                w: (m[1][0] - m[0][1]) / s,
+                x: (m[0][2] + m[2][0]) / s,
+                y: (m[1][2] + m[2][1]) / s,
+                z: 0.25 * s
+            }
+        };
+
+        q.unit()
+    }
+}
+
+/// Composition: `a * b` rotates by `b` first and by `a` second.
+impl Mul<Quaternion> for Quaternion {
+    type Output = Self;
+
+    fn mul(self, o: Self) -> Self {
+        Self {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w
+        }
+    }
+}
+
+impl From<Quaternion> for Transform {
+    fn from(q: Quaternion) -> Self {
+        q.to_transform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector, b: Vector) {
+        assert!((a - b).norm() < 1E-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn rotates_like_the_transform() {
+        let axis = Vector{ x: 1.0, y: 2.0, z: -1.0 };
+        let q = Quaternion::from_axis_angle(axis, 0.7);
+        let transform = Transform::rotate(axis, 0.7);
+        let v = Vector{ x: 0.3, y: -1.0, z: 2.0 };
+        assert_close(q.rotate(v), transform.apply_vector(v));
+        assert_close(q.to_transform().apply_vector(v), transform.apply_vector(v));
+    }
+
+    #[test]
+    fn conjugate_is_the_inverse() {
+        let q = Quaternion::from_axis_angle(Vector{ x: 0.0, y: 1.0, z: 1.0 }, 2.5);
+        let v = Vector{ x: 1.0, y: 2.0, z: 3.0 };
+        assert_close(q.conjugate().rotate(q.rotate(v)), v);
+        let identity = q * q.conjugate();
+        assert!((identity.w - 1.0).abs() < 1E-6 && identity.x.abs() < 1E-6 && identity.y.abs() < 1E-6 && identity.z.abs() < 1E-6);
+    }
+
+    #[test]
+    fn from_transform_round_trip() {
+        // Angles picking every branch of `from_transform`.
+        let axes = [Vector{ x: 1.0, y: 0.0, z: 0.0 }, Vector{ x: 0.0, y: 1.0, z: 0.0 }, Vector{ x: 0.0, y: 0.0, z: 1.0 }];
+        let v = Vector{ x: 0.3, y: -1.0, z: 2.0 };
+        for axis in axes {
+            for angle in [0.5, 3.0] {
+                let q = Quaternion::from_axis_angle(axis, angle);
+                let back = Quaternion::from_transform(&q.to_transform());
+                assert_close(back.rotate(v), q.rotate(v));
+            }
+        }
+    }
+
+    #[test]
+    fn slerp_ends_and_middle() {
+        let axis = Vector{ x: 0.0, y: 0.0, z: 1.0 };
+        let (a, b) = (Quaternion::IDENTITY, Quaternion::from_axis_angle(axis, 1.0));
+        let v = Vector{ x: 1.0, y: 0.0, z: 0.0 };
+        assert_close(a.slerp(b, 0.0).rotate(v), v);
+        assert_close(a.slerp(b, 1.0).rotate(v), b.rotate(v));
+        assert_close(a.slerp(b, 0.5).rotate(v), Quaternion::from_axis_angle(axis, 0.5).rotate(v));
+    }
+}
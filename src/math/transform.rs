@@ -0,0 +1,269 @@
+use std::ops::Mul;
+
+use super::Vector;
+
+/// Row-major 4×4 matrix acting on column vectors in homogeneous
+/// coordinates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4]
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        m: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ]
+    };
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Self { m }
+    }
+
+    /// Inverse by Gauss-Jordan elimination with partial pivoting.
+    /// Returns `None` for singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::IDENTITY.m;
+
+        for col in 0 .. 4 {
+            let pivot = (col .. 4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+
+            if a[pivot][col].abs() < 1E-12 {
+                return None;
+            }
+
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let d = a[col][col];
+            for k in 0 .. 4 {
+                a[col][k] /= d;
+                inv[col][k] /= d;
+            }
+
+            for row in 0 .. 4 {
+                if row == col {
+                    continue;
+                }
+
+                let f = a[row][col];
+                for k in 0 .. 4 {
+                    a[row][k] -= f * a[col][k];
+                    inv[row][k] -= f * inv[col][k];
+                }
+            }
+        }
+
+        Some(Self { m: inv })
+    }
+
+    pub fn apply_point(&self, p: Vector) -> Vector {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+
+        if w == 1.0 {
+            Vector{ x, y, z }
+        } else {
+            Vector{ x, y, z } / w
+        }
+    }
+
+    /// Apply the linear part only, ignoring the translation.
+    pub fn apply_vector(&self, v: Vector) -> Vector {
+        let m = &self.m;
+        Vector{
+            x: m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            y: m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            z: m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z
+        }
+    }
+}
+
+impl Mul<Mat4> for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0 .. 4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Self { m }
+    }
+}
+
+/// Affine transformation together with its inverse, so that going both
+/// ways and transforming normals costs no extra inversion.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub matrix: Mat4,
+    pub inverse: Mat4
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        matrix: Mat4::IDENTITY,
+        inverse: Mat4::IDENTITY
+    };
+
+    /// Transform given by an arbitrary matrix. Returns `None` if the
+    /// matrix cannot be inverted.
+    pub fn from_matrix(matrix: Mat4) -> Option<Self> {
+        Some(Self { matrix, inverse: matrix.inverse()? })
+    }
+
+    pub fn translate(offset: Vector) -> Self {
+        let mut matrix = Mat4::IDENTITY;
+        let mut inverse = Mat4::IDENTITY;
+        matrix.m[0][3] = offset.x;
+        matrix.m[1][3] = offset.y;
+        matrix.m[2][3] = offset.z;
+        inverse.m[0][3] = -offset.x;
+        inverse.m[1][3] = -offset.y;
+        inverse.m[2][3] = -offset.z;
+        Self { matrix, inverse }
+    }
+
+    /// Scaling about the origin by a separate non-zero factor along
+    /// every axis.
+    pub fn scale(factor: Vector) -> Self {
+        let mut matrix = Mat4::IDENTITY;
+        let mut inverse = Mat4::IDENTITY;
+        matrix.m[0][0] = factor.x;
+        matrix.m[1][1] = factor.y;
+        matrix.m[2][2] = factor.z;
+        inverse.m[0][0] = 1.0 / factor.x;
+        inverse.m[1][1] = 1.0 / factor.y;
+        inverse.m[2][2] = 1.0 / factor.z;
+        Self { matrix, inverse }
+    }
+
+    /// Rotation about the `axis` through the origin by `angle` radians,
+    /// counter-clockwise when looking against the axis.
+    pub fn rotate(axis: Vector, angle: f32) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = angle.sin_cos();
+        let k = 1.0 - cos;
+
+        let mut matrix = Mat4::IDENTITY;
+        matrix.m[0] = [cos + a.x * a.x * k, a.x * a.y * k - a.z * sin, a.x * a.z * k + a.y * sin, 0.0];
+        matrix.m[1] = [a.y * a.x * k + a.z * sin, cos + a.y * a.y * k, a.y * a.z * k - a.x * sin, 0.0];
+        matrix.m[2] = [a.z * a.x * k - a.y * sin, a.z * a.y * k + a.x * sin, cos + a.z * a.z * k, 0.0];
+
+        // Rotations are orthogonal, so the inverse is the transpose.
+        Self { matrix, inverse: matrix.transpose() }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn apply_point(&self, p: Vector) -> Vector {
+        self.matrix.apply_point(p)
+    }
+
+    pub fn apply_vector(&self, v: Vector) -> Vector {
+        self.matrix.apply_vector(v)
+    }
+
+    /// Normals stay perpendicular to the surface when transformed with
+    /// the inverse transpose of the matrix.
+    pub fn apply_normal(&self, n: Vector) -> Vector {
+        self.inverse.transpose().apply_vector(n)
+    }
+}
+
+/// Composition: `a * b` applies `b` first and `a` second.
+impl Mul<Transform> for Transform {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            matrix: self.matrix * other.matrix,
+            inverse: other.inverse * self.inverse
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Mat4, b: &Mat4) {
+        for (row_a, row_b) in a.m.iter().zip(&b.m) {
+            for (x, y) in row_a.iter().zip(row_b) {
+                assert!((x - y).abs() < 1E-5, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn transforms() -> [Transform; 4] {
+        let t = Transform::translate(Vector{ x: 1.0, y: -2.0, z: 3.0 });
+        let s = Transform::scale(Vector{ x: 2.0, y: 0.5, z: -4.0 });
+        let r = Transform::rotate(Vector{ x: 1.0, y: 2.0, z: -1.0 }, 0.7);
+        [t, s, r, t * r * s]
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        for transform in transforms() {
+            assert_close(&(transform.matrix * transform.inverse), &Mat4::IDENTITY);
+            assert_close(&(transform.inverse * transform.matrix), &Mat4::IDENTITY);
+            assert_close(&transform.matrix.inverse().unwrap(), &transform.inverse);
+        }
+    }
+
+    #[test]
+    fn inverse_with_a_pivot() {
+        let matrix = Mat4 {
+            m: [
+                [0.0, 2.0, 0.0, 1.0],
+                [3.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 5.0],
+                [0.0, 0.0, 4.0, 1.0]
+            ]
+        };
+        let transform = Transform::from_matrix(matrix).unwrap();
+        assert_close(&(matrix * transform.inverse), &Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        let mut matrix = Mat4::IDENTITY;
+        matrix.m[2] = [1.0, 2.0, 0.0, 0.0];
+        matrix.m[1] = [2.0, 4.0, 0.0, 0.0];
+        assert!(matrix.inverse().is_none());
+        assert!(Transform::from_matrix(matrix).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let transform = transforms()[3];
+        let (u, v) = (Vector{ x: 1.0, y: 0.0, z: 0.0 }, Vector{ x: 0.0, y: 1.0, z: 1.0 });
+        let n = transform.apply_normal(u.cross(v));
+        assert!(n.dot(transform.apply_vector(u)).abs() < 1E-5);
+        assert!(n.dot(transform.apply_vector(v)).abs() < 1E-5);
+    }
+}
@@ -0,0 +1,113 @@
+//! Building blocks of the microfacet reflection models: the GGX
+//! (Trowbridge-Reitz) distribution of the microfacet normals, Smith
+//! shadowing-masking and Fresnel reflectance.
+
+use std::f32::consts::PI;
+
+use crate::math::Vector;
+use crate::sampler::Sampler;
+
+/// GGX normal distribution for a microfacet with the cosine `n_dot_h`
+/// between its normal and the macroscopic one. `alpha` is the squared
+/// perceptual roughness.
+pub fn ggx_d(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+/// Smith masking term of the GGX distribution for one direction making
+/// the cosine `n_dot_v` with the normal.
+pub fn smith_g1(n_dot_v: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (a2 + (1.0 - a2) * n_dot_v * n_dot_v).sqrt())
+}
+
+/// Separable Smith shadowing-masking for the incoming and the outgoing
+/// directions.
+pub fn smith_g(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    smith_g1(n_dot_l, alpha) * smith_g1(n_dot_v, alpha)
+}
+
+/// Schlick's Fresnel approximation with a colored reflectance `f0` at
+/// normal incidence.
+pub fn fresnel_schlick(cos: f32, f0: Vector) -> Vector {
+    let k = (1.0 - cos).clamp(0.0, 1.0).powi(5);
+    f0 + k * (1.0 - f0)
+}
+
+/// Reflectance at normal incidence of a conductor with the complex
+/// index of refraction `eta + ik`, given per color channel. Makes the
+/// `f0` of the Schlick approximation for measured metals.
+pub fn conductor_f0(eta: Vector, k: Vector) -> Vector {
+    let f0 = |eta: f32, k: f32| ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+    Vector{ x: f0(eta.x, k.x), y: f0(eta.y, k.y), z: f0(eta.z, k.z) }
+}
+
+/// Sample a microfacet normal around `n` proportionally to
+/// `ggx_d(n·h) (n·h)`.
+pub fn sample_ggx(n: Vector, alpha: f32, sampler: &mut dyn Sampler) -> Vector {
+    let (e1, e2) = n.basis();
+
+    let (xi1, xi2) = sampler.next_2d();
+
+    let cos2 = (1.0 - xi1) / (1.0 + (alpha * alpha - 1.0) * xi1);
+    let cos = cos2.sqrt();
+    let sin = (1.0 - cos2).max(0.0).sqrt();
+    let phi = 2.0 * PI * xi2;
+
+    (sin * phi.cos()) * e1 + (sin * phi.sin()) * e2 + cos * n
+}
+
+/// Probability density of the direction `wi` obtained by reflecting
+/// `wo` about a normal sampled with `sample_ggx`.
+pub fn ggx_pdf(n: Vector, wo: Vector, wi: Vector, alpha: f32) -> f32 {
+    let h = (wo + wi).unit();
+    let n_dot_h = n.dot(h).max(0.0);
+    let wo_dot_h = wo.dot(h).abs().max(1E-6);
+    ggx_d(n_dot_h, alpha) * n_dot_h / (4.0 * wo_dot_h)
+}
+
+/// Berry (GTR1) distribution used for the clearcoat layer, with longer
+/// tails than GGX.
+pub fn gtr1_d(n_dot_h: f32, alpha: f32) -> f32 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * n_dot_h * n_dot_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+/// Sample a microfacet normal around `n` proportionally to
+/// `gtr1_d(n·h) (n·h)`.
+pub fn sample_gtr1(n: Vector, alpha: f32, sampler: &mut dyn Sampler) -> Vector {
+    let (e1, e2) = n.basis();
+
+    let (xi1, xi2) = sampler.next_2d();
+
+    let a2 = alpha * alpha;
+    let cos2 = ((1.0 - a2.powf(1.0 - xi1)) / (1.0 - a2)).clamp(0.0, 1.0);
+    let cos = cos2.sqrt();
+    let sin = (1.0 - cos2).sqrt();
+    let phi = 2.0 * PI * xi2;
+
+    (sin * phi.cos()) * e1 + (sin * phi.sin()) * e2 + cos * n
+}
+
+/// Probability density of the direction `wi` obtained by reflecting
+/// `wo` about a normal sampled with `sample_gtr1`.
+pub fn gtr1_pdf(n: Vector, wo: Vector, wi: Vector, alpha: f32) -> f32 {
+    let h = (wo + wi).unit();
+    let n_dot_h = n.dot(h).max(0.0);
+    let wo_dot_h = wo.dot(h).abs().max(1E-6);
+    gtr1_d(n_dot_h, alpha) * n_dot_h / (4.0 * wo_dot_h)
+}
+
+/// Cosine-weighted random direction in the hemisphere around `n`, with
+/// the density of `cos / π`.
+pub fn sample_cosine(n: Vector, sampler: &mut dyn Sampler) -> Vector {
+    let d = n + sampler.unit_vector();
+    if d.is_near_zero() { n } else { d.unit() }
+}
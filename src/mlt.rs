@@ -0,0 +1,212 @@
+//! Primary sample space Metropolis light transport, after Kelemen et
+//! al., "A Simple and Robust Mutation Strategy for the Metropolis Light
+//! Transport Algorithm".
+//!
+//! A path is a function of the random numbers the path tracer draws
+//! along it. Rather than drawing new numbers for every sample, a Markov
+//! chain wanders among them: every step either nudges the numbers of the
+//! current path a little or draws all of them anew, and the new path is
+//! taken with the probability that makes the chain visit the paths as
+//! often as they are bright. Once a chain finds a path that is hard to
+//! come by, such as a caustic seen through glass, it goes on to explore
+//! the ones around it instead of losing it again.
+//!
+//! Every tile runs a chain of its own over its own pixels, so that the
+//! tiles are traced in parallel as usual. The chain starts from a path
+//! picked among a few independent ones, which also tell how bright the
+//! tile is overall. The reconstruction filter is not used: every sample
+//! lands in the pixel it was taken in.
+
+use std::f32::consts::PI;
+
+use crate::camera::Camera;
+use crate::geometry::World;
+use crate::math::Vector;
+use crate::render::{clamped, ray_color, Settings, Tile};
+use crate::sampler::{Pcg32, Sampler};
+
+/// Probability of drawing all the numbers of a path anew rather than
+/// nudging them.
+pub const LARGE_STEP_PROBABILITY: f32 = 0.3;
+
+/// Deviation of the nudges.
+pub const MUTATION_SIGMA: f32 = 0.01;
+
+/// Independent paths traced per pixel of a tile to pick the start of
+/// the chain from.
+pub const BOOTSTRAP_FRACTION: f32 = 0.25;
+
+/// Random number of a path, along with the state to restore if the step
+/// that changed it is rejected.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+struct PrimarySample {
+    value: f32,
+    modified: u64, // Step it was last changed in
+    backup: f32,
+    backup_modified: u64
+}
+
+/// Sampler handing out the numbers of the current path of a chain, as
+/// changed by the current step. The numbers are only changed as they
+/// are asked for, so that the steps don't depend on how deep the paths
+/// go.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MltSampler {
+    rng: Pcg32,
+    samples: Vec<PrimarySample>,
+    step: u64,
+    large_step: bool,
+    last_large_step: u64, // Last accepted step that drew all the numbers anew
+    dimension: usize
+}
+
+impl MltSampler {
+    /// Sampler drawing the numbers of its first path from `rng`.
+    pub fn new(rng: Pcg32) -> Self {
+        Self { rng, samples: vec![], step: 0, large_step: true, last_large_step: 0, dimension: 0 }
+    }
+
+    pub fn start_step(&mut self, large_step: bool) {
+        self.step += 1;
+        self.large_step = large_step;
+        self.dimension = 0;
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.step;
+        }
+    }
+
+    pub fn reject(&mut self) {
+        for sample in &mut self.samples {
+            if sample.modified == self.step {
+                sample.value = sample.backup;
+                sample.modified = sample.backup_modified;
+            }
+        }
+        self.step -= 1;
+    }
+
+    fn gaussian(&mut self) -> f32 {
+        let u = 1.0 - self.rng.next_f32();
+        let v = self.rng.next_f32();
+        (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+    }
+}
+
+impl Sampler for MltSampler {
+    fn start_pixel(&mut self, _i: usize, _j: usize, _sample: u32) {}
+
+    fn next_1d(&mut self) -> f32 {
+        let k = self.dimension;
+        self.dimension += 1;
+        if k == self.samples.len() {
+            self.samples.push(PrimarySample::default());
+        }
+
+        // The numbers no path has used since the last large step are
+        // behind and get drawn anew first.
+        if self.samples[k].modified < self.last_large_step {
+            self.samples[k].value = self.rng.next_f32();
+            self.samples[k].modified = self.last_large_step;
+        }
+
+        let mut sample = self.samples[k];
+        sample.backup = sample.value;
+        sample.backup_modified = sample.modified;
+        if self.large_step {
+            sample.value = self.rng.next_f32();
+        } else {
+            // Nudges the number missed since it was last used add up.
+            let steps = (self.step - sample.modified) as f32;
+            let value = (sample.value + MUTATION_SIGMA * steps.sqrt() * self.gaussian()).rem_euclid(1.0);
+            sample.value = if value < 1.0 { value } else { 0.0 };
+        }
+        sample.modified = self.step;
+
+        self.samples[k] = sample;
+        sample.value
+    }
+}
+
+fn luminance(color: Vector) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// Trace the path the sampler's numbers give in the tile: the index of
+/// its pixel in the order of `Tile::pixels` and its color.
+fn trace(tile: Tile, settings: &Settings, camera: &Camera, world: &World, sampler: &mut dyn Sampler) -> (usize, Vector) {
+    let (width, height) = (tile.j1 - tile.j0, tile.i1 - tile.i0);
+    let (x, y) = sampler.next_2d();
+    let (x, y) = (x * width as f32, y * height as f32);
+    let (dj, di) = ((x as usize).min(width - 1), (y as usize).min(height - 1));
+
+    let u = (tile.j0 as f32 + x) / (settings.width  as f32 - 1.0);
+    let v = (tile.i0 as f32 + y) / (settings.height as f32 - 1.0);
+    let color = match camera.get_ray(u, v, sampler) {
+        Some(ray) => ray_color(&ray, world, settings.max_depth, settings.transparent, sampler),
+        None => Vector{ x: 0.0, y: 0.0, z: 0.0 }
+    };
+
+    (di * width + dj, clamped(color, settings.max_radiance))
+}
+
+/// The `sample`-th pass of a chain over the pixels of the tile, in the
+/// order of `Tile::pixels`. It takes as many steps as there are pixels,
+/// and the pixels come out as bright as they are on average.
+pub fn render_tile(tile: Tile, sample: u32, settings: &Settings, camera: &Camera, world: &World) -> Vec<Vector> {
+    let pixels = (tile.i1 - tile.i0) * (tile.j1 - tile.j0);
+    let mut image = vec![Vector{ x: 0.0, y: 0.0, z: 0.0 }; pixels];
+
+    let mut rng = Pcg32::for_pixel(settings.seed, tile.i0, tile.j0, sample);
+    let seed = (rng.next_u32() as u64) << 32 | rng.next_u32() as u64;
+
+    // Independent paths, each traced with numbers of its own stream, so
+    // that the one the chain starts from can be traced again.
+    let bootstrap = ((pixels as f32 * BOOTSTRAP_FRACTION) as u64).max(1);
+    let weights: Vec<f32> = (0 .. bootstrap)
+        .map(|k| {
+            let mut sampler = MltSampler::new(Pcg32::new(seed, k + 1));
+            luminance(trace(tile, settings, camera, world, &mut sampler).1)
+        })
+        .collect();
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return image;
+    }
+    let brightness = total / bootstrap as f32;
+
+    // Start from a path picked as often as it is bright.
+    let target = rng.next_f32() * total;
+    let mut sum = 0.0;
+    let start = weights.iter().position(|&w| { sum += w; sum > target }).unwrap_or(bootstrap as usize - 1);
+    let mut sampler = MltSampler::new(Pcg32::new(seed, start as u64 + 1));
+    let mut current = trace(tile, settings, camera, world, &mut sampler);
+
+    // Both the path taken and the one passed over count, by the chance
+    // of taking them, which is as good as counting the visits but
+    // less noisy.
+    for _ in 0 .. pixels {
+        sampler.start_step(rng.next_f32() < LARGE_STEP_PROBABILITY);
+        let proposed = trace(tile, settings, camera, world, &mut sampler);
+
+        let (y, y_proposed) = (luminance(current.1), luminance(proposed.1));
+        let accept = if y > 0.0 { (y_proposed / y).min(1.0) } else { 1.0 };
+        if y_proposed > 0.0 {
+            image[proposed.0] += (accept / y_proposed) * proposed.1;
+        }
+        if y > 0.0 {
+            image[current.0] += ((1.0 - accept) / y) * current.1;
+        }
+
+        if rng.next_f32() < accept {
+            current = proposed;
+            sampler.accept();
+        } else {
+            sampler.reject();
+        }
+    }
+
+    image.iter().map(|&c| brightness * c).collect()
+}
@@ -0,0 +1,432 @@
+//! Writing the rendered image to files.
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::{DynamicImage, ImageOutputFormat, ImageResult, Rgb, RgbImage, Rgba, RgbaImage};
+
+use crate::color::Color;
+use crate::math::Vector;
+use crate::render::{Aovs, Image};
+
+/// Save the accumulation buffer averaged over `samples` samples as a
+/// dithered PNG file, with the averaged alpha channel if given. The
+/// colors are not to be multiplied by the alpha.
+pub fn save_png<P: AsRef<Path>>(image: &Image, samples: u32, alpha: Option<&Image>, path: P) -> ImageResult<()> {
+    png_image(image, samples, alpha).save(path)
+}
+
+/// The PNG file `save_png` saves, in memory.
+pub fn encode_png(image: &Image, samples: u32, alpha: Option<&Image>) -> ImageResult<Vec<u8>> {
+    let mut bytes = Cursor::new(vec![]);
+    png_image(image, samples, alpha).write_to(&mut bytes, ImageOutputFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+fn png_image(image: &Image, samples: u32, alpha: Option<&Image>) -> DynamicImage {
+    let (width, height) = (image.width as u32, image.height as u32);
+    let color = |i: usize, j: usize| Color::from(image.get(i, j) / samples as f32).to_srgb8_dithered(i, j);
+
+    // The accumulation buffer is stored bottom row first, while image
+    // files go top to bottom.
+    match alpha {
+        Some(alpha) => DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            let (i, j) = (image.height - 1 - y as usize, x as usize);
+            let [r, g, b] = color(i, j);
+            let a = (255.0 * alpha.get(i, j).x.clamp(0.0, 1.0)).round() as u8;
+            Rgba([r, g, b, a])
+        })),
+        None => DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            Rgb(color(image.height - 1 - y as usize, x as usize))
+        }))
+    }
+}
+
+/// Colors of the image divided by the alpha, for the formats that don't
+/// want them multiplied by it. Colors with no alpha at all are black.
+pub fn unpremultiply(image: &Image, alpha: &Image) -> Image {
+    let pixels = image.pixels.iter()
+        .zip(&alpha.pixels)
+        .map(|(&c, a)| if a.x > 0.0 { c / a.x } else { Vector{ x: 0.0, y: 0.0, z: 0.0 } })
+        .collect();
+    Image { width: image.width, height: image.height, pixels }
+}
+
+/// Save the accumulation buffer averaged over `samples` samples as a
+/// binary PPM file of dithered 8-bit colors, which takes no more than a
+/// header in front of the bytes.
+pub fn save_ppm<P: AsRef<Path>>(image: &Image, samples: u32, path: P) -> io::Result<()> {
+    let mut out = format!("P6\n{} {}\n255\n", image.width, image.height).into_bytes();
+    out.extend(rgb8(image, samples));
+    fs::write(path, out)
+}
+
+/// Dithered 8-bit colors of the accumulation buffer averaged over
+/// `samples` samples, three bytes a pixel, top row first.
+pub fn rgb8(image: &Image, samples: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(3 * image.pixels.len());
+    for (i, row) in image.rows().enumerate().rev() {
+        for (j, pixel) in row.iter().enumerate() {
+            out.extend(Color::from(*pixel / samples as f32).to_srgb8_dithered(i, j));
+        }
+    }
+    out
+}
+
+/// Save the accumulation buffer averaged over `samples` samples as a
+/// PFM file: a header in front of the linear radiance as it is, in
+/// little-endian 32-bit floats. Like the accumulation buffer, the file
+/// goes bottom row first.
+pub fn save_pfm<P: AsRef<Path>>(image: &Image, samples: u32, path: P) -> io::Result<()> {
+    // The negative scale stands for little-endian.
+    let mut out = format!("PF\n{} {}\n-1.0\n", image.width, image.height).into_bytes();
+    for pixel in &image.pixels {
+        let pixel = *pixel / samples as f32;
+        for c in [pixel.x, pixel.y, pixel.z] {
+            out.extend(c.to_le_bytes());
+        }
+    }
+    fs::write(path, out)
+}
+
+/// Formats the image can be saved in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Ppm,
+    Pfm
+}
+
+impl Format {
+    /// Format told by the extension of the file name, PNG unless it is
+    /// `ppm` or `pfm`.
+    pub fn of<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("ppm") => Format::Ppm,
+            Some("pfm") => Format::Pfm,
+            _ => Format::Png
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Ppm => "ppm",
+            Format::Pfm => "pfm"
+        }
+    }
+
+    /// Whether the files keep the full range of the light, rather than
+    /// colors clamped to the range of the screen.
+    pub fn is_hdr(&self) -> bool {
+        *self == Format::Pfm
+    }
+}
+
+/// Save the accumulation buffer averaged over `samples` samples in the
+/// given format, with the alpha channel if given and the format has one.
+pub fn save_image<P: AsRef<Path>>(image: &Image, samples: u32, alpha: Option<&Image>, format: Format, path: P) -> ImageResult<()> {
+    match format {
+        Format::Png => save_png(image, samples, alpha, path),
+        Format::Ppm => Ok(save_ppm(image, samples, path)?),
+        Format::Pfm => Ok(save_pfm(image, samples, path)?)
+    }
+}
+
+/// Save the averaged arbitrary output variables in the given format to
+/// files named `<prefix>-albedo.png` and so on. To fit the colors of
+/// the formats that clamp them, the normals are mapped from -1 .. 1 to
+/// 0 .. 1 and the distances are divided by the farthest one. Returns
+/// the names of the files.
+pub fn save_aovs(aovs: &Aovs, prefix: &str, format: Format) -> ImageResult<Vec<String>> {
+    let farthest = aovs.depth.pixels.iter().fold(0.0, |max: f32, p| max.max(p.x));
+    let one = Vector{ x: 1.0, y: 1.0, z: 1.0 };
+
+    let mut names = vec![];
+    for (name, layer) in aovs.layers() {
+        let mut image = layer.clone();
+        if !format.is_hdr() {
+            for pixel in &mut image.pixels {
+                *pixel = match name {
+                    "normal" if !pixel.is_near_zero() => 0.5 * (*pixel + one),
+                    "depth" if farthest > 0.0 => *pixel / farthest,
+                    _ => *pixel
+                };
+            }
+        }
+
+        let path = format!("{}-{}.{}", prefix, name, format.extension());
+        save_image(&image, 1, None, format, &path)?;
+        names.push(path);
+    }
+    Ok(names)
+}
+
+/// Precision of the channels of OpenEXR files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// 16-bit floats: plenty for colors, in half the size.
+    #[default]
+    Half,
+    /// 32-bit floats.
+    Float
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half" => Ok(Precision::Half),
+            "float" => Ok(Precision::Float),
+            _ => Err(format!("unknown precision {}, expected half or float", s))
+        }
+    }
+}
+
+/// Save the accumulation buffer averaged over `samples` samples as an
+/// uncompressed OpenEXR file, which keeps the radiance as it is rather
+/// than clamped and quantized. The averaged alpha, if given, goes into
+/// the channel A, with the colors multiplied by it as OpenEXR has them.
+/// The averaged arbitrary output variables, if given, go into the same
+/// file as the layers `albedo` (R, G, B), `normal` (X, Y, Z) and `depth`
+/// (Z).
+pub fn save_exr<P: AsRef<Path>>(image: &Image, samples: u32, alpha: Option<&Image>, aovs: Option<&Aovs>, precision: Precision, path: P) -> io::Result<()> {
+    // Every channel is a component of a buffer, scaled. The file lists
+    // them sorted by the name.
+    let mut channels = vec![
+        ("R".to_string(), image, 0, 1.0 / samples as f32),
+        ("G".to_string(), image, 1, 1.0 / samples as f32),
+        ("B".to_string(), image, 2, 1.0 / samples as f32)
+    ];
+    if let Some(alpha) = alpha {
+        channels.push(("A".to_string(), alpha, 0, 1.0));
+    }
+    for (layer, buffer) in aovs.iter().flat_map(|aovs| aovs.layers()) {
+        let names: &[&str] = match layer {
+            "normal" => &["X", "Y", "Z"],
+            "depth" => &["Z"],
+            _ => &["R", "G", "B"]
+        };
+        for (component, name) in names.iter().enumerate() {
+            channels.push((format!("{}.{}", layer, name), buffer, component, 1.0));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let (pixel_type, size) = match precision {
+        Precision::Half => (1i32, 2),
+        Precision::Float => (2, 4)
+    };
+    let (width, height) = (image.width, image.height);
+
+    let mut list = vec![];
+    for (name, ..) in &channels {
+        list.extend(name.as_bytes());
+        list.push(0);
+        list.extend(pixel_type.to_le_bytes());
+        list.extend([0; 4]); // Not perceptually linear, and reserved
+        list.extend(1i32.to_le_bytes());
+        list.extend(1i32.to_le_bytes());
+    }
+    list.push(0);
+
+    let mut window = vec![];
+    for bound in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend(bound.to_le_bytes());
+    }
+
+    let mut out = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    attribute(&mut out, "channels", "chlist", &list);
+    attribute(&mut out, "compression", "compression", &[0]);
+    attribute(&mut out, "dataWindow", "box2i", &window);
+    attribute(&mut out, "displayWindow", "box2i", &window);
+    attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut out, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut out, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    out.push(0);
+
+    // Every scanline is a chunk of its own, listed in the offset table
+    // in front of them.
+    let line = 8 + width * channels.len() * size;
+    let start = out.len() + 8 * height;
+    for y in 0 .. height {
+        out.extend(((start + y * line) as u64).to_le_bytes());
+    }
+
+    // The accumulation buffer is stored bottom row first, while the
+    // scanlines go top to bottom.
+    for y in 0 .. height {
+        out.extend((y as i32).to_le_bytes());
+        out.extend(((line - 8) as i32).to_le_bytes());
+        for (_, buffer, component, scale) in &channels {
+            for pixel in &buffer.pixels[(height - 1 - y) * width ..][.. width] {
+                let value = scale * [pixel.x, pixel.y, pixel.z][*component];
+                match precision {
+                    Precision::Half => out.extend(to_half(value).to_le_bytes()),
+                    Precision::Float => out.extend(value.to_le_bytes())
+                }
+            }
+        }
+    }
+
+    fs::write(path, out)
+}
+
+/// Append an attribute of the header of an OpenEXR file.
+fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend(name.as_bytes());
+    out.push(0);
+    out.extend(kind.as_bytes());
+    out.push(0);
+    out.extend((value.len() as i32).to_le_bytes());
+    out.extend(value);
+}
+
+/// Bits of the 16-bit float nearest to the value. Values too large for
+/// it become infinite, and too small ones zero.
+fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinities stay infinite and NaNs NaN.
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal, with the leading one of the mantissa spelled out.
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+
+    // A carry of the rounding into the exponent is still right.
+    let round = (mantissa >> 12) & 1;
+    sign | ((((exponent as u32) << 10) | (mantissa >> 13)) + round) as u16
+}
+
+/// File name made of `prefix`, the current UTC date and time, and
+/// `extension`, such as `render-20240131-235959.png`.
+pub fn timestamped_name(prefix: &str, extension: &str) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Civil date from the number of days since 1970-01-01, after Howard
+    // Hinnant's `civil_from_days`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}.{}",
+        prefix, year, month, day, time / 3600, time / 60 % 60, time % 60, extension
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::convert::TryInto;
+
+    use super::*;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(to_half(0.0), 0x0000);
+        assert_eq!(to_half(-0.0), 0x8000);
+        assert_eq!(to_half(1.0), 0x3c00);
+        assert_eq!(to_half(-2.0), 0xc000);
+        assert_eq!(to_half(0.5), 0x3800);
+        assert_eq!(to_half(1.0 / 3.0), 0x3555);
+        assert_eq!(to_half(65504.0), 0x7bff);
+        // Rounding up past the largest one carries into infinity.
+        assert_eq!(to_half(65520.0), 0x7c00);
+        assert_eq!(to_half(1E6), 0x7c00);
+        assert_eq!(to_half(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(to_half(f32::NAN) & 0x7e00, 0x7e00);
+        assert_eq!(to_half(2f32.powi(-14)), 0x0400);
+        assert_eq!(to_half(2f32.powi(-24)), 0x0001);
+        assert_eq!(to_half(1E-10), 0x0000);
+    }
+
+    #[test]
+    fn exr_layout() {
+        let (width, height) = (3, 2);
+        let mut image = Image::new(width, height);
+        for (k, pixel) in image.pixels.iter_mut().enumerate() {
+            *pixel = Vector{ x: k as f32, y: 10.0 + k as f32, z: 20.0 + k as f32 };
+        }
+        let path = std::env::temp_dir().join(format!("rtrace-{}-layout.exr", std::process::id()));
+        save_exr(&image, 2, Some(&image), None, Precision::Float, &path).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(data[.. 8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        // Attributes: the name, the type, the size and the value, till an
+        // empty name.
+        let mut at = 8;
+        let string = |at: &mut usize| {
+            let end = *at + data[*at ..].iter().position(|&b| b == 0).unwrap();
+            let s = String::from_utf8(data[*at .. end].to_vec()).unwrap();
+            *at = end + 1;
+            s
+        };
+        let mut attributes = HashMap::new();
+        loop {
+            let name = string(&mut at);
+            if name.is_empty() {
+                break;
+            }
+            let kind = string(&mut at);
+            let size = i32::from_le_bytes(data[at .. at + 4].try_into().unwrap()) as usize;
+            attributes.insert(name, (kind, data[at + 4 .. at + 4 + size].to_vec()));
+            at += 4 + size;
+        }
+        let (kind, list) = &attributes["channels"];
+        assert_eq!(kind, "chlist");
+        let names: Vec<&[u8]> = list.split(|&b| b == 0).filter(|s| s.len() == 1 && s[0].is_ascii_uppercase()).collect();
+        assert_eq!(names, [b"A", b"B", b"G", b"R"]);
+        assert_eq!(attributes["dataWindow"].1, [0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0]);
+
+        // The offset table points right past itself, then every scanline
+        // is its number, its size and the channels one after another.
+        let word = |at: usize| u32::from_le_bytes(data[at .. at + 4].try_into().unwrap());
+        let line = 8 + width * 4 * 4;
+        assert_eq!(word(at) as usize, at + 8 * height);
+        assert_eq!(word(at + 8) as usize, at + 8 * height + line);
+        assert_eq!(data.len(), at + 8 * height + height * line);
+
+        // The first scanline is the top row, the last one of the buffer,
+        // with the colors averaged and the alpha as it is.
+        let first = at + 8 * height;
+        assert_eq!((word(first), word(first + 4) as usize), (0, line - 8));
+        let value = |channel: usize, x: usize| f32::from_bits(word(first + 8 + 4 * (channel * width + x)));
+        assert_eq!(value(0, 1), 4.0);
+        assert_eq!(value(1, 1), 12.0);
+        assert_eq!(value(3, 2), 2.5);
+    }
+}
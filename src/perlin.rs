@@ -0,0 +1,112 @@
+//! Perlin gradient noise.
+
+use crate::math::Vector;
+use crate::sampler::Pcg32;
+
+const POINT_COUNT: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Perlin {
+    gradients: Vec<Vector>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Perlin {
+    /// The noise is the same every time, so that the textures made of
+    /// it don't change from one render to another.
+    pub fn new() -> Self {
+        let mut rng = Pcg32::new(0, 0);
+        let gradients = (0 .. POINT_COUNT).map(|_| random_unit(&mut rng)).collect();
+
+        Self {
+            gradients,
+            perm_x: Self::permutation(&mut rng),
+            perm_y: Self::permutation(&mut rng),
+            perm_z: Self::permutation(&mut rng)
+        }
+    }
+
+    /// Fisher-Yates shuffle of the lattice indices.
+    fn permutation(rng: &mut Pcg32) -> Vec<usize> {
+        let mut perm: Vec<usize> = (0 .. POINT_COUNT).collect();
+        for k in (1 .. POINT_COUNT).rev() {
+            perm.swap(k, rng.next_u32() as usize % (k + 1));
+        }
+        perm
+    }
+
+    /// Smooth noise in the range [-1, 1] with features roughly one unit
+    /// in size.
+    pub fn noise(&self, p: Vector) -> f32 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        // Hermite smoothing of the interpolation weights hides the
+        // lattice.
+        let su = u * u * (3.0 - 2.0 * u);
+        let sv = v * v * (3.0 - 2.0 * v);
+        let sw = w * w * (3.0 - 2.0 * w);
+
+        let mask = POINT_COUNT as i64 - 1;
+        let mut sum = 0.0;
+        for di in 0 .. 2 {
+            for dj in 0 .. 2 {
+                for dk in 0 .. 2 {
+                    let index = self.perm_x[((i + di) & mask) as usize]
+                        ^ self.perm_y[((j + dj) & mask) as usize]
+                        ^ self.perm_z[((k + dk) & mask) as usize];
+
+                    let (a, b, c) = (di as f32, dj as f32, dk as f32);
+                    let offset = Vector{ x: u - a, y: v - b, z: w - c };
+
+                    sum += (a * su + (1.0 - a) * (1.0 - su))
+                        * (b * sv + (1.0 - b) * (1.0 - sv))
+                        * (c * sw + (1.0 - c) * (1.0 - sw))
+                        * self.gradients[index].dot(offset);
+                }
+            }
+        }
+
+        sum
+    }
+
+    /// Turbulence: sum of `octaves` layers of noise, each twice the
+    /// frequency and half the amplitude of the previous one.
+    pub fn turbulence(&self, p: Vector, octaves: usize) -> f32 {
+        let mut sum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+
+        for _ in 0 .. octaves {
+            sum += weight * self.noise(p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+
+        sum.abs()
+    }
+}
+
+/// Random unit vector, picked from the unit ball and pushed to its
+/// surface.
+fn random_unit(rng: &mut Pcg32) -> Vector {
+    loop {
+        let v = Vector{
+            x: 2.0 * rng.next_f32() - 1.0,
+            y: 2.0 * rng.next_f32() - 1.0,
+            z: 2.0 * rng.next_f32() - 1.0
+        };
+        if v.sqnorm() < 1.0 && !v.is_near_zero() {
+            return v.unit();
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Photon map of the caustics: the light that comes to a diffuse surface
+//! off mirrors or through glass.
+//!
+//! Paths from the camera hardly ever find such light, as they have to
+//! run into a small light after the specular bounces. It is traced from
+//! the lights instead: photons are shot from them, and the ones that
+//! land on a diffuse surface after specular bounces are kept. The light
+//! of the caustics at a point is then told from how densely the nearest
+//! photons lie around it.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::PI;
+
+use rayon::prelude::*;
+
+use crate::geometry::{Hit, Hittable, Ray, World};
+use crate::math::Vector;
+use crate::render::Settings;
+use crate::sampler::{RandomSampler, Sampler};
+
+/// Photons the light at a point is told from.
+pub const PHOTONS_PER_ESTIMATE: usize = 50;
+
+/// Photons traced with the same stream of random numbers.
+const CHUNK: usize = 4096;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Photon {
+    p: Vector,
+    n: Vector,         // Normal of the side of the surface the photon landed on
+    direction: Vector, // Unit vector back to where the photon came from
+    power: Vector
+}
+
+/// Photons kept in a balanced kd-tree, laid out in the array like a
+/// binary search: the root is in the middle, the left half is the left
+/// subtree and the right half the right one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<u8>,   // Axis every photon splits its subtree along
+    max_radius: f32  // Farthest a photon can be to count for a point
+}
+
+impl PhotonMap {
+    /// Shoot the photons the settings ask for from the lights of the
+    /// world, or none if it asks for none.
+    pub fn build(world: &World, settings: &Settings) -> Option<Self> {
+        let count = settings.photons as usize;
+        if count == 0 || world.lights.is_empty() {
+            return None;
+        }
+
+        let mut photons: Vec<Photon> = (0 .. count.div_ceil(CHUNK))
+            .into_par_iter()
+            .flat_map_iter(|chunk| {
+                let mut sampler = RandomSampler::new(1, settings.seed);
+                sampler.start_pixel(0, chunk, 0);
+
+                let mut photons = vec![];
+                for _ in chunk * CHUNK .. ((chunk + 1) * CHUNK).min(count) {
+                    shoot(world, count, settings.max_depth, &mut sampler, &mut photons);
+                }
+                photons
+            })
+            .collect();
+
+        let mut axes = vec![0; photons.len()];
+        arrange(&mut photons, &mut axes);
+
+        // The estimates reach out twice as far as they do around a typical
+        // photon, which keeps them from taking in all the stray ones where
+        // the caustics are sparse.
+        let mut map = Self { photons, axes, max_radius: f32::INFINITY };
+        let step = (map.photons.len() / 64).max(1);
+        let mut radii: Vec<f32> = (0 .. map.photons.len())
+            .step_by(step)
+            .map(|k| {
+                let mut nearest = BinaryHeap::new();
+                map.nearest(0, map.photons.len(), map.photons[k].p, &mut nearest);
+                nearest.peek().map_or(0.0, |c| c.distance2)
+            })
+            .collect();
+        radii.sort_unstable_by(f32::total_cmp);
+        map.max_radius = 2.0 * radii.get(radii.len() / 2).map_or(0.0, |r| r.sqrt());
+        Some(map)
+    }
+
+    /// Light of the caustics leaving the hit point back along the ray.
+    pub fn radiance(&self, ray: &Ray, hit: &Hit) -> Vector {
+        let mut nearest = BinaryHeap::new();
+        self.nearest(0, self.photons.len(), hit.p, &mut nearest);
+        let radius2 = if nearest.len() == PHOTONS_PER_ESTIMATE {
+            nearest.peek().map_or(0.0, |c: &Candidate| c.distance2)
+        } else {
+            self.max_radius * self.max_radius
+        };
+        if radius2 <= 0.0 {
+            return Vector{ x: 0.0, y: 0.0, z: 0.0 };
+        }
+
+        let n = hit.facing_normal(ray);
+        let mut color = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+        for candidate in nearest {
+            // Photons on other surfaces, round a corner or on the other
+            // side of a thin wall, don't count.
+            let photon = &self.photons[candidate.index];
+            let cos = n.dot(photon.direction);
+            if photon.n.dot(n) < 0.9 || cos <= 0.0 {
+                continue;
+            }
+
+            // The cosine is in the power of the photon already.
+            color += (1.0 / cos) * (hit.material.eval(ray, hit, photon.direction) * photon.power);
+        }
+
+        color / (PI * radius2)
+    }
+
+    /// Gather the photons nearest to `p` in the subtree of the range
+    /// `lo .. hi` of the array into the heap, the farthest on top.
+    fn nearest(&self, lo: usize, hi: usize, p: Vector, heap: &mut BinaryHeap<Candidate>) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = (lo + hi) / 2;
+        let photon = &self.photons[mid];
+        let axis = self.axes[mid];
+        let offset = coordinate(p, axis) - coordinate(photon.p, axis);
+        let (near, far) = if offset < 0.0 { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+
+        self.nearest(near.0, near.1, p, heap);
+
+        let distance2 = (photon.p - p).sqnorm();
+        let limit = |heap: &BinaryHeap<Candidate>| match heap.peek() {
+            Some(c) if heap.len() == PHOTONS_PER_ESTIMATE => c.distance2,
+            _ => self.max_radius * self.max_radius
+        };
+        if distance2 < limit(heap) {
+            heap.push(Candidate { distance2, index: mid });
+            if heap.len() > PHOTONS_PER_ESTIMATE {
+                heap.pop();
+            }
+        }
+
+        if offset * offset < limit(heap) {
+            self.nearest(far.0, far.1, p, heap);
+        }
+    }
+}
+
+/// Photon found near a point, ordered by the distance.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Candidate {
+    distance2: f32,
+    index: usize
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance2.total_cmp(&other.distance2)
+    }
+}
+
+fn coordinate(v: Vector, axis: u8) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z
+    }
+}
+
+/// Arrange the photons into a kd-tree, split along the longest side of
+/// the box around every subtree.
+fn arrange(photons: &mut [Photon], axes: &mut [u8]) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let (mut lo, mut hi) = (photons[0].p, photons[0].p);
+    for photon in photons.iter() {
+        lo = Vector{ x: lo.x.min(photon.p.x), y: lo.y.min(photon.p.y), z: lo.z.min(photon.p.z) };
+        hi = Vector{ x: hi.x.max(photon.p.x), y: hi.y.max(photon.p.y), z: hi.z.max(photon.p.z) };
+    }
+    let extent = hi - lo;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| coordinate(a.p, axis).total_cmp(&coordinate(b.p, axis)));
+    axes[mid] = axis;
+
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    arrange(left, left_axes);
+    arrange(&mut right[1 ..], &mut right_axes[1 ..]);
+}
+
+/// Shoot a photon from a light picked uniformly, one of the `count`
+/// ones, and keep it if it lands on a diffuse surface after bouncing
+/// off specular ones.
+fn shoot(world: &World, count: usize, depth: u8, sampler: &mut dyn Sampler, photons: &mut Vec<Photon>) {
+    let lights = world.lights.len();
+    let index = ((sampler.next_1d() * lights as f32) as usize).min(lights - 1);
+    let e = world.lights[index].emit(sampler);
+    if e.pdf_direction <= 0.0 {
+        return;
+    }
+
+    let cos = if e.normal.is_near_zero() { 1.0 } else { e.normal.dot(e.direction).abs() };
+    let mut power = (cos * lights as f32 / (e.pdf_position * e.pdf_direction * count as f32)) * e.radiance;
+    let mut ray = Ray::new(e.point, e.direction);
+    let mut specular = false;
+
+    for _ in 0 .. depth {
+        let h = match world.hit(&ray) {
+            Some(h) => h,
+            None => return
+        };
+
+        if !h.material.is_specular() {
+            if specular {
+                photons.push(Photon { p: h.p, n: h.facing_normal(&ray), direction: -ray.direction, power });
+            }
+            return;
+        }
+
+        let s = match h.material.scatter(&ray, &h, sampler) {
+            Some(s) => s,
+            None => return
+        };
+        power = power * s.attenuation;
+        ray = s.ray;
+        specular = true;
+    }
+}
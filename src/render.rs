@@ -0,0 +1,846 @@
+//! Ray tracing algorithm and the sampling loop.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::checkpoint::Checkpoint;
+use crate::filter::Filter;
+use crate::geometry::{AcceleratorKind, Hit, Hittable, Ray, Split, World};
+use crate::integrator::{
+    AmbientOcclusion, BidirectionalPathTracer, Depth, DirectLighting, Integrator, IntegratorKind, Normals, PathTracer,
+    Uvs
+};
+use crate::math::Vector;
+use crate::mlt;
+use crate::sampler::{HaltonSampler, RandomSampler, Sampler, SamplerKind};
+use crate::tonemap::ToneMap;
+
+/// Image size and rendering algorithm parameters.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Settings {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: u32,
+    pub max_depth: u8,
+    pub integrator: IntegratorKind,
+    pub photons: u32, // Photons shot from the lights for the caustics
+    pub ao_distance: f32, // Farthest an object occludes a point from, for ambient occlusion
+    pub aovs: bool, // Whether to render the albedo, normal and depth buffers too
+    pub denoise: bool, // Whether to filter the noise out of the finished image, with the help of the buffers
+    pub transparent: bool, // Whether the background is left out of the image and shows in its alpha channel
+    pub split_eyes: bool, // Whether the halves of a stereo image are saved to files of their own
+    pub threshold: Option<f32>, // Relative error at which pixels stop being sampled
+    pub sampler: SamplerKind,
+    pub blue_noise: bool, // Shift the sample sequences of the pixels by a blue noise mask
+    pub seed: u64,
+    pub filter: Filter,
+    pub max_radiance: Option<f32>, // Brightest a sample can be, to keep fireflies out
+    pub tone_map: ToneMap, // How the light is squeezed into the colors of the screen and the PNG files
+    pub exposure: f32, // Stops the image is made brighter by before the tone mapping
+    pub accelerator: AcceleratorKind, // Structure finding what the rays hit, over the objects and the triangles of the meshes
+    pub bvh: Split // How the nodes of the BVHs are split
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            width: 500,
+            height: 500,
+            samples_per_pixel: 100,
+            max_depth: 7,
+            integrator: IntegratorKind::Path,
+            photons: 0,
+            ao_distance: 1.0,
+            aovs: false,
+            denoise: false,
+            transparent: false,
+            split_eyes: false,
+            threshold: None,
+            sampler: SamplerKind::Random,
+            blue_noise: false,
+            seed: 0,
+            filter: Filter::Box,
+            max_radiance: None,
+            tone_map: ToneMap::Clamp,
+            exposure: 0.0,
+            accelerator: AcceleratorKind::Bvh,
+            bvh: Split::Sah
+        }
+    }
+}
+
+impl Settings {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    /// Whether the arbitrary output variables are needed: to be saved,
+    /// for denoising or for the alpha channel.
+    pub fn needs_aovs(&self) -> bool {
+        self.aovs || self.denoise || self.transparent
+    }
+
+    /// A new sampler of the kind the settings ask for.
+    pub fn sampler(&self) -> Box<dyn Sampler> {
+        match self.sampler {
+            SamplerKind::Random => Box::new(RandomSampler::new(self.samples_per_pixel, self.seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(self.blue_noise, self.seed))
+        }
+    }
+
+    /// A new integrator of the kind the settings ask for. The Markov
+    /// chains sample whole tiles and trace the paths of the path tracer,
+    /// which is what they get.
+    pub fn integrator(&self) -> Box<dyn Integrator> {
+        let (max_depth, transparent) = (self.max_depth, self.transparent);
+        match self.integrator {
+            IntegratorKind::Path | IntegratorKind::Metropolis => Box::new(PathTracer { max_depth, transparent }),
+            IntegratorKind::Bidirectional => Box::new(BidirectionalPathTracer { max_depth, transparent }),
+            IntegratorKind::Direct => Box::new(DirectLighting { max_depth, transparent }),
+            IntegratorKind::AmbientOcclusion => Box::new(AmbientOcclusion { distance: self.ao_distance, transparent }),
+            IntegratorKind::Normals => Box::new(Normals),
+            IntegratorKind::Depth => Box::new(Depth),
+            IntegratorKind::Uvs => Box::new(Uvs)
+        }
+    }
+}
+
+/// Accumulation buffer: a sum of all the samples taken so far for
+/// every pixel, stored row by row, bottom row first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vector>
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Vector {x: 0.0, y: 0.0, z: 0.0}; width * height]
+        }
+    }
+
+    /// Pixel in the row `i` counting from the bottom and the column `j`.
+    pub fn get(&self, i: usize, j: usize) -> Vector {
+        self.pixels[i * self.width + j]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[Vector]> + ExactSizeIterator {
+        self.pixels.chunks(self.width)
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|p| *p = Vector {x: 0.0, y: 0.0, z: 0.0});
+    }
+
+    /// Image of the columns `j0 .. j1`.
+    pub fn columns(&self, j0: usize, j1: usize) -> Self {
+        Self {
+            width: j1 - j0,
+            height: self.height,
+            pixels: self.rows().flat_map(|row| row[j0 .. j1].iter().copied()).collect()
+        }
+    }
+}
+
+/// Arbitrary output variables: what the camera rays first run into,
+/// summed over the samples like the image, for denoisers and
+/// compositing. Where nothing is hit, all of them are zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aovs {
+    pub albedo: Image,
+    pub normal: Image, // Shading normal, from -1 to 1
+    pub depth: Image,  // Distance along the ray, the same in all three channels
+    pub alpha: Image   // Whether anything is hit at all, the same in all three channels
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            albedo: Image::new(width, height),
+            normal: Image::new(width, height),
+            depth: Image::new(width, height),
+            alpha: Image::new(width, height)
+        }
+    }
+
+    /// The buffers of the columns `j0 .. j1`.
+    pub fn columns(&self, j0: usize, j1: usize) -> Self {
+        Self {
+            albedo: self.albedo.columns(j0, j1),
+            normal: self.normal.columns(j0, j1),
+            depth: self.depth.columns(j0, j1),
+            alpha: self.alpha.columns(j0, j1)
+        }
+    }
+
+    /// The buffers along with their names, but for the alpha, which goes
+    /// with the image itself.
+    pub fn layers(&self) -> [(&'static str, &Image); 3] {
+        [("albedo", &self.albedo), ("normal", &self.normal), ("depth", &self.depth)]
+    }
+
+    pub(crate) fn add(&mut self, index: usize, sample: &AovSample) {
+        self.albedo.pixels[index] += sample.albedo;
+        self.normal.pixels[index] += sample.normal;
+        self.depth.pixels[index] += Vector{ x: sample.depth, y: sample.depth, z: sample.depth };
+        self.alpha.pixels[index] += Vector{ x: sample.alpha, y: sample.alpha, z: sample.alpha };
+    }
+}
+
+/// Values of the arbitrary output variables for one sample.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AovSample {
+    pub albedo: Vector,
+    pub normal: Vector,
+    pub depth: f32,
+    pub alpha: f32
+}
+
+/// Light reaching the hit point straight from the light sources of the
+/// world and reflected back along the ray.
+///
+/// The light of the glowing objects can also be found by bouncing off
+/// the surface into them, so it is weighted against that with the power
+/// heuristic: whichever of the two picks the direction more often gets
+/// the most of its weight. Without `mis`, as on the last bounce of a
+/// path, the shadow rays are all there is and count in full, as they do
+/// for the lights that are no objects of the world and can't be bounced
+/// into.
+pub fn direct_light(ray: &Ray, hit: &Hit, world: &World, mis: bool, sampler: &mut dyn Sampler) -> Vector {
+    let mut color = Vector {x: 0.0, y: 0.0, z: 0.0};
+    for (index, light) in world.lights.iter().enumerate() {
+        let sample = light.sample(hit.p, sampler);
+
+        let f = hit.material.eval(ray, hit, sample.direction);
+        if f.is_near_zero() || world.is_occluded(hit.p, sample.direction, sample.distance, ray.time) {
+            continue;
+        }
+
+        let hittable = mis && world.is_hittable(index);
+        let weight = if hittable { power_heuristic(sample.pdf, hit.material.pdf(ray, hit, sample.direction)) } else { 1.0 };
+        color += weight * (f * sample.intensity);
+    }
+
+    color
+}
+
+/// Weight of a sample taken with the density `f` when another strategy
+/// would have taken it with the density `g`.
+pub fn power_heuristic(f: f32, g: f32) -> f32 {
+    if f.is_infinite() {
+        return 1.0;
+    }
+    if f <= 0.0 {
+        return 0.0;
+    }
+
+    f * f / (f * f + g * g)
+}
+
+/// Bounces every path makes before Russian roulette may end it.
+pub const ROULETTE_BOUNCES: u8 = 3;
+
+/// Follow the ray as it bounces around the world, at most `depth`
+/// times, and add up the light that makes it back along it. The
+/// throughput is the fraction of the light found at the current bounce
+/// that reaches the camera.
+///
+/// The light of the lights is gathered at every bounce with shadow rays.
+/// When a path runs into a glowing object that is sampled as a light,
+/// its light is weighted against the shadow rays of the bounce before,
+/// unless that bounce was specular and sampled no lights.
+///
+/// With a photon map of the caustics in the world, the caustics are
+/// looked up at the first diffuse bounce, and the path doesn't count
+/// the light it finds from there through specular bounces only.
+///
+/// With `transparent`, the ray given comes out black if it misses
+/// everything, the background only showing past the first bounce.
+pub fn ray_color(ray: &Ray, world: &World, depth: u8, transparent: bool, sampler: &mut dyn Sampler) -> Vector {
+    let mut color = Vector {x: 0.0, y: 0.0, z: 0.0};
+    let mut throughput = Vector {x: 1.0, y: 1.0, z: 1.0};
+    let mut ray = *ray;
+    let mut specular = true;
+    let mut pdf = 0.0; // Density with which the last bounce picked the direction of the ray
+    let mut gathered = false; // Whether the caustics were looked up already
+    let mut caustic = false;  // Whether the bounces since the lookup were all specular
+
+    for bounce in 0 .. depth {
+        let h = match world.hit(&ray) {
+            Some(h) => h,
+            None => {
+                if !(transparent && bounce == 0) {
+                    color += throughput * world.background.color(&ray);
+                }
+                break;
+            }
+        };
+
+        let weight = match world.emitters.get(&h.object) {
+            Some(_) if caustic && specular => 0.0,
+            Some(&light) if !specular => power_heuristic(pdf, world.lights[light].pdf(ray.origin, h.p)),
+            _ => 1.0
+        };
+        color += weight * (throughput * h.material.emitted(&ray, &h));
+        color += throughput * direct_light(&ray, &h, world, bounce + 1 < depth, sampler);
+
+        let gather = !gathered && !h.material.is_specular();
+        if let Some(caustics) = world.caustics.as_ref().filter(|_| gather) {
+            color += throughput * caustics.radiance(&ray, &h);
+            gathered = true;
+        }
+
+        let s = match h.material.scatter(&ray, &h, sampler) {
+            Some(s) => s,
+            None => break
+        };
+        throughput = throughput * s.attenuation;
+        specular = h.material.is_specular();
+        pdf = h.material.pdf(&ray, &h, s.ray.direction);
+        caustic = gathered && (gather || (caustic && specular));
+
+        // Russian roulette: past the first few bounces, the paths that
+        // carry little light are ended at random, and the ones that go
+        // on count for those that were ended.
+        if bounce >= ROULETTE_BOUNCES {
+            let survival = throughput.max_component();
+            if survival < 1.0 {
+                if sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+        }
+
+        ray = s.ray;
+    }
+
+    color
+}
+
+/// Edge of the square tiles the image is split into for tracing.
+pub const TILE_SIZE: usize = 32;
+
+/// Block of pixels: the rows `i0 .. i1` counting from the bottom and the
+/// columns `j0 .. j1`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tile {
+    pub i0: usize,
+    pub i1: usize,
+    pub j0: usize,
+    pub j1: usize
+}
+
+impl Tile {
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let Tile { i0, i1, j0, j1 } = *self;
+        (i0 .. i1).flat_map(move |i| (j0 .. j1).map(move |j| (i, j)))
+    }
+}
+
+/// Tiles covering the image, top ones first. The ones on the right and
+/// top edges can be smaller.
+pub fn tiles(width: usize, height: usize) -> Vec<Tile> {
+    let mut tiles = vec![];
+    for i0 in (0 .. height).step_by(TILE_SIZE).rev() {
+        for j0 in (0 .. width).step_by(TILE_SIZE) {
+            tiles.push(Tile {
+                i0,
+                i1: (i0 + TILE_SIZE).min(height),
+                j0,
+                j1: (j0 + TILE_SIZE).min(width)
+            });
+        }
+    }
+    tiles
+}
+
+/// The `sample`-th sample for every pixel of the tile, in the order of
+/// `Tile::pixels`.
+pub fn render_tile(tile: Tile, sample: u32, settings: &Settings, camera: &Camera, world: &World) -> Vec<Vector> {
+    if settings.integrator == IntegratorKind::Metropolis {
+        return mlt::render_tile(tile, sample, settings, camera, world);
+    }
+
+    let mut sampler = settings.sampler();
+    let integrator = settings.integrator();
+    tile.pixels()
+        .map(|pixel| render_pixel(pixel, sample, settings, camera, world, integrator.as_ref(), sampler.as_mut()))
+        .collect()
+}
+
+/// The `sample`-th sample of the pixel in the row `i` counting from the
+/// bottom and the column `j`, traced by the `integrator` of the settings.
+pub fn render_pixel((i, j): (usize, usize), sample: u32, settings: &Settings, camera: &Camera, world: &World, integrator: &dyn Integrator, sampler: &mut dyn Sampler) -> Vector {
+    // Where the camera sees nothing, the sample counts for nothing.
+    let (ray, weight) = match pixel_ray(i, j, sample, settings, camera, sampler) {
+        Some(r) => r,
+        None => return Vector{ x: 0.0, y: 0.0, z: 0.0 }
+    };
+
+    // Perform ray tracing and see what color the ray should be.
+    let color = integrator.li(&ray, world, sampler);
+
+    weight * clamped(color, settings.max_radiance)
+}
+
+/// The arbitrary output variables of the `sample`-th sample of the
+/// pixel, taken along the same camera ray as `render_pixel` takes.
+///
+/// The albedo and the normal are the ones of what is seen in mirrors and
+/// through glass, up to the depth of the settings, as that is what the
+/// light there comes from. The depth is the distance to the mirror or
+/// the glass itself, and the alpha is whether anything is hit at all.
+pub fn render_aovs((i, j): (usize, usize), sample: u32, settings: &Settings, camera: &Camera, world: &World, sampler: &mut dyn Sampler) -> AovSample {
+    let zero = Vector{ x: 0.0, y: 0.0, z: 0.0 };
+    let mut aovs = AovSample { albedo: zero, normal: zero, depth: 0.0, alpha: 0.0 };
+    let (mut ray, weight) = match pixel_ray(i, j, sample, settings, camera, sampler) {
+        Some(r) => r,
+        None => return aovs
+    };
+    let mut throughput = Vector{ x: 1.0, y: 1.0, z: 1.0 };
+
+    for bounce in 0 .. settings.max_depth {
+        let h = match world.hit(&ray) {
+            Some(h) => h,
+            None => {
+                aovs.albedo = zero;
+                aovs.normal = zero;
+                break;
+            }
+        };
+
+        if bounce == 0 {
+            aovs.depth = weight * h.t;
+            aovs.alpha = weight;
+        }
+        aovs.albedo = weight * (throughput * h.material.albedo(&h));
+        aovs.normal = weight * h.material.shading_normal(&h);
+        if !h.material.is_specular() {
+            break;
+        }
+
+        match h.material.scatter(&ray, &h, sampler) {
+            Some(s) => {
+                throughput = throughput * s.attenuation;
+                ray = s.ray;
+            },
+            None => break
+        }
+    }
+
+    aovs
+}
+
+/// Camera ray of the `sample`-th sample of the pixel, and the weight the
+/// filter gives it. `None` where the camera sees nothing.
+fn pixel_ray(i: usize, j: usize, sample: u32, settings: &Settings, camera: &Camera, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+    sampler.start_pixel(i, j, sample);
+
+    // Calculate coordinates of the point relative to the viewport,
+    // placed around the center of the pixel as the filter is spread.
+    let (dx, dy) = sampler.next_2d();
+    let (dx, wx) = settings.filter.sample(dx);
+    let (dy, wy) = settings.filter.sample(dy);
+    let u = (j as f32 + 0.5 + dx) / (settings.width  as f32 - 1.0);
+    let v = (i as f32 + 0.5 + dy) / (settings.height as f32 - 1.0);
+
+    // Construct a ray going through the point on the viewport.
+    Some((camera.get_ray(u, v, sampler)?, wx * wy))
+}
+
+/// Color dimmed to be no brighter than `max_radiance`, if given.
+///
+/// Rare paths that find a small bright light leave speckles that would
+/// take many more samples to average out. Dimming them biases the image,
+/// but only where it is that bright.
+pub fn clamped(color: Vector, max_radiance: Option<f32>) -> Vector {
+    match max_radiance {
+        Some(max) if color.max_component() > max => color * (max / color.max_component()),
+        _ => color
+    }
+}
+
+impl Image {
+    /// Add the samples of a tile given in the order of `Tile::pixels`.
+    pub fn add_tile(&mut self, tile: Tile, samples: &[Vector]) {
+        for ((i, j), sample) in tile.pixels().zip(samples) {
+            self.pixels[i * self.width + j] += *sample;
+        }
+    }
+}
+
+/// Take the `sample`-th sample for every pixel of the image and add it
+/// to the accumulation buffer. Tiles are traced in parallel, idle
+/// threads stealing the ones not yet started from the busy ones.
+pub fn render_sample(image: &mut Image, sample: u32, settings: &Settings, camera: &Camera, world: &World) {
+    let tiles = tiles(settings.width, settings.height);
+    let samples: Vec<Vec<Vector>> = tiles.par_iter()
+        .map(|&tile| render_tile(tile, sample, settings, camera, world))
+        .collect();
+
+    for (&tile, samples) in tiles.iter().zip(&samples) {
+        image.add_tile(tile, samples);
+    }
+}
+
+/// Samples every pixel gets before telling whether it has converged.
+pub const MIN_ADAPTIVE_SAMPLES: u32 = 8;
+
+/// Running sums of the luminance of the samples of a pixel, for telling
+/// how noisy their average still is.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Moments {
+    pub count: u32,
+    pub sum: f32,
+    pub sum_squares: f32
+}
+
+impl Moments {
+    pub fn add(&mut self, sample: Vector) {
+        let y = 0.2126 * sample.x + 0.7152 * sample.y + 0.0722 * sample.z;
+        self.count += 1;
+        self.sum += y;
+        self.sum_squares += y * y;
+    }
+
+    /// Whether the standard error of the mean luminance is within the
+    /// `threshold` fraction of it. Very dark pixels are compared to a
+    /// small floor instead, or they would never settle.
+    pub fn converged(&self, threshold: f32) -> bool {
+        if self.count < MIN_ADAPTIVE_SAMPLES {
+            return false;
+        }
+
+        let n = self.count as f32;
+        let mean = self.sum / n;
+        let variance = (self.sum_squares / n - mean * mean).max(0.0) * n / (n - 1.0);
+        (variance / n).sqrt() <= threshold * mean.max(1E-2)
+    }
+}
+
+/// Sampling loop running on a thread of its own, so that the window
+/// stays responsive. It can be paused, and started over with another
+/// camera or settings. Tiles are added to the image as soon as they are
+/// traced, so a sample doesn't have to be complete to be seen.
+///
+/// With a threshold in the settings, the pixels that have converged are
+/// left alone and the time goes to the noisy ones, up to the number of
+/// samples per pixel.
+pub struct RenderThread {
+    shared: Arc<(Mutex<Progress>, Condvar)>,
+    handle: Option<JoinHandle<()>>
+}
+
+struct Progress {
+    settings: Settings,
+    camera: Camera,
+    image: Image,
+    aovs: Option<Aovs>, // If the settings ask for them
+    moments: Vec<Moments>, // For every pixel, in the order of the image
+    tiles: Vec<Tile>,
+    tile_samples: Vec<u32>, // Passes made so far over every tile
+    samples: u32, // Passes made over the whole image
+    converged: bool, // Whether all the pixels have converged
+    generation: u64, // Bumped when starting over, so that the tiles in flight get dropped
+    paused: bool,
+    stop: bool
+}
+
+impl Progress {
+    fn new(settings: Settings, camera: Camera, generation: u64, paused: bool) -> Self {
+        let tiles = tiles(settings.width, settings.height);
+        Self {
+            settings,
+            camera,
+            image: Image::new(settings.width, settings.height),
+            aovs: if settings.needs_aovs() { Some(Aovs::new(settings.width, settings.height)) } else { None },
+            moments: vec![Moments::default(); settings.width * settings.height],
+            tile_samples: vec![0; tiles.len()],
+            tiles,
+            samples: 0,
+            converged: false,
+            generation,
+            paused,
+            stop: false
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.converged || self.samples >= self.settings.samples_per_pixel
+    }
+
+    /// Whether the pixel still needs samples.
+    fn active(&self, i: usize, j: usize) -> bool {
+        let moments = &self.moments[i * self.settings.width + j];
+        match self.settings.threshold {
+            // The chains sample whole tiles, not pixels.
+            _ if self.settings.integrator == IntegratorKind::Metropolis => true,
+            Some(threshold) => {
+                moments.count < self.settings.samples_per_pixel && !moments.converged(threshold)
+            },
+            None => true
+        }
+    }
+
+    /// Whether none of the pixels need any more samples to converge.
+    fn all_converged(&self) -> bool {
+        self.settings.threshold.is_some()
+            && !(0 .. self.settings.height).any(|i| (0 .. self.settings.width).any(|j| self.active(i, j)))
+    }
+
+    /// Whether the tiles in flight of the given generation are still
+    /// wanted.
+    fn wants(&self, generation: u64) -> bool {
+        self.generation == generation && !self.paused && !self.stop
+    }
+}
+
+impl RenderThread {
+    pub fn spawn(settings: Settings, camera: Camera, world: Arc<World>) -> Self {
+        let progress = Progress::new(settings, camera, 0, false);
+        let shared = Arc::new((Mutex::new(progress), Condvar::new()));
+
+        let handle = {
+            let shared = shared.clone();
+            thread::spawn(move || sample_loop(&shared, &world))
+        };
+
+        Self { shared, handle: Some(handle) }
+    }
+
+    /// Throw away the samples taken so far and start over.
+    pub fn restart(&self, settings: Settings, camera: Camera) {
+        self.update(|progress| {
+            *progress = Progress::new(settings, camera, progress.generation + 1, progress.paused);
+        });
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.update(|progress| progress.paused = paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.0.lock().unwrap().paused
+    }
+
+    /// Whether all the samples are taken, or all the pixels converged.
+    pub fn is_finished(&self) -> bool {
+        self.shared.0.lock().unwrap().finished()
+    }
+
+    /// Whether the sampling loop is still running, which it does till the
+    /// thread is dropped unless it panics.
+    pub fn is_alive(&self) -> bool {
+        self.handle.as_ref().is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Number of passes made so far over the whole image.
+    pub fn samples(&self) -> u32 {
+        self.shared.0.lock().unwrap().samples
+    }
+
+    /// Average of the samples taken so far for every pixel, and the
+    /// number of passes made over the whole image.
+    pub fn snapshot(&self) -> (Image, u32) {
+        let progress = self.shared.0.lock().unwrap();
+        let mut image = progress.image.clone();
+        for (pixel, moments) in image.pixels.iter_mut().zip(&progress.moments) {
+            *pixel = *pixel / moments.count.max(1) as f32;
+        }
+        (image, progress.samples)
+    }
+
+    /// Average of the arbitrary output variables sampled so far for every
+    /// pixel, if the settings need them.
+    pub fn aovs(&self) -> Option<Aovs> {
+        let progress = self.shared.0.lock().unwrap();
+        let mut aovs = progress.aovs.clone()?;
+        for image in [&mut aovs.albedo, &mut aovs.normal, &mut aovs.depth, &mut aovs.alpha] {
+            for (pixel, moments) in image.pixels.iter_mut().zip(&progress.moments) {
+                *pixel = *pixel / moments.count.max(1) as f32;
+            }
+        }
+        Some(aovs)
+    }
+
+    /// State of the render so far, to be picked up later with `resume`.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let progress = self.shared.0.lock().unwrap();
+        Checkpoint {
+            seed: progress.settings.seed,
+            sampler: progress.settings.sampler,
+            blue_noise: progress.settings.blue_noise,
+            samples: progress.samples,
+            tile_samples: progress.tile_samples.clone(),
+            image: progress.image.clone(),
+            moments: progress.moments.clone(),
+            aovs: progress.aovs.clone()
+        }
+    }
+
+    /// Throw away the samples taken so far and carry on from the
+    /// checkpoint instead, which is to be of a render of the same scene.
+    pub fn resume(&self, checkpoint: Checkpoint) -> Result<(), String> {
+        let mut result = Ok(());
+        self.update(|progress| {
+            let (width, height) = (progress.settings.width, progress.settings.height);
+            if checkpoint.image.width != width || checkpoint.image.height != height {
+                result = Err(format!(
+                    "the checkpoint is of a {}×{} image, not {}×{}",
+                    checkpoint.image.width, checkpoint.image.height, width, height
+                ));
+                return;
+            }
+            if checkpoint.aovs.is_some() != progress.settings.needs_aovs() {
+                result = Err("the checkpoint doesn't match the AOV settings".to_string());
+                return;
+            }
+            let settings = &progress.settings;
+            if checkpoint.seed != settings.seed {
+                result = Err(format!("the checkpoint is of a render with the seed {}, not {}", checkpoint.seed, settings.seed));
+                return;
+            }
+            if checkpoint.sampler != settings.sampler || checkpoint.blue_noise != settings.blue_noise {
+                result = Err("the checkpoint doesn't match the sampler settings".to_string());
+                return;
+            }
+
+            *progress = Progress {
+                image: checkpoint.image,
+                aovs: checkpoint.aovs,
+                moments: checkpoint.moments,
+                tile_samples: checkpoint.tile_samples,
+                samples: checkpoint.samples,
+                ..Progress::new(progress.settings, progress.camera, progress.generation + 1, progress.paused)
+            };
+            progress.converged = progress.all_converged();
+        });
+        result
+    }
+
+    /// Block till the rendering is finished or `timeout` is over, and
+    /// tell whether it is finished.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let (lock, condvar) = &*self.shared;
+        let progress = lock.lock().unwrap();
+        let (progress, _) = condvar.wait_timeout_while(progress, timeout, |p| !p.finished()).unwrap();
+        progress.finished()
+    }
+
+    /// Block till the rendering is finished and return the snapshot.
+    pub fn wait(&self) -> (Image, u32) {
+        {
+            let (lock, condvar) = &*self.shared;
+            let mut progress = lock.lock().unwrap();
+            while !progress.finished() {
+                progress = condvar.wait(progress).unwrap();
+            }
+        }
+        self.snapshot()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Progress)) {
+        let (lock, condvar) = &*self.shared;
+        change(&mut lock.lock().unwrap());
+        condvar.notify_all();
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.update(|progress| progress.stop = true);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn sample_loop(shared: &(Mutex<Progress>, Condvar), world: &World) {
+    let (lock, condvar) = shared;
+    loop {
+        let (settings, camera, generation, todo) = {
+            let mut progress = lock.lock().unwrap();
+            while !progress.stop && (progress.paused || progress.finished()) {
+                progress = condvar.wait(progress).unwrap();
+            }
+            if progress.stop {
+                return;
+            }
+
+            // A pause can leave a pass half done, only the tiles that
+            // are behind are traced then.
+            let todo: Vec<(usize, Tile)> = progress.tiles.iter()
+                .copied()
+                .enumerate()
+                .filter(|&(k, _)| progress.tile_samples[k] == progress.samples)
+                .collect();
+            (progress.settings, progress.camera, progress.generation, todo)
+        };
+
+        // The lock is only held to pick the pixels of the tile that need
+        // samples and to add the samples to the image.
+        todo.par_iter().for_each(|&(k, tile)| {
+            let (pixels, pass): (Vec<(usize, usize, u32)>, u32) = {
+                let progress = lock.lock().unwrap();
+                if !progress.wants(generation) {
+                    return;
+                }
+                let pixels = tile.pixels()
+                    .filter(|&(i, j)| progress.active(i, j))
+                    .map(|(i, j)| (i, j, progress.moments[i * settings.width + j].count))
+                    .collect();
+                (pixels, progress.tile_samples[k])
+            };
+
+            let samples: Vec<Vector> = if settings.integrator == IntegratorKind::Metropolis {
+                render_tile(tile, pass, &settings, &camera, world)
+            } else {
+                let mut sampler = settings.sampler();
+                let integrator = settings.integrator();
+                pixels.iter()
+                    .map(|&(i, j, n)| render_pixel((i, j), n, &settings, &camera, world, integrator.as_ref(), sampler.as_mut()))
+                    .collect()
+            };
+            let aovs: Vec<AovSample> = if settings.needs_aovs() {
+                let mut sampler = settings.sampler();
+                pixels.iter()
+                    .map(|&(i, j, n)| render_aovs((i, j), n, &settings, &camera, world, sampler.as_mut()))
+                    .collect()
+            } else {
+                vec![]
+            };
+
+            let mut progress = lock.lock().unwrap();
+            if progress.generation == generation {
+                for (&(i, j, _), &sample) in pixels.iter().zip(&samples) {
+                    let index = i * settings.width + j;
+                    progress.image.pixels[index] += sample;
+                    progress.moments[index].add(sample);
+                }
+                if let Some(buffers) = progress.aovs.as_mut() {
+                    for (&(i, j, _), sample) in pixels.iter().zip(&aovs) {
+                        buffers.add(i * settings.width + j, sample);
+                    }
+                }
+                progress.tile_samples[k] += 1;
+            }
+        });
+
+        let mut progress = lock.lock().unwrap();
+        if progress.generation == generation && progress.tile_samples.iter().all(|&n| n > progress.samples) {
+            println!("{:?}", progress.samples);
+            progress.samples += 1;
+            progress.converged = progress.all_converged();
+            condvar.notify_all();
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! Random numbers of the samples.
+//!
+//! Every sample of a pixel draws its numbers one after another from a
+//! `Sampler`: the jitter inside the pixel, the point of the lens, the
+//! moment of time, then whatever the materials and the lights along the
+//! path need. Each number is a dimension of the sample. White noise
+//! draws them independently, while the Halton sequence spreads the
+//! samples of a pixel evenly over every dimension, which converges
+//! faster.
+//!
+//! The Halton sequence is shifted randomly for every pixel, so that the
+//! neighbours don't repeat one another. Taking the shifts from a blue
+//! noise mask instead of white noise leaves the error of the neighbours
+//! as different as it gets, which looks like fine grain rather than
+//! blotches at a few samples per pixel.
+//!
+//! Whatever random numbers there are come from a PCG generator seeded by
+//! the pixel, the sample and a global seed, so that the same seed gives
+//! the same image every time, however the work is split between the
+//! threads.
+
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::math::Vector;
+
+/// Source of the numbers of the samples, from 0 to 1.
+pub trait Sampler {
+    /// Start drawing the numbers of the `sample`-th sample of the pixel
+    /// in the row `i` and the column `j`, from the first dimension.
+    fn start_pixel(&mut self, i: usize, j: usize, sample: u32);
+
+    /// Number of the next dimension.
+    fn next_1d(&mut self) -> f32;
+
+    /// Numbers of the next two dimensions.
+    fn next_2d(&mut self) -> (f32, f32) {
+        let u = self.next_1d();
+        (u, self.next_1d())
+    }
+
+    /// Point of the unit disk in the xy plane. The concentric mapping
+    /// keeps the numbers that are close together close on the disk too.
+    fn in_unit_disk(&mut self) -> Vector {
+        let (u, v) = self.next_2d();
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vector{ x: 0.0, y: 0.0, z: 0.0 };
+        }
+
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Vector{ x: r * theta.cos(), y: r * theta.sin(), z: 0.0 }
+    }
+
+    /// Uniformly distributed unit vector.
+    fn unit_vector(&mut self) -> Vector {
+        let (u, v) = self.next_2d();
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        Vector{ x: r * phi.cos(), y: r * phi.sin(), z }
+    }
+}
+
+/// Which sampler to render with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SamplerKind {
+    /// Independent random numbers, with the pixel jitter stratified.
+    #[default]
+    Random,
+    /// Halton sequence with scrambled digits, shifted randomly for
+    /// every pixel.
+    Halton
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(SamplerKind::Random),
+            "halton" => Ok(SamplerKind::Halton),
+            _ => Err(format!("unknown sampler {}, expected random or halton", s))
+        }
+    }
+}
+
+/// White noise. The first two dimensions, the jitter inside the pixel,
+/// are stratified: the pixel is split into a √N × √N grid for N samples
+/// per pixel and every cell gets a sample, so that the samples don't
+/// clump together. Samples past the last full round of the grid are
+/// plain random.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RandomSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    rng: Pcg32,
+    sample: u32,
+    dimension: u32
+}
+
+impl RandomSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self { samples_per_pixel, seed, rng: Pcg32::new(seed, 0), sample: 0, dimension: 0 }
+    }
+
+    fn stratified(&mut self) -> (f32, f32) {
+        let n = (self.samples_per_pixel as f32).sqrt() as u32;
+        let cells = n * n;
+        if n <= 1 || self.sample >= cells {
+            return (self.rng.next_f32(), self.rng.next_f32());
+        }
+
+        // Cells are visited in steps of about the golden ratio of their
+        // count rather than row by row, so that the first few samples are
+        // spread all over the pixel too. Any step coprime with the count
+        // visits every cell once.
+        let mut step = (cells as f32 * 0.618) as u32;
+        while gcd(step, cells) != 1 {
+            step += 1;
+        }
+        let cell = (self.sample as u64 * step as u64 % cells as u64) as u32;
+
+        let (sx, sy) = (cell % n, cell / n);
+        (
+            (sx as f32 + self.rng.next_f32()) / n as f32,
+            (sy as f32 + self.rng.next_f32()) / n as f32
+        )
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn start_pixel(&mut self, i: usize, j: usize, sample: u32) {
+        self.rng = Pcg32::for_pixel(self.seed, i, j, sample);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        self.dimension += 1;
+        self.rng.next_f32()
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let first = self.dimension == 0;
+        self.dimension += 2;
+        if first { self.stratified() } else { (self.rng.next_f32(), self.rng.next_f32()) }
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Bases of the dimensions of the Halton sequence. The dimensions past
+/// the last one are white noise: paths rarely get that deep and high
+/// bases are poorly distributed anyway.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311
+];
+
+/// Largest float below one.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Scrambled Halton sequence. With `blue_noise`, the pixels are shifted
+/// by the blue noise mask rather than by white noise.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HaltonSampler {
+    blue_noise: bool,
+    seed: u64,
+    rng: Pcg32, // For the dimensions past the last base
+    pixel: (u32, u32),
+    sample: u32,
+    dimension: u32
+}
+
+impl HaltonSampler {
+    pub fn new(blue_noise: bool, seed: u64) -> Self {
+        Self { blue_noise, seed, rng: Pcg32::new(seed, 0), pixel: (0, 0), sample: 0, dimension: 0 }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel(&mut self, i: usize, j: usize, sample: u32) {
+        self.rng = Pcg32::for_pixel(self.seed, i, j, sample);
+        self.pixel = (i as u32, j as u32);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let base = match PRIMES.get(dimension as usize) {
+            Some(&base) => base,
+            None => return self.rng.next_f32()
+        };
+
+        // Shifting every dimension by a random amount per pixel keeps
+        // the neighbouring pixels from having the same noise.
+        let (i, j) = self.pixel;
+        let shift = if self.blue_noise {
+            // Every dimension looks at the mask from a different place,
+            // or they would all be shifted alike.
+            let offset = mix(self.seed ^ hash(dimension, 0));
+            blue_noise(i.wrapping_add(offset as u32), j.wrapping_add((offset >> 32) as u32))
+        } else {
+            (mix(self.seed ^ hash(i << 16 ^ j, dimension)) >> 40) as f32 / (1u64 << 24) as f32
+        };
+        let x = scrambled_radical_inverse(base, self.sample, dimension) + shift;
+        (x - x.floor()).min(ONE_MINUS_EPSILON)
+    }
+}
+
+/// Digits of `n` in the given base mirrored around the decimal point,
+/// each of them shuffled with a permutation of its own for every
+/// dimension and position. Unscrambled, the first samples of the large
+/// bases would all be tiny and alike.
+fn scrambled_radical_inverse(base: u32, mut n: u32, dimension: u32) -> f32 {
+    let inverse = 1.0 / base as f64;
+    let mut x = 0.0;
+    let mut scale = inverse;
+    let mut position = 0;
+
+    // The zeros past the last digit get shuffled too, down to what a
+    // float can tell apart.
+    while n > 0 || scale > 1E-8 {
+        // Multiplying by a nonzero number and adding another one is a
+        // permutation of the digits of a prime base.
+        let h = hash(dimension, position);
+        let a = 1 + h % (base as u64 - 1);
+        let c = (h >> 32) % base as u64;
+        let digit = (n % base) as u64;
+
+        x += ((digit * a + c) % base as u64) as f64 * scale;
+        scale *= inverse;
+        position += 1;
+        n /= base;
+    }
+
+    x as f32
+}
+
+/// Bits that look random for every pair of numbers.
+pub(crate) fn hash(a: u32, b: u32) -> u64 {
+    mix((a as u64) << 32 | b as u64)
+}
+
+/// Finalizer of SplitMix64: every bit of the input flips about half of
+/// the bits of the output.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+/// PCG32 random number generator (XSH RR): small, fast and, unlike the
+/// generator of the thread, the same on every run for the same seed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6364136223846793005;
+
+    /// Generator of the given seed. Different streams give unrelated
+    /// sequences for the same seed.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self { state: 0, increment: stream << 1 | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// Generator of the `sample`-th sample of the pixel in the row `i`
+    /// and the column `j`: every pixel has a stream of its own, and the
+    /// seed mixes the global one with the number of the sample.
+    pub fn for_pixel(seed: u64, i: usize, j: usize, sample: u32) -> Self {
+        let stream = (i as u64) << 32 | j as u64;
+        Self::new(mix(seed ^ mix(sample as u64)), stream)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(Self::MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    /// Number from 0 to 1, with 1 excluded.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// Edge of the square blue noise mask, tiled over the image.
+const MASK_SIZE: usize = 64;
+
+/// Value of the blue noise mask from 0 to 1 at the pixel in the row `i`
+/// and the column `j`.
+pub fn blue_noise(i: u32, j: u32) -> f32 {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    let mask = MASK.get_or_init(void_and_cluster);
+    let (i, j) = (i as usize % MASK_SIZE, j as usize % MASK_SIZE);
+    mask[i * MASK_SIZE + j]
+}
+
+/// Blue noise mask made with Ulichney's void-and-cluster method: every
+/// pixel is ranked by the order in which it is added to a pattern, the
+/// next one always going into the largest void so far.
+fn void_and_cluster() -> Vec<f32> {
+    const N: usize = MASK_SIZE * MASK_SIZE;
+
+    // Gaussian weights for every offset, wrapping around the edges.
+    let sigma = 1.5;
+    let weights: Vec<f32> = (0 .. N)
+        .map(|k| {
+            let wrap = |d: usize| d.min(MASK_SIZE - d) as f32;
+            let (di, dj) = (wrap(k / MASK_SIZE), wrap(k % MASK_SIZE));
+            (-(di * di + dj * dj) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+
+    let mut pattern = vec![false; N];
+    let mut energy = vec![0.0; N];
+    let toggle = |pattern: &mut Vec<bool>, energy: &mut Vec<f32>, k: usize| {
+        pattern[k] = !pattern[k];
+        let sign = if pattern[k] { 1.0 } else { -1.0 };
+        let (i0, j0) = (k / MASK_SIZE, k % MASK_SIZE);
+        for (m, e) in energy.iter_mut().enumerate() {
+            let di = (m / MASK_SIZE + MASK_SIZE - i0) % MASK_SIZE;
+            let dj = (m % MASK_SIZE + MASK_SIZE - j0) % MASK_SIZE;
+            *e += sign * weights[di * MASK_SIZE + dj];
+        }
+    };
+    // Densest of the set pixels and emptiest of the clear ones.
+    let tightest = |pattern: &[bool], energy: &[f32]| {
+        (0 .. N).filter(|&k| pattern[k]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[f32]| {
+        (0 .. N).filter(|&k| !pattern[k]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // Start from a tenth of the pixels set at random and move the ones
+    // in the clusters to the voids till that changes nothing.
+    for k in 0 .. N {
+        if hash(k as u32, N as u32).is_multiple_of(10) {
+            toggle(&mut pattern, &mut energy, k);
+        }
+    }
+    loop {
+        let cluster = tightest(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; N];
+    let initial = (pattern.clone(), energy.clone());
+    let ones = pattern.iter().filter(|&&p| p).count();
+
+    // The initial pixels are ranked by taking the tightest clusters
+    // away, the rest by filling the largest voids.
+    for r in (0 .. ones).rev() {
+        let k = tightest(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, k);
+        rank[k] = r;
+    }
+    let (mut pattern, mut energy) = initial;
+    for r in ones .. N {
+        let k = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, k);
+        rank[k] = r;
+    }
+
+    rank.into_iter().map(|r| (r as f32 + 0.5) / N as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stratified_jitter() {
+        // Every cell of the 4 × 4 grid gets one of the first 16 samples,
+        // the 17th is plain random.
+        let mut sampler = RandomSampler::new(17, 3);
+        let mut cells = vec![0; 16];
+        for sample in 0 .. 16 {
+            sampler.start_pixel(5, 7, sample);
+            let (u, v) = sampler.next_2d();
+            cells[(4.0 * v) as usize * 4 + (4.0 * u) as usize] += 1;
+        }
+        assert_eq!(cells, vec![1; 16]);
+
+        // The same seed gives the same numbers.
+        let mut other = RandomSampler::new(17, 3);
+        sampler.start_pixel(5, 7, 16);
+        other.start_pixel(5, 7, 16);
+        assert_eq!(sampler.next_2d(), other.next_2d());
+        assert_eq!(sampler.next_1d(), other.next_1d());
+    }
+
+    #[test]
+    fn scrambled_digits_stay_stratified() {
+        // Whatever the scrambling, the first 27 numbers of base 3 fall
+        // one into every interval of a 27th.
+        for dimension in [1, 7] {
+            let mut intervals: Vec<usize> = (0 .. 27)
+                .map(|n| (27.0 * scrambled_radical_inverse(3, n, dimension)) as usize)
+                .collect();
+            intervals.sort_unstable();
+            assert_eq!(intervals, (0 .. 27).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn halton_sampler() {
+        // The shift of the pixel keeps the first dimension stratified
+        // modulo one: 8 samples are an eighth apart.
+        let mut sampler = HaltonSampler::new(false, 11);
+        let mut first: Vec<f32> = (0 .. 8)
+            .map(|sample| {
+                sampler.start_pixel(2, 3, sample);
+                sampler.next_1d()
+            })
+            .collect();
+        first.sort_by(f32::total_cmp);
+        let gaps: Vec<f32> = first.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(gaps.iter().all(|&gap| (gap - 0.125).abs() < 1E-3), "{:?}", gaps);
+
+        // Every dimension, the ones past the bases too, stays in [0, 1).
+        sampler.start_pixel(2, 3, 5);
+        assert!((0 .. 100).map(|_| sampler.next_1d()).all(|x| (0.0 .. 1.0).contains(&x)));
+    }
+
+    #[test]
+    fn blue_noise_mask() {
+        // Every rank is taken once, and the mask tiles the plane.
+        let mut values: Vec<f32> = (0 .. 64).flat_map(|i| (0 .. 64).map(move |j| blue_noise(i, j))).collect();
+        values.sort_by(f32::total_cmp);
+        assert!(values.iter().enumerate().all(|(k, &x)| x == (k as f32 + 0.5) / 4096.0));
+        assert_eq!(blue_noise(3, 5), blue_noise(3 + 64, 5 + 128));
+
+        // Neighbours differ more than white noise would have them, which
+        // keeps the averages of 2 × 2 blocks closer to a half: white noise
+        // would spread them with a variance of 1/48.
+        let blocks: Vec<f32> = (0 .. 32)
+            .flat_map(|i| (0 .. 32).map(move |j| (i, j)))
+            .map(|(i, j)| (0 .. 4).map(|k| blue_noise(2 * i + k / 2, 2 * j + k % 2)).sum::<f32>() / 4.0)
+            .collect();
+        let variance = blocks.iter().map(|&x| (x - 0.5) * (x - 0.5)).sum::<f32>() / blocks.len() as f32;
+        assert!(variance < 0.5 / 48.0, "{}", variance);
+    }
+
+    #[test]
+    fn blue_noise_shifts_wrap() {
+        // The offsets of some seeds and dimensions are near the top of
+        // the range, and far pixels are added to them.
+        for seed in 0 .. 64 {
+            let mut sampler = HaltonSampler::new(true, seed);
+            sampler.start_pixel(u32::MAX as usize, u32::MAX as usize - 1, 0);
+            assert!((0 .. 8).map(|_| sampler.next_1d()).all(|x| (0.0 .. 1.0).contains(&x)));
+        }
+    }
+}
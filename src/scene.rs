@@ -0,0 +1,1539 @@
+//! Scene description files.
+//!
+//! A scene is a TOML file listing the render settings, the camera, the
+//! background, named textures and materials, the objects and the
+//! lights. Everything but the objects has sensible defaults:
+//!
+//! ```toml
+//! [render]
+//! width = 800
+//! height = 400
+//! samples_per_pixel = 100
+//! max_depth = 10
+//! integrator = "bdpt"
+//! photons = 200000
+//! ao_distance = 0.5
+//! aovs = true
+//! denoise = true
+//! transparent = true
+//! threshold = 0.01
+//! sampler = "halton"
+//! blue_noise = true
+//! seed = 42
+//! filter = "mitchell"
+//! max_radiance = 10.0
+//! tone_map = "aces"
+//! exposure = -1.0
+//! accelerator = "kdtree"
+//! bvh = "median"
+//!
+//! [camera]
+//! origin = [0.0, 1.0, 3.0]
+//! look_at = [0.0, 0.0, -1.0]
+//! vfov = 40.0
+//!
+//! [background]
+//! type = "solid"
+//! color = [0.0, 0.0, 0.0]
+//!
+//! [textures.floor]
+//! type = "checker"
+//! even = [0.9, 0.9, 0.9]
+//! odd = [0.2, 0.3, 0.1]
+//!
+//! [materials.ground]
+//! type = "lambertian"
+//! albedo = "floor"
+//!
+//! [materials.glass]
+//! type = "dielectric"
+//! refractive_index = 1.5
+//!
+//! [[objects]]
+//! type = "plane"
+//! point = [0.0, -0.5, 0.0]
+//! normal = [0.0, 1.0, 0.0]
+//! material = "ground"
+//!
+//! [[objects]]
+//! type = "sphere"
+//! center = [0.0, 0.0, -1.0]
+//! radius = 0.5
+//! material = "glass"
+//!
+//! [[lights]]
+//! type = "point"
+//! position = [2.0, 3.0, 1.0]
+//! intensity = [5.0, 5.0, 5.0]
+//! ```
+//!
+//! Wherever a texture is expected, either a texture name or a plain
+//! `[r, g, b]` color can be given. Relative file paths are resolved
+//! against the directory of the scene file. Models can be brought in
+//! from OBJ (`type = "mesh"`), STL (`type = "stl"`) and PLY
+//! (`type = "ply"`) files with a material of the scene, optional for
+//! the latter two, or from glTF (`type = "gltf"`) and MagicaVoxel
+//! (`type = "vox"`) files with their own materials. The normals of the
+//! models are interpolated over their faces, and models without any
+//! look faceted, unless they are made `smooth`: then every vertex gets
+//! the average normal of the faces around it. A file loaded several
+//! times over with the same material and smoothing is loaded once, its
+//! copies placed by their transforms sharing the one mesh.
+//!
+//! A `box` along the axes spans from its `min` corner to its `max` one;
+//! turned by a `transform`, it can stand any way. Each of its faces has
+//! texture coordinates of its own, from 0 to 1 across it.
+//!
+//! A `cylinder` of the `radius` goes from the center of its `base` to
+//! that of its `top`, and a `cone` of the `radius` from the center of
+//! its `base` to its `apex`. Their ends are closed by disks, unless
+//! `caps = false` for a tube or `cap = false` for a hollow cone. The u
+//! texture coordinate of their sides goes around the axis and v up it.
+//!
+//! A `heightfield` is terrain over the unit square from the origin to
+//! (1, 0, 1), as high as the grayscale image at its `path` is bright,
+//! from 0 for black to 1 for white, the top of the image at z = 0. A
+//! `noise_heightfield` is made of hills of turbulent noise instead, a
+//! grid of `resolution` by `resolution` samples (256 by default) with
+//! `frequency` features across and `octaves` layers of detail. Either is
+//! sized and placed by its `transform`.
+//!
+//! An `sdf` object is the surface of a signed distance field, found by
+//! sphere tracing. Its `shape` is a `sphere` (`center`, `radius`), a
+//! `box` (`center`, `size`, edges rounded by `rounding`), a `torus` in
+//! the xz plane (`center`, `major_radius`, `minor_radius`), a fractal,
+//! or the `union` or `intersection` of a list of `shapes`, or the `difference`
+//! of a `shape` and the shapes it is `minus`. The operators blend their
+//! shapes over a `smoothness` distance, so that a union of spheres melts
+//! into one blob:
+//!
+//! ```toml
+//! [[objects]]
+//! type = "sdf"
+//! material = "clay"
+//! shape = { type = "union", smoothness = 0.3, shapes = [
+//!     { type = "sphere", center = [0.0, 0.0, 0.0], radius = 0.5 },
+//!     { type = "torus", center = [0.0, -0.3, 0.0], major_radius = 0.6, minor_radius = 0.1 }
+//! ] }
+//! ```
+//!
+//! The fractals are the `mandelbulb` of a `power` (8 by default), about
+//! the y axis, and the `julia` set of the quaternion `c = [w, x, y, z]`,
+//! sliced through the 4D set at `slice` (0 by default), both of radius
+//! about `scale` (1 by default) around the `center` (the origin by
+//! default). Their distances are estimated by iterating a point up to
+//! `iterations` times (12 by default), more of which bring out finer
+//! detail, till it escapes past the `bailout` (4 by default).
+//!
+//! `metaballs` are the blobby surface where the Gaussians of the
+//! `balls`, each with a `center`, a `radius` and a `weight` (1 by
+//! default), add up to the `threshold` (0.5 by default): a lone ball is
+//! a sphere somewhat smaller than its radius, and balls close together
+//! melt into one.
+//!
+//! A scene can also be a script with the `.rhai` extension, which
+//! builds these tables by a program instead, such as a grid of spheres;
+//! see the `script` module of the loaders.
+//!
+//! The `projection` of the camera is `perspective` (the default),
+//! `cubemap`, six views along the axes of the world side by side in an
+//! image six times as wide as it is high, `equirectangular`, a 360°
+//! panorama twice as wide as it is high, or `equidistant` or
+//! `equisolid`, fisheye lenses seeing `fisheye_fov` degrees (180 by
+//! default) across the height of the image, or `orthographic`, parallel
+//! rays from a rectangle `view_height` high, by default as high as the
+//! field of view is at the focus distance, or `realistic`, a lens of
+//! real elements, a 50 mm double Gauss unless given as `lens = [[radius,
+//! thickness, ior, aperture], ...]` from the front to the back in
+//! millimeters, before a film `sensor_height` millimeters high (24 by
+//! default); see `Projection` and the `lens` module. With an
+//! `eye_separation`, the left half of the image is seen by the left eye
+//! and the right half by the right one, both converging at the focus
+//! distance, and with `split_eyes` under `[render]`, the halves are saved
+//! to files of their own.
+//!
+//! The `integrator` is one of `path` (the default), tracing paths from
+//! the camera, `bdpt`, joining them with paths from the lights, `mlt`,
+//! exploring the paths from the camera with Markov chains, `direct`,
+//! leaving out the light of the bounces off diffuse surfaces, `ao`,
+//! shading the surfaces by how much the objects within `ao_distance`
+//! (1 by default) hide them, and `normals`, `depth` and `uvs`, showing
+//! the shading normals, the distances and the texture coordinates of
+//! the surfaces as colors; see the `integrator` module. With `photons`,
+//! that many photons are shot from the lights to find the caustics, see
+//! the `photon` module. With `aovs`, the albedo, the normals and the
+//! distances of what the camera sees are saved next to the image, and
+//! with `denoise`, they guide a filter smoothing out the noise of the
+//! image, see the `denoise` module. With `transparent`, the background
+//! is left out of the image and its alpha channel is saved too, though
+//! it still lights the scene.
+//!
+//! With a `threshold`, sampling is adaptive: a pixel stops getting
+//! samples once the error of its average drops below that fraction of
+//! it, and `samples_per_pixel` is the most any pixel gets. The
+//! `sampler` is either `random` (the default) or `halton`, see the
+//! `sampler` module, and `blue_noise` shifts the Halton sequence of
+//! every pixel by a blue noise mask. The same `seed` renders the same
+//! image every time. The reconstruction `filter` is one of `box` (the
+//! default), `tent`, `gaussian` and `mitchell`. With `max_radiance`,
+//! no sample is brighter than that, which keeps the rare bright ones
+//! from showing as speckles at the cost of a little energy. The
+//! `tone_map` of the image shown and saved to PNG files is `clamp` (the
+//! default), `reinhard` or `aces`, see the `tonemap` module, and the
+//! image is made `exposure` stops brighter before it, or darker if it
+//! is negative. What the rays hit is found with bounding volume
+//! hierarchies over the objects and the triangles of the meshes
+//! (`accelerator = "bvh"`, the default) or with kd-trees (`accelerator
+//! = "kdtree"`), see `Accelerator`. The nodes of the BVHs are split by
+//! the surface area heuristic (`bvh = "sah"`, the default), or at the
+//! median (`bvh = "median"`), quicker to build but slower to trace.
+//!
+//! With any of `iso`, `shutter_speed` (in seconds) and `f_stop` under
+//! `[camera]`, the image is exposed as a physical camera with these
+//! settings would, the rest of them going by the "sunny 16" rule (ISO
+//! 100, 1/100 s and f/16), for the light in physical units: the
+//! luminance of 1.2 · 2^EV100 saturates the film, see `Exposure`. The
+//! `exposure` of the render settings adds to that. The f-stop only
+//! tells the brightness; the depth of field is still the `aperture`'s.
+//!
+//! With more than one of `frames` under `[animation]`, the scene is an
+//! animation played at `fps` frames a second (24 by default), the frames
+//! counted from 1. The camera moves through the poses of its
+//! `[[camera.keyframes]]`, each at a `frame` and with any of `origin`,
+//! `look_at`, `up` and `vfov`, the rest as in `[camera]`. An object moves
+//! through the placements of its `[[objects.keyframes]]`, each at a
+//! `frame` and with any of `translate`, `rotate` and `scale` as in a
+//! `transform`, which they take the place of. Between two key frames,
+//! everything is interpolated: at a steady pace by default, or starting
+//! and stopping gently with `easing = "smooth"`. Before the first key
+//! frame and after the last one, it stays put.
+//!
+//! For a fly-through, `[camera.path]` takes the camera along a `spline`
+//! through its `points`: `catmull_rom` (the default) goes through all of
+//! them, `bezier` joins cubic Bézier curves, 3n + 1 points of which the
+//! first, every third and the last are on the path. It looks at the
+//! points of the same kind of spline along the `targets`, or at its
+//! `look_at` if there are none. The path is flown from the `start` frame
+//! (1 by default) to the `end` one (the last by default), eased as the
+//! key frames are, and wins over them for the origin and the target.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use crate::animation::{interpolate, Animation, Easing, Spline};
+use crate::background::{Background, EnvironmentMap};
+use crate::camera::{Camera, Exposure, FisheyeMapping, Projection, FISHEYE_FOV};
+use crate::filter::Filter;
+use crate::geometry::{
+    AcceleratorKind, Cone, ConstantMedium, Cuboid, Cylinder, DistanceField, Heightfield,
+    HeterogeneousMedium, Hittable, Instance, Julia, Mandelbulb, Mesh, Metaball, Metaballs,
+    MovingSphere, NoiseDensity, Plane, Quad, Sdf, SdfBox, SdfSphere, SdfTorus, SmoothDifference,
+    SmoothIntersection, SmoothUnion, Sphere, Split, Triangle, World
+};
+use crate::integrator::IntegratorKind;
+use crate::lens::{Lens, LensElement, SENSOR_HEIGHT};
+use crate::light::{AreaLight, Light, PointLight, SphereLight, TriangleLight};
+use crate::loaders::gltf::load_gltf;
+use crate::loaders::mitsuba::load_mitsuba;
+use crate::loaders::obj::load_obj;
+use crate::loaders::pbrt::load_pbrt;
+use crate::loaders::ply::load_ply;
+use crate::loaders::script::run_script;
+use crate::loaders::stl::load_stl;
+use crate::loaders::vox::load_vox;
+use crate::loaders::LoadError;
+use crate::material::{
+    BumpMapped, Dielectric, Emissive, Isotropic, Lambertian, Material, Metal, NormalMapped, Pbr,
+    Principled
+};
+use crate::math::{Quaternion, Transform, Vector};
+use crate::perlin::Perlin;
+use crate::photon::PhotonMap;
+use crate::render::Settings;
+use crate::sampler::SamplerKind;
+use crate::texture::{
+    Checker, CheckerSpace, ImageTexture, LiveColor, MarbleTexture, NoiseTexture, SolidColor, Stripes,
+    Texture
+};
+use crate::tonemap::ToneMap;
+
+/// Everything needed to render an image.
+pub struct Scene {
+    pub settings: Settings,
+    pub camera: Camera,
+    pub world: World,
+    pub colors: Vec<(String, Arc<LiveColor>)>, // Plain colors of the materials, by material name
+    pub animation: Animation
+}
+
+impl Scene {
+    /// Load a scene file, telling PBRT and Mitsuba scenes and scripts
+    /// from TOML ones by the extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        Self::load_frame(path, 1)
+    }
+
+    /// Load the scene as it is at the `frame` of its animation, counting
+    /// from 1. PBRT and Mitsuba scenes are not animated.
+    pub fn load_frame<P: AsRef<Path>>(path: P, frame: u32) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pbrt") => return load_pbrt(path),
+            Some("xml") => return load_mitsuba(path),
+            _ => {}
+        }
+
+        let source = fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("rhai") => Self::parse_script(&source, base, frame),
+            _ => Self::parse_frame(&source, base, frame)
+        }
+    }
+
+    /// Build a scene from the TOML source, resolving the relative paths
+    /// against `base`.
+    pub fn parse(source: &str, base: &Path) -> Result<Self, LoadError> {
+        Self::parse_frame(source, base, 1)
+    }
+
+    /// Build the scene as it is at the `frame` of its animation from the
+    /// TOML source.
+    pub fn parse_frame(source: &str, base: &Path, frame: u32) -> Result<Self, LoadError> {
+        let file: SceneFile = toml::from_str(source)
+            .map_err(|err| LoadError::invalid(err.to_string()))?;
+        Builder::new(&file, base, frame as f32).build()
+    }
+
+    /// Build the scene the script `source` makes for the `frame` of its
+    /// animation, see the `script` module.
+    pub fn parse_script(source: &str, base: &Path, frame: u32) -> Result<Self, LoadError> {
+        let file: SceneFile = toml::Value::Table(run_script(source, frame)?)
+            .try_into()
+            .map_err(|err: toml::de::Error| LoadError::invalid(err.to_string()))?;
+        Builder::new(&file, base, frame as f32).build()
+    }
+}
+
+type Vec3 = [f32; 3];
+
+fn vector(v: Vec3) -> Vector {
+    Vector{ x: v[0], y: v[1], z: v[2] }
+}
+
+fn one() -> f32 { 1.0 }
+fn yes() -> bool { true }
+
+/// Setting given by its name, the one the command line takes.
+fn named<'de, D: Deserializer<'de>, T: FromStr<Err = String>>(deserializer: D) -> Result<T, D::Error> {
+    String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+}
+
+/// Key frames in the order of the frames.
+fn sorted<T>(mut keys: Vec<(f32, T)>) -> Vec<(f32, T)> {
+    keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+    keys
+}
+fn white() -> Vec3 { [1.0, 1.0, 1.0] }
+
+#[derive(Deserialize)]
+struct SceneFile {
+    #[serde(default)]
+    render: RenderConfig,
+    #[serde(default)]
+    camera: CameraConfig,
+    #[serde(default)]
+    background: BackgroundConfig,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureConfig>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialConfig>,
+    #[serde(default)]
+    objects: Vec<ObjectConfig>,
+    #[serde(default)]
+    lights: Vec<LightConfig>,
+    #[serde(default)]
+    animation: AnimationConfig
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RenderConfig {
+    width: usize,
+    height: usize,
+    samples_per_pixel: u32,
+    max_depth: u8,
+    #[serde(deserialize_with = "named")]
+    integrator: IntegratorKind,
+    photons: u32,
+    ao_distance: f32,
+    aovs: bool,
+    denoise: bool,
+    transparent: bool,
+    split_eyes: bool,
+    threshold: Option<f32>,
+    #[serde(deserialize_with = "named")]
+    sampler: SamplerKind,
+    blue_noise: bool,
+    seed: u64,
+    #[serde(deserialize_with = "named")]
+    filter: Filter,
+    max_radiance: Option<f32>,
+    #[serde(deserialize_with = "named")]
+    tone_map: ToneMap,
+    exposure: f32,
+    #[serde(deserialize_with = "named")]
+    accelerator: AcceleratorKind,
+    #[serde(deserialize_with = "named")]
+    bvh: Split
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        let settings = Settings::default();
+        Self {
+            width: settings.width,
+            height: settings.height,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            integrator: settings.integrator,
+            photons: settings.photons,
+            ao_distance: settings.ao_distance,
+            aovs: settings.aovs,
+            denoise: settings.denoise,
+            transparent: settings.transparent,
+            split_eyes: settings.split_eyes,
+            threshold: settings.threshold,
+            sampler: settings.sampler,
+            blue_noise: settings.blue_noise,
+            seed: settings.seed,
+            filter: settings.filter,
+            max_radiance: settings.max_radiance,
+            tone_map: settings.tone_map,
+            exposure: settings.exposure,
+            accelerator: settings.accelerator,
+            bvh: settings.bvh
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CameraConfig {
+    origin: Vec3,
+    look_at: Vec3,
+    up: Vec3,
+    vfov: f32,
+    aperture: f32,
+    focus_distance: Option<f32>,
+    shutter: [f32; 2],
+    projection: ProjectionConfig,
+    fisheye_fov: f32,
+    view_height: Option<f32>,
+    eye_separation: f32,
+    lens: Option<Vec<[f32; 4]>>,
+    sensor_height: f32,
+    iso: Option<f32>,
+    shutter_speed: Option<f32>,
+    f_stop: Option<f32>,
+    keyframes: Vec<CameraKeyframe>,
+    path: Option<CameraPath>
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            origin: [0.0, 0.0, 0.0],
+            look_at: [0.0, 0.0, -1.0],
+            up: [0.0, 1.0, 0.0],
+            vfov: 90.0,
+            aperture: 0.0,
+            focus_distance: None,
+            shutter: [0.0, 0.0],
+            projection: ProjectionConfig::default(),
+            fisheye_fov: FISHEYE_FOV,
+            view_height: None,
+            eye_separation: 0.0,
+            lens: None,
+            sensor_height: SENSOR_HEIGHT,
+            iso: None,
+            shutter_speed: None,
+            f_stop: None,
+            keyframes: vec![],
+            path: None
+        }
+    }
+}
+
+/// Pose of the camera at a key frame, the one of `[camera]` for what is
+/// left out.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraKeyframe {
+    frame: f32,
+    origin: Option<Vec3>,
+    look_at: Option<Vec3>,
+    up: Option<Vec3>,
+    vfov: Option<f32>
+}
+
+/// Spline the camera flies along from the `start` frame to the `end`
+/// one, looking at the points of another spline along the `targets` if
+/// given.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraPath {
+    #[serde(default)]
+    spline: SplineConfig,
+    points: Vec<Vec3>,
+    #[serde(default)]
+    targets: Vec<Vec3>,
+    #[serde(default = "one")]
+    start: f32,
+    end: Option<f32> // The last frame of the animation if not given
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum SplineConfig {
+    #[default]
+    CatmullRom,
+    Bezier
+}
+
+/// Where the camera is, where it looks and how far it sees.
+#[derive(Clone)]
+struct CameraPose {
+    origin: Vector,
+    look_at: Vector,
+    up: Vector,
+    vfov: f32
+}
+
+impl CameraPose {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            origin: self.origin + t * (other.origin - self.origin),
+            look_at: self.look_at + t * (other.look_at - self.look_at),
+            up: self.up + t * (other.up - self.up),
+            vfov: self.vfov + t * (other.vfov - self.vfov)
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum ProjectionConfig {
+    #[default]
+    Perspective,
+    Cubemap,
+    Equirectangular,
+    Equidistant,
+    Equisolid,
+    Orthographic,
+    Realistic
+}
+
+#[derive(Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundConfig {
+    #[default]
+    Sky,
+    Solid { color: Vec3 },
+    Environment { path: PathBuf }
+}
+
+/// Either a name of a texture or a plain color.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum TextureRef {
+    Color(Vec3),
+    Name(String)
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum CheckerSpaceConfig {
+    #[default]
+    World,
+    Uv
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureConfig {
+    Solid { color: Vec3 },
+    Image { path: PathBuf },
+    Checker {
+        even: TextureRef,
+        odd: TextureRef,
+        #[serde(default = "one")]
+        size: f32,
+        #[serde(default)]
+        space: CheckerSpaceConfig
+    },
+    Stripes {
+        even: TextureRef,
+        odd: TextureRef,
+        axis: Vec3,
+        #[serde(default = "one")]
+        width: f32
+    },
+    Noise {
+        #[serde(default = "white")]
+        color: Vec3,
+        #[serde(default = "one")]
+        frequency: f32,
+        #[serde(default = "default_octaves")]
+        octaves: usize
+    },
+    Marble {
+        #[serde(default = "white")]
+        color: Vec3,
+        #[serde(default = "one")]
+        frequency: f32,
+        #[serde(default = "default_octaves")]
+        octaves: usize,
+        #[serde(default = "default_turbulence")]
+        turbulence: f32
+    }
+}
+
+fn default_octaves() -> usize { 7 }
+fn default_resolution() -> usize { 256 }
+fn default_power() -> f32 { 8.0 }
+fn default_iterations() -> usize { 12 }
+fn default_bailout() -> f32 { 4.0 }
+fn default_threshold() -> f32 { 0.5 }
+fn default_turbulence() -> f32 { 10.0 }
+
+#[derive(Deserialize)]
+struct MaterialConfig {
+    #[serde(flatten)]
+    kind: MaterialKind,
+    normal_map: Option<PathBuf>,
+    bump_map: Option<PathBuf>,
+    #[serde(default = "one")]
+    bump_strength: f32
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MaterialKind {
+    Lambertian { albedo: TextureRef },
+    Metal {
+        albedo: Vec3,
+        #[serde(default)]
+        fuzz: f32
+    },
+    Dielectric { refractive_index: f32 },
+    Emissive { radiance: Vec3 },
+    Isotropic { albedo: TextureRef },
+    Pbr {
+        base_color: TextureRef,
+        #[serde(default)]
+        metallic: f32,
+        #[serde(default = "default_roughness")]
+        roughness: f32
+    },
+    Principled {
+        base_color: TextureRef,
+        metallic: Option<f32>,
+        roughness: Option<f32>,
+        specular: Option<f32>,
+        specular_tint: Option<f32>,
+        sheen: Option<f32>,
+        sheen_tint: Option<f32>,
+        clearcoat: Option<f32>,
+        clearcoat_gloss: Option<f32>
+    }
+}
+
+fn default_roughness() -> f32 { 0.5 }
+
+#[derive(Deserialize)]
+struct ObjectConfig {
+    #[serde(flatten)]
+    shape: ShapeConfig,
+    transform: Option<TransformConfig>,
+    #[serde(default)]
+    keyframes: Vec<ObjectKeyframe>
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ShapeConfig {
+    Sphere { center: Vec3, radius: f32, material: String },
+    MovingSphere {
+        center0: Vec3,
+        center1: Vec3,
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "one")]
+        time1: f32,
+        radius: f32,
+        material: String
+    },
+    Plane { point: Vec3, normal: Vec3, material: String },
+    Quad { corner: Vec3, u: Vec3, v: Vec3, material: String },
+    Triangle { a: Vec3, b: Vec3, c: Vec3, material: String },
+    Box { min: Vec3, max: Vec3, material: String },
+    Cylinder {
+        base: Vec3,
+        top: Vec3,
+        radius: f32,
+        #[serde(default = "yes")]
+        caps: bool,
+        material: String
+    },
+    Cone {
+        base: Vec3,
+        apex: Vec3,
+        radius: f32,
+        #[serde(default = "yes")]
+        cap: bool,
+        material: String
+    },
+    Mesh {
+        path: PathBuf,
+        material: String,
+        #[serde(default)]
+        smooth: bool
+    },
+    Gltf { path: PathBuf },
+    Stl {
+        path: PathBuf,
+        material: Option<String>,
+        #[serde(default)]
+        smooth: bool
+    },
+    Ply {
+        path: PathBuf,
+        material: Option<String>,
+        #[serde(default)]
+        smooth: bool
+    },
+    Vox { path: PathBuf },
+    Heightfield { path: PathBuf, material: String },
+    NoiseHeightfield {
+        #[serde(default = "default_resolution")]
+        resolution: usize,
+        #[serde(default = "one")]
+        frequency: f32,
+        #[serde(default = "default_octaves")]
+        octaves: usize,
+        material: String
+    },
+    Sdf { shape: SdfConfig, material: String },
+    ConstantMedium { boundary: Box<ObjectConfig>, density: f32, albedo: TextureRef },
+    NoiseMedium {
+        boundary: Box<ObjectConfig>,
+        density: f32,
+        #[serde(default = "one")]
+        frequency: f32,
+        #[serde(default = "default_octaves")]
+        octaves: usize,
+        albedo: TextureRef
+    }
+}
+
+/// Shape of an `sdf` object, built up from primitives.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SdfConfig {
+    Sphere { center: Vec3, radius: f32 },
+    Box {
+        center: Vec3,
+        size: Vec3,
+        #[serde(default)]
+        rounding: f32
+    },
+    Torus { center: Vec3, major_radius: f32, minor_radius: f32 },
+    Mandelbulb {
+        #[serde(default)]
+        center: Vec3,
+        #[serde(default = "one")]
+        scale: f32,
+        #[serde(default = "default_power")]
+        power: f32,
+        #[serde(default = "default_iterations")]
+        iterations: usize,
+        #[serde(default = "default_bailout")]
+        bailout: f32
+    },
+    Julia {
+        #[serde(default)]
+        center: Vec3,
+        #[serde(default = "one")]
+        scale: f32,
+        c: [f32; 4],
+        #[serde(default)]
+        slice: f32,
+        #[serde(default = "default_iterations")]
+        iterations: usize,
+        #[serde(default = "default_bailout")]
+        bailout: f32
+    },
+    Metaballs {
+        balls: Vec<MetaballConfig>,
+        #[serde(default = "default_threshold")]
+        threshold: f32
+    },
+    Union {
+        shapes: Vec<SdfConfig>,
+        #[serde(default)]
+        smoothness: f32
+    },
+    Intersection {
+        shapes: Vec<SdfConfig>,
+        #[serde(default)]
+        smoothness: f32
+    },
+    Difference {
+        shape: Box<SdfConfig>,
+        minus: Vec<SdfConfig>,
+        #[serde(default)]
+        smoothness: f32
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MetaballConfig {
+    center: Vec3,
+    radius: f32,
+    #[serde(default = "one")]
+    weight: f32
+}
+
+impl SdfConfig {
+    fn field(&self) -> Result<Arc<dyn DistanceField>, LoadError> {
+        Ok(match self {
+            SdfConfig::Sphere { center, radius } if *radius > 0.0 => {
+                Arc::new(SdfSphere { center: vector(*center), radius: *radius })
+            },
+            SdfConfig::Box { center, size, rounding } if size.iter().all(|&s| s > 0.0) => {
+                let half_size = 0.5 * vector(*size);
+                Arc::new(SdfBox { center: vector(*center), half_size, rounding: rounding.clamp(0.0, half_size.x.min(half_size.y).min(half_size.z)) })
+            },
+            SdfConfig::Torus { center, major_radius, minor_radius } if *minor_radius > 0.0 => {
+                Arc::new(SdfTorus { center: vector(*center), major: *major_radius, minor: *minor_radius })
+            },
+            SdfConfig::Mandelbulb { center, scale, power, iterations, bailout } if *scale > 0.0 => {
+                Arc::new(Mandelbulb {
+                    center: vector(*center),
+                    scale: *scale,
+                    power: *power,
+                    iterations: *iterations,
+                    bailout: *bailout
+                })
+            },
+            SdfConfig::Julia { center, scale, c: [w, x, y, z], slice, iterations, bailout } if *scale > 0.0 => {
+                Arc::new(Julia {
+                    center: vector(*center),
+                    scale: *scale,
+                    c: Quaternion { w: *w, x: *x, y: *y, z: *z },
+                    slice: *slice,
+                    iterations: *iterations,
+                    bailout: *bailout
+                })
+            },
+            SdfConfig::Sphere { .. } | SdfConfig::Box { .. } | SdfConfig::Torus { .. } => {
+                return Err(LoadError::invalid("sdf primitives need a positive radius and size"))
+            },
+            SdfConfig::Mandelbulb { .. } | SdfConfig::Julia { .. } => {
+                return Err(LoadError::invalid("fractals need a positive scale"))
+            },
+            SdfConfig::Metaballs { balls, threshold } => {
+                if *threshold <= 0.0 || balls.iter().any(|ball| ball.radius <= 0.0 || ball.weight <= 0.0) {
+                    return Err(LoadError::invalid("metaballs need a positive threshold, radii and weights"));
+                }
+                let balls = balls.iter()
+                    .map(|ball| Metaball { center: vector(ball.center), radius: ball.radius, weight: ball.weight })
+                    .collect();
+                Arc::new(Metaballs { balls, threshold: *threshold })
+            },
+            SdfConfig::Union { shapes, smoothness } => {
+                let smoothness = *smoothness;
+                fold_fields(shapes, |a, b| Arc::new(SmoothUnion { a, b, smoothness }))?
+            },
+            SdfConfig::Intersection { shapes, smoothness } => {
+                let smoothness = *smoothness;
+                fold_fields(shapes, |a, b| Arc::new(SmoothIntersection { a, b, smoothness }))?
+            },
+            SdfConfig::Difference { shape, minus, smoothness } => {
+                let smoothness = *smoothness;
+                let mut field = shape.field()?;
+                for b in minus {
+                    field = Arc::new(SmoothDifference { a: field, b: b.field()?, smoothness });
+                }
+                field
+            }
+        })
+    }
+}
+
+/// Fields of the shapes joined pairwise by the operator, from left to
+/// right.
+fn fold_fields(
+    shapes: &[SdfConfig],
+    op: impl Fn(Arc<dyn DistanceField>, Arc<dyn DistanceField>) -> Arc<dyn DistanceField>
+) -> Result<Arc<dyn DistanceField>, LoadError> {
+    let (first, rest) = shapes.split_first()
+        .ok_or_else(|| LoadError::invalid("sdf operators need at least one shape"))?;
+    let mut field = first.field()?;
+    for shape in rest {
+        field = op(field, shape.field()?);
+    }
+    Ok(field)
+}
+
+/// The mesh with normals averaged over the faces around its vertices if
+/// it is to be `smooth` and has none of its own.
+fn smoothed(mut mesh: Mesh, smooth: bool) -> Mesh {
+    if smooth && mesh.normals.is_empty() {
+        mesh.smooth_normals();
+    }
+    mesh
+}
+
+/// Placement of an object: scaled first, rotated next and moved last.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct TransformConfig {
+    translate: Option<Vec3>,
+    rotate: Option<RotationConfig>,
+    scale: Option<ScaleConfig>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationConfig {
+    axis: Vec3,
+    angle: f32 // Degrees
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleConfig {
+    Uniform(f32),
+    Axes(Vec3)
+}
+
+impl TransformConfig {
+    fn transform(&self) -> Transform {
+        let mut transform = Transform::IDENTITY;
+
+        if let Some(scale) = &self.scale {
+            let factor = match scale {
+                ScaleConfig::Uniform(s) => [*s, *s, *s],
+                ScaleConfig::Axes(v) => *v
+            };
+            transform = Transform::scale(vector(factor)) * transform;
+        }
+
+        if let Some(rotation) = &self.rotate {
+            transform = Transform::rotate(vector(rotation.axis), rotation.angle.to_radians()) * transform;
+        }
+
+        if let Some(offset) = self.translate {
+            transform = Transform::translate(vector(offset)) * transform;
+        }
+
+        transform
+    }
+}
+
+/// Placement of an object at a key frame, in place of its `transform`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectKeyframe {
+    frame: f32,
+    translate: Option<Vec3>,
+    rotate: Option<RotationConfig>,
+    scale: Option<ScaleConfig>
+}
+
+/// Placement of an object in the parts that interpolate: scaled first,
+/// turned by `angle` radians about the `axis` next and moved last.
+#[derive(Clone)]
+struct ObjectPose {
+    translate: Vector,
+    axis: Vector,
+    angle: f32,
+    scale: Vector
+}
+
+impl ObjectPose {
+    fn new(keyframe: &ObjectKeyframe) -> Self {
+        let (axis, angle) = match &keyframe.rotate {
+            Some(rotation) => (vector(rotation.axis).unit(), rotation.angle.to_radians()),
+            None => (Vector{ x: 0.0, y: 1.0, z: 0.0 }, 0.0)
+        };
+        let scale = match keyframe.scale {
+            Some(ScaleConfig::Uniform(s)) => [s, s, s],
+            Some(ScaleConfig::Axes(v)) => v,
+            None => [1.0, 1.0, 1.0]
+        };
+        Self { translate: vector(keyframe.translate.unwrap_or([0.0, 0.0, 0.0])), axis, angle, scale: vector(scale) }
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        // About the same axis, the angle itself is interpolated, so that
+        // an object can turn all the way round between two key frames.
+        // About different axes, the rotation takes the shortest way.
+        let (axis, angle) = if self.angle == 0.0 || other.angle == 0.0 || (self.axis - other.axis).is_near_zero() {
+            let axis = if self.angle == 0.0 { other.axis } else { self.axis };
+            (axis, self.angle + t * (other.angle - self.angle))
+        } else {
+            let q = Quaternion::from_axis_angle(self.axis, self.angle)
+                .slerp(Quaternion::from_axis_angle(other.axis, other.angle), t);
+            let axis = Vector{ x: q.x, y: q.y, z: q.z };
+            if axis.is_near_zero() {
+                (self.axis, 0.0)
+            } else {
+                (axis.unit(), 2.0 * q.w.clamp(-1.0, 1.0).acos())
+            }
+        };
+
+        Self {
+            translate: self.translate + t * (other.translate - self.translate),
+            axis,
+            angle,
+            scale: self.scale + t * (other.scale - self.scale)
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        Transform::translate(self.translate) * Transform::rotate(self.axis, self.angle) * Transform::scale(self.scale)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AnimationConfig {
+    frames: u32,
+    fps: f32,
+    easing: EasingConfig
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        let animation = Animation::default();
+        Self { frames: animation.frames, fps: animation.fps, easing: EasingConfig::default() }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum EasingConfig {
+    #[default]
+    Linear,
+    Smooth
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightConfig {
+    Point { position: Vec3, intensity: Vec3 },
+    Area { corner: Vec3, u: Vec3, v: Vec3, radiance: Vec3 }
+}
+
+/// Turns the parsed file into the actual scene, resolving the names of
+/// the textures and the materials and sharing them between their users.
+struct Builder<'a> {
+    file: &'a SceneFile,
+    base: &'a Path,
+    frame: f32,
+    easing: Easing,
+    noise: Arc<Perlin>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    meshes: HashMap<(PathBuf, Option<String>, bool), Arc<Mesh>>, // By the path, the material and the smoothing
+    colors: Vec<(String, Arc<LiveColor>)>,
+    resolving: HashSet<String>
+}
+
+impl<'a> Builder<'a> {
+    fn new(file: &'a SceneFile, base: &'a Path, frame: f32) -> Self {
+        Self {
+            file,
+            base,
+            frame,
+            easing: match file.animation.easing {
+                EasingConfig::Linear => Easing::Linear,
+                EasingConfig::Smooth => Easing::Smooth
+            },
+            noise: Arc::new(Perlin::new()),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+            colors: vec![],
+            resolving: HashSet::new()
+        }
+    }
+
+    fn path(&self, path: &Path) -> PathBuf {
+        self.base.join(path)
+    }
+
+    /// Mesh loaded from the file at the path of the key, the first time
+    /// it is asked for with the material and the smoothing of the key,
+    /// and shared ever after.
+    fn mesh(
+        &mut self,
+        key: (PathBuf, Option<String>, bool),
+        load: impl FnOnce(&Path) -> Result<Mesh, LoadError>
+    ) -> Result<Arc<Mesh>, LoadError> {
+        if let Some(mesh) = self.meshes.get(&key) {
+            return Ok(mesh.clone());
+        }
+
+        let mesh = Arc::new(smoothed(load(&key.0)?, key.2));
+        self.meshes.insert(key, mesh.clone());
+        Ok(mesh)
+    }
+
+    fn build(mut self) -> Result<Scene, LoadError> {
+        let file = self.file;
+
+        let render = &file.render;
+        if render.width < 2 || render.height < 2 {
+            return Err(LoadError::invalid("the image must be at least 2×2 pixels"));
+        }
+
+        let settings = Settings {
+            width: render.width,
+            height: render.height,
+            samples_per_pixel: render.samples_per_pixel,
+            max_depth: render.max_depth,
+            integrator: render.integrator,
+            photons: render.photons,
+            ao_distance: render.ao_distance,
+            aovs: render.aovs,
+            denoise: render.denoise,
+            transparent: render.transparent,
+            split_eyes: render.split_eyes,
+            threshold: render.threshold,
+            sampler: render.sampler,
+            blue_noise: render.blue_noise,
+            seed: render.seed,
+            filter: render.filter,
+            max_radiance: render.max_radiance,
+            tone_map: render.tone_map,
+            exposure: render.exposure,
+            accelerator: render.accelerator,
+            bvh: render.bvh
+        };
+
+        let c = &file.camera;
+        let pose = CameraPose { origin: vector(c.origin), look_at: vector(c.look_at), up: vector(c.up), vfov: c.vfov };
+        let keys: Vec<(f32, CameraPose)> = c.keyframes.iter()
+            .map(|k| (k.frame, CameraPose {
+                origin: k.origin.map_or(pose.origin, vector),
+                look_at: k.look_at.map_or(pose.look_at, vector),
+                up: k.up.map_or(pose.up, vector),
+                vfov: k.vfov.unwrap_or(pose.vfov)
+            }))
+            .collect();
+        let mut pose = interpolate(&sorted(keys), self.frame, self.easing, CameraPose::lerp).unwrap_or(pose);
+        if let Some(path) = &c.path {
+            let spline = match path.spline {
+                SplineConfig::CatmullRom => Spline::CatmullRom,
+                SplineConfig::Bezier => Spline::Bezier
+            };
+            let end = path.end.unwrap_or(file.animation.frames as f32);
+            let t = if end > path.start { ((self.frame - path.start) / (end - path.start)).clamp(0.0, 1.0) } else { 0.0 };
+            let t = self.easing.apply(t);
+
+            let points: Vec<Vector> = path.points.iter().copied().map(vector).collect();
+            spline.check(points.len()).map_err(|err| LoadError::invalid(format!("camera path: {}", err)))?;
+            pose.origin = spline.at(&points, t);
+            if !path.targets.is_empty() {
+                let targets: Vec<Vector> = path.targets.iter().copied().map(vector).collect();
+                spline.check(targets.len()).map_err(|err| LoadError::invalid(format!("camera targets: {}", err)))?;
+                pose.look_at = spline.at(&targets, t);
+            }
+        }
+        let focus_distance = c.focus_distance
+            .unwrap_or_else(|| (pose.look_at - pose.origin).norm());
+        let lens = match &c.lens {
+            Some(elements) => elements.iter()
+                .map(|&[radius, thickness, ior, aperture]| LensElement { radius, thickness, ior, aperture })
+                .collect(),
+            None => Lens::double_gauss().elements().to_vec()
+        };
+        let camera = Camera::new(pose.origin, pose.look_at, pose.up, pose.vfov, settings.aspect_ratio())
+            .with_lens(c.aperture, focus_distance)
+            .with_shutter(c.shutter[0], c.shutter[1])
+            .with_realistic_lens(Lens::new(&lens, c.sensor_height).map_err(LoadError::invalid)?)
+            .with_projection(match c.projection {
+                ProjectionConfig::Perspective => Projection::Perspective,
+                ProjectionConfig::Cubemap => Projection::Cubemap,
+                ProjectionConfig::Equirectangular => Projection::Equirectangular,
+                ProjectionConfig::Equidistant => {
+                    Projection::Fisheye { mapping: FisheyeMapping::Equidistant, fov: c.fisheye_fov }
+                },
+                ProjectionConfig::Equisolid => {
+                    Projection::Fisheye { mapping: FisheyeMapping::Equisolid, fov: c.fisheye_fov }
+                },
+                ProjectionConfig::Orthographic => Projection::Orthographic,
+                ProjectionConfig::Realistic => Projection::Realistic
+            });
+        let camera = match c.view_height {
+            Some(height) => camera.with_view_height(height),
+            None => camera
+        };
+        let camera = camera.with_stereo(c.eye_separation);
+        let camera = if c.iso.is_some() || c.shutter_speed.is_some() || c.f_stop.is_some() {
+            let exposure = Exposure::default();
+            camera.with_exposure(Exposure {
+                iso: c.iso.unwrap_or(exposure.iso),
+                shutter: c.shutter_speed.unwrap_or(exposure.shutter),
+                f_stop: c.f_stop.unwrap_or(exposure.f_stop)
+            })
+        } else {
+            camera
+        };
+
+        let mut world = World::new();
+
+        world.background = match &file.background {
+            BackgroundConfig::Sky => Background::Sky,
+            BackgroundConfig::Solid { color } => Background::Solid(vector(*color)),
+            BackgroundConfig::Environment { path } => {
+                Background::Environment(Arc::new(EnvironmentMap::load(self.path(path))?))
+            }
+        };
+
+        for object in &file.objects {
+            if let Some(light) = self.emitter(object) {
+                world.emitters.insert(world.objects.len(), world.lights.len());
+                world.lights.push(light);
+            }
+            let object = self.object(object)?;
+            world.objects.push(object);
+        }
+
+        for light in &file.lights {
+            world.lights.push(match light {
+                LightConfig::Point { position, intensity } => Box::new(PointLight {
+                    position: vector(*position),
+                    intensity: vector(*intensity)
+                }),
+                LightConfig::Area { corner, u, v, radiance } => Box::new(AreaLight {
+                    corner: vector(*corner),
+                    u: vector(*u),
+                    v: vector(*v),
+                    radiance: vector(*radiance)
+                })
+            });
+        }
+
+        world.prepare(settings.accelerator, settings.bvh);
+        world.caustics = PhotonMap::build(&world, &settings);
+
+        let animation = Animation { frames: file.animation.frames.max(1), fps: file.animation.fps, easing: self.easing };
+        Ok(Scene { settings, camera, world, colors: self.colors, animation })
+    }
+
+    fn texture(&mut self, texture: &TextureRef) -> Result<Arc<dyn Texture>, LoadError> {
+        let name = match texture {
+            TextureRef::Color(color) => return Ok(Arc::new(SolidColor{ color: vector(*color) })),
+            TextureRef::Name(name) => name
+        };
+
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let config = self.file.textures.get(name)
+            .ok_or_else(|| LoadError::invalid(format!("unknown texture '{}'", name)))?;
+
+        if !self.resolving.insert(name.clone()) {
+            return Err(LoadError::invalid(format!("texture '{}' refers to itself", name)));
+        }
+
+        let texture: Arc<dyn Texture> = match config {
+            TextureConfig::Solid { color } => Arc::new(SolidColor{ color: vector(*color) }),
+            TextureConfig::Image { path } => Arc::new(ImageTexture::load(self.path(path))?),
+            TextureConfig::Checker { even, odd, size, space } => Arc::new(Checker {
+                even: self.texture(even)?,
+                odd: self.texture(odd)?,
+                size: *size,
+                space: match space {
+                    CheckerSpaceConfig::World => CheckerSpace::World,
+                    CheckerSpaceConfig::Uv => CheckerSpace::Uv
+                }
+            }),
+            TextureConfig::Stripes { even, odd, axis, width } => Arc::new(Stripes {
+                even: self.texture(even)?,
+                odd: self.texture(odd)?,
+                axis: vector(*axis),
+                width: *width
+            }),
+            TextureConfig::Noise { color, frequency, octaves } => Arc::new(NoiseTexture {
+                noise: self.noise.clone(),
+                color: vector(*color),
+                frequency: *frequency,
+                octaves: *octaves
+            }),
+            TextureConfig::Marble { color, frequency, octaves, turbulence } => Arc::new(MarbleTexture {
+                noise: self.noise.clone(),
+                color: vector(*color),
+                frequency: *frequency,
+                octaves: *octaves,
+                turbulence: *turbulence
+            })
+        };
+
+        self.resolving.remove(name);
+        self.textures.insert(name.clone(), texture.clone());
+        Ok(texture)
+    }
+
+    /// Texture giving the color of a material. Plain colors can be
+    /// changed later through `Scene::colors`.
+    fn color(&mut self, material: &str, texture: &TextureRef) -> Result<Arc<dyn Texture>, LoadError> {
+        if let TextureRef::Color(color) = texture {
+            let color = Arc::new(LiveColor::new(vector(*color)));
+            self.colors.push((material.to_string(), color.clone()));
+            return Ok(color);
+        }
+        self.texture(texture)
+    }
+
+    fn material(&mut self, name: &str) -> Result<Arc<dyn Material>, LoadError> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+
+        let config = self.file.materials.get(name)
+            .ok_or_else(|| LoadError::invalid(format!("unknown material '{}'", name)))?;
+
+        let mut material: Arc<dyn Material> = match &config.kind {
+            MaterialKind::Lambertian { albedo } => Arc::new(Lambertian{ albedo: self.color(name, albedo)? }),
+            MaterialKind::Metal { albedo, fuzz } => Arc::new(Metal::new(vector(*albedo), *fuzz)),
+            MaterialKind::Dielectric { refractive_index } => {
+                Arc::new(Dielectric{ refractive_index: *refractive_index })
+            },
+            MaterialKind::Emissive { radiance } => Arc::new(Emissive{ radiance: vector(*radiance) }),
+            MaterialKind::Isotropic { albedo } => Arc::new(Isotropic{ albedo: self.color(name, albedo)? }),
+            MaterialKind::Pbr { base_color, metallic, roughness } => Arc::new(Pbr {
+                base_color: self.color(name, base_color)?,
+                metallic: *metallic,
+                roughness: *roughness
+            }),
+            MaterialKind::Principled {
+                base_color, metallic, roughness, specular, specular_tint,
+                sheen, sheen_tint, clearcoat, clearcoat_gloss
+            } => {
+                let mut principled = Principled::new(self.color(name, base_color)?);
+                let parameters = [
+                    (&mut principled.metallic, metallic),
+                    (&mut principled.roughness, roughness),
+                    (&mut principled.specular, specular),
+                    (&mut principled.specular_tint, specular_tint),
+                    (&mut principled.sheen, sheen),
+                    (&mut principled.sheen_tint, sheen_tint),
+                    (&mut principled.clearcoat, clearcoat),
+                    (&mut principled.clearcoat_gloss, clearcoat_gloss)
+                ];
+                for (parameter, value) in parameters {
+                    if let Some(value) = value {
+                        *parameter = *value;
+                    }
+                }
+                Arc::new(principled)
+            }
+        };
+
+        if let Some(path) = &config.normal_map {
+            material = Arc::new(NormalMapped {
+                material,
+                normal_map: Arc::new(ImageTexture::load_linear(self.path(path))?)
+            });
+        }
+
+        if let Some(path) = &config.bump_map {
+            material = Arc::new(BumpMapped {
+                material,
+                height_map: Arc::new(ImageTexture::load_linear(self.path(path))?),
+                strength: config.bump_strength
+            });
+        }
+
+        self.materials.insert(name.to_string(), material.clone());
+        Ok(material)
+    }
+
+    /// Light sampling the object if it is a glowing sphere, quad or
+    /// triangle that is not transformed.
+    fn emitter(&self, config: &ObjectConfig) -> Option<Box<dyn Light>> {
+        if config.transform.is_some() || !config.keyframes.is_empty() {
+            return None;
+        }
+
+        let radiance = |name: &str| match self.file.materials.get(name).map(|m| &m.kind) {
+            Some(MaterialKind::Emissive { radiance }) => Some(vector(*radiance)),
+            _ => None
+        };
+
+        match &config.shape {
+            ShapeConfig::Sphere { center, radius, material } if *radius > 0.0 => Some(Box::new(SphereLight {
+                center: vector(*center),
+                radius: *radius,
+                radiance: radiance(material)?
+            })),
+            ShapeConfig::Quad { corner, u, v, material } => Some(Box::new(AreaLight {
+                corner: vector(*corner),
+                u: vector(*u),
+                v: vector(*v),
+                radiance: radiance(material)?
+            })),
+            ShapeConfig::Triangle { a, b, c, material } => Some(Box::new(TriangleLight {
+                a: vector(*a),
+                b: vector(*b),
+                c: vector(*c),
+                radiance: radiance(material)?
+            })),
+            _ => None
+        }
+    }
+
+    fn object(&mut self, config: &ObjectConfig) -> Result<Box<dyn Hittable>, LoadError> {
+        let object: Box<dyn Hittable> = match &config.shape {
+            ShapeConfig::Sphere { center, radius, material } => Box::new(Sphere {
+                center: vector(*center),
+                radius: *radius,
+                material: self.material(material)?
+            }),
+            ShapeConfig::MovingSphere { center0, center1, time0, time1, radius, material } => {
+                Box::new(MovingSphere {
+                    center0: vector(*center0),
+                    center1: vector(*center1),
+                    time0: *time0,
+                    time1: *time1,
+                    radius: *radius,
+                    material: self.material(material)?
+                })
+            },
+            ShapeConfig::Plane { point, normal, material } => Box::new(Plane {
+                point: vector(*point),
+                normal: vector(*normal),
+                material: self.material(material)?
+            }),
+            ShapeConfig::Quad { corner, u, v, material } => Box::new(Quad {
+                corner: vector(*corner),
+                u: vector(*u),
+                v: vector(*v),
+                material: self.material(material)?
+            }),
+            ShapeConfig::Triangle { a, b, c, material } => Box::new(Triangle {
+                a: vector(*a),
+                b: vector(*b),
+                c: vector(*c),
+                material: self.material(material)?
+            }),
+            ShapeConfig::Box { min, max, material } => {
+                if (0 .. 3).any(|a| min[a] >= max[a]) {
+                    return Err(LoadError::invalid("the min corner of a box must be below its max one"));
+                }
+                Box::new(Cuboid {
+                    min: vector(*min),
+                    max: vector(*max),
+                    material: self.material(material)?
+                })
+            },
+            ShapeConfig::Cylinder { base, top, radius, caps, material } => {
+                if *radius <= 0.0 || base == top {
+                    return Err(LoadError::invalid("a cylinder needs a positive radius and height"));
+                }
+                Box::new(Cylinder {
+                    base: vector(*base),
+                    top: vector(*top),
+                    radius: *radius,
+                    caps: *caps,
+                    material: self.material(material)?
+                })
+            },
+            ShapeConfig::Cone { base, apex, radius, cap, material } => {
+                if *radius <= 0.0 || base == apex {
+                    return Err(LoadError::invalid("a cone needs a positive radius and height"));
+                }
+                Box::new(Cone {
+                    base: vector(*base),
+                    apex: vector(*apex),
+                    radius: *radius,
+                    cap: *cap,
+                    material: self.material(material)?
+                })
+            },
+            ShapeConfig::Mesh { path, material, smooth } => {
+                let key = (self.path(path), Some(material.clone()), *smooth);
+                let material = self.material(material)?;
+                Box::new(self.mesh(key, |path| load_obj(path, material))?)
+            },
+            ShapeConfig::Gltf { path } => Box::new(load_gltf(self.path(path))?),
+            ShapeConfig::Stl { path, material, smooth } => {
+                let key = (self.path(path), material.clone(), *smooth);
+                let material = material.as_deref().map(|name| self.material(name)).transpose()?;
+                Box::new(self.mesh(key, |path| load_stl(path, material))?)
+            },
+            ShapeConfig::Ply { path, material, smooth } => {
+                let key = (self.path(path), material.clone(), *smooth);
+                let material = material.as_deref().map(|name| self.material(name)).transpose()?;
+                Box::new(self.mesh(key, |path| load_ply(path, material))?)
+            },
+            ShapeConfig::Vox { path } => Box::new(load_vox(self.path(path))?),
+            ShapeConfig::Heightfield { path, material } => {
+                let material = self.material(material)?;
+                Box::new(Heightfield::load(self.path(path), material)?)
+            },
+            ShapeConfig::NoiseHeightfield { resolution, frequency, octaves, material } => {
+                if *resolution < 2 {
+                    return Err(LoadError::invalid("a heightfield needs a resolution of at least 2"));
+                }
+                let material = self.material(material)?;
+                Box::new(Heightfield::noise(&self.noise, *resolution, *resolution, *frequency, *octaves, material))
+            },
+            ShapeConfig::Sdf { shape, material } => {
+                let material = self.material(material)?;
+                Box::new(Sdf { field: shape.field()?, material })
+            },
+            ShapeConfig::ConstantMedium { boundary, density, albedo } => {
+                let boundary = self.object(boundary)?;
+                Box::new(ConstantMedium::new(boundary, *density, self.texture(albedo)?))
+            },
+            ShapeConfig::NoiseMedium { boundary, density, frequency, octaves, albedo } => {
+                let boundary = self.object(boundary)?;
+                let field = NoiseDensity {
+                    noise: self.noise.clone(),
+                    density: *density,
+                    frequency: *frequency,
+                    octaves: *octaves
+                };
+
+                // The turbulence stays below two.
+                let max_density = 2.0 * density;
+                Box::new(HeterogeneousMedium::new(boundary, Arc::new(field), max_density, self.texture(albedo)?))
+            }
+        };
+
+        let keys: Vec<(f32, ObjectPose)> = config.keyframes.iter().map(|k| (k.frame, ObjectPose::new(k))).collect();
+        let transform = match interpolate(&sorted(keys), self.frame, self.easing, ObjectPose::lerp) {
+            Some(pose) => Some(pose.transform()),
+            None => config.transform.as_ref().map(TransformConfig::transform)
+        };
+        Ok(match transform {
+            Some(transform) => Box::new(Instance::new(Arc::from(object), transform)),
+            None => object
+        })
+    }
+}
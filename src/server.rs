@@ -0,0 +1,389 @@
+//! Rendering as a service: a small HTTP server taking scene files, for
+//! scripts and web frontends.
+//!
+//! The scenes are rendered one after another, in the order they come,
+//! each with all the threads. The requests it understands are:
+//!
+//! - `POST /jobs` with a TOML scene as the body queues it and answers
+//!   with the job, its `id` among the rest. Relative file paths of the
+//!   scene are resolved against the working directory of the server.
+//!   Scenes asking for too large an image, too many samples or photons
+//!   are turned down, and so are all of them while the queue is full.
+//! - `GET /jobs` lists all the jobs.
+//! - `GET /jobs/<id>` tells how the job is doing: its `status`, one of
+//!   `queued`, `rendering`, `done`, `failed`, `cancelled` and `expired`,
+//!   and the `samples` taken out of `samples_per_pixel`.
+//! - `GET /jobs/<id>/image` gives the finished image as a PNG file, tone
+//!   mapped, denoised and transparent as the scene asks. Only the images
+//!   of the latest jobs are kept, those of the older ones expire.
+//! - `DELETE /jobs/<id>` cancels a job that is queued or rendering.
+//!
+//! The answers but for the image are JSON. Every connection carries a
+//! single request.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::denoise::denoise;
+use crate::output::{encode_png, unpremultiply};
+use crate::render::RenderThread;
+use crate::scene::Scene;
+
+/// Largest scene file taken, in bytes.
+const MAX_BODY: usize = 16 << 20;
+
+/// Largest image rendered, in pixels.
+const MAX_PIXELS: i64 = 4096 * 4096;
+
+/// Most samples per pixel taken.
+const MAX_SAMPLES: i64 = 1 << 16;
+
+/// Most photons shot for the caustics.
+const MAX_PHOTONS: i64 = 1 << 24;
+
+/// Most jobs waiting for their turn.
+const MAX_QUEUED: usize = 64;
+
+/// How many finished images are kept.
+const MAX_FINISHED: usize = 32;
+
+/// How often the progress of the job being rendered is looked at.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+enum Status {
+    Queued(Box<Scene>),
+    Rendering,
+    Done(Vec<u8>), // The PNG file
+    Failed(String),
+    Cancelled,
+    Expired // Done, but the image is thrown away to make room for newer ones
+}
+
+struct Job {
+    status: Status,
+    samples: u32,
+    samples_per_pixel: u32
+}
+
+impl Job {
+    fn to_json(&self, id: usize) -> String {
+        let (status, error) = match &self.status {
+            Status::Queued(_) => ("queued", None),
+            Status::Rendering => ("rendering", None),
+            Status::Done(_) => ("done", None),
+            Status::Failed(err) => ("failed", Some(err)),
+            Status::Cancelled => ("cancelled", None),
+            Status::Expired => ("expired", None)
+        };
+        let error = error.map_or(String::new(), |err| format!(", \"error\": {}", json_string(err)));
+        format!(
+            "{{\"id\": {}, \"status\": \"{}\", \"samples\": {}, \"samples_per_pixel\": {}{}}}",
+            id, status, self.samples, self.samples_per_pixel, error
+        )
+    }
+}
+
+/// Jobs by id, which is the index, and a condition variable telling the
+/// renderer about the new ones.
+type Jobs = (Mutex<Vec<Job>>, Condvar);
+
+/// The jobs, even if a thread panicked holding the lock: every change
+/// to them is a single assignment, so they are never left half done.
+fn lock(jobs: &Mutex<Vec<Job>>) -> MutexGuard<'_, Vec<Job>> {
+    jobs.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Answer the requests coming to `address` till the process is stopped.
+pub fn serve<A: ToSocketAddrs>(address: A) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("Serving on http://{}", listener.local_addr()?);
+
+    let jobs: Arc<Jobs> = Arc::new((Mutex::new(vec![]), Condvar::new()));
+    {
+        let jobs = jobs.clone();
+        thread::spawn(move || render_jobs(&jobs));
+    }
+
+    for stream in listener.incoming() {
+        let jobs = jobs.clone();
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(err) = answer(stream, &jobs) {
+                        eprintln!("Failed to answer a request: {}", err);
+                    }
+                });
+            },
+            Err(err) => eprintln!("Failed to accept a connection: {}", err)
+        }
+    }
+    Ok(())
+}
+
+/// Render the queued jobs one at a time, oldest first.
+fn render_jobs(jobs: &Jobs) {
+    let (jobs, condvar) = jobs;
+    loop {
+        let (id, scene) = {
+            let mut jobs = lock(jobs);
+            loop {
+                let queued = jobs.iter().position(|job| matches!(job.status, Status::Queued(_)));
+                if let Some(id) = queued {
+                    match std::mem::replace(&mut jobs[id].status, Status::Rendering) {
+                        Status::Queued(scene) => break (id, scene),
+                        _ => unreachable!()
+                    }
+                }
+                jobs = condvar.wait(jobs).unwrap_or_else(PoisonError::into_inner);
+            }
+        };
+        println!("Rendering job {}", id);
+
+        // A job bringing the renderer down fails alone.
+        let status = match panic::catch_unwind(AssertUnwindSafe(|| render(id, *scene, jobs))) {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                println!("Cancelled job {}", id);
+                continue;
+            },
+            Err(_) => Status::Failed("the renderer crashed".to_string())
+        };
+
+        let mut jobs = lock(jobs);
+        if matches!(jobs[id].status, Status::Cancelled) {
+            println!("Cancelled job {}", id);
+            continue;
+        }
+        jobs[id].status = status;
+        let done: Vec<usize> = (0 .. jobs.len()).filter(|&k| matches!(jobs[k].status, Status::Done(_))).collect();
+        for &k in done.iter().rev().skip(MAX_FINISHED) {
+            jobs[k].status = Status::Expired;
+        }
+        println!("Finished job {}", id);
+    }
+}
+
+/// Render the scene of the job and encode the image, as `main` would
+/// save it to a PNG file. `None` if the job is cancelled meanwhile.
+fn render(id: usize, scene: Scene, jobs: &Mutex<Vec<Job>>) -> Option<Status> {
+    let Scene { settings, camera, world, .. } = scene;
+    let renderer = RenderThread::spawn(settings, camera, Arc::new(world));
+    while !renderer.wait_timeout(POLL_INTERVAL) {
+        let mut jobs = lock(jobs);
+        if matches!(jobs[id].status, Status::Cancelled) {
+            return None;
+        }
+        if !renderer.is_alive() {
+            return Some(Status::Failed("the renderer crashed".to_string()));
+        }
+        jobs[id].samples = renderer.samples();
+    }
+
+    let (image, samples) = renderer.snapshot();
+    let aovs = renderer.aovs();
+    let image = match &aovs {
+        Some(aovs) if settings.denoise => denoise(&image, aovs),
+        _ => image
+    };
+    let alpha = aovs.filter(|_| settings.transparent).map(|aovs| aovs.alpha);
+    let exposure = settings.exposure + camera.exposure_stops();
+    let mapped = match &alpha {
+        Some(alpha) => settings.tone_map.map_image(&unpremultiply(&image, alpha), 1, exposure),
+        None => settings.tone_map.map_image(&image, 1, exposure)
+    };
+
+    lock(jobs)[id].samples = samples;
+    match encode_png(&mapped, 1, alpha.as_ref()) {
+        Ok(png) => Some(Status::Done(png)),
+        Err(err) => Some(Status::Failed(err.to_string()))
+    }
+}
+
+/// Why the scene asks for more than the server renders, if it does.
+/// Looked at before the scene is built, as the photons are shot then.
+fn over_limits(source: &str) -> Option<String> {
+    let table: toml::Table = source.parse().ok()?;
+    let render = table.get("render")?.as_table()?;
+    let integer = |name: &str| render.get(name).and_then(|value| value.as_integer()).unwrap_or(0);
+
+    if integer("width").saturating_mul(integer("height")) > MAX_PIXELS {
+        Some(format!("an image of more than {} pixels", MAX_PIXELS))
+    } else if integer("samples_per_pixel") > MAX_SAMPLES {
+        Some(format!("more than {} samples per pixel", MAX_SAMPLES))
+    } else if integer("photons") > MAX_PHOTONS {
+        Some(format!("more than {} photons", MAX_PHOTONS))
+    } else {
+        None
+    }
+}
+
+/// Read the request coming over `stream` and write the answer back.
+fn answer(stream: TcpStream, jobs: &Jobs) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    // Of the headers, only the length of the body matters.
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return respond_json(&mut writer, 413, &error("the scene is too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let (jobs, condvar) = jobs;
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let id = segments.get(1).and_then(|id| id.parse::<usize>().ok());
+    match (method, segments.as_slice()) {
+        ("POST", ["jobs"]) => {
+            let source = match String::from_utf8(body) {
+                Ok(source) => source,
+                Err(_) => return respond_json(&mut writer, 400, &error("the scene is not UTF-8"))
+            };
+            if let Some(reason) = over_limits(&source) {
+                return respond_json(&mut writer, 400, &error(&format!("the scene asks for {}", reason)));
+            }
+            if lock(jobs).iter().filter(|job| matches!(job.status, Status::Queued(_))).count() >= MAX_QUEUED {
+                return respond_json(&mut writer, 503, &error("the queue is full"));
+            }
+            let scene = match Scene::parse(&source, Path::new(".")) {
+                Ok(scene) => scene,
+                Err(err) => return respond_json(&mut writer, 400, &error(&err.to_string()))
+            };
+
+            let mut jobs = lock(jobs);
+            let samples_per_pixel = scene.settings.samples_per_pixel;
+            jobs.push(Job { status: Status::Queued(Box::new(scene)), samples: 0, samples_per_pixel });
+            condvar.notify_all();
+            let id = jobs.len() - 1;
+            println!("Queued job {}", id);
+            respond_json(&mut writer, 201, &jobs[id].to_json(id))
+        },
+        ("GET", ["jobs"]) => {
+            let jobs = lock(jobs);
+            let list: Vec<String> = jobs.iter().enumerate().map(|(id, job)| job.to_json(id)).collect();
+            respond_json(&mut writer, 200, &format!("[{}]", list.join(", ")))
+        },
+        ("GET", ["jobs", _]) => {
+            let jobs = lock(jobs);
+            match id.and_then(|id| jobs.get(id).map(|job| job.to_json(id))) {
+                Some(json) => respond_json(&mut writer, 200, &json),
+                None => respond_json(&mut writer, 404, &error("no such job"))
+            }
+        },
+        ("GET", ["jobs", _, "image"]) => {
+            let jobs = lock(jobs);
+            match id.and_then(|id| jobs.get(id)).map(|job| &job.status) {
+                Some(Status::Done(png)) => respond(&mut writer, 200, "image/png", png),
+                Some(Status::Expired) => respond_json(&mut writer, 410, &error("the image has expired")),
+                Some(_) => respond_json(&mut writer, 409, &error("the job is not done")),
+                None => respond_json(&mut writer, 404, &error("no such job"))
+            }
+        },
+        ("DELETE", ["jobs", _]) => {
+            let mut jobs = lock(jobs);
+            match id.and_then(|id| jobs.get_mut(id).map(|job| (id, job))) {
+                Some((id, job)) if matches!(job.status, Status::Queued(_) | Status::Rendering) => {
+                    job.status = Status::Cancelled;
+                    respond_json(&mut writer, 200, &job.to_json(id))
+                },
+                Some(_) => respond_json(&mut writer, 409, &error("the job is over")),
+                None => respond_json(&mut writer, 404, &error("no such job"))
+            }
+        },
+        (_, ["jobs"]) | (_, ["jobs", _]) | (_, ["jobs", _, "image"]) => {
+            respond_json(&mut writer, 405, &error("method not allowed"))
+        },
+        _ => respond_json(&mut writer, 404, &error("not found"))
+    }
+}
+
+fn respond(writer: &mut impl Write, code: u16, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let reason = match code {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        410 => "Gone",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => ""
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        code, reason, content_type, body.len()
+    )?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+fn respond_json(writer: &mut impl Write, code: u16, json: &str) -> io::Result<()> {
+    respond(writer, code, "application/json", json.as_bytes())
+}
+
+fn error(message: &str) -> String {
+    format!("{{\"error\": {}}}", json_string(message))
+}
+
+/// The string quoted for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        assert_eq!(over_limits("[render]\nwidth = 4096\nheight = 4096\nsamples_per_pixel = 65536\n"), None);
+        assert_eq!(over_limits("[[objects]]\ntype = \"sphere\"\n"), None);
+        assert!(over_limits("[render]\nwidth = 4097\nheight = 4096\n").is_some());
+        assert!(over_limits("[render]\nwidth = 9223372036854775807\nheight = 2\n").is_some());
+        assert!(over_limits("[render]\nsamples_per_pixel = 65537\n").is_some());
+        assert!(over_limits("[render]\nphotons = 100000000\n").is_some());
+    }
+
+    #[test]
+    fn json() {
+        let job = Job { status: Status::Failed("a \"bad\"\nscene".to_string()), samples: 3, samples_per_pixel: 8 };
+        assert_eq!(
+            job.to_json(5),
+            "{\"id\": 5, \"status\": \"failed\", \"samples\": 3, \"samples_per_pixel\": 8, \"error\": \"a \\\"bad\\\"\\nscene\"}"
+        );
+    }
+}
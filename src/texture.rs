@@ -0,0 +1,213 @@
+//! Spatially varying colors for the materials.
+
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use image::ImageResult;
+
+use crate::color::srgb_decode;
+use crate::math::Vector;
+use crate::perlin::Perlin;
+
+pub trait Texture: Debug + Send + Sync {
+    /// Color at the surface point `p` with texture coordinates (u, v).
+    fn value(&self, u: f32, v: f32, p: Vector) -> Vector;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SolidColor {
+    pub color: Vector
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: Vector) -> Vector {
+        self.color
+    }
+}
+
+/// Solid color that can be changed while the scene is being rendered.
+#[derive(Debug)]
+pub struct LiveColor {
+    rgb: [AtomicU32; 3] // Bits of the channels
+}
+
+impl LiveColor {
+    pub fn new(color: Vector) -> Self {
+        Self {
+            rgb: [
+                AtomicU32::new(color.x.to_bits()),
+                AtomicU32::new(color.y.to_bits()),
+                AtomicU32::new(color.z.to_bits())
+            ]
+        }
+    }
+
+    pub fn get(&self) -> Vector {
+        let [r, g, b] = &self.rgb;
+        Vector {
+            x: f32::from_bits(r.load(Ordering::Relaxed)),
+            y: f32::from_bits(g.load(Ordering::Relaxed)),
+            z: f32::from_bits(b.load(Ordering::Relaxed))
+        }
+    }
+
+    pub fn set(&self, color: Vector) {
+        let [r, g, b] = &self.rgb;
+        r.store(color.x.to_bits(), Ordering::Relaxed);
+        g.store(color.y.to_bits(), Ordering::Relaxed);
+        b.store(color.z.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl Texture for LiveColor {
+    fn value(&self, _u: f32, _v: f32, _p: Vector) -> Vector {
+        self.get()
+    }
+}
+
+/// Texture read from an image file and mapped onto the unit square of
+/// the texture coordinates, with v going upwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vector> // Linear color, top row first
+}
+
+impl ImageTexture {
+    /// Load a texture from a file. Colors of the usual 8-bit images are
+    /// sRGB encoded and get converted back to linear here.
+    pub fn load<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Self::read(path, false)
+    }
+
+    /// Load a texture storing data rather than colors, such as a normal
+    /// or a height map, taking the values as they are.
+    pub fn load_linear<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Self::read(path, true)
+    }
+
+    fn read<P: AsRef<Path>>(path: P, linear: bool) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        let linear = linear
+            || matches!(image.color(), image::ColorType::Rgb32F | image::ColorType::Rgba32F);
+
+        let image = image.into_rgb32f();
+        let decode = |c: f32| if linear { c } else { srgb_decode(c) };
+        let pixels = image.pixels()
+            .map(|p| Vector{ x: decode(p[0]), y: decode(p[1]), z: decode(p[2]) })
+            .collect();
+
+        Ok(Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: Vector) -> Vector {
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+
+        let i = ((v * self.height as f32) as usize).min(self.height - 1);
+        let j = ((u * self.width as f32) as usize).min(self.width - 1);
+
+        self.pixels[i * self.width + j]
+    }
+}
+
+/// Procedural parallel stripes alternating between two textures. The
+/// stripes run across the `axis` direction and are `width` wide.
+#[derive(Debug, Clone)]
+pub struct Stripes {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub axis: Vector,
+    pub width: f32
+}
+
+impl Texture for Stripes {
+    fn value(&self, u: f32, v: f32, p: Vector) -> Vector {
+        let k = (p.dot(self.axis.unit()) / self.width).floor() as i64;
+        if k.rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// Grayscale turbulent noise tinted with `color`. The `frequency`
+/// scales the noise features and `octaves` sets how much fine detail is
+/// layered on top.
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    pub noise: Arc<Perlin>,
+    pub color: Vector,
+    pub frequency: f32,
+    pub octaves: usize
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: Vector) -> Vector {
+        let t = self.noise.turbulence(self.frequency * p, self.octaves).min(1.0);
+        t * self.color
+    }
+}
+
+/// Marble-like veins: a sine wave along the z axis with its phase
+/// distorted by turbulence of the given strength.
+#[derive(Debug, Clone)]
+pub struct MarbleTexture {
+    pub noise: Arc<Perlin>,
+    pub color: Vector,
+    pub frequency: f32,
+    pub octaves: usize,
+    pub turbulence: f32
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f32, _v: f32, p: Vector) -> Vector {
+        let phase = self.frequency * p.z + self.turbulence * self.noise.turbulence(p, self.octaves);
+        0.5 * (1.0 + phase.sin()) * self.color
+    }
+}
+
+/// Where the checker pattern lives.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CheckerSpace {
+    /// Solid 3D cubes of the world space, independent of the shape.
+    World,
+    /// Squares of the texture coordinates, following the surface.
+    Uv
+}
+
+/// Checkerboard alternating between two textures. `size` is the side of
+/// a cell, either in world units or in the texture coordinates.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub size: f32,
+    pub space: CheckerSpace
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: Vector) -> Vector {
+        let cell = |x: f32| (x / self.size).floor() as i64;
+        let parity = match self.space {
+            CheckerSpace::World => cell(p.x) + cell(p.y) + cell(p.z),
+            CheckerSpace::Uv => cell(u) + cell(v)
+        };
+
+        if parity.rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Tone mapping: squeezing the light of the image, which is as bright as
+//! it gets, into the range of the screen and the image files.
+//!
+//! Clamping leaves the colors that fit as they are and cuts off the
+//! rest, so that bright lights and their reflections burn out to flat
+//! white. The operators roll off the highlights gradually instead.
+
+use std::str::FromStr;
+
+use crate::math::Vector;
+use crate::render::Image;
+
+/// How the light of a pixel is mapped to the range 0 .. 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ToneMap {
+    /// Cut off whatever is brighter than 1.
+    #[default]
+    Clamp,
+    /// Reinhard's operator, L / (1 + L) of the luminance, which keeps the
+    /// hues but dims even the colors that fit.
+    Reinhard,
+    /// Narkowicz's fit of the filmic curve of ACES, with a bit of
+    /// contrast in the shadows and the highlights going to white.
+    Aces
+}
+
+impl ToneMap {
+    /// Map the linear color made `exposure` stops brighter first.
+    pub fn map(&self, color: Vector, exposure: f32) -> Vector {
+        let color = exposure.exp2() * color;
+        let clamp = |c: f32| c.clamp(0.0, 1.0);
+
+        let mapped = match self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => {
+                let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+                color / (1.0 + luminance.max(0.0))
+            },
+            ToneMap::Aces => {
+                let curve = |c: f32| {
+                    let c = 0.6 * c;
+                    (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+                };
+                Vector{ x: curve(color.x), y: curve(color.y), z: curve(color.z) }
+            }
+        };
+        Vector{ x: clamp(mapped.x), y: clamp(mapped.y), z: clamp(mapped.z) }
+    }
+
+    /// The accumulation buffer divided by `samples`, with every pixel
+    /// mapped.
+    pub fn map_image(&self, image: &Image, samples: u32, exposure: f32) -> Image {
+        Image {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels.iter().map(|&c| self.map(c / samples as f32, exposure)).collect()
+        }
+    }
+
+    /// The operator after this one, going round.
+    pub fn next(&self) -> Self {
+        match self {
+            ToneMap::Clamp => ToneMap::Reinhard,
+            ToneMap::Reinhard => ToneMap::Aces,
+            ToneMap::Aces => ToneMap::Clamp
+        }
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!("unknown tone mapping {}, expected clamp, reinhard or aces", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(c: f32) -> Vector {
+        Vector{ x: c, y: c, z: c }
+    }
+
+    #[test]
+    fn clamp_and_exposure() {
+        let color = Vector{ x: 0.25, y: 0.5, z: 2.0 };
+        assert_eq!(ToneMap::Clamp.map(color, 0.0), Vector{ x: 0.25, y: 0.5, z: 1.0 });
+        assert_eq!(ToneMap::Clamp.map(color, 1.0), Vector{ x: 0.5, y: 1.0, z: 1.0 });
+        assert_eq!(ToneMap::Clamp.map(color, -2.0), Vector{ x: 0.0625, y: 0.125, z: 0.5 });
+        assert_eq!(ToneMap::Clamp.map(gray(-1.0), 0.0), gray(0.0));
+    }
+
+    #[test]
+    fn reinhard() {
+        // Grays have the luminance of their channels.
+        for &l in &[0.0, 0.5, 1.0, 4.0, 1000.0] {
+            let mapped = ToneMap::Reinhard.map(gray(l), 0.0);
+            assert!((mapped.x - l / (1.0 + l)).abs() < 1e-5, "{} -> {:?}", l, mapped);
+            assert_eq!(mapped.x, mapped.y);
+            assert_eq!(mapped.y, mapped.z);
+        }
+
+        // The hue stays: the channels are all scaled by the same factor.
+        let color = Vector{ x: 0.8, y: 0.4, z: 0.2 };
+        let mapped = ToneMap::Reinhard.map(color, 0.0);
+        assert!((mapped.x / mapped.y - 2.0).abs() < 1e-5);
+        assert!((mapped.y / mapped.z - 2.0).abs() < 1e-5);
+        assert!(mapped.x < color.x);
+    }
+
+    #[test]
+    fn aces() {
+        assert_eq!(ToneMap::Aces.map(gray(0.0), 0.0), gray(0.0));
+
+        // The curve only grows and goes to white in the highlights.
+        let mut last = 0.0;
+        for k in 1 ..= 100 {
+            let c = ToneMap::Aces.map(gray(k as f32 / 10.0), 0.0).x;
+            assert!(c >= last, "{} after {}", c, last);
+            last = c;
+        }
+        assert!(ToneMap::Aces.map(gray(100.0), 0.0).x > 0.99);
+
+        // Mid gray stays in the middle of the screen range.
+        let middle = ToneMap::Aces.map(gray(0.18), 0.0).x;
+        assert!(0.1 < middle && middle < 0.2, "{}", middle);
+    }
+
+    #[test]
+    fn map_image() {
+        let image = Image { width: 2, height: 1, pixels: vec![gray(2.0), gray(8.0)] };
+        let mapped = ToneMap::Clamp.map_image(&image, 4, 0.0);
+        assert_eq!((mapped.width, mapped.height), (2, 1));
+        assert_eq!(mapped.pixels, vec![gray(0.5), gray(1.0)]);
+    }
+
+    #[test]
+    fn names_and_order() {
+        let mut tonemap = ToneMap::default();
+        for name in ["clamp", "reinhard", "aces", "clamp"] {
+            assert_eq!(name.parse::<ToneMap>(), Ok(tonemap));
+            tonemap = tonemap.next();
+        }
+        assert!("filmic".parse::<ToneMap>().is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! Animations written straight to a video file, by piping the frames to
+//! ffmpeg as they are rendered.
+//!
+//! ffmpeg is taken from the `PATH`, or from where the `FFMPEG`
+//! environment variable tells. It gets the frames as raw 8-bit RGB and
+//! picks the codec by the extension of the file. All but GIFs are
+//! encoded in YUV 4:2:0, the one most players understand, which needs
+//! the width and the height to be even: an odd one is padded by a row or
+//! a column of black.
+
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::output::rgb8;
+use crate::render::Image;
+
+/// ffmpeg encoding the frames written to it into a video file.
+pub struct Video {
+    child: Child,
+    stdin: Option<ChildStdin>, // Closed to tell ffmpeg the video is over
+    width: usize,
+    height: usize
+}
+
+impl Video {
+    /// Start ffmpeg writing a video of frames `width` by `height` pixels
+    /// playing at `fps` frames a second to `path`, overwriting the file
+    /// if there is one.
+    pub fn open<P: AsRef<Path>>(path: P, width: usize, height: usize, fps: f32) -> io::Result<Self> {
+        let path = path.as_ref();
+        let program = env::var_os("FFMPEG").unwrap_or_else(|| OsString::from("ffmpeg"));
+        let mut command = Command::new(&program);
+        command
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-video_size", &format!("{}x{}", width, height)])
+            .args(["-framerate", &fps.to_string()])
+            .args(["-i", "-"]);
+        let gif = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+        if !gif {
+            command.args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"]);
+        }
+        command.arg(path).stdin(Stdio::piped());
+
+        let mut child = command.spawn().map_err(|err| {
+            io::Error::new(err.kind(), format!("failed to run {}: {}", program.to_string_lossy(), err))
+        })?;
+        let stdin = child.stdin.take();
+        Ok(Self { child, stdin, width, height })
+    }
+
+    /// Add the averaged and tone mapped `image` as the next frame.
+    pub fn write_frame(&mut self, image: &Image) -> io::Result<()> {
+        if (image.width, image.height) != (self.width, self.height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the frame is not the size of the video"));
+        }
+        match self.stdin.as_mut() {
+            Some(stdin) => stdin.write_all(&rgb8(image, 1)),
+            None => Err(io::Error::new(io::ErrorKind::BrokenPipe, "ffmpeg has no input"))
+        }
+    }
+
+    /// Tell ffmpeg there are no more frames and wait for it to finish the
+    /// file.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.stdin = None;
+        let status = self.child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("ffmpeg failed with {}", status)))
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Rendering in the browser.
+//!
+//! The page owns the canvas and the animation loop; it asks a
+//! `Renderer` for one sample at a time and draws the pixels it gets
+//! back. See `web/` for the page.
+
+use std::path::Path;
+
+use wasm_bindgen::prelude::*;
+
+use crate::display::Frame;
+use crate::render::{render_sample, Image};
+use crate::scene::Scene;
+
+#[wasm_bindgen]
+pub struct Renderer {
+    scene: Scene,
+    image: Image,
+    samples: u32 // Taken so far
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    /// Renderer of a TOML scene. Files the scene refers to can't be
+    /// read in the browser, so it has to be self-contained.
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str) -> Result<Renderer, JsError> {
+        let scene = Scene::parse(source, Path::new("."))
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        let image = Image::new(scene.settings.width, scene.settings.height);
+        Ok(Renderer{ scene, image, samples: 0 })
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn done(&self) -> bool {
+        self.samples >= self.scene.settings.samples_per_pixel
+    }
+
+    pub fn set_samples_per_pixel(&mut self, samples_per_pixel: u32) {
+        self.scene.settings.samples_per_pixel = samples_per_pixel;
+    }
+
+    /// Changing the depth invalidates the samples taken so far.
+    pub fn set_max_depth(&mut self, max_depth: u8) {
+        self.scene.settings.max_depth = max_depth;
+        self.restart();
+    }
+
+    pub fn restart(&mut self) {
+        self.image.clear();
+        self.samples = 0;
+    }
+
+    /// Take one more sample for every pixel, unless all of them are
+    /// taken already.
+    pub fn render_sample(&mut self) {
+        if self.done() {
+            return;
+        }
+        let Scene { settings, camera, world, .. } = &self.scene;
+        render_sample(&mut self.image, self.samples, settings, camera, world);
+        self.samples += 1;
+    }
+
+    /// Averaged and tone mapped image as RGBA bytes, top row first,
+    /// ready for an `ImageData`.
+    pub fn pixels(&self) -> Vec<u8> {
+        let Scene { settings, camera, .. } = &self.scene;
+        let exposure = settings.exposure + camera.exposure_stops();
+        let image = settings.tone_map.map_image(&self.image, self.samples.max(1), exposure);
+        let frame = Frame::from_image(&image, 1);
+        frame.pixels.iter().flat_map(|&[r, g, b]| [r, g, b, 255]).collect()
+    }
+}
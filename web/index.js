@@ -0,0 +1,68 @@
+// Loads a scene into the renderer and draws every new sample into the
+// canvas. The page parameters can also be given in the query string,
+// e.g. `?scene=scenes/balls.toml&samples=20&depth=5`.
+
+import init, { Renderer } from "./pkg/rtrace.js";
+
+const form = document.getElementById("parameters");
+const canvas = document.getElementById("canvas");
+const status = document.getElementById("status");
+
+let renderer = null;
+let frame = null;
+
+async function start() {
+  if (frame !== null) {
+    cancelAnimationFrame(frame);
+    frame = null;
+  }
+  if (renderer !== null) {
+    renderer.free();
+    renderer = null;
+  }
+
+  const response = await fetch(form.scene.value);
+  if (!response.ok) {
+    status.textContent = `Failed to fetch ${form.scene.value}: ${response.status}`;
+    return;
+  }
+
+  try {
+    renderer = new Renderer(await response.text());
+  } catch (err) {
+    status.textContent = `Failed to load ${form.scene.value}: ${err.message}`;
+    return;
+  }
+  renderer.set_samples_per_pixel(Number(form.samples.value));
+  renderer.set_max_depth(Number(form.depth.value));
+
+  canvas.width = renderer.width();
+  canvas.height = renderer.height();
+  frame = requestAnimationFrame(step);
+}
+
+function step() {
+  renderer.render_sample();
+
+  const pixels = new Uint8ClampedArray(renderer.pixels());
+  const image = new ImageData(pixels, renderer.width(), renderer.height());
+  canvas.getContext("2d").putImageData(image, 0, 0);
+  status.textContent = `Sample ${renderer.samples()}`;
+
+  frame = renderer.done() ? null : requestAnimationFrame(step);
+}
+
+await init();
+
+const query = new URLSearchParams(location.search);
+for (const name of ["scene", "samples", "depth"]) {
+  if (query.has(name)) {
+    form[name].value = query.get(name);
+  }
+}
+
+form.addEventListener("submit", (event) => {
+  event.preventDefault();
+  start();
+});
+start();